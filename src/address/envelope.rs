@@ -3,7 +3,7 @@ use super::Address;
 use crate::message::header::{self, Headers};
 #[cfg(feature = "builder")]
 use crate::message::{Mailbox, Mailboxes};
-use crate::Error;
+use crate::{error, Error};
 
 /// Simple email envelope representation
 ///
@@ -17,8 +17,19 @@ pub struct Envelope {
     forward_path: Vec<Address>,
     /// The envelope sender address
     reverse_path: Option<Address>,
+    /// The identifier of the envelope in delivery status notifications
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    envelope_id: Option<String>,
 }
 
+/// Maximum length of an envelope identifier
+///
+/// [RFC 3461, section 4.4](https://tools.ietf.org/html/rfc3461#section-4.4)
+const MAX_ENVELOPE_ID_LEN: usize = 100;
+
 impl Envelope {
     /// Creates a new envelope, which may fail if `to` is empty.
     ///
@@ -46,9 +57,51 @@ impl Envelope {
         Ok(Envelope {
             forward_path: to,
             reverse_path: from,
+            envelope_id: None,
         })
     }
 
+    /// Sets the identifier of the envelope, returned in the delivery
+    /// status notifications about the email
+    ///
+    /// It is sent as the `ENVID` parameter of `MAIL FROM` when the server
+    /// supports DSN, defined in [RFC 3461](https://tools.ietf.org/html/rfc3461#section-4.4),
+    /// so that bounces can be matched with the email, for example with the
+    /// identifier of the request which sent it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use lettre::address::{Address, Envelope};
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let sender = "from@email.com".parse::<Address>()?;
+    /// let recipients = vec!["to@email.com".parse::<Address>()?];
+    ///
+    /// let envelope = Envelope::new(Some(sender), recipients)?;
+    /// assert!(envelope.clone().with_envelope_id("ré-42").is_err());
+    ///
+    /// let envelope = envelope.with_envelope_id("req-42")?;
+    /// assert_eq!(envelope.envelope_id(), Some("req-42"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If `id` is empty, longer than 100 characters, or contains other
+    /// characters than printable ASCII and spaces.
+    pub fn with_envelope_id(mut self, id: &str) -> Result<Envelope, Error> {
+        if id.is_empty()
+            || id.len() > MAX_ENVELOPE_ID_LEN
+            || !id.bytes().all(|b| matches!(b, b' '..=b'~'))
+        {
+            return Err(error::invalid_envelope_id());
+        }
+        self.envelope_id = Some(id.to_owned());
+        Ok(self)
+    }
+
     /// Gets the destination addresses of the envelope.
     ///
     /// # Examples
@@ -92,6 +145,12 @@ impl Envelope {
         self.reverse_path.as_ref()
     }
 
+    /// Gets the identifier of the envelope, if set with
+    /// [`Envelope::with_envelope_id`]
+    pub fn envelope_id(&self) -> Option<&str> {
+        self.envelope_id.as_deref()
+    }
+
     #[cfg(feature = "smtp-transport")]
     /// Check if any of the addresses in the envelope contains non-ascii chars
     pub(crate) fn has_non_ascii_addresses(&self) -> bool {
@@ -72,7 +72,8 @@ impl<'de> Deserialize<'de> for Address {
             where
                 E: DeError,
             {
-                s.parse().map_err(DeError::custom)
+                s.parse()
+                    .map_err(|err| DeError::custom(format!("{err} in address {s:?}")))
             }
 
             fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
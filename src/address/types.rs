@@ -1,6 +1,7 @@
 //! Representation of an email address
 
 use std::{
+    borrow::Cow,
     error::Error,
     ffi::OsStr,
     fmt::{Display, Formatter, Result as FmtResult},
@@ -145,9 +146,26 @@ impl Address {
         Err(AddressError::InvalidDomain)
     }
 
+    /// Checks the address against the stricter `Mailbox` syntax of
+    /// [RFC 5321](https://tools.ietf.org/html/rfc5321#section-4.1.2)
+    ///
+    /// Unlike RFC 5322, it doesn't allow any control character,
+    /// including tabs in quoted local parts.
+    #[cfg(feature = "builder")]
+    pub(crate) fn check_smtp(&self) -> Result<(), AddressError> {
+        let (user, domain) = (self.user(), self.domain());
+        if user.chars().any(char::is_control) {
+            return Err(AddressError::InvalidUser);
+        }
+        if domain.chars().any(char::is_control) {
+            return Err(AddressError::InvalidDomain);
+        }
+        Ok(())
+    }
+
     #[cfg(feature = "smtp-transport")]
     /// Check if the address contains non-ascii chars
-    pub(super) fn is_ascii(&self) -> bool {
+    pub(crate) fn is_ascii(&self) -> bool {
         self.serialized.is_ascii()
     }
 }
@@ -204,6 +222,30 @@ impl TryFrom<String> for Address {
     }
 }
 
+impl TryFrom<&str> for Address {
+    type Error = AddressError;
+
+    fn try_from(serialized: &str) -> Result<Self, AddressError> {
+        serialized.parse()
+    }
+}
+
+impl TryFrom<&String> for Address {
+    type Error = AddressError;
+
+    fn try_from(serialized: &String) -> Result<Self, AddressError> {
+        serialized.parse()
+    }
+}
+
+impl TryFrom<Cow<'_, str>> for Address {
+    type Error = AddressError;
+
+    fn try_from(serialized: Cow<'_, str>) -> Result<Self, AddressError> {
+        Address::try_from(serialized.into_owned())
+    }
+}
+
 impl AsRef<str> for Address {
     fn as_ref(&self) -> &str {
         &self.serialized
@@ -296,6 +338,25 @@ mod tests {
         assert_eq!(addr2.domain(), "[2606:4700:4700::1111]");
     }
 
+    #[test]
+    fn address_from_strings() {
+        let addr = Address::new("something", "example.com").unwrap();
+        let owned = String::from("something@example.com");
+
+        assert_eq!(Address::try_from("something@example.com"), Ok(addr.clone()));
+        assert_eq!(Address::try_from(&owned), Ok(addr.clone()));
+        assert_eq!(Address::try_from(owned.clone()), Ok(addr.clone()));
+        assert_eq!(
+            Address::try_from(Cow::Borrowed(owned.as_str())),
+            Ok(addr.clone())
+        );
+        assert_eq!(Address::try_from(Cow::<str>::Owned(owned)), Ok(addr));
+        assert_eq!(
+            Address::try_from(&String::from("something")),
+            Err(AddressError::MissingParts)
+        );
+    }
+
     #[test]
     fn check_parts() {
         assert!(Address::check_user("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa").is_err());
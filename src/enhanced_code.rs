@@ -0,0 +1,32 @@
+//! Parser of enhanced status codes, shared by SMTP replies and delivery
+//! status notifications
+
+/// Parses a code like `5.1.1` into its class, subject and detail
+///
+/// The class is `2`, `4` or `5`, and the subject and the detail have
+/// one to three digits.
+///
+/// Defined in [RFC3463](https://tools.ietf.org/html/rfc3463#section-2)
+pub(crate) fn parse(s: &str) -> Option<(u8, u16, u16)> {
+    let mut parts = s.split('.');
+    let class = match parts.next()? {
+        "2" => 2,
+        "4" => 4,
+        "5" => 5,
+        _ => return None,
+    };
+    let mut number = || {
+        parts
+            .next()
+            .filter(|part| {
+                (1..=3).contains(&part.len()) && part.bytes().all(|c| c.is_ascii_digit())
+            })
+            .and_then(|part| part.parse().ok())
+    };
+    let subject = number()?;
+    let detail = number()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((class, subject, detail))
+}
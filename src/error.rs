@@ -3,8 +3,11 @@
 use std::{
     error::Error as StdError,
     fmt::{self, Display, Formatter},
+    io,
 };
 
+use crate::Address;
+
 // FIXME message-specific errors
 /// Error type for email content
 #[derive(Debug)]
@@ -24,11 +27,27 @@ pub enum Error {
     /// Cannot parse filename for attachment
     CannotParseFilename,
     /// IO error
+    ///
+    /// Also carries the [`ValidationError`] of a failed validation check,
+    /// see [`Error::validation`].
     Io(std::io::Error),
     /// Non-ASCII chars
     NonAsciiChars,
 }
 
+impl Error {
+    /// Returns the reason why the message or its envelope failed validation
+    ///
+    /// Validation failures are returned as [`Error::Io`] errors of kind
+    /// [`InvalidInput`](io::ErrorKind::InvalidInput), wrapping a [`ValidationError`].
+    pub fn validation(&self) -> Option<&ValidationError> {
+        match self {
+            Error::Io(err) => err.get_ref()?.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
         match self {
@@ -52,3 +71,133 @@ impl From<std::io::Error> for Error {
 }
 
 impl StdError for Error {}
+
+/// The reason why a message or its envelope failed validation
+///
+/// Returned by [`Error::validation`]. New checks may be added in minor
+/// releases, use the `is_*` methods to find out which one failed.
+#[derive(Debug)]
+pub struct ValidationError {
+    kind: ValidationKind,
+}
+
+#[derive(Debug)]
+#[cfg_attr(not(feature = "builder"), allow(dead_code))]
+enum ValidationKind {
+    InvalidAddresses(Vec<Address>),
+    EmptyListUnsubscribe,
+    BoundaryInContent,
+    DmarcMisaligned,
+    InvalidEnvelopeId,
+    MessageSizeTooSmall,
+}
+
+impl ValidationError {
+    /// Returns the addresses which aren't valid for SMTP, if the message
+    /// was rejected because of them
+    ///
+    /// See [`MessageBuilder::skip_address_validation`](crate::message::MessageBuilder::skip_address_validation).
+    pub fn invalid_addresses(&self) -> Option<&[Address]> {
+        match &self.kind {
+            ValidationKind::InvalidAddresses(addresses) => Some(addresses),
+            _ => None,
+        }
+    }
+
+    /// Returns true if `List-Unsubscribe` was set without any URI
+    pub fn is_empty_list_unsubscribe(&self) -> bool {
+        matches!(self.kind, ValidationKind::EmptyListUnsubscribe)
+    }
+
+    /// Returns true if the boundary of a multipart appears in the content of its parts
+    pub fn is_boundary_in_content(&self) -> bool {
+        matches!(self.kind, ValidationKind::BoundaryInContent)
+    }
+
+    /// Returns true if the envelope sender isn't aligned with the `From` header
+    ///
+    /// See [`DmarcAlignmentPolicy`](crate::message::DmarcAlignmentPolicy).
+    pub fn is_dmarc_misaligned(&self) -> bool {
+        matches!(self.kind, ValidationKind::DmarcMisaligned)
+    }
+
+    /// Returns true if the maximum size given to
+    /// [`MessageBuilder::split_attachment`](crate::message::MessageBuilder::split_attachment)
+    /// can't fit the headers and any content
+    pub fn is_message_size_too_small(&self) -> bool {
+        matches!(self.kind, ValidationKind::MessageSizeTooSmall)
+    }
+
+    /// Returns true if the envelope identifier is empty, too long or not printable ASCII
+    pub fn is_invalid_envelope_id(&self) -> bool {
+        matches!(self.kind, ValidationKind::InvalidEnvelopeId)
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match &self.kind {
+            ValidationKind::EmptyListUnsubscribe => {
+                f.write_str("List-Unsubscribe requires a mailto or an HTTPS URI")
+            }
+            ValidationKind::BoundaryInContent => {
+                f.write_str("multipart boundary appears in the content of a part")
+            }
+            ValidationKind::DmarcMisaligned => {
+                f.write_str("the envelope sender isn't aligned with the From header")
+            }
+            ValidationKind::MessageSizeTooSmall => {
+                f.write_str("the maximum message size can't fit the headers and any content")
+            }
+            ValidationKind::InvalidEnvelopeId => f.write_str(
+                "the envelope identifier must be 1 to 100 printable ASCII characters long",
+            ),
+            ValidationKind::InvalidAddresses(addresses) => {
+                f.write_str("invalid email addresses:")?;
+                for (i, address) in addresses.iter().enumerate() {
+                    let sep = if i == 0 { " " } else { ", " };
+                    write!(f, "{sep}{address}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl StdError for ValidationError {}
+
+fn validation(kind: ValidationKind) -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        ValidationError { kind },
+    ))
+}
+
+#[cfg(feature = "builder")]
+pub(crate) fn invalid_addresses(addresses: Vec<Address>) -> Error {
+    validation(ValidationKind::InvalidAddresses(addresses))
+}
+
+#[cfg(feature = "builder")]
+pub(crate) fn empty_list_unsubscribe() -> Error {
+    validation(ValidationKind::EmptyListUnsubscribe)
+}
+
+#[cfg(feature = "builder")]
+pub(crate) fn boundary_in_content() -> Error {
+    validation(ValidationKind::BoundaryInContent)
+}
+
+#[cfg(feature = "builder")]
+pub(crate) fn dmarc_misaligned() -> Error {
+    validation(ValidationKind::DmarcMisaligned)
+}
+
+pub(crate) fn invalid_envelope_id() -> Error {
+    validation(ValidationKind::InvalidEnvelopeId)
+}
+
+#[cfg(feature = "builder")]
+pub(crate) fn message_size_too_small() -> Error {
+    validation(ValidationKind::MessageSizeTooSmall)
+}
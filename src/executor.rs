@@ -76,7 +76,22 @@ pub trait Executor: Debug + Send + Sync + 'static + private::Sealed {
 
     #[doc(hidden)]
     #[cfg(feature = "file-transport")]
-    async fn fs_write(path: &Path, contents: &[u8]) -> IoResult<()>;
+    async fn fs_write_new(path: &Path, contents: &[u8]) -> IoResult<()>;
+
+    #[doc(hidden)]
+    #[cfg(feature = "file-transport")]
+    async fn fs_rename(from: &Path, to: &Path) -> IoResult<()>;
+
+    #[doc(hidden)]
+    #[cfg(feature = "file-transport")]
+    async fn fs_remove_file(path: &Path) -> IoResult<()>;
+
+    #[doc(hidden)]
+    #[cfg(any(feature = "file-transport", feature = "smtp-transport"))]
+    async fn spawn_blocking<F, T>(f: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static;
 }
 
 #[doc(hidden)]
@@ -169,8 +184,38 @@ impl Executor for Tokio1Executor {
     }
 
     #[cfg(feature = "file-transport")]
-    async fn fs_write(path: &Path, contents: &[u8]) -> IoResult<()> {
-        tokio1_crate::fs::write(path, contents).await
+    async fn fs_write_new(path: &Path, contents: &[u8]) -> IoResult<()> {
+        use tokio1_crate::io::AsyncWriteExt;
+
+        let mut file = tokio1_crate::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .await?;
+        file.write_all(contents).await?;
+        file.sync_all().await
+    }
+
+    #[cfg(feature = "file-transport")]
+    async fn fs_rename(from: &Path, to: &Path) -> IoResult<()> {
+        tokio1_crate::fs::rename(from, to).await
+    }
+
+    #[cfg(feature = "file-transport")]
+    async fn fs_remove_file(path: &Path) -> IoResult<()> {
+        tokio1_crate::fs::remove_file(path).await
+    }
+
+    #[cfg(any(feature = "file-transport", feature = "smtp-transport"))]
+    async fn spawn_blocking<F, T>(f: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        match tokio1_crate::task::spawn_blocking(f).await {
+            Ok(t) => t,
+            Err(err) => std::panic::resume_unwind(err.into_panic()),
+        }
     }
 }
 
@@ -265,8 +310,35 @@ impl Executor for AsyncStd1Executor {
     }
 
     #[cfg(feature = "file-transport")]
-    async fn fs_write(path: &Path, contents: &[u8]) -> IoResult<()> {
-        async_std::fs::write(path, contents).await
+    async fn fs_write_new(path: &Path, contents: &[u8]) -> IoResult<()> {
+        use futures_util::AsyncWriteExt;
+
+        let mut file = async_std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .await?;
+        file.write_all(contents).await?;
+        file.sync_all().await
+    }
+
+    #[cfg(feature = "file-transport")]
+    async fn fs_rename(from: &Path, to: &Path) -> IoResult<()> {
+        async_std::fs::rename(from, to).await
+    }
+
+    #[cfg(feature = "file-transport")]
+    async fn fs_remove_file(path: &Path) -> IoResult<()> {
+        async_std::fs::remove_file(path).await
+    }
+
+    #[cfg(any(feature = "file-transport", feature = "smtp-transport"))]
+    async fn spawn_blocking<F, T>(f: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        async_std::task::spawn_blocking(f).await
     }
 }
 
@@ -29,6 +29,7 @@
 //! * **smtp-transport** 📫: Enable the SMTP transport
 //! * **pool** 📫: Connection pool for SMTP transport
 //! * **hostname** 📫: Try to use the actual system hostname for the SMTP `CLIENTID`
+//! * **compression**: DEFLATE compression of the SMTP connections, for servers supporting `COMPRESS`
 //!
 //! #### SMTP over TLS via the native-tls crate
 //!
@@ -205,8 +206,10 @@ Make sure to apply the same to any of your crate dependencies that use the `lett
 }
 
 pub mod address;
-#[cfg(any(feature = "smtp-transport", feature = "dkim"))]
+#[cfg(any(feature = "builder", feature = "smtp-transport", feature = "dkim"))]
 mod base64;
+#[cfg(any(feature = "builder", feature = "smtp-transport"))]
+mod enhanced_code;
 pub mod error;
 #[cfg(any(feature = "tokio1", feature = "async-std1"))]
 mod executor;
@@ -318,4 +321,102 @@ mod test {
 
         assert!(Envelope::try_from(&headers).is_err(),);
     }
+
+    #[test]
+    fn envelope_from_headers_groups() {
+        use crate::message::header::{HeaderName, HeaderValue};
+
+        let from = Mailboxes::new().with("kayo@example.com".parse().unwrap());
+        let mut headers = Headers::new();
+        headers.set(header::From(from));
+        headers.insert_raw(HeaderValue::new(
+            HeaderName::new_from_ascii_str("To"),
+            "Team: Alice <alice@example.com>, bob@example.com;, amousset@example.com".to_owned(),
+        ));
+        headers.insert_raw(HeaderValue::new(
+            HeaderName::new_from_ascii_str("Cc"),
+            "undisclosed-recipients:;".to_owned(),
+        ));
+
+        assert_eq!(
+            Envelope::try_from(&headers).unwrap(),
+            Envelope::new(
+                Some(Address::new("kayo", "example.com").unwrap()),
+                vec![
+                    Address::new("alice", "example.com").unwrap(),
+                    Address::new("bob", "example.com").unwrap(),
+                    Address::new("amousset", "example.com").unwrap(),
+                ]
+            )
+            .unwrap()
+        );
+    }
+
+    /// Formatting values ends up in logs on error paths, so it must not
+    /// panic even when they are empty
+    #[test]
+    fn format_empty_values() {
+        use crate::message::{header::ContentType, DsnReportBuilder, Group};
+
+        fn format<T: std::fmt::Display + std::fmt::Debug>(value: T) {
+            let _ = format!("{value} {value:?} {value:#?}");
+        }
+
+        format(Mailboxes::new());
+        format(Group::new(String::new(), Mailboxes::new()));
+        format(Headers::new());
+        let _ = format!("{:?}", ContentType::TEXT_PLAIN);
+        format(header::Priority::default());
+        format(Mailbox::new(
+            Some(String::new()),
+            "a@example.com".parse().unwrap(),
+        ));
+        let _ = format!(
+            "{:?}",
+            Envelope::new(None, vec!["a@example.com".parse().unwrap()])
+        );
+        let _ = format!("{:?}", DsnReportBuilder::new(String::new()));
+        format(
+            DsnReportBuilder::new(String::new())
+                .build(String::new())
+                .unwrap_err(),
+        );
+        format(Error::MissingFrom);
+
+        #[cfg(feature = "smtp-transport")]
+        {
+            use crate::transport::smtp::{
+                authentication::{Credentials, Mechanism},
+                client::NegotiatedFeatures,
+                commands::Auth,
+                extension::{ClientId, ServerInfo},
+                response::{Category, Code, Detail, Response, Severity},
+                PoolConfig,
+            };
+
+            let code = Code::new(
+                Severity::PositiveCompletion,
+                Category::MailSystem,
+                Detail::Zero,
+            );
+            format(Response::new(code, Vec::new()));
+            format(Response::new(code, vec![String::new()]));
+            format(Response::new(code, vec![String::new(), String::new()]));
+            let _ = Response::new(code, Vec::new()).log_fields();
+            format(ServerInfo::default());
+            format(ClientId::Domain(String::new()));
+            let _ = format!("{:?}", NegotiatedFeatures::default());
+            let _ = format!("{:?}", PoolConfig::default());
+            let _ = format!("{:?}", Credentials::new(String::new(), String::new()));
+            for mechanism in [Mechanism::Plain, Mechanism::Login, Mechanism::Xoauth2] {
+                let auth = Auth::new(
+                    mechanism,
+                    Credentials::new(String::new(), String::new()),
+                    None,
+                );
+                format(auth.unwrap());
+            }
+            format("".parse::<Response>().unwrap_err());
+        }
+    }
 }
@@ -1,8 +1,18 @@
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
 use crate::message::{
-    header::{self, ContentType},
+    header::{self, ContentTransferEncoding, ContentType, Headers},
     IntoBody, SinglePart,
 };
 
+/// Number of bytes read from a [`LazyAttachment`] file at once,
+/// a multiple of the 57 bytes encoded on each base64 line
+const LAZY_CHUNK_LEN: usize = 57 * 128;
+
 /// `SinglePart` builder for attachments
 ///
 /// Allows building attachment parts easily.
@@ -11,6 +21,31 @@ pub struct Attachment {
     disposition: Disposition,
 }
 
+/// Subtype of a text attachment, see [`Attachment::text`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TextSubtype {
+    /// `text/plain`, like a log file
+    Plain,
+    /// `text/csv`
+    Csv,
+    /// `text/html`
+    Html,
+    /// `text/calendar`, like an invitation
+    Calendar,
+}
+
+impl TextSubtype {
+    fn content_type(self) -> ContentType {
+        match self {
+            TextSubtype::Plain => ContentType::TEXT_PLAIN,
+            TextSubtype::Html => ContentType::TEXT_HTML,
+            TextSubtype::Csv => ContentType::parse("text/csv; charset=utf-8").unwrap(),
+            TextSubtype::Calendar => ContentType::parse("text/calendar; charset=utf-8").unwrap(),
+        }
+    }
+}
+
 #[derive(Clone)]
 enum Disposition {
     /// File name
@@ -102,11 +137,169 @@ impl Attachment {
         builder = builder.header(content_type);
         builder.body(content)
     }
+
+    /// Build a text attachment into a [`SinglePart`], with a `charset=utf-8`
+    /// content type of the given `subtype`
+    ///
+    /// The content is sent as is with the `7bit` encoding when it's ASCII with
+    /// short enough lines, otherwise with the most compact of `quoted-printable`
+    /// and `base64`. Unlike binary content, an ASCII file doesn't grow.
+    ///
+    /// ```rust
+    /// use lettre::message::{Attachment, TextSubtype};
+    ///
+    /// let csv = String::from("name,count\r\napples,3\r\n");
+    /// let attachment = Attachment::new(String::from("fruits.csv")).text(csv, TextSubtype::Csv);
+    /// ```
+    pub fn text(self, content: String, subtype: TextSubtype) -> SinglePart {
+        self.body(content, subtype.content_type())
+    }
+
+    /// Build the attachment into a [`LazyAttachment`], which reads the content of
+    /// the file at `path` only when the email is sent
+    ///
+    /// ```rust
+    /// use lettre::message::{header::ContentType, Attachment, MultiPart, SinglePart};
+    ///
+    /// let content_type = ContentType::parse("application/pdf").unwrap();
+    /// let attachment = Attachment::new(String::from("report.pdf")).lazy("report.pdf", content_type);
+    ///
+    /// let body = MultiPart::mixed()
+    ///     .singlepart(SinglePart::plain(String::from("The report is attached")))
+    ///     .lazy_attachment(attachment);
+    /// ```
+    pub fn lazy<P: AsRef<Path>>(self, path: P, content_type: ContentType) -> LazyAttachment {
+        let mut headers = self.body(Vec::new(), content_type).headers().clone();
+        headers.set(ContentTransferEncoding::Base64);
+
+        LazyAttachment {
+            headers,
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+/// Attachment whose content is read from a file when the email is sent
+///
+/// The content is always encoded as `base64`. [`SmtpTransport`] reads and
+/// encodes the file in small chunks while transmitting the email, so that it's
+/// never held in memory in full. Other transports, and [`Message::formatted`],
+/// read the whole file.
+///
+/// Building a message fails with [`Error::Io`] if the file can't be opened.
+/// If it can't be read anymore when the email is sent, the transports of
+/// this crate return an error.
+///
+/// Created with [`Attachment::lazy`] and added to an email with
+/// [`MultiPart::lazy_attachment`].
+///
+/// [`Error::Io`]: crate::Error::Io
+/// [`SmtpTransport`]: crate::SmtpTransport
+/// [`Message::formatted`]: crate::Message::formatted
+/// [`MultiPart::lazy_attachment`]: crate::message::MultiPart::lazy_attachment
+#[derive(Debug, Clone)]
+pub struct LazyAttachment {
+    headers: Headers,
+    path: PathBuf,
+}
+
+impl LazyAttachment {
+    /// Get the headers of the attachment
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Get the path of the attached file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    #[cfg(feature = "smtp-transport")]
+    pub(super) fn is_ascii(&self) -> bool {
+        self.headers.to_string().is_ascii()
+    }
+
+    /// Check that the attached file can be opened
+    pub(super) fn open(&self) -> io::Result<()> {
+        File::open(&self.path).map(drop)
+    }
+
+    /// Length of the formatted part, from the size of the file
+    #[cfg(feature = "smtp-transport")]
+    pub(super) fn formatted_len(&self) -> io::Result<u64> {
+        let size = std::fs::metadata(&self.path)?.len();
+        let encoded = email_encoding::body::base64::encoded_len(size as usize);
+        Ok((self.headers.to_string().len() + 2 + encoded + 2) as u64)
+    }
+
+    /// Get a reader of the formatted part, reading and encoding the file in chunks
+    pub(super) fn reader(&self) -> io::Result<LazyReader> {
+        Ok(LazyReader {
+            file: Some(File::open(&self.path)?),
+            chunk: Vec::with_capacity(LAZY_CHUNK_LEN),
+            encoded: format!("{}\r\n", self.headers),
+            pos: 0,
+            first: true,
+        })
+    }
+
+    /// Write the part to `out`, reading and encoding the file in chunks
+    pub(super) fn write_to<W: Write + ?Sized>(&self, out: &mut W) -> io::Result<()> {
+        io::copy(&mut self.reader()?, out).map(drop)
+    }
+}
+
+/// Reader of a formatted [`LazyAttachment`]
+#[derive(Debug)]
+pub(super) struct LazyReader {
+    /// The attached file, until all of it was read
+    file: Option<File>,
+    chunk: Vec<u8>,
+    encoded: String,
+    /// Position of the next byte to read in `encoded`
+    pos: usize,
+    first: bool,
+}
+
+impl Read for LazyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.encoded.len() {
+            let Some(file) = &mut self.file else {
+                return Ok(0);
+            };
+
+            self.chunk.clear();
+            file.take(LAZY_CHUNK_LEN as u64)
+                .read_to_end(&mut self.chunk)?;
+
+            self.encoded.clear();
+            self.pos = 0;
+            if self.chunk.is_empty() {
+                self.encoded.push_str("\r\n");
+                self.file = None;
+            } else {
+                if !self.first {
+                    self.encoded.push_str("\r\n");
+                }
+                email_encoding::body::base64::encode(&self.chunk, &mut self.encoded)
+                    .expect("encode body as base64");
+                self.first = false;
+            }
+        }
+
+        let len = (&self.encoded.as_bytes()[self.pos..]).read(buf)?;
+        self.pos += len;
+        Ok(len)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::message::header::ContentType;
+    use super::TextSubtype;
+    use crate::message::{
+        header::{ContentTransferEncoding, ContentType},
+        Body,
+    };
 
     #[test]
     fn attachment() {
@@ -125,6 +318,103 @@ mod tests {
         );
     }
 
+    #[test]
+    fn attachment_text() {
+        let csv = (0..500)
+            .map(|i| format!("{i},item-{i},{},in stock\r\n", i * 7))
+            .collect::<String>();
+        let part =
+            super::Attachment::new(String::from("stock.csv")).text(csv.clone(), TextSubtype::Csv);
+        let formatted = String::from_utf8(part.formatted()).unwrap();
+        assert!(formatted.starts_with(concat!(
+            "Content-Disposition: attachment; filename=\"stock.csv\"\r\n",
+            "Content-Type: text/csv; charset=utf-8\r\n",
+            "Content-Transfer-Encoding: 7bit\r\n\r\n",
+        )));
+        assert!(part.raw_body().len() <= csv.len() * 102 / 100);
+
+        // Non-ASCII text isn't base64 encoded when quoted-printable is more compact
+        let log = "2024-01-01 12:00:00 INFO connexion établie\r\n".repeat(100);
+        let part =
+            super::Attachment::new(String::from("app.log")).text(log.clone(), TextSubtype::Plain);
+        assert_eq!(
+            part.headers().get::<ContentTransferEncoding>(),
+            Some(ContentTransferEncoding::QuotedPrintable)
+        );
+        assert_eq!(
+            part.headers().get_raw("Content-Type"),
+            Some("text/plain; charset=utf-8")
+        );
+        assert!(part.raw_body().len() <= log.len() * 110 / 100);
+
+        let part = super::Attachment::new(String::from("invite.ics")).text(
+            String::from("BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n"),
+            TextSubtype::Calendar,
+        );
+        assert_eq!(
+            part.headers().get_raw("Content-Type"),
+            Some("text/calendar; charset=utf-8")
+        );
+        let part = super::Attachment::new(String::from("page.html"))
+            .text(String::from("<p>Hi</p>"), TextSubtype::Html);
+        assert_eq!(
+            part.headers().get_raw("Content-Type"),
+            Some("text/html; charset=utf-8")
+        );
+    }
+
+    #[test]
+    fn attachment_lazy() {
+        let path = std::env::temp_dir().join("lettre-attachment-lazy.bin");
+        let content = (0..10_000u32).map(|i| i as u8).collect::<Vec<_>>();
+        std::fs::write(&path, &content).unwrap();
+
+        let content_type = ContentType::parse("application/octet-stream").unwrap();
+        let eager = super::Attachment::new(String::from("test.bin")).body(
+            Body::new_with_encoding(content, ContentTransferEncoding::Base64).unwrap(),
+            content_type.clone(),
+        );
+        let lazy = super::Attachment::new(String::from("test.bin")).lazy(&path, content_type);
+
+        let mut out = Vec::new();
+        lazy.write_to(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(eager.formatted()).unwrap()
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(lazy.write_to(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn attachment_lazy_unreadable() {
+        use crate::{transport::stub::StubTransport, Message, Transport};
+
+        let path = std::env::temp_dir().join("lettre-attachment-lazy-unreadable.bin");
+        let message = |path: &std::path::Path| {
+            let content_type = ContentType::parse("application/octet-stream").unwrap();
+            Message::builder()
+                .from("NoBody <nobody@domain.tld>".parse().unwrap())
+                .to("Hei <hei@domain.tld>".parse().unwrap())
+                .multipart(crate::message::MultiPart::mixed().lazy_attachment(
+                    super::Attachment::new(String::from("test.bin")).lazy(path, content_type),
+                ))
+        };
+
+        let _ = std::fs::remove_file(&path);
+        assert!(matches!(message(&path), Err(crate::Error::Io(_))));
+
+        std::fs::write(&path, b"content").unwrap();
+        let email = message(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // The file disappeared after the message was built
+        let sender = StubTransport::new_ok();
+        assert!(sender.send(&email).is_err());
+        assert!(sender.messages().is_empty());
+    }
+
     #[test]
     fn attachment_inline() {
         let part = super::Attachment::new_inline(String::from("id")).body(
@@ -112,6 +112,11 @@ enum InnerDkimSigningKey {
 }
 
 impl DkimSigningKey {
+    /// Parses a private key to sign messages with `algorithm`
+    ///
+    /// RSA keys are expected in the PKCS#1 PEM format, and Ed25519 keys
+    /// as the base64 encoding of their 32 bytes seed, as defined in
+    /// [RFC 8463](https://tools.ietf.org/html/rfc8463).
     pub fn new(
         private_key: &str,
         algorithm: DkimSigningAlgorithm,
@@ -422,7 +427,7 @@ mod test {
     use super::{
         super::{
             header::{HeaderName, HeaderValue},
-            Header, Message,
+            Header, Headers, Message,
         },
         dkim_canonicalize_body, dkim_canonicalize_headers, dkim_sign_fixed_time,
         DkimCanonicalization, DkimCanonicalizationType, DkimConfig, DkimSigningAlgorithm,
@@ -614,4 +619,75 @@ cJ5Ku0OTwRtSMaseRPX+T4EfG1Caa/eunPPN4rh+CSup2BVVarOT
             )
         );
     }
+
+    #[test]
+    fn test_signature_ed25519() {
+        use ed25519_dalek::{Signature, SigningKey, Verifier};
+        use sha2::{Digest, Sha256};
+
+        let seed = [42; 32];
+        let signing_key =
+            DkimSigningKey::new(&crate::base64::encode(seed), DkimSigningAlgorithm::Ed25519)
+                .unwrap();
+        let headers = ["Date", "From", "Subject", "To"];
+        let mut message = test_message();
+        dkim_sign_fixed_time(
+            &mut message,
+            &DkimConfig::new(
+                "dkimtest".to_owned(),
+                "example.org".to_owned(),
+                signing_key,
+                headers
+                    .iter()
+                    .map(|name| HeaderName::new_from_ascii_str(name))
+                    .collect(),
+                DkimCanonicalization {
+                    header: DkimCanonicalizationType::Relaxed,
+                    body: DkimCanonicalizationType::Relaxed,
+                },
+            ),
+            std::time::UNIX_EPOCH,
+        );
+
+        let dkim_header = message.headers().get_raw("DKIM-Signature").unwrap();
+        assert!(dkim_header.starts_with("v=1; a=ed25519-sha256; d=example.org; s=dkimtest;"));
+        let (unsigned, signature) = dkim_header.rsplit_once("; b=").unwrap();
+
+        let body_hash = Sha256::digest(dkim_canonicalize_body(
+            &message.body_raw(),
+            DkimCanonicalizationType::Relaxed,
+        ));
+        assert!(unsigned.ends_with(&format!("; bh={}", crate::base64::encode(body_hash))));
+
+        let mut unsigned_header = Headers::new();
+        unsigned_header.insert_raw(HeaderValue::new(
+            HeaderName::new_from_ascii_str("DKIM-Signature"),
+            format!("{unsigned}; b="),
+        ));
+        let mut hashed_headers = Sha256::new();
+        hashed_headers.update(
+            dkim_canonicalize_headers(
+                headers,
+                message.headers(),
+                DkimCanonicalizationType::Relaxed,
+            )
+            .as_bytes(),
+        );
+        hashed_headers.update(
+            dkim_canonicalize_headers(
+                ["DKIM-Signature"],
+                &unsigned_header,
+                DkimCanonicalizationType::Relaxed,
+            )
+            .trim_end()
+            .as_bytes(),
+        );
+
+        let signature = crate::base64::decode(signature).unwrap();
+        let signature = Signature::from_slice(&signature).unwrap();
+        SigningKey::from_bytes(&seed)
+            .verifying_key()
+            .verify(&hashed_headers.finalize(), &signature)
+            .unwrap();
+    }
 }
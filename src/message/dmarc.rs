@@ -0,0 +1,134 @@
+use std::fmt::{self, Display};
+
+/// How the domain of the envelope sender aligns with the domain of the `From` header
+///
+/// DMARC only accepts an SPF pass if the domain checked by SPF, the one of
+/// the envelope sender, is aligned with the domain of the `From` header.
+/// Defined in [RFC7489](https://tools.ietf.org/html/rfc7489#section-3.1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmarcAlignment {
+    /// The domains are the same, which passes both the strict and the relaxed modes
+    Strict,
+    /// The domains have the same organizational domain, which only passes the relaxed mode
+    Relaxed,
+    /// The domains are unrelated, or one of them is missing
+    None,
+}
+
+impl Display for DmarcAlignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Strict => "strict",
+            Self::Relaxed => "relaxed",
+            Self::None => "none",
+        })
+    }
+}
+
+/// What to do when building a message whose envelope sender isn't
+/// aligned with its `From` header
+///
+/// See [`MessageBuilder::dmarc_alignment_policy`](super::MessageBuilder::dmarc_alignment_policy).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DmarcAlignmentPolicy {
+    /// Don't check the alignment
+    #[default]
+    Ignore,
+    /// Log a warning, with the `tracing` feature
+    Warn,
+    /// Fail with an error for which
+    /// [`ValidationError::is_dmarc_misaligned`](crate::error::ValidationError::is_dmarc_misaligned)
+    /// returns `true`
+    Reject,
+}
+
+/// Returns the organizational domain of `domain`, guessed as its last two labels
+///
+/// This doesn't use the public suffix list, so it is wrong for domains
+/// under multi-label suffixes like `co.uk`: `a.example.co.uk` and
+/// `b.other.co.uk` would both have `co.uk` as organizational domain.
+/// Use [`Message::dmarc_alignment_with`](super::Message::dmarc_alignment_with)
+/// with a better rule when sending from such domains.
+pub fn organizational_domain(domain: &str) -> &str {
+    let domain = domain.trim_end_matches('.');
+    match domain.rmatch_indices('.').nth(1) {
+        Some((dot, _)) => &domain[dot + 1..],
+        None => domain,
+    }
+}
+
+/// Compares the domain of the `From` header with the one of the envelope sender
+pub(super) fn alignment(
+    from_domain: &str,
+    envelope_domain: &str,
+    organizational_domain: &dyn Fn(&str) -> &str,
+) -> DmarcAlignment {
+    let from_domain = from_domain.trim_end_matches('.');
+    let envelope_domain = envelope_domain.trim_end_matches('.');
+    if from_domain.eq_ignore_ascii_case(envelope_domain) {
+        DmarcAlignment::Strict
+    } else if organizational_domain(from_domain)
+        .eq_ignore_ascii_case(organizational_domain(envelope_domain))
+    {
+        DmarcAlignment::Relaxed
+    } else {
+        DmarcAlignment::None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::{alignment, organizational_domain, DmarcAlignment};
+
+    #[test]
+    fn dmarc_organizational_domain() {
+        assert_eq!(organizational_domain("brand.com"), "brand.com");
+        assert_eq!(organizational_domain("mail.eu.brand.com"), "brand.com");
+        assert_eq!(organizational_domain("mail.brand.com."), "brand.com");
+        assert_eq!(organizational_domain("localhost"), "localhost");
+    }
+
+    #[test]
+    fn dmarc_alignment_rules() {
+        let org = &organizational_domain;
+        assert_eq!(
+            alignment("brand.com", "Brand.COM", org),
+            DmarcAlignment::Strict
+        );
+        assert_eq!(
+            alignment("brand.com", "bounces.brand.com", org),
+            DmarcAlignment::Relaxed
+        );
+        assert_eq!(alignment("brand.com", "esp.net", org), DmarcAlignment::None);
+        assert_eq!(
+            alignment("brand.com", "brand.com.esp.net", org),
+            DmarcAlignment::None
+        );
+
+        // A custom rule for a multi-label public suffix
+        fn with_suffix(suffix: String) -> impl Fn(&str) -> &str {
+            move |domain| {
+                let labels = if domain.ends_with(&suffix) { 3 } else { 2 };
+                match domain.rmatch_indices('.').nth(labels - 1) {
+                    Some((dot, _)) => &domain[dot + 1..],
+                    None => domain,
+                }
+            }
+        }
+        let co_uk = with_suffix(String::from(".co.uk"));
+        assert_eq!(
+            alignment("brand.co.uk", "esp.co.uk", org),
+            DmarcAlignment::Relaxed
+        );
+        assert_eq!(
+            alignment("brand.co.uk", "esp.co.uk", &co_uk),
+            DmarcAlignment::None
+        );
+        assert_eq!(
+            alignment("brand.co.uk", "bounces.brand.co.uk", &co_uk),
+            DmarcAlignment::Relaxed
+        );
+    }
+}
@@ -0,0 +1,470 @@
+use std::{
+    error::Error as StdError,
+    fmt::{self, Display, Write},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use super::{
+    header::{self, ContentTransferEncoding, ContentType, Header, Headers},
+    Body, IntoBody, MultiPart, SinglePart,
+};
+#[cfg(feature = "smtp-transport")]
+use crate::transport::smtp::response::{EnhancedCode, Severity};
+use crate::Address;
+
+/// The action taken for a recipient of a delivery status notification
+///
+/// Defined in [RFC3464](https://tools.ietf.org/html/rfc3464#section-2.3.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DsnAction {
+    /// The message could not be delivered
+    Failed,
+    /// The delivery is delayed, and will be attempted again
+    Delayed,
+    /// The message was delivered
+    Delivered,
+    /// The message was relayed to a system which doesn't send notifications
+    Relayed,
+    /// The message was delivered to the recipient and forwarded to other addresses
+    Expanded,
+}
+
+impl Display for DsnAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DsnAction::Failed => "failed",
+            DsnAction::Delayed => "delayed",
+            DsnAction::Delivered => "delivered",
+            DsnAction::Relayed => "relayed",
+            DsnAction::Expanded => "expanded",
+        })
+    }
+}
+
+/// An enhanced status code, like `5.1.1`
+///
+/// Made of a class, `2` for success, `4` for a transient failure or `5` for a
+/// permanent failure, followed by a subject and a detail of one to three digits.
+///
+/// Defined in [RFC3463](https://tools.ietf.org/html/rfc3463)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DsnStatus {
+    class: u8,
+    subject: u16,
+    detail: u16,
+}
+
+impl DsnStatus {
+    /// Get the class of the status
+    pub fn class(&self) -> u8 {
+        self.class
+    }
+
+    /// Get the subject of the status
+    pub fn subject(&self) -> u16 {
+        self.subject
+    }
+
+    /// Get the detail of the status
+    pub fn detail(&self) -> u16 {
+        self.detail
+    }
+}
+
+impl FromStr for DsnStatus {
+    type Err = DsnErr;
+
+    /// Parse a status like `5.1.1`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lettre::message::DsnStatus;
+    ///
+    /// assert!("5.1.1".parse::<DsnStatus>().is_ok());
+    /// assert!("3.1.1".parse::<DsnStatus>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (class, subject, detail) =
+            crate::enhanced_code::parse(s).ok_or(DsnErr::InvalidStatus)?;
+        Ok(Self {
+            class,
+            subject,
+            detail,
+        })
+    }
+}
+
+/// Converts the enhanced status code of an SMTP reply
+///
+/// Fails with [`DsnErr::InvalidStatus`] for the `3` class, which
+/// isn't a valid enhanced status code.
+#[cfg(feature = "smtp-transport")]
+impl TryFrom<EnhancedCode> for DsnStatus {
+    type Error = DsnErr;
+
+    fn try_from(code: EnhancedCode) -> Result<Self, Self::Error> {
+        let class = match code.class {
+            Severity::PositiveCompletion => 2,
+            Severity::TransientNegativeCompletion => 4,
+            Severity::PermanentNegativeCompletion => 5,
+            Severity::PositiveIntermediate => return Err(DsnErr::InvalidStatus),
+        };
+        Ok(Self {
+            class,
+            subject: code.subject,
+            detail: code.detail,
+        })
+    }
+}
+
+impl Display for DsnStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.class, self.subject, self.detail)
+    }
+}
+
+/// The delivery status of a recipient, in a [`DsnReportBuilder`]
+#[derive(Debug, Clone)]
+pub struct DsnRecipient {
+    final_recipient: Address,
+    action: DsnAction,
+    status: DsnStatus,
+    remote_mta: Option<String>,
+    diagnostic_code: Option<String>,
+}
+
+impl DsnRecipient {
+    /// Creates the status of the delivery to `final_recipient`
+    pub fn new(final_recipient: Address, action: DsnAction, status: DsnStatus) -> Self {
+        Self {
+            final_recipient,
+            action,
+            status,
+            remote_mta: None,
+            diagnostic_code: None,
+        }
+    }
+
+    /// Set the name of the server which attempted the delivery
+    pub fn remote_mta(mut self, remote_mta: String) -> Self {
+        self.remote_mta = Some(remote_mta);
+        self
+    }
+
+    /// Set the SMTP reply of the server which attempted the delivery,
+    /// like `550 5.1.1 User unknown`
+    pub fn diagnostic_code(mut self, reply: String) -> Self {
+        self.diagnostic_code = Some(reply);
+        self
+    }
+}
+
+/// A builder for delivery status notifications, like bounces
+///
+/// Builds a `multipart/report` made of a human readable explanation,
+/// the `message/delivery-status` fields and, if set, the headers of
+/// the original message.
+///
+/// Defined in [RFC3464](https://tools.ietf.org/html/rfc3464)
+///
+/// # Examples
+///
+/// ```
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// use lettre::message::{DsnAction, DsnRecipient, DsnReportBuilder, Message};
+///
+/// let report = DsnReportBuilder::new("mx.domain.tld".to_owned())
+///     .recipient(
+///         DsnRecipient::new("hei@domain.tld".parse()?, DsnAction::Failed, "5.1.1".parse()?)
+///             .diagnostic_code("550 5.1.1 User unknown".to_owned()),
+///     )
+///     .build(String::from("Your message could not be delivered."))?;
+///
+/// let m = Message::builder()
+///     .from("Mail Delivery System <mailer-daemon@domain.tld>".parse()?)
+///     .to("NoBody <nobody@domain.tld>".parse()?)
+///     .subject("Undelivered Mail Returned to Sender")
+///     .multipart(report)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DsnReportBuilder {
+    reporting_mta: String,
+    arrival_date: Option<SystemTime>,
+    recipients: Vec<DsnRecipient>,
+    original_headers: Option<String>,
+}
+
+impl DsnReportBuilder {
+    /// Creates a report sent by the server named `reporting_mta`
+    pub fn new(reporting_mta: String) -> Self {
+        Self {
+            reporting_mta,
+            arrival_date: None,
+            recipients: Vec::new(),
+            original_headers: None,
+        }
+    }
+
+    /// Set the date the original message was received
+    pub fn arrival_date(mut self, date: SystemTime) -> Self {
+        self.arrival_date = Some(date);
+        self
+    }
+
+    /// Add the delivery status of a recipient
+    pub fn recipient(mut self, recipient: DsnRecipient) -> Self {
+        self.recipients.push(recipient);
+        self
+    }
+
+    /// Set the headers of the original message, included in the report
+    pub fn original_headers(mut self, headers: &Headers) -> Self {
+        self.original_headers = Some(headers.to_string());
+        self
+    }
+
+    /// Build the report, with `text` explaining it to humans
+    ///
+    /// Fails without recipients, or if a field contains a line break or
+    /// a non-ASCII character.
+    pub fn build<T: IntoBody>(self, text: T) -> Result<MultiPart, DsnErr> {
+        if self.recipients.is_empty() {
+            return Err(DsnErr::NoRecipient);
+        }
+
+        let mut fields = String::new();
+        write_field(&mut fields, "Reporting-MTA", "dns", &self.reporting_mta)?;
+        if let Some(date) = self.arrival_date {
+            let date = header::Date::new(date).display();
+            write_field(&mut fields, "Arrival-Date", "", date.get_raw())?;
+        }
+        for recipient in &self.recipients {
+            fields.push_str("\r\n");
+            write_field(
+                &mut fields,
+                "Final-Recipient",
+                "rfc822",
+                recipient.final_recipient.as_ref(),
+            )?;
+            write_field(&mut fields, "Action", "", &recipient.action.to_string())?;
+            write_field(&mut fields, "Status", "", &recipient.status.to_string())?;
+            if let Some(remote_mta) = &recipient.remote_mta {
+                write_field(&mut fields, "Remote-MTA", "dns", remote_mta)?;
+            }
+            if let Some(reply) = &recipient.diagnostic_code {
+                write_field(&mut fields, "Diagnostic-Code", "smtp", reply)?;
+            }
+        }
+        let fields = Body::new_with_encoding(fields, ContentTransferEncoding::SevenBit)
+            .map_err(|_| DsnErr::InvalidField)?;
+
+        let report = MultiPart::report("delivery-status".to_owned())
+            .singlepart(SinglePart::plain(text))
+            .singlepart(
+                SinglePart::builder()
+                    .header(content_type("message/delivery-status")?)
+                    .body(fields),
+            );
+        Ok(match self.original_headers {
+            Some(headers) => report.singlepart(
+                SinglePart::builder()
+                    .header(content_type("text/rfc822-headers")?)
+                    .body(headers),
+            ),
+            None => report,
+        })
+    }
+}
+
+fn content_type(s: &str) -> Result<ContentType, DsnErr> {
+    ContentType::parse(s).map_err(|_| DsnErr::InvalidField)
+}
+
+/// Writes a field, its value being prefixed by `type_` if not empty
+fn write_field(out: &mut String, name: &str, type_: &str, value: &str) -> Result<(), DsnErr> {
+    if value.contains(['\r', '\n']) {
+        return Err(DsnErr::InvalidField);
+    }
+
+    if type_.is_empty() {
+        write!(out, "{name}: {value}\r\n")
+    } else {
+        write!(out, "{name}: {type_}; {value}\r\n")
+    }
+    .expect("writing to a String doesn't fail");
+    Ok(())
+}
+
+/// An error occurred while building a delivery status notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DsnErr {
+    /// The status isn't of the form `x.y.z`
+    InvalidStatus,
+    /// The report doesn't have any recipient
+    NoRecipient,
+    /// A field contains a line break or a non-ASCII character
+    InvalidField,
+}
+
+impl StdError for DsnErr {}
+
+impl Display for DsnErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DsnErr::InvalidStatus => f.write_str("invalid delivery status code"),
+            DsnErr::NoRecipient => f.write_str("delivery status notification without recipient"),
+            DsnErr::InvalidField => f.write_str("invalid field in delivery status notification"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use pretty_assertions::assert_eq;
+
+    use super::{DsnAction, DsnErr, DsnRecipient, DsnReportBuilder, DsnStatus};
+    use crate::message::{header, header::Headers};
+
+    #[test]
+    fn parse_dsn_status() {
+        let status: DsnStatus = "5.1.1".parse().unwrap();
+        assert_eq!(
+            (status.class(), status.subject(), status.detail()),
+            (5, 1, 1)
+        );
+        assert_eq!(
+            "4.7.100".parse::<DsnStatus>().unwrap().to_string(),
+            "4.7.100"
+        );
+
+        for invalid in [
+            "", "5", "5.1", "3.1.1", "5.1.1.1", "5.1000.1", "5..1", "5.a.1",
+        ] {
+            assert_eq!(invalid.parse::<DsnStatus>(), Err(DsnErr::InvalidStatus));
+        }
+    }
+
+    #[cfg(feature = "smtp-transport")]
+    #[test]
+    fn dsn_status_from_enhanced_code() {
+        use crate::transport::smtp::response::{EnhancedCode, Response, Severity};
+
+        let response: Response = "550 5.1.1 User unknown\r\n".parse().unwrap();
+        let status = DsnStatus::try_from(response.enhanced_code().unwrap()).unwrap();
+        assert_eq!(status, "5.1.1".parse().unwrap());
+
+        let code = EnhancedCode::new(Severity::PositiveIntermediate, 0, 0);
+        assert_eq!(DsnStatus::try_from(code), Err(DsnErr::InvalidStatus));
+    }
+
+    #[test]
+    fn dsn_report() {
+        let mut original = Headers::new();
+        original.set(header::From("NoBody <nobody@domain.tld>".parse().unwrap()));
+        original.set(header::To("Hei <hei@domain.tld>".parse().unwrap()));
+        original.set(header::Subject::from("Happy new year".to_owned()));
+
+        let report = DsnReportBuilder::new("mx.domain.tld".to_owned())
+            .arrival_date(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+            .recipient(
+                DsnRecipient::new(
+                    "hei@domain.tld".parse().unwrap(),
+                    DsnAction::Failed,
+                    "5.1.1".parse().unwrap(),
+                )
+                .remote_mta("mx.hei.tld".to_owned())
+                .diagnostic_code("550 5.1.1 User unknown".to_owned()),
+            )
+            .recipient(DsnRecipient::new(
+                "yuin@domain.tld".parse().unwrap(),
+                DsnAction::Delayed,
+                "4.4.1".parse().unwrap(),
+            ))
+            .original_headers(&original)
+            .build(String::from("Your message could not be delivered."))
+            .unwrap();
+
+        let content_type = report.headers().get::<header::ContentType>().unwrap();
+        let mime = content_type.as_ref();
+        assert_eq!(mime.essence_str(), "multipart/report");
+        assert_eq!(mime.get_param("report-type").unwrap(), "delivery-status");
+
+        let body = report.formatted();
+        let body = String::from_utf8(body).unwrap();
+        let (_, body) = body.split_once("\r\n\r\n").unwrap();
+        assert_eq!(
+            body.replace(&report.boundary(), "BOUNDARY"),
+            concat!(
+                "--BOUNDARY\r\n",
+                "Content-Type: text/plain; charset=utf-8\r\n",
+                "Content-Transfer-Encoding: 7bit\r\n",
+                "\r\n",
+                "Your message could not be delivered.\r\n",
+                "--BOUNDARY\r\n",
+                "Content-Type: message/delivery-status\r\n",
+                "Content-Transfer-Encoding: 7bit\r\n",
+                "\r\n",
+                "Reporting-MTA: dns; mx.domain.tld\r\n",
+                "Arrival-Date: Tue, 14 Nov 2023 22:13:20 +0000\r\n",
+                "\r\n",
+                "Final-Recipient: rfc822; hei@domain.tld\r\n",
+                "Action: failed\r\n",
+                "Status: 5.1.1\r\n",
+                "Remote-MTA: dns; mx.hei.tld\r\n",
+                "Diagnostic-Code: smtp; 550 5.1.1 User unknown\r\n",
+                "\r\n",
+                "Final-Recipient: rfc822; yuin@domain.tld\r\n",
+                "Action: delayed\r\n",
+                "Status: 4.4.1\r\n",
+                "\r\n",
+                "--BOUNDARY\r\n",
+                "Content-Type: text/rfc822-headers\r\n",
+                "Content-Transfer-Encoding: 7bit\r\n",
+                "\r\n",
+                "From: NoBody <nobody@domain.tld>\r\n",
+                "To: Hei <hei@domain.tld>\r\n",
+                "Subject: Happy new year\r\n",
+                "\r\n",
+                "--BOUNDARY--\r\n",
+            )
+        );
+    }
+
+    #[test]
+    fn dsn_report_invalid() {
+        let recipient = DsnRecipient::new(
+            "hei@domain.tld".parse().unwrap(),
+            DsnAction::Failed,
+            "5.1.1".parse().unwrap(),
+        );
+
+        assert_eq!(
+            DsnReportBuilder::new("mx.domain.tld".to_owned())
+                .build(String::new())
+                .unwrap_err(),
+            DsnErr::NoRecipient
+        );
+        assert_eq!(
+            DsnReportBuilder::new("mx.domain.tld\r\nStatus: 2.0.0".to_owned())
+                .recipient(recipient.clone())
+                .build(String::new())
+                .unwrap_err(),
+            DsnErr::InvalidField
+        );
+        assert_eq!(
+            DsnReportBuilder::new("mx.domain.tld".to_owned())
+                .recipient(recipient.diagnostic_code("550 Utilisateur inconnu é".to_owned()))
+                .build(String::new())
+                .unwrap_err(),
+            DsnErr::InvalidField
+        );
+    }
+}
@@ -104,6 +104,27 @@ mod test {
         );
     }
 
+    #[test]
+    fn format_date_padding() {
+        let mut headers = Headers::new();
+
+        // Thu, 05 Mar 2026 07:04:09 GMT
+        headers.set(Date::from(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1772694249),
+        ));
+
+        assert_eq!(
+            headers.to_string(),
+            "Date: Thu, 05 Mar 2026 07:04:09 +0000\r\n"
+        );
+        assert_eq!(
+            headers.get::<Date>(),
+            Some(Date::from(
+                SystemTime::UNIX_EPOCH + Duration::from_secs(1772694249),
+            ))
+        );
+    }
+
     #[test]
     fn parse_date() {
         let mut headers = Headers::new();
@@ -0,0 +1,168 @@
+use std::{
+    error::Error as StdError,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use super::{Header, HeaderName, HeaderValue};
+use crate::BoxError;
+
+/// Maximum number of colon-separated fields in a `Feedback-ID`
+const MAX_FIELDS: usize = 4;
+/// Maximum length of each field of a `Feedback-ID`
+const MAX_FIELD_LEN: usize = 64;
+
+/// `Feedback-ID` header, used by bulk senders to track complaints
+///
+/// Made of up to four colon-separated fields, the last one identifying
+/// the sender, as in `CampaignId:CustomerId:MailType:SenderId`. Each field
+/// is at most 64 characters long and can only contain ASCII letters, digits,
+/// `-`, `_` and `.`.
+///
+/// Defined by [Gmail's Feedback Loop](https://support.google.com/mail/answer/6254652)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedbackId(String);
+
+impl FeedbackId {
+    /// Parse `s` into `FeedbackId`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lettre::message::header::FeedbackId;
+    ///
+    /// assert!(FeedbackId::parse("campaign42:customer7:newsletter:lettre").is_ok());
+    /// assert!(FeedbackId::parse("a:b:c:d:e").is_err());
+    /// ```
+    pub fn parse(s: &str) -> Result<FeedbackId, FeedbackIdErr> {
+        let mut fields = 0;
+        for field in s.split(':') {
+            fields += 1;
+            if fields > MAX_FIELDS {
+                return Err(FeedbackIdErr::TooManyFields);
+            }
+            if field.is_empty() {
+                return Err(FeedbackIdErr::EmptyField);
+            }
+            if field.len() > MAX_FIELD_LEN {
+                return Err(FeedbackIdErr::FieldTooLong);
+            }
+            if !field
+                .bytes()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, b'-' | b'_' | b'.'))
+            {
+                return Err(FeedbackIdErr::InvalidChar);
+            }
+        }
+
+        Ok(Self(s.to_owned()))
+    }
+
+    /// Get the `Feedback-ID` as a string
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Header for FeedbackId {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("Feedback-ID")
+    }
+
+    fn parse(s: &str) -> Result<Self, BoxError> {
+        Ok(Self::parse(s)?)
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::dangerous_new_pre_encoded(Self::name(), self.0.clone(), self.0.clone())
+    }
+}
+
+impl FromStr for FeedbackId {
+    type Err = FeedbackIdErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// An error occurred while trying to [`FeedbackId::parse`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FeedbackIdErr {
+    /// More than four colon-separated fields
+    TooManyFields,
+    /// A field is empty
+    EmptyField,
+    /// A field is longer than 64 characters
+    FieldTooLong,
+    /// A field contains a character other than ASCII letters, digits, `-`, `_` and `.`
+    InvalidChar,
+}
+
+impl StdError for FeedbackIdErr {}
+
+impl Display for FeedbackIdErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeedbackIdErr::TooManyFields => f.write_str("too many fields in Feedback-ID"),
+            FeedbackIdErr::EmptyField => f.write_str("empty field in Feedback-ID"),
+            FeedbackIdErr::FieldTooLong => f.write_str("field too long in Feedback-ID"),
+            FeedbackIdErr::InvalidChar => f.write_str("invalid character in Feedback-ID"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::{FeedbackId, FeedbackIdErr};
+    use crate::message::header::{HeaderName, HeaderValue, Headers};
+
+    #[test]
+    fn format_feedback_id() {
+        let mut headers = Headers::new();
+
+        headers.set(FeedbackId::parse("campaign-42:customer_7:news.letter:lettre").unwrap());
+
+        assert_eq!(
+            headers.to_string(),
+            "Feedback-ID: campaign-42:customer_7:news.letter:lettre\r\n"
+        );
+    }
+
+    #[test]
+    fn parse_feedback_id() {
+        let mut headers = Headers::new();
+
+        headers.insert_raw(HeaderValue::new(
+            HeaderName::new_from_ascii_str("Feedback-ID"),
+            "a:lettre".to_owned(),
+        ));
+
+        assert_eq!(
+            headers.get::<FeedbackId>().map(|id| id.as_str().to_owned()),
+            Some("a:lettre".to_owned())
+        );
+    }
+
+    #[test]
+    fn invalid_feedback_id() {
+        let long = format!("a:{}", "b".repeat(65));
+        assert_eq!(FeedbackId::parse(&long), Err(FeedbackIdErr::FieldTooLong));
+        assert!(FeedbackId::parse(&format!("a:{}", "b".repeat(64))).is_ok());
+
+        assert_eq!(
+            FeedbackId::parse("a:b:c:d:e"),
+            Err(FeedbackIdErr::TooManyFields)
+        );
+        assert_eq!(FeedbackId::parse("a::d"), Err(FeedbackIdErr::EmptyField));
+        assert_eq!(FeedbackId::parse(""), Err(FeedbackIdErr::EmptyField));
+        assert_eq!(FeedbackId::parse("a b:d"), Err(FeedbackIdErr::InvalidChar));
+        assert_eq!(
+            FeedbackId::parse("a\r\nBcc: x:d"),
+            Err(FeedbackIdErr::InvalidChar)
+        );
+    }
+}
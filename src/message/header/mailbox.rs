@@ -2,7 +2,7 @@ use email_encoding::headers::EmailWriter;
 
 use super::{Header, HeaderName, HeaderValue};
 use crate::{
-    message::mailbox::{Mailbox, Mailboxes},
+    message::mailbox::{Group, Mailbox, Mailboxes},
     BoxError,
 };
 
@@ -105,6 +105,20 @@ macro_rules! mailboxes_header {
     };
 }
 
+/// Returns the value of the address header `name` containing `group`
+pub(crate) fn group_value(name: HeaderName, group: &Group) -> HeaderValue {
+    let mut encoded_value = String::new();
+    let line_len = name.len() + ": ".len();
+    {
+        let mut w = EmailWriter::new(&mut encoded_value, line_len, 0, false, false);
+        group
+            .encode(&mut w)
+            .expect("writing `Group` returned an error");
+    }
+
+    HeaderValue::dangerous_new_pre_encoded(name, group.to_string(), encoded_value)
+}
+
 mailbox_header! {
     /**
 
@@ -14,7 +14,9 @@ pub use self::{
     content_disposition::ContentDisposition,
     content_type::{ContentType, ContentTypeErr},
     date::Date,
+    feedback_id::{FeedbackId, FeedbackIdErr},
     mailbox::*,
+    priority::Priority,
     special::*,
     textual::*,
 };
@@ -24,7 +26,9 @@ mod content;
 mod content_disposition;
 mod content_type;
 mod date;
+mod feedback_id;
 mod mailbox;
+mod priority;
 mod special;
 mod textual;
 
@@ -116,6 +120,25 @@ impl Headers {
         }
     }
 
+    /// Appends the addresses of `value` to the address list of the header
+    /// with the same name, or inserts `value` if it isn't present
+    ///
+    /// Unlike parsing the list and setting it again, this keeps its groups.
+    pub(crate) fn append_addresses(&mut self, value: HeaderValue) {
+        match self.find_header_mut(&value.name) {
+            Some(current_value) => {
+                current_value.raw_value.push_str(", ");
+                current_value.raw_value.push_str(&value.raw_value);
+                // `value` is encoded as the start of a line
+                current_value.encoded_value.push_str(",\r\n ");
+                current_value.encoded_value.push_str(&value.encoded_value);
+            }
+            None => {
+                self.headers.push(value);
+            }
+        }
+    }
+
     /// Remove a raw header from `Headers`, returning it
     ///
     /// Returns `None` if `name` isn't present in `Headers`.
@@ -323,7 +346,6 @@ impl HeaderValue {
         }
     }
 
-    #[cfg(feature = "dkim")]
     pub(crate) fn get_raw(&self) -> &str {
         &self.raw_value
     }
@@ -334,10 +356,17 @@ impl HeaderValue {
     }
 }
 
+/// Maximum length of a line of the message, excluding the `CRLF`
+///
+/// Defined in [RFC 5322](https://datatracker.ietf.org/doc/html/rfc5322#section-2.1.1)
+const MAX_LINE_LEN: usize = 998;
+
 /// [RFC 1522](https://tools.ietf.org/html/rfc1522) header value encoder
 struct HeaderValueEncoder<'a> {
     writer: EmailWriter<'a>,
     encode_buf: String,
+    /// Length of the longest word which can be written as is
+    max_word_len: usize,
 }
 
 impl<'a> HeaderValueEncoder<'a> {
@@ -353,12 +382,14 @@ impl<'a> HeaderValueEncoder<'a> {
         Self {
             writer,
             encode_buf: String::new(),
+            max_word_len: MAX_LINE_LEN - line_len,
         }
     }
 
     fn format(mut self, words_iter: impl Iterator<Item = &'a str>) -> fmt::Result {
         for next_word in words_iter {
-            let allowed = allowed_str(next_word);
+            // Words which can't fit in a line are encoded, as encoded-words can be split
+            let allowed = allowed_str(next_word) && next_word.len() <= self.max_word_len;
 
             if allowed {
                 // This word only contains allowed characters
@@ -411,7 +442,7 @@ mod tests {
     use pretty_assertions::assert_eq;
 
     use super::{HeaderName, HeaderValue, Headers, To};
-    use crate::message::Mailboxes;
+    use crate::message::{Mailbox, Mailboxes};
 
     #[test]
     fn valid_headername() {
@@ -723,4 +754,72 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn format_long_subject() {
+        let words = (0..400).map(|i| format!("w{i}")).collect::<Vec<_>>();
+        let subject = words.join(" ");
+        assert!(subject.len() > 1500);
+
+        let mut headers = Headers::new();
+        headers.insert_raw(HeaderValue::new(
+            HeaderName::new_from_ascii_str("Subject"),
+            subject,
+        ));
+
+        let formatted = headers.to_string();
+        assert!(formatted.lines().count() > 20);
+        assert!(formatted.lines().all(|line| line.len() <= 78));
+        assert_eq!(
+            formatted.replace("\r\n ", " "),
+            format!("Subject: {}\r\n", words.join(" "))
+        );
+    }
+
+    #[test]
+    fn format_long_word_subject() {
+        let mut headers = Headers::new();
+        headers.insert_raw(HeaderValue::new(
+            HeaderName::new_from_ascii_str("Subject"),
+            "x".repeat(2000),
+        ));
+
+        let formatted = headers.to_string();
+        assert!(formatted.starts_with("Subject: =?utf-8?b?"));
+        assert!(formatted.lines().count() > 20);
+        assert!(formatted.lines().all(|line| line.len() <= 78));
+
+        let mut headers = Headers::new();
+        headers.insert_raw(HeaderValue::new(
+            HeaderName::new_from_ascii_str("Subject"),
+            "x".repeat(900),
+        ));
+        assert_eq!(
+            headers.to_string(),
+            format!("Subject: {}\r\n", "x".repeat(900))
+        );
+    }
+
+    #[test]
+    fn format_many_recipients() {
+        let mailboxes = (0..30)
+            .map(|i| {
+                Mailbox::new(
+                    Some(format!("Recipient {i}")),
+                    format!("recipient{i}@example.com").parse().unwrap(),
+                )
+            })
+            .collect::<Mailboxes>();
+
+        let mut headers = Headers::new();
+        headers.set(To::from(mailboxes));
+
+        let formatted = headers.to_string();
+        assert!(formatted.lines().count() > 10);
+        assert!(formatted.lines().all(|line| line.len() <= 998));
+        for i in 0..30 {
+            let address = format!("<recipient{i}@example.com>");
+            assert!(formatted.lines().any(|line| line.contains(&address)));
+        }
+    }
 }
@@ -0,0 +1,89 @@
+use std::fmt::{self, Display};
+
+use super::{HeaderName, HeaderValue};
+
+/// Priority of a message, set with [`MessageBuilder::priority`]
+///
+/// Sets the `X-Priority` and `Importance` headers used by most email clients,
+/// and the `Priority` header defined in
+/// [RFC2156](https://tools.ietf.org/html/rfc2156#section-5.3.6).
+///
+/// [`MessageBuilder::priority`]: crate::message::MessageBuilder::priority
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Priority {
+    /// Urgent message
+    High,
+    /// Message without any particular priority
+    #[default]
+    Normal,
+    /// Message which can wait
+    Low,
+}
+
+impl Priority {
+    /// The `X-Priority`, `Importance` and `Priority` headers of this priority
+    pub(crate) fn headers(self) -> [HeaderValue; 3] {
+        let (x_priority, importance, priority) = match self {
+            Priority::High => ("1 (Highest)", "High", "urgent"),
+            Priority::Normal => ("3 (Normal)", "Normal", "normal"),
+            Priority::Low => ("5 (Lowest)", "Low", "non-urgent"),
+        };
+
+        [
+            ("X-Priority", x_priority),
+            ("Importance", importance),
+            ("Priority", priority),
+        ]
+        .map(|(name, value)| {
+            HeaderValue::dangerous_new_pre_encoded(
+                HeaderName::new_from_ascii_str(name),
+                value.to_owned(),
+                value.to_owned(),
+            )
+        })
+    }
+}
+
+impl Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Priority::High => "High",
+            Priority::Normal => "Normal",
+            Priority::Low => "Low",
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::Priority;
+    use crate::message::header::Headers;
+
+    #[test]
+    fn format_priority() {
+        for (priority, expected) in [
+            (
+                Priority::High,
+                "X-Priority: 1 (Highest)\r\nImportance: High\r\nPriority: urgent\r\n",
+            ),
+            (
+                Priority::Normal,
+                "X-Priority: 3 (Normal)\r\nImportance: Normal\r\nPriority: normal\r\n",
+            ),
+            (
+                Priority::Low,
+                "X-Priority: 5 (Lowest)\r\nImportance: Low\r\nPriority: non-urgent\r\n",
+            ),
+        ] {
+            let mut headers = Headers::new();
+            for value in priority.headers() {
+                headers.insert_raw(value);
+            }
+            assert_eq!(headers.to_string(), expected);
+        }
+
+        assert_eq!(Priority::High.to_string(), "High");
+    }
+}
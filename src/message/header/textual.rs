@@ -72,6 +72,12 @@ text_header!(
     /// defined in [draft-melnikov-email-user-agent-00](https://tools.ietf.org/html/draft-melnikov-email-user-agent-00#section-3)
     Header(UserAgent, "User-Agent")
 );
+text_header!(
+    /// `TLS-Required` header. The only defined value is `No`, which requests the
+    /// message to be delivered even if TLS can't be used,
+    /// defined in [RFC8689](https://tools.ietf.org/html/rfc8689#section-5)
+    Header(TlsRequired, "TLS-Required")
+);
 text_header! {
     /// `Content-Id` header,
     /// defined in [RFC2045](https://tools.ietf.org/html/rfc2045#section-7)
@@ -82,6 +88,36 @@ text_header! {
     /// defined in [RFC2110](https://tools.ietf.org/html/rfc2110#section-4.3)
     Header(ContentLocation, "Content-Location")
 }
+text_header!(
+    /// `List-Unsubscribe` header. Contains one or more comma-separated URIs,
+    /// in angle brackets, used to unsubscribe from a mailing list,
+    /// defined in [RFC2369](https://tools.ietf.org/html/rfc2369#section-3.2)
+    Header(ListUnsubscribe, "List-Unsubscribe")
+);
+text_header!(
+    /// `List-Unsubscribe-Post` header. Its only defined value is
+    /// `List-Unsubscribe=One-Click`, which allows unsubscribing with a `POST` to
+    /// the HTTPS URI of the `List-Unsubscribe` header,
+    /// defined in [RFC8058](https://tools.ietf.org/html/rfc8058#section-3.1)
+    Header(ListUnsubscribePost, "List-Unsubscribe-Post")
+);
+text_header!(
+    /// `List-Id` header. Contains the identifier of a mailing list, in angle brackets,
+    /// defined in [RFC2919](https://tools.ietf.org/html/rfc2919#section-2)
+    Header(ListId, "List-Id")
+);
+text_header!(
+    /// `X-Part` header. Contains the position of a message in a sequence of
+    /// messages, as in `1/3`, for example set by
+    /// [`MessageBuilder::split_attachment`](crate::message::MessageBuilder::split_attachment)
+    Header(XPart, "X-Part")
+);
+text_header!(
+    /// `X-Request-Id` header. Contains the identifier of the request which sent
+    /// the message, set from the [envelope identifier](crate::address::Envelope::with_envelope_id)
+    /// by [`MessageBuilder`](crate::message::MessageBuilder) when absent
+    Header(XRequestId, "X-Request-Id")
+);
 
 #[cfg(test)]
 mod test {
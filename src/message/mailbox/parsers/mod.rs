@@ -2,4 +2,4 @@ mod rfc2234;
 mod rfc2822;
 mod rfc5336;
 
-pub(crate) use rfc2822::{mailbox, mailbox_list};
+pub(crate) use rfc2822::{address_list, group, mailbox, ParsedMailbox};
@@ -167,10 +167,24 @@ fn phrase() -> impl Parser<char, Vec<char>, Error = Cheap<char>> {
 // 3.4. Address Specification
 // https://datatracker.ietf.org/doc/html/rfc2822#section-3.4
 
+/// A mailbox, made of its optional display name and its local part and domain
+pub(crate) type ParsedMailbox = (Option<String>, (String, String));
+
+// address         =       mailbox / group
+fn address() -> impl Parser<char, Vec<ParsedMailbox>, Error = Cheap<char>> {
+    choice((
+        group_inner().map(|(_name, members)| members),
+        mailbox_inner().map(|mailbox| vec![mailbox]),
+    ))
+}
+
 // mailbox         =       name-addr / addr-spec
-pub(crate) fn mailbox() -> impl Parser<char, (Option<String>, (String, String)), Error = Cheap<char>>
-{
-    choice((name_addr(), addr_spec().map(|addr| (None, addr)))).then_ignore(end())
+pub(crate) fn mailbox() -> impl Parser<char, ParsedMailbox, Error = Cheap<char>> {
+    mailbox_inner().then_ignore(end())
+}
+
+fn mailbox_inner() -> impl Parser<char, ParsedMailbox, Error = Cheap<char>> {
+    choice((name_addr(), addr_spec().map(|addr| (None, addr))))
 }
 
 // name-addr       =       [display-name] angle-addr
@@ -191,10 +205,31 @@ fn display_name() -> impl Parser<char, Vec<char>, Error = Cheap<char>> {
 }
 
 // mailbox-list    =       (mailbox *("," mailbox)) / obs-mbox-list
-pub(crate) fn mailbox_list(
-) -> impl Parser<char, Vec<(Option<String>, (String, String))>, Error = Cheap<char>> {
-    choice((name_addr(), addr_spec().map(|addr| (None, addr))))
+fn mailbox_list() -> impl Parser<char, Vec<ParsedMailbox>, Error = Cheap<char>> {
+    mailbox_inner().separated_by(just(',').padded())
+}
+
+// group           =       display-name ":" [mailbox-list / CFWS] ";"
+//                         [CFWS]
+pub(crate) fn group() -> impl Parser<char, (String, Vec<ParsedMailbox>), Error = Cheap<char>> {
+    group_inner().then_ignore(end())
+}
+
+fn group_inner() -> impl Parser<char, (String, Vec<ParsedMailbox>), Error = Cheap<char>> {
+    display_name()
+        .collect()
+        .then_ignore(just(':').padded())
+        .then(mailbox_list())
+        .then_ignore(just(';').padded())
+}
+
+// address-list    =       (address *("," address)) / obs-addr-list
+//
+// The members of the groups are flattened into a single list
+pub(crate) fn address_list() -> impl Parser<char, Vec<ParsedMailbox>, Error = Cheap<char>> {
+    address()
         .separated_by(just(',').padded())
+        .flatten()
         .then_ignore(end())
 }
 
@@ -172,6 +172,17 @@ mod test {
         assert_eq!(m, "kayo@example.com".parse().unwrap());
     }
 
+    #[test]
+    fn parse_address_list_invalid() {
+        let err =
+            from_str::<Vec<Address>>(r#"["kayo@example.com", "hei@exa mple.com", "yin@dtb.com"]"#)
+                .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"Invalid email domain in address "hei@exa mple.com" at line 1 column 39"#
+        );
+    }
+
     #[test]
     fn parse_mailbox_string() {
         let m: Mailbox = from_str(r#""Kai <kayo@example.com>""#).unwrap();
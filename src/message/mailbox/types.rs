@@ -1,4 +1,5 @@
 use std::{
+    borrow::Cow,
     fmt::{Display, Formatter, Result as FmtResult, Write},
     mem,
     slice::Iter,
@@ -69,9 +70,33 @@ impl Mailbox {
         Mailbox { name, email }
     }
 
+    /// Parses a `Mailbox` from a bare address or a `Name <address>` string.
+    ///
+    /// Display names containing commas or other special characters
+    /// must be quoted. This is the same as using [`str::parse`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lettre::message::Mailbox;
+    ///
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let mailbox = Mailbox::parse(r#""Doe, Jane" <jane@example.org>"#)?;
+    /// assert_eq!(mailbox.name.as_deref(), Some("Doe, Jane"));
+    /// assert_eq!(mailbox.email.to_string(), "jane@example.org");
+    ///
+    /// assert!(Mailbox::parse("Jane Doe jane@example.org").is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn parse(src: &str) -> Result<Self, AddressError> {
+        src.parse()
+    }
+
     pub(crate) fn encode(&self, w: &mut EmailWriter<'_>) -> FmtResult {
         if let Some(name) = &self.name {
-            email_encoding::headers::quoted_string::encode(name, w)?;
+            email_encoding::headers::quoted_string::encode(&fold_line_breaks(name), w)?;
             w.optional_breakpoint();
             w.write_char('<')?;
         }
@@ -125,6 +150,38 @@ impl FromStr for Mailbox {
     }
 }
 
+impl TryFrom<&str> for Mailbox {
+    type Error = AddressError;
+
+    fn try_from(src: &str) -> Result<Self, Self::Error> {
+        src.parse()
+    }
+}
+
+impl TryFrom<String> for Mailbox {
+    type Error = AddressError;
+
+    fn try_from(src: String) -> Result<Self, Self::Error> {
+        src.parse()
+    }
+}
+
+impl TryFrom<&String> for Mailbox {
+    type Error = AddressError;
+
+    fn try_from(src: &String) -> Result<Self, Self::Error> {
+        src.parse()
+    }
+}
+
+impl TryFrom<Cow<'_, str>> for Mailbox {
+    type Error = AddressError;
+
+    fn try_from(src: Cow<'_, str>) -> Result<Self, Self::Error> {
+        src.parse()
+    }
+}
+
 impl From<Address> for Mailbox {
     fn from(value: Address) -> Self {
         Self::new(None, value)
@@ -342,14 +399,23 @@ impl Display for Mailboxes {
 impl FromStr for Mailboxes {
     type Err = AddressError;
 
+    /// Parses a comma-separated list of mailboxes
+    ///
+    /// The members of [`Group`]s appearing in the list are included, without
+    /// the name of their group.
     fn from_str(src: &str) -> Result<Self, Self::Err> {
-        let mut mailboxes = Vec::new();
-
-        let parsed_mailboxes = parsers::mailbox_list().parse(src).map_err(|_errs| {
+        let parsed_mailboxes = parsers::address_list().parse(src).map_err(|_errs| {
             // TODO: improve error management
             AddressError::InvalidInput
         })?;
 
+        Mailboxes::from_parsed(parsed_mailboxes)
+    }
+}
+
+impl Mailboxes {
+    fn from_parsed(parsed_mailboxes: Vec<parsers::ParsedMailbox>) -> Result<Self, AddressError> {
+        let mut mailboxes = Vec::with_capacity(parsed_mailboxes.len());
         for (name, (user, domain)) in parsed_mailboxes {
             mailboxes.push(Mailbox::new(name, Address::new(user, domain)?))
         }
@@ -358,6 +424,105 @@ impl FromStr for Mailboxes {
     }
 }
 
+/// Represents a named group of mailboxes
+///
+/// Address headers may contain groups, like `Team: a@example.com, b@example.com;`,
+/// whose name is only displayed. A group can also be empty, like
+/// `undisclosed-recipients:;`, to show that the recipients are hidden.
+///
+/// Parsing an address header as [`Mailboxes`], as done to build an
+/// [`Envelope`](crate::address::Envelope), includes the members of its groups.
+///
+/// # Examples
+///
+/// ```
+/// # use lettre::message::Group;
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// let group: Group = "Team: Alice <alice@example.com>, bob@example.com;".parse()?;
+/// assert_eq!(group.name(), "Team");
+/// assert_eq!(group.members().iter().count(), 2);
+/// assert_eq!(
+///     group.to_string(),
+///     "Team: Alice <alice@example.com>, bob@example.com;"
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct Group {
+    name: String,
+    members: Mailboxes,
+}
+
+impl Group {
+    /// Creates a new group named `name`
+    pub fn new(name: String, members: Mailboxes) -> Self {
+        Self { name, members }
+    }
+
+    /// Returns the display name of the group
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the mailboxes of the group
+    pub fn members(&self) -> &Mailboxes {
+        &self.members
+    }
+
+    pub(crate) fn encode(&self, w: &mut EmailWriter<'_>) -> FmtResult {
+        email_encoding::headers::quoted_string::encode(&fold_line_breaks(self.name.trim()), w)?;
+        w.write_char(':')?;
+        if self.members.iter().next().is_some() {
+            w.optional_breakpoint();
+            self.members.encode(w)?;
+        }
+        w.write_char(';')
+    }
+}
+
+impl Display for Group {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write_word(f, self.name.trim())?;
+        f.write_char(':')?;
+        if self.members.iter().next().is_some() {
+            f.write_char(' ')?;
+            self.members.fmt(f)?;
+        }
+        f.write_char(';')
+    }
+}
+
+impl FromStr for Group {
+    type Err = AddressError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let (name, members) = parsers::group().parse(src).map_err(|_errs| {
+            // TODO: improve error management
+            AddressError::InvalidInput
+        })?;
+
+        Ok(Group::new(name, Mailboxes::from_parsed(members)?))
+    }
+}
+
+impl From<Group> for Mailboxes {
+    fn from(group: Group) -> Mailboxes {
+        group.members
+    }
+}
+
+/// Replaces the line breaks of a display name with spaces, so that
+/// it can't be used to inject headers
+fn fold_line_breaks(name: &str) -> Cow<'_, str> {
+    if name.contains(['\r', '\n']) {
+        Cow::Owned(name.replace(['\r', '\n'], " "))
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
 // https://datatracker.ietf.org/doc/html/rfc2822#section-3.2.6
 fn write_word(f: &mut Formatter<'_>, s: &str) -> FmtResult {
     if s.as_bytes().iter().copied().all(is_valid_atom_char) {
@@ -411,8 +576,8 @@ fn is_valid_atom_char(c: u8) -> bool {
 // https://datatracker.ietf.org/doc/html/rfc2822#section-3.2.5
 fn write_quoted_string_char(f: &mut Formatter<'_>, c: char) -> FmtResult {
     match c {
-        // Can not be encoded.
-        '\n' | '\r' => Err(std::fmt::Error),
+        // Can not be encoded, and could be used to inject headers.
+        '\n' | '\r' => f.write_char(' '),
 
         // Note, not qcontent but can be put before or after any qcontent.
         '\t' | ' ' => f.write_char(c),
@@ -444,11 +609,12 @@ fn write_quoted_string_char(f: &mut Formatter<'_>, c: char) -> FmtResult {
 
 #[cfg(test)]
 mod test {
-    use std::convert::TryInto;
+    use std::{borrow::Cow, convert::TryInto};
 
     use pretty_assertions::assert_eq;
 
-    use super::Mailbox;
+    use super::{Group, Mailbox, Mailboxes};
+    use crate::address::AddressError;
 
     #[test]
     fn mailbox_format_address_only() {
@@ -472,6 +638,20 @@ mod test {
         );
     }
 
+    #[test]
+    fn mailbox_format_address_with_line_breaks() {
+        assert_eq!(
+            format!(
+                "{}",
+                Mailbox::new(
+                    Some("K.\r\nBcc: evil@example.com".into()),
+                    "kayo@example.com".parse().unwrap()
+                )
+            ),
+            r#""K.  Bcc: evil@example.com" <kayo@example.com>"#
+        );
+    }
+
     #[test]
     fn mailbox_format_address_with_comma() {
         assert_eq!(
@@ -585,6 +765,85 @@ mod test {
         );
     }
 
+    #[test]
+    fn parse_address_with_quoted_name() {
+        assert_eq!(
+            Mailbox::parse(r#""Doe, Jane" <jane@example.org>"#),
+            Ok(Mailbox::new(
+                Some("Doe, Jane".into()),
+                "jane@example.org".parse().unwrap()
+            ))
+        );
+        assert_eq!(
+            Mailbox::parse(r#""Jane \"JD\" Doe" <jane@example.org>"#),
+            Ok(Mailbox::new(
+                Some(r#"Jane "JD" Doe"#.into()),
+                "jane@example.org".parse().unwrap()
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_address_plain() {
+        assert_eq!(
+            Mailbox::parse("jane@example.org"),
+            Ok(Mailbox::new(None, "jane@example.org".parse().unwrap()))
+        );
+    }
+
+    #[test]
+    fn parse_address_malformed() {
+        for src in [
+            "Jane Doe jane@example.org",
+            "Jane Doe <jane@example.org",
+            "Jane Doe jane@example.org>",
+            "Doe, Jane <jane@example.org>",
+            "Jane Doe <>",
+            "Jane Doe",
+            "",
+        ] {
+            assert_eq!(
+                Mailbox::parse(src),
+                Err(AddressError::InvalidInput),
+                "{src:?}"
+            );
+        }
+        assert!(Mailbox::parse("Jane Doe <jane.example.org>").is_err());
+    }
+
+    #[test]
+    fn parse_address_from_strings() {
+        let mailbox = Mailbox::new(Some("K.".into()), "kayo@example.com".parse().unwrap());
+        let owned = String::from("K. <kayo@example.com>");
+
+        assert_eq!(
+            Mailbox::try_from("K. <kayo@example.com>"),
+            Ok(mailbox.clone())
+        );
+        assert_eq!(Mailbox::try_from(&owned), Ok(mailbox.clone()));
+        assert_eq!(Mailbox::try_from(owned.clone()), Ok(mailbox.clone()));
+        assert_eq!(
+            Mailbox::try_from(Cow::Borrowed(owned.as_str())),
+            Ok(mailbox.clone())
+        );
+        assert_eq!(Mailbox::try_from(Cow::<str>::Owned(owned)), Ok(mailbox));
+        assert!(Mailbox::try_from(String::from("K. kayo@example.com")).is_err());
+    }
+
+    #[test]
+    fn parse_address_from_owned_tuple() {
+        let name = String::from("K.");
+        let address = String::from("kayo@example.com");
+
+        assert_eq!(
+            Mailbox::try_from((name, address)),
+            Ok(Mailbox::new(
+                Some("K.".into()),
+                "kayo@example.com".parse().unwrap()
+            ))
+        );
+    }
+
     #[test]
     fn parse_address_from_tuple() {
         assert_eq!(
@@ -595,4 +854,59 @@ mod test {
             ))
         );
     }
+
+    #[test]
+    fn parse_group() {
+        let group: Group = "Team: Alice <alice@example.com>, bob@example.com;"
+            .parse()
+            .unwrap();
+        assert_eq!(group.name(), "Team");
+        assert_eq!(
+            group.members(),
+            &Mailboxes::new()
+                .with(Mailbox::new(
+                    Some("Alice".into()),
+                    "alice@example.com".parse().unwrap()
+                ))
+                .with(Mailbox::new(None, "bob@example.com".parse().unwrap()))
+        );
+        assert_eq!(
+            group.to_string(),
+            "Team: Alice <alice@example.com>, bob@example.com;"
+        );
+        assert_eq!(group.to_string().parse::<Group>(), Ok(group));
+
+        let group: Group = "undisclosed-recipients:;".parse().unwrap();
+        assert_eq!(group.name(), "undisclosed-recipients");
+        assert_eq!(group.members(), &Mailboxes::new());
+        assert_eq!(group.to_string(), "undisclosed-recipients:;");
+
+        let group: Group = r#""Team: East" : alice@example.com ;"#.parse().unwrap();
+        assert_eq!(group.name(), "Team: East");
+        assert_eq!(group.to_string(), r#""Team: East": alice@example.com;"#);
+
+        assert_eq!(
+            "Team alice@example.com;".parse::<Group>(),
+            Err(AddressError::InvalidInput)
+        );
+        assert_eq!(
+            "Team: alice@example.com".parse::<Group>(),
+            Err(AddressError::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn parse_mailboxes_with_groups() {
+        let mailboxes: Mailboxes =
+            "kayo@example.com, Team: alice@example.com, bob@example.com;, Empty:;"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            mailboxes,
+            Mailboxes::new()
+                .with(Mailbox::new(None, "kayo@example.com".parse().unwrap()))
+                .with(Mailbox::new(None, "alice@example.com".parse().unwrap()))
+                .with(Mailbox::new(None, "bob@example.com".parse().unwrap()))
+        );
+    }
 }
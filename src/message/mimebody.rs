@@ -1,9 +1,17 @@
-use std::{io::Write, iter::repeat_with};
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    fmt::{self, Display},
+    io::{self, Write},
+    iter::repeat_with,
+};
 
 use mime::Mime;
 
 use crate::message::{
+    attachment::LazyAttachment,
     header::{self, ContentTransferEncoding, ContentType, Header, Headers},
+    reader::Segment,
     EmailFormat, IntoBody,
 };
 
@@ -15,6 +23,150 @@ pub(super) enum Part {
 
     /// Multiple parts of content
     Multi(MultiPart),
+
+    /// Single part with content read from a file
+    Lazy(LazyAttachment),
+}
+
+impl Part {
+    /// Write the part to `out`, reading lazy attachments in chunks
+    pub(super) fn write_to<W: Write + ?Sized>(&self, out: &mut W) -> io::Result<()> {
+        match self {
+            Part::Single(part) => out.write_all(&part.formatted()),
+            Part::Multi(part) => part.write_to(out),
+            Part::Lazy(part) => part.write_to(out),
+        }
+    }
+
+    /// Append the pieces of the formatted part to `out`
+    pub(super) fn segments<'a>(&'a self, out: &mut VecDeque<Segment<'a>>) {
+        match self {
+            Part::Single(part) => part.segments(out),
+            Part::Multi(part) => part.segments(out),
+            Part::Lazy(part) => out.push_back(Segment::Lazy(part)),
+        }
+    }
+
+    /// Check that the files of the lazy attachments can be opened
+    pub(super) fn open_lazy_attachments(&self) -> io::Result<()> {
+        match self {
+            Part::Single(_) => Ok(()),
+            Part::Multi(part) => part.parts.iter().try_for_each(Part::open_lazy_attachments),
+            Part::Lazy(part) => part.open(),
+        }
+    }
+
+    /// Whether the formatted part contains `needle`, without reading lazy attachments
+    fn contains(&self, needle: &str) -> bool {
+        match self {
+            Part::Single(part) => part
+                .formatted()
+                .windows(needle.len())
+                .any(|window| window == needle.as_bytes()),
+            Part::Multi(part) => {
+                part.headers.to_string().contains(needle)
+                    || part.parts.iter().any(|part| part.contains(needle))
+            }
+            Part::Lazy(_) => false,
+        }
+    }
+
+    /// Whether the formatted part only contains ASCII characters,
+    /// without reading lazy attachments
+    #[cfg(feature = "smtp-transport")]
+    pub(super) fn is_ascii(&self) -> bool {
+        match self {
+            Part::Single(part) => part.formatted().is_ascii(),
+            Part::Multi(part) => {
+                part.headers.to_string().is_ascii() && part.parts.iter().all(Part::is_ascii)
+            }
+            Part::Lazy(part) => part.is_ascii(),
+        }
+    }
+
+    /// Compares the `cid:` URLs of the HTML parts with the `Content-ID`s of the parts
+    pub(super) fn cid_warnings(&self) -> Vec<CidWarning> {
+        let mut references = Vec::new();
+        let mut ids = Vec::new();
+        self.collect_cids(&mut references, &mut ids);
+
+        let missing = references
+            .iter()
+            .filter(|reference| !ids.contains(reference))
+            .map(|reference| CidWarning::MissingPart(reference.clone()));
+        let unreferenced = ids
+            .iter()
+            .filter(|id| !references.contains(id))
+            .map(|id| CidWarning::UnreferencedPart(id.clone()));
+        missing.chain(unreferenced).collect()
+    }
+
+    fn collect_cids(&self, references: &mut Vec<String>, ids: &mut Vec<String>) {
+        let headers = match self {
+            Part::Single(part) => &part.headers,
+            Part::Multi(part) => {
+                for part in &part.parts {
+                    part.collect_cids(references, ids);
+                }
+                return;
+            }
+            Part::Lazy(part) => part.headers(),
+        };
+
+        if let Some(id) = headers.get_raw(&header::ContentId::name()) {
+            let id = id.trim().trim_start_matches('<').trim_end_matches('>');
+            if !ids.iter().any(|known| known == id) {
+                ids.push(id.to_owned());
+            }
+        }
+
+        let Part::Single(part) = self else { return };
+        let is_html = headers
+            .get::<ContentType>()
+            .is_some_and(|content_type| content_type.as_ref().essence_str() == "text/html");
+        if !is_html {
+            return;
+        }
+        let html = part.decoded_body();
+        let html = String::from_utf8_lossy(&html);
+        let lowercase = html.to_ascii_lowercase();
+        for (start, _) in lowercase.match_indices("cid:") {
+            let reference = &html[start + 4..];
+            let end = reference
+                .find(|c: char| {
+                    c.is_whitespace() || matches!(c, '"' | '\'' | '(' | ')' | '<' | '>')
+                })
+                .unwrap_or(reference.len());
+            let reference = &reference[..end];
+            if !reference.is_empty() && !references.iter().any(|known| known == reference) {
+                references.push(reference.to_owned());
+            }
+        }
+    }
+}
+
+/// A mismatch between the `cid:` URLs in the HTML parts of a message
+/// and the `Content-ID`s of its parts
+///
+/// See [`Message::cid_warnings`](super::Message::cid_warnings).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CidWarning {
+    /// An HTML part references this content ID, but no part has it
+    MissingPart(String),
+    /// A part has this content ID, but no HTML part references it
+    UnreferencedPart(String),
+}
+
+impl Display for CidWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CidWarning::MissingPart(id) => write!(f, "no part has the content ID of cid:{id}"),
+            CidWarning::UnreferencedPart(id) => {
+                write!(f, "the part with the content ID <{id}> isn't referenced")
+            }
+        }
+    }
 }
 
 impl EmailFormat for Part {
@@ -22,6 +174,9 @@ impl EmailFormat for Part {
         match self {
             Part::Single(part) => part.format(out),
             Part::Multi(part) => part.format(out),
+            Part::Lazy(part) => part
+                .write_to(out)
+                .expect("failed to read the content of a lazy attachment"),
         }
     }
 }
@@ -52,6 +207,42 @@ impl SinglePartBuilder {
         self
     }
 
+    /// Set the Content-Transfer-Encoding header of the singlepart
+    ///
+    /// The body is encoded with `encoding` by [`SinglePartBuilder::body`].
+    /// When not set, the most efficient valid encoding for the body is chosen.
+    pub fn transfer_encoding(mut self, encoding: ContentTransferEncoding) -> Self {
+        self.headers.set(encoding);
+        self
+    }
+
+    /// Remove a header from the singlepart
+    ///
+    /// Does nothing if the header hasn't been set.
+    pub fn remove_header<H: Header>(mut self) -> Self {
+        self.headers.remove::<H>();
+        self
+    }
+
+    /// Build singlepart using body, encoded with the provided `Content-Transfer-Encoding`
+    ///
+    /// Shortcut for `self.header(encoding).body(body)`.
+    ///
+    /// [`SinglePartBuilder::body`] is generally the better option, as it automatically
+    /// chooses the most efficient valid encoding for `body`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `body` can't be encoded using `encoding`, for example when
+    /// `7bit` is requested for a body containing non-ascii characters.
+    pub fn body_with_encoding<T: IntoBody>(
+        self,
+        body: T,
+        encoding: ContentTransferEncoding,
+    ) -> SinglePart {
+        self.header(encoding).body(body)
+    }
+
     /// Build singlepart using body
     pub fn body<T: IntoBody>(mut self, body: T) -> SinglePart {
         let maybe_encoding = self.headers.get::<ContentTransferEncoding>();
@@ -132,6 +323,35 @@ impl SinglePart {
         self.format(&mut out);
         out
     }
+
+    fn segments<'a>(&'a self, out: &mut VecDeque<Segment<'a>>) {
+        out.push_back(Segment::Bytes(Cow::Owned(
+            format!("{}\r\n", self.headers).into_bytes(),
+        )));
+        out.push_back(Segment::Bytes(Cow::Borrowed(&self.body)));
+        out.push_back(Segment::Bytes(Cow::Borrowed(b"\r\n")));
+    }
+
+    /// Decodes the body according to its `Content-Transfer-Encoding`,
+    /// falling back to the encoded body if it is invalid
+    fn decoded_body(&self) -> Vec<u8> {
+        match self.headers.get::<ContentTransferEncoding>() {
+            Some(ContentTransferEncoding::QuotedPrintable) => {
+                quoted_printable::decode(&self.body, quoted_printable::ParseMode::Robust)
+                    .unwrap_or_else(|_| self.body.clone())
+            }
+            Some(ContentTransferEncoding::Base64) => {
+                let encoded: Vec<u8> = self
+                    .body
+                    .iter()
+                    .copied()
+                    .filter(|b| !b.is_ascii_whitespace())
+                    .collect();
+                crate::base64::decode(encoded).unwrap_or_else(|_| self.body.clone())
+            }
+            _ => self.body.clone(),
+        }
+    }
 }
 
 impl EmailFormat for SinglePart {
@@ -175,46 +395,54 @@ fn make_boundary() -> String {
     repeat_with(fastrand::alphanumeric).take(40).collect()
 }
 
+/// Create a MIME boundary using the provided random number generator.
+fn make_boundary_with(rng: &mut fastrand::Rng) -> String {
+    repeat_with(|| rng.alphanumeric()).take(40).collect()
+}
+
+/// Create a `multipart/{subtype}` media type with a random boundary
+/// followed by `params`
+fn multipart_mime(subtype: &str, params: &str) -> Mime {
+    format!(
+        "multipart/{subtype}; boundary=\"{}\"{params}",
+        make_boundary()
+    )
+    .parse()
+    .unwrap()
+}
+
+/// Replace the boundary of a multipart media type, keeping its subtype
+/// and its other parameters
+fn with_boundary(mime: &Mime, boundary: &str) -> Mime {
+    let mut params = String::new();
+    for (name, value) in mime.params() {
+        if name != "boundary" {
+            params.push_str(&format!("; {name}=\"{value}\""));
+        }
+    }
+
+    format!(
+        "{}/{}; boundary=\"{boundary}\"{params}",
+        mime.type_(),
+        mime.subtype()
+    )
+    .parse()
+    .unwrap()
+}
+
 impl MultiPartKind {
-    pub(crate) fn to_mime<S: Into<String>>(&self, boundary: Option<S>) -> Mime {
-        let boundary = boundary.map_or_else(make_boundary, Into::into);
-
-        format!(
-            "multipart/{}; boundary=\"{}\"{}",
-            match self {
-                Self::Mixed => "mixed",
-                Self::Alternative => "alternative",
-                Self::Related => "related",
-                Self::Encrypted { .. } => "encrypted",
-                Self::Signed { .. } => "signed",
-            },
-            boundary,
-            match self {
-                Self::Encrypted { protocol } => format!("; protocol=\"{protocol}\""),
-                Self::Signed { protocol, micalg } =>
-                    format!("; protocol=\"{protocol}\"; micalg=\"{micalg}\""),
-                _ => String::new(),
+    pub(crate) fn to_mime(&self) -> Mime {
+        match self {
+            Self::Mixed => multipart_mime("mixed", ""),
+            Self::Alternative => multipart_mime("alternative", ""),
+            Self::Related => multipart_mime("related", ""),
+            Self::Encrypted { protocol } => {
+                multipart_mime("encrypted", &format!("; protocol=\"{protocol}\""))
             }
-        )
-        .parse()
-        .unwrap()
-    }
-
-    fn from_mime(m: &Mime) -> Option<Self> {
-        match m.subtype().as_ref() {
-            "mixed" => Some(Self::Mixed),
-            "alternative" => Some(Self::Alternative),
-            "related" => Some(Self::Related),
-            "signed" => m.get_param("protocol").and_then(|p| {
-                m.get_param("micalg").map(|micalg| Self::Signed {
-                    protocol: p.as_str().to_owned(),
-                    micalg: micalg.as_str().to_owned(),
-                })
-            }),
-            "encrypted" => m.get_param("protocol").map(|p| Self::Encrypted {
-                protocol: p.as_str().to_owned(),
-            }),
-            _ => None,
+            Self::Signed { protocol, micalg } => multipart_mime(
+                "signed",
+                &format!("; protocol=\"{protocol}\"; micalg=\"{micalg}\""),
+            ),
         }
     }
 }
@@ -223,6 +451,8 @@ impl MultiPartKind {
 #[derive(Debug, Clone)]
 pub struct MultiPartBuilder {
     headers: Headers,
+    /// Whether the boundary was set with [`MultiPartBuilder::boundary`]
+    pinned_boundary: bool,
 }
 
 impl MultiPartBuilder {
@@ -230,6 +460,7 @@ impl MultiPartBuilder {
     pub fn new() -> Self {
         Self {
             headers: Headers::new(),
+            pinned_boundary: false,
         }
     }
 
@@ -241,16 +472,20 @@ impl MultiPartBuilder {
 
     /// Set `Content-Type` header using [`MultiPartKind`]
     pub fn kind(self, kind: MultiPartKind) -> Self {
-        self.header(ContentType::from_mime(kind.to_mime::<String>(None)))
+        self.header(ContentType::from_mime(kind.to_mime()))
     }
 
     /// Set custom boundary
-    pub fn boundary<S: Into<String>>(self, boundary: S) -> Self {
-        let kind = {
+    ///
+    /// The boundary is kept by [`MessageBuilder::deterministic`].
+    ///
+    /// [`MessageBuilder::deterministic`]: crate::message::MessageBuilder::deterministic
+    pub fn boundary<S: Into<String>>(mut self, boundary: S) -> Self {
+        let mime = {
             let content_type = self.headers.get::<ContentType>().unwrap();
-            MultiPartKind::from_mime(content_type.as_ref()).unwrap()
+            with_boundary(content_type.as_ref(), &boundary.into())
         };
-        let mime = kind.to_mime(Some(boundary));
+        self.pinned_boundary = true;
         self.header(ContentType::from_mime(mime))
     }
 
@@ -259,6 +494,7 @@ impl MultiPartBuilder {
         MultiPart {
             headers: self.headers,
             parts: Vec::new(),
+            pinned_boundary: self.pinned_boundary,
         }
     }
 
@@ -271,6 +507,11 @@ impl MultiPartBuilder {
     pub fn multipart(self, part: MultiPart) -> MultiPart {
         self.build().multipart(part)
     }
+
+    /// Creates multipart using a lazy attachment
+    pub fn lazy_attachment(self, part: LazyAttachment) -> MultiPart {
+        self.build().lazy_attachment(part)
+    }
 }
 
 impl Default for MultiPartBuilder {
@@ -284,6 +525,8 @@ impl Default for MultiPartBuilder {
 pub struct MultiPart {
     headers: Headers,
     parts: Vec<Part>,
+    /// Whether the boundary was set with [`MultiPartBuilder::boundary`]
+    pinned_boundary: bool,
 }
 
 impl MultiPart {
@@ -313,6 +556,23 @@ impl MultiPart {
         MultiPart::builder().kind(MultiPartKind::Related)
     }
 
+    /// Creates digest multipart builder
+    ///
+    /// Its parts are expected to be `message/rfc822` messages, like the ones
+    /// of a mailing list digest.
+    pub fn digest() -> MultiPartBuilder {
+        MultiPart::builder().header(ContentType::from_mime(multipart_mime("digest", "")))
+    }
+
+    /// Creates report multipart builder
+    ///
+    /// Used for reports sent to the sender of a message, like delivery status
+    /// notifications, as defined in [RFC6522](https://tools.ietf.org/html/rfc6522).
+    pub fn report(report_type: String) -> MultiPartBuilder {
+        let params = format!("; report-type=\"{report_type}\"");
+        MultiPart::builder().header(ContentType::from_mime(multipart_mime("report", &params)))
+    }
+
     /// Creates encrypted multipart builder
     ///
     /// Shortcut for `MultiPart::builder().kind(MultiPartKind::Encrypted{ protocol })`
@@ -334,6 +594,18 @@ impl MultiPart {
             .singlepart(SinglePart::html(html))
     }
 
+    /// Alias for HTML content and the resources it references, like inline images
+    pub fn related_html<T, I>(html: T, resources: I) -> Self
+    where
+        T: IntoBody,
+        I: IntoIterator<Item = SinglePart>,
+    {
+        resources.into_iter().fold(
+            Self::related().singlepart(SinglePart::html(html)),
+            |related, resource| related.singlepart(resource),
+        )
+    }
+
     /// Add single part to multipart
     pub fn singlepart(mut self, part: SinglePart) -> Self {
         self.parts.push(Part::Single(part));
@@ -346,6 +618,14 @@ impl MultiPart {
         self
     }
 
+    /// Add a lazy attachment to multipart
+    ///
+    /// The content of the attachment is read when the email is sent.
+    pub fn lazy_attachment(mut self, part: LazyAttachment) -> Self {
+        self.parts.push(Part::Lazy(part));
+        self
+    }
+
     /// Get the boundary of multipart contents
     pub fn boundary(&self) -> String {
         let content_type = self.headers.get::<ContentType>().unwrap();
@@ -357,6 +637,44 @@ impl MultiPart {
             .into()
     }
 
+    /// Whether the boundary of this multipart or of a nested one appears
+    /// in the content of its parts, without reading lazy attachments
+    pub(super) fn boundary_in_content(&self) -> bool {
+        let boundary = self.boundary();
+        self.parts.iter().any(|part| {
+            part.contains(&boundary)
+                || matches!(part, Part::Multi(part) if part.boundary_in_content())
+        })
+    }
+
+    /// Replace the boundary of this multipart and of all the nested ones
+    /// with boundaries generated from `rng`, except the ones set with
+    /// [`MultiPartBuilder::boundary`]
+    ///
+    /// A new boundary is drawn until it doesn't appear in the content of the parts,
+    /// so that a deterministic boundary can't be forged by the content.
+    pub(super) fn regenerate_boundaries(&mut self, rng: &mut fastrand::Rng) {
+        for part in &mut self.parts {
+            if let Part::Multi(part) = part {
+                part.regenerate_boundaries(rng);
+            }
+        }
+
+        if self.pinned_boundary {
+            return;
+        }
+        if let Some(content_type) = self.headers.get::<ContentType>() {
+            let boundary = loop {
+                let boundary = make_boundary_with(rng);
+                if !self.parts.iter().any(|part| part.contains(&boundary)) {
+                    break boundary;
+                }
+            };
+            let mime = with_boundary(content_type.as_ref(), &boundary);
+            self.headers.set(ContentType::from_mime(mime));
+        }
+    }
+
     /// Get the headers from the multipart
     pub fn headers(&self) -> &Headers {
         &self.headers
@@ -368,31 +686,53 @@ impl MultiPart {
     }
 
     /// Get message content formatted for SMTP
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file of a [`LazyAttachment`] can't be read.
     pub fn formatted(&self) -> Vec<u8> {
         let mut out = Vec::new();
         self.format(&mut out);
         out
     }
-}
 
-impl EmailFormat for MultiPart {
-    fn format(&self, out: &mut Vec<u8>) {
-        write!(out, "{}", self.headers)
-            .expect("A Write implementation panicked while formatting headers");
-        out.extend_from_slice(b"\r\n");
+    fn segments<'a>(&'a self, out: &mut VecDeque<Segment<'a>>) {
+        out.push_back(Segment::Bytes(Cow::Owned(
+            format!("{}\r\n", self.headers).into_bytes(),
+        )));
 
         let boundary = self.boundary();
 
         for part in &self.parts {
-            out.extend_from_slice(b"--");
-            out.extend_from_slice(boundary.as_bytes());
-            out.extend_from_slice(b"\r\n");
-            part.format(out);
+            out.push_back(Segment::Bytes(Cow::Owned(
+                format!("--{boundary}\r\n").into_bytes(),
+            )));
+            part.segments(out);
         }
 
-        out.extend_from_slice(b"--");
-        out.extend_from_slice(boundary.as_bytes());
-        out.extend_from_slice(b"--\r\n");
+        out.push_back(Segment::Bytes(Cow::Owned(
+            format!("--{boundary}--\r\n").into_bytes(),
+        )));
+    }
+
+    fn write_to<W: Write + ?Sized>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "{}\r\n", self.headers)?;
+
+        let boundary = self.boundary();
+
+        for part in &self.parts {
+            write!(out, "--{boundary}\r\n")?;
+            part.write_to(out)?;
+        }
+
+        write!(out, "--{boundary}--\r\n")
+    }
+}
+
+impl EmailFormat for MultiPart {
+    fn format(&self, out: &mut Vec<u8>) {
+        self.write_to(out)
+            .expect("failed to read the content of a lazy attachment");
     }
 }
 
@@ -458,6 +798,146 @@ mod test {
         );
     }
 
+    #[test]
+    fn single_part_replace_content_type() {
+        let part = SinglePart::builder()
+            .content_type(header::ContentType::TEXT_HTML)
+            .content_type(header::ContentType::TEXT_PLAIN)
+            .header(header::ContentDisposition::attachment("example.txt"))
+            .remove_header::<header::ContentDisposition>()
+            .body(String::from("Hello"));
+
+        assert_eq!(
+            String::from_utf8(part.formatted()).unwrap(),
+            concat!(
+                "Content-Type: text/plain; charset=utf-8\r\n",
+                "Content-Transfer-Encoding: 7bit\r\n",
+                "\r\n",
+                "Hello\r\n"
+            )
+        );
+    }
+
+    #[test]
+    fn single_part_with_encoding() {
+        let part = SinglePart::builder()
+            .header(header::ContentType::TEXT_PLAIN)
+            .body_with_encoding(
+                String::from("Café"),
+                ContentTransferEncoding::QuotedPrintable,
+            );
+
+        assert_eq!(
+            String::from_utf8(part.formatted()).unwrap(),
+            concat!(
+                "Content-Type: text/plain; charset=utf-8\r\n",
+                "Content-Transfer-Encoding: quoted-printable\r\n",
+                "\r\n",
+                "Caf=C3=A9\r\n"
+            )
+        );
+
+        let part = SinglePart::builder()
+            .header(header::ContentType::TEXT_PLAIN)
+            .body_with_encoding(String::from("Café"), ContentTransferEncoding::Base64);
+
+        assert_eq!(
+            String::from_utf8(part.formatted()).unwrap(),
+            concat!(
+                "Content-Type: text/plain; charset=utf-8\r\n",
+                "Content-Transfer-Encoding: base64\r\n",
+                "\r\n",
+                "Q2Fmw6k=\r\n"
+            )
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid encoding")]
+    fn single_part_with_invalid_encoding() {
+        SinglePart::builder()
+            .header(header::ContentType::TEXT_PLAIN)
+            .body_with_encoding(String::from("Café"), ContentTransferEncoding::SevenBit);
+    }
+
+    /// The signature and first chunk of a PNG image
+    const PNG: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f,
+        0x15, 0xc4, 0x89,
+    ];
+
+    fn split_body(formatted: &[u8]) -> (&[u8], &[u8]) {
+        let end = formatted.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        (&formatted[..end + 2], &formatted[end + 4..])
+    }
+
+    #[test]
+    fn single_part_binary_round_trip() {
+        use base64::Engine;
+
+        let part = SinglePart::builder()
+            .content_type(header::ContentType::parse("image/png").unwrap())
+            .body(PNG.to_vec());
+        assert_eq!(
+            part.headers().get::<ContentTransferEncoding>(),
+            Some(ContentTransferEncoding::Base64)
+        );
+
+        let formatted = part.formatted();
+        let (headers, body) = split_body(&formatted);
+        assert!(headers.ends_with(b"Content-Transfer-Encoding: base64\r\n"));
+        let body: Vec<u8> = body
+            .iter()
+            .copied()
+            .filter(|b| !b.is_ascii_whitespace())
+            .collect();
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(body)
+            .unwrap();
+        assert_eq!(decoded, PNG);
+
+        let part = SinglePart::builder()
+            .content_type(header::ContentType::parse("image/png").unwrap())
+            .transfer_encoding(ContentTransferEncoding::QuotedPrintable)
+            .body(PNG.to_vec());
+
+        let formatted = part.formatted();
+        let (headers, body) = split_body(&formatted);
+        assert!(headers.ends_with(b"Content-Transfer-Encoding: quoted-printable\r\n"));
+        assert!(!body.contains(&0));
+        let decoded = quoted_printable::decode(body, quoted_printable::ParseMode::Strict).unwrap();
+        assert_eq!(decoded.strip_suffix(b"\r\n").unwrap(), PNG);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid encoding")]
+    fn single_part_binary_without_encoding() {
+        SinglePart::builder()
+            .content_type(header::ContentType::parse("image/png").unwrap())
+            .transfer_encoding(ContentTransferEncoding::EightBit)
+            .body(PNG.to_vec());
+    }
+
+    #[test]
+    fn single_part_auto_encoding() {
+        let part = SinglePart::plain(String::from("Café au lait"));
+        assert_eq!(
+            part.headers().get::<ContentTransferEncoding>(),
+            Some(ContentTransferEncoding::QuotedPrintable)
+        );
+
+        let part = SinglePart::plain("a".repeat(2000));
+        assert_eq!(
+            part.headers().get::<ContentTransferEncoding>(),
+            Some(ContentTransferEncoding::QuotedPrintable)
+        );
+        assert!(part
+            .raw_body()
+            .split(|&b| b == b'\n')
+            .all(|line| line.len() <= 78));
+    }
+
     #[test]
     fn multi_part_mixed() {
         let part = MultiPart::mixed()
@@ -692,6 +1172,101 @@ mod test {
                            "--0oVZ2r6AoLAhLlb0gPNSKy6BEqdS2IfwxrcbUuo1--\r\n"));
     }
 
+    /// Returns the content types of `part` and its children depth first,
+    /// without their parameters
+    fn content_types(part: &MultiPart) -> Vec<String> {
+        let essence = |headers: &Headers| {
+            let content_type = headers.get::<ContentType>().unwrap();
+            content_type.as_ref().essence_str().to_owned()
+        };
+        let mut types = vec![essence(part.headers())];
+        for part in &part.parts {
+            match part {
+                Part::Single(part) => types.push(essence(part.headers())),
+                Part::Multi(part) => types.extend(content_types(part)),
+                Part::Lazy(_) => {}
+            }
+        }
+        types
+    }
+
+    #[test]
+    fn multi_part_related_html_with_attachments() {
+        let image = SinglePart::builder()
+            .header(header::ContentType::parse("image/png").unwrap())
+            .header(header::ContentDisposition::inline())
+            .header(header::ContentId::from(String::from("<logo>")))
+            .body(vec![0x89, 0x50, 0x4e, 0x47]);
+        let part = MultiPart::mixed()
+            .multipart(
+                MultiPart::alternative()
+                    .singlepart(SinglePart::plain(String::from("Hello")))
+                    .multipart(MultiPart::related_html(
+                        String::from("<p>Hello <img src=\"cid:logo\"></p>"),
+                        [image],
+                    )),
+            )
+            .singlepart(
+                SinglePart::builder()
+                    .header(header::ContentType::TEXT_PLAIN)
+                    .header(header::ContentDisposition::attachment("notes.txt"))
+                    .body(String::from("Notes")),
+            );
+
+        assert_eq!(
+            content_types(&part),
+            [
+                "multipart/mixed",
+                "multipart/alternative",
+                "text/plain",
+                "multipart/related",
+                "text/html",
+                "image/png",
+                "text/plain",
+            ]
+        );
+    }
+
+    #[test]
+    fn multi_part_digest() {
+        let message = |subject: &str| {
+            SinglePart::builder()
+                .header(header::ContentType::parse("message/rfc822").unwrap())
+                .body(format!("Subject: {subject}\r\n\r\nHi"))
+        };
+        let part = MultiPart::digest()
+            .boundary("digest")
+            .singlepart(message("First"))
+            .singlepart(message("Second"));
+
+        assert_eq!(
+            content_types(&part),
+            ["multipart/digest", "message/rfc822", "message/rfc822"]
+        );
+        assert_eq!(part.boundary(), "digest");
+    }
+
+    #[test]
+    fn multi_part_boundary_keeps_params() {
+        let part = MultiPart::signed(
+            "application/pgp-signature".to_owned(),
+            "pgp-sha256".to_owned(),
+        )
+        .boundary("first")
+        .boundary("second")
+        .build();
+
+        let content_type = part.headers().get::<ContentType>().unwrap();
+        let mime = content_type.as_ref();
+        assert_eq!(mime.essence_str(), "multipart/signed");
+        assert_eq!(part.boundary(), "second");
+        assert_eq!(
+            mime.get_param("protocol").unwrap(),
+            "application/pgp-signature"
+        );
+        assert_eq!(mime.get_param("micalg").unwrap(), "pgp-sha256");
+    }
+
     #[test]
     fn test_make_boundary() {
         let mut boundaries = std::collections::HashSet::with_capacity(10);
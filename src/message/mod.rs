@@ -198,26 +198,47 @@
 //! ```
 //! </details>
 
-use std::{io::Write, iter, time::SystemTime};
+use std::{
+    io::{self, Write},
+    iter,
+    time::SystemTime,
+};
 
-pub use attachment::Attachment;
+pub use attachment::{Attachment, LazyAttachment, TextSubtype};
 pub use body::{Body, IntoBody, MaybeString};
 #[cfg(feature = "dkim")]
 pub use dkim::*;
+pub use dmarc::{organizational_domain, DmarcAlignment, DmarcAlignmentPolicy};
+pub use dsn::{DsnAction, DsnErr, DsnRecipient, DsnReportBuilder, DsnStatus};
 pub use mailbox::*;
 pub use mimebody::*;
+pub use raw::RawEmail;
+pub(crate) use raw::{normalize_line_endings, parse_headers};
+#[cfg(all(
+    feature = "smtp-transport",
+    any(feature = "tokio1", feature = "async-std1")
+))]
+pub(crate) use reader::async_reader;
+pub use reader::MessageReader;
 
 mod attachment;
 mod body;
 #[cfg(feature = "dkim")]
 pub mod dkim;
+mod dmarc;
+mod dsn;
 pub mod header;
 mod mailbox;
 mod mimebody;
+mod raw;
+mod reader;
 
 use crate::{
-    address::Envelope,
-    message::header::{ContentTransferEncoding, Header, Headers, MailboxesHeader},
+    address::{Address, Envelope},
+    error,
+    message::header::{
+        ContentTransferEncoding, ContentType, Header, HeaderName, Headers, MailboxesHeader,
+    },
     Error as EmailError,
 };
 
@@ -235,6 +256,13 @@ pub struct MessageBuilder {
     headers: Headers,
     envelope: Option<Envelope>,
     drop_bcc: bool,
+    validate_addresses: bool,
+    invalid_addresses: Vec<Address>,
+    empty_list_unsubscribe: bool,
+    dmarc_alignment_policy: DmarcAlignmentPolicy,
+    generate_message_id: bool,
+    message_id_domain: Option<String>,
+    rng: Option<fastrand::Rng>,
 }
 
 impl MessageBuilder {
@@ -244,6 +272,13 @@ impl MessageBuilder {
             headers: Headers::new(),
             envelope: None,
             drop_bcc: true,
+            validate_addresses: true,
+            invalid_addresses: Vec::new(),
+            empty_list_unsubscribe: false,
+            dmarc_alignment_policy: DmarcAlignmentPolicy::Ignore,
+            generate_message_id: false,
+            message_id_domain: None,
+            rng: None,
         }
     }
 
@@ -253,7 +288,8 @@ impl MessageBuilder {
     ///
     /// Shortcut for `self.mailbox(header::From(mbox))`.
     pub fn from(self, mbox: Mailbox) -> Self {
-        self.mailbox(header::From::from(Mailboxes::from(mbox)))
+        self.check_address(&mbox.email)
+            .mailbox(header::From::from(Mailboxes::from(mbox)))
     }
 
     /// Set `Sender` header. Should be used when providing several `From` mailboxes.
@@ -262,11 +298,15 @@ impl MessageBuilder {
     ///
     /// Shortcut for `self.header(header::Sender(mbox))`.
     pub fn sender(self, mbox: Mailbox) -> Self {
-        self.header(header::Sender::from(mbox))
+        self.check_address(&mbox.email)
+            .header(header::Sender::from(mbox))
     }
 
     /// Add `Date` header to message
     ///
+    /// The date is formatted as specified by RFC 5322, in UTC,
+    /// like `Tue, 15 Nov 1994 08:12:31 +0000`.
+    ///
     /// Shortcut for `self.header(header::Date::new(st))`.
     pub fn date(self, st: SystemTime) -> Self {
         self.header(header::Date::new(st))
@@ -286,28 +326,61 @@ impl MessageBuilder {
     ///
     /// Shortcut for `self.mailbox(header::ReplyTo(mbox))`.
     pub fn reply_to(self, mbox: Mailbox) -> Self {
-        self.mailbox(header::ReplyTo(mbox.into()))
+        self.check_address(&mbox.email)
+            .mailbox(header::ReplyTo(mbox.into()))
     }
 
     /// Set or add mailbox to `To` header
     ///
     /// Shortcut for `self.mailbox(header::To(mbox))`.
     pub fn to(self, mbox: Mailbox) -> Self {
-        self.mailbox(header::To(mbox.into()))
+        self.check_address(&mbox.email)
+            .mailbox(header::To(mbox.into()))
     }
 
     /// Set or add mailbox to `Cc` header
     ///
     /// Shortcut for `self.mailbox(header::Cc(mbox))`.
     pub fn cc(self, mbox: Mailbox) -> Self {
-        self.mailbox(header::Cc(mbox.into()))
+        self.check_address(&mbox.email)
+            .mailbox(header::Cc(mbox.into()))
     }
 
     /// Set or add mailbox to `Bcc` header
     ///
     /// Shortcut for `self.mailbox(header::Bcc(mbox))`.
+    ///
+    /// The mailbox is added to the recipients of the derived envelope, but the
+    /// `Bcc` header is removed from the message unless [`keep_bcc`](Self::keep_bcc)
+    /// is used.
     pub fn bcc(self, mbox: Mailbox) -> Self {
-        self.mailbox(header::Bcc(mbox.into()))
+        self.check_address(&mbox.email)
+            .mailbox(header::Bcc(mbox.into()))
+    }
+
+    /// Add a group of mailboxes to `To` header
+    ///
+    /// The group is displayed as `name: a, b;`, and its members are added to
+    /// the recipients of the derived envelope. An empty group, like
+    /// `undisclosed-recipients:;`, shows that the recipients are hidden.
+    pub fn to_group(self, group: Group) -> Self {
+        self.group(header::To::name(), group)
+    }
+
+    /// Add a group of mailboxes to `Cc` header
+    ///
+    /// See [`to_group`](Self::to_group).
+    pub fn cc_group(self, group: Group) -> Self {
+        self.group(header::Cc::name(), group)
+    }
+
+    fn group(mut self, name: HeaderName, group: Group) -> Self {
+        for mbox in group.members().iter() {
+            self = self.check_address(&mbox.email);
+        }
+        self.headers
+            .append_addresses(header::group_value(name, &group));
+        self
     }
 
     /// Set or add message id to [`In-Reply-To`
@@ -335,43 +408,184 @@ impl MessageBuilder {
     ///
     /// Should generally be inserted by the mail relay.
     ///
-    /// If `None` is provided, an id will be generated in the
-    /// `<UUID@HOSTNAME>`.
-    pub fn message_id(self, id: Option<String>) -> Self {
+    /// If `None` is provided, an id will be generated when building the
+    /// message, in the `<UUID@DOMAIN>` form, unless a `Message-ID` header
+    /// was set with [`MessageBuilder::header`] in the meantime. The domain is,
+    /// in order of preference, the one set with [`MessageBuilder::message_id_domain`],
+    /// the one of the first `From` address, the hostname of the machine (with the
+    /// `hostname` feature) or `localhost`.
+    pub fn message_id(mut self, id: Option<String>) -> Self {
         match id {
-            Some(i) => self.header(header::MessageId::from(i)),
+            Some(i) => {
+                self.generate_message_id = false;
+                self.header(header::MessageId::from(i))
+            }
             None => {
-                #[cfg(feature = "hostname")]
-                let hostname = hostname::get()
-                    .map_err(|_| ())
-                    .and_then(|s| s.into_string().map_err(|_| ()))
-                    .unwrap_or_else(|_| DEFAULT_MESSAGE_ID_DOMAIN.to_owned());
-                #[cfg(not(feature = "hostname"))]
-                let hostname = DEFAULT_MESSAGE_ID_DOMAIN.to_owned();
-
-                self.header(header::MessageId::from(
-                    // https://tools.ietf.org/html/rfc5322#section-3.6.4
-                    format!("<{}@{}>", make_message_id(), hostname),
-                ))
+                self.generate_message_id = true;
+                self.remove_header::<header::MessageId>()
             }
         }
     }
 
+    /// Set the domain of the `Message-ID` generated by
+    /// [`MessageBuilder::message_id`]
+    ///
+    /// Using a domain of the sender, like `mail.example.com`, rather than the
+    /// default makes the message look less like spam.
+    pub fn message_id_domain(mut self, domain: &str) -> Self {
+        self.message_id_domain = Some(domain.to_owned());
+        self
+    }
+
+    /// Generate the `Message-ID` asked for by [`MessageBuilder::message_id`]
+    fn generated_message_id(&mut self) -> header::MessageId {
+        let from_domain = || {
+            let header::From(from) = self.headers.get::<header::From>()?;
+            let from: Vec<Mailbox> = from.into();
+            Some(from.first()?.email.domain().to_owned())
+        };
+        let domain = self.message_id_domain.clone().or_else(from_domain);
+
+        if let Some(rng) = &mut self.rng {
+            let id = make_message_id_with(rng);
+            let domain = domain.as_deref().unwrap_or(DEFAULT_MESSAGE_ID_DOMAIN);
+            return header::MessageId::from(format!("<{id}@{domain}>"));
+        }
+
+        #[cfg(feature = "hostname")]
+        let domain = domain.or_else(|| hostname::get().ok()?.into_string().ok());
+        let domain = domain.as_deref().unwrap_or(DEFAULT_MESSAGE_ID_DOMAIN);
+
+        // https://tools.ietf.org/html/rfc5322#section-3.6.4
+        header::MessageId::from(format!("<{}@{}>", make_message_id(), domain))
+    }
+
     /// Set [User-Agent
     /// header](https://tools.ietf.org/html/draft-melnikov-email-user-agent-00)
     pub fn user_agent(self, id: String) -> Self {
         self.header(header::UserAgent::from(id))
     }
 
+    /// Set the [`TLS-Required: No`
+    /// header](https://tools.ietf.org/html/rfc8689#section-5)
+    ///
+    /// Asks the servers to deliver the message even if it can't be
+    /// relayed over TLS, for example for messages reporting TLS issues.
+    pub fn tls_not_required(self) -> Self {
+        self.header(header::TlsRequired::from("No".to_owned()))
+    }
+
+    /// Set [`Feedback-ID`
+    /// header](https://support.google.com/mail/answer/6254652)
+    ///
+    /// Used by bulk senders to identify their campaigns in the
+    /// complaint statistics of mailbox providers.
+    ///
+    /// Shortcut for `self.header(id)`.
+    pub fn feedback_id(self, id: header::FeedbackId) -> Self {
+        self.header(id)
+    }
+
+    /// Set [`List-Unsubscribe`
+    /// header](https://tools.ietf.org/html/rfc2369#section-3.2)
+    ///
+    /// `mailto` is the address unsubscribe requests can be sent to, with or without
+    /// the `mailto:` scheme, and `url` an HTTPS URI to unsubscribe. When `url` is set,
+    /// the [`List-Unsubscribe-Post`](https://tools.ietf.org/html/rfc8058#section-3.1)
+    /// header is also set to request one-click unsubscriptions.
+    ///
+    /// Building the message fails if neither is provided.
+    ///
+    /// ```
+    /// # use lettre::Message;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let email = Message::builder()
+    ///     .from("NoBody <nobody@domain.tld>".parse()?)
+    ///     .to("Hei <hei@domain.tld>".parse()?)
+    ///     .list_id("newsletter.domain.tld")
+    ///     .list_unsubscribe(
+    ///         Some("unsubscribe@domain.tld"),
+    ///         Some("https://domain.tld/unsubscribe?id=42"),
+    ///     )
+    ///     .body(String::from("Happy new year!"))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn list_unsubscribe(mut self, mailto: Option<&str>, url: Option<&str>) -> Self {
+        let mailto = mailto.map(|mailto| {
+            if mailto.starts_with("mailto:") {
+                format!("<{mailto}>")
+            } else {
+                format!("<mailto:{mailto}>")
+            }
+        });
+        let url = url.map(|url| format!("<{url}>"));
+
+        self.headers.remove::<header::ListUnsubscribePost>();
+        self.empty_list_unsubscribe = false;
+        match (mailto, url) {
+            (None, None) => {
+                self.headers.remove::<header::ListUnsubscribe>();
+                self.empty_list_unsubscribe = true;
+                self
+            }
+            (Some(mailto), None) => self.header(header::ListUnsubscribe::from(mailto)),
+            (mailto, Some(url)) => {
+                let value = match mailto {
+                    Some(mailto) => format!("{mailto}, {url}"),
+                    None => url,
+                };
+                self.header(header::ListUnsubscribe::from(value)).header(
+                    header::ListUnsubscribePost::from("List-Unsubscribe=One-Click".to_owned()),
+                )
+            }
+        }
+    }
+
+    /// Set [`List-Id` header](https://tools.ietf.org/html/rfc2919#section-2)
+    ///
+    /// `id` is the identifier of the list, like `newsletter.example.com`,
+    /// which gets enclosed in angle brackets.
+    pub fn list_id(self, id: &str) -> Self {
+        self.header(header::ListId::from(format!("<{id}>")))
+    }
+
+    /// Set the priority of the message
+    ///
+    /// Sets the `X-Priority`, `Importance` and `Priority` headers,
+    /// replacing the ones set by a previous call.
+    pub fn priority(mut self, priority: header::Priority) -> Self {
+        for value in priority.headers() {
+            self.headers.insert_raw(value);
+        }
+        self
+    }
+
     /// Set custom header to message
     pub fn header<H: Header>(mut self, header: H) -> Self {
         self.headers.set(header);
         self
     }
 
+    /// Remove a header from the message
+    ///
+    /// Does nothing if the header hasn't been set.
+    pub fn remove_header<H: Header>(mut self) -> Self {
+        self.headers.remove::<H>();
+        self
+    }
+
     /// Add mailbox to header
-    pub fn mailbox<H: Header + MailboxesHeader>(self, header: H) -> Self {
+    pub fn mailbox<H: Header + MailboxesHeader>(mut self, header: H) -> Self {
         match self.headers.get::<H>() {
+            // Setting the joined mailboxes would replace the groups of the
+            // header with their members
+            Some(header_)
+                if self.headers.get_raw(&H::name()) != Some(header_.display().get_raw()) =>
+            {
+                self.headers.append_addresses(header.display());
+                self
+            }
             Some(mut header_) => {
                 header_.join_mailboxes(header);
                 self.header(header_)
@@ -381,7 +595,14 @@ impl MessageBuilder {
     }
 
     /// Force specific envelope (by default it is derived from headers)
+    ///
+    /// If the envelope has an [identifier](Envelope::with_envelope_id) and
+    /// no [`X-Request-Id`](header::XRequestId) header is set, the built
+    /// message gets one with the identifier.
     pub fn envelope(mut self, envelope: Envelope) -> Self {
+        for address in envelope.from().into_iter().chain(envelope.to()) {
+            self = self.check_address(address);
+        }
         self.envelope = Some(envelope);
         self
     }
@@ -400,20 +621,174 @@ impl MessageBuilder {
         self
     }
 
+    /// Don't check the addresses against the SMTP syntax
+    ///
+    /// By default, building the message fails with an error for which
+    /// [`Error::validation`](crate::Error::validation) is `Some` if any address
+    /// passed to [`from`](MessageBuilder::from), [`sender`](MessageBuilder::sender),
+    /// [`reply_to`](MessageBuilder::reply_to), [`to`](MessageBuilder::to),
+    /// [`cc`](MessageBuilder::cc), [`bcc`](MessageBuilder::bcc) or
+    /// [`envelope`](MessageBuilder::envelope) isn't valid according to
+    /// [RFC 5321](https://tools.ietf.org/html/rfc5321#section-4.1.2), which is
+    /// stricter than the RFC 5322 syntax accepted when parsing an [`Address`](crate::Address)
+    /// as it rejects control characters like tabs in quoted local parts.
+    ///
+    /// Calling this method disables the check, for those who need
+    /// full control over the produced message.
+    pub fn skip_address_validation(mut self) -> Self {
+        self.validate_addresses = false;
+        self
+    }
+
+    /// Record `address` to be reported by `build` if it isn't valid for SMTP
+    fn check_address(mut self, address: &Address) -> Self {
+        if address.check_smtp().is_err() && !self.invalid_addresses.contains(address) {
+            self.invalid_addresses.push(address.clone());
+        }
+        self
+    }
+
+    /// Check that the envelope sender is aligned with the `From` header
+    ///
+    /// Under a DMARC policy, a message sent from `bounces@esp.net` with a
+    /// `From: marketing@brand.com` header doesn't pass SPF alignment, and may
+    /// be rejected. With [`DmarcAlignmentPolicy::Warn`] or
+    /// [`DmarcAlignmentPolicy::Reject`], building a message whose
+    /// [`Message::dmarc_alignment`] is [`DmarcAlignment::None`] logs a warning
+    /// or fails. Messages without envelope sender, like bounces, aren't
+    /// checked. The check relies on a heuristic, see [`organizational_domain`].
+    pub fn dmarc_alignment_policy(mut self, policy: DmarcAlignmentPolicy) -> Self {
+        self.dmarc_alignment_policy = policy;
+        self
+    }
+
+    /// Make the built message deterministic
+    ///
+    /// All the randomness used while building the message is derived from `seed`:
+    /// the generated `Message-ID` uses the seeded generator and never the hostname,
+    /// the boundaries of all multiparts are regenerated from it (skipping the ones
+    /// appearing in the content of the parts), except the ones set with
+    /// [`MultiPartBuilder::boundary`](crate::message::MultiPartBuilder::boundary),
+    /// and the `Date` header
+    /// defaults to the UNIX epoch instead of the current time. Building the same message
+    /// twice with the same seed produces byte-identical output, which is useful
+    /// for snapshot testing.
+    pub fn deterministic(mut self, seed: u64) -> Self {
+        self.rng = Some(fastrand::Rng::with_seed(seed));
+        self
+    }
+
     // TODO: High-level methods for attachments and embedded files
 
+    /// Create messages each carrying a chunk of the `content` of `attachment`,
+    /// formatted in at most `max_message_size` bytes
+    ///
+    /// Useful when an attachment is larger than the size accepted by the
+    /// server, like the one advertised with the `SIZE` extension. Every
+    /// message gets the headers of the builder, and an [`X-Part`](header::XPart)
+    /// header with its position in the sequence, as in `X-Part: 1/3`. The
+    /// chunks are encoded in base64, so they're sized for the encoded content
+    /// and the headers to fit in `max_message_size`. The receiver has to put
+    /// the chunks back together.
+    ///
+    /// As all the messages share the headers of the builder, a `Message-ID`
+    /// set on the builder would be the same for all of them, unlike the ones
+    /// generated with `message_id(None)`.
+    ///
+    /// ```rust
+    /// use lettre::message::{header::ContentType, Attachment, Message};
+    ///
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let messages = Message::builder()
+    ///     .from("NoBody <nobody@domain.tld>".parse()?)
+    ///     .to("Hei <hei@domain.tld>".parse()?)
+    ///     .subject("Backup")
+    ///     .split_attachment(
+    ///         Attachment::new("backup.tar".to_owned()),
+    ///         vec![0; 2_500_000],
+    ///         ContentType::parse("application/x-tar")?,
+    ///         1_000_000,
+    ///     )?;
+    /// assert_eq!(messages.len(), 4);
+    /// assert!(messages.iter().all(|m| m.formatted().len() <= 1_000_000));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Fails with a [`ValidationError`](crate::error::ValidationError) if
+    /// `max_message_size` is too small for the headers and any content.
+    pub fn split_attachment(
+        self,
+        attachment: Attachment,
+        content: Vec<u8>,
+        content_type: ContentType,
+        max_message_size: usize,
+    ) -> Result<Vec<Message>, EmailError> {
+        let message = |n: usize, total: usize, chunk: &[u8]| {
+            let body = Body::new_with_encoding(chunk.to_vec(), ContentTransferEncoding::Base64)
+                .expect("base64 can encode any content");
+            self.clone()
+                .header(header::XPart::from(format!("{n}/{total}")))
+                .singlepart(attachment.clone().body(body, content_type.clone()))
+        };
+
+        // There are at most as many messages as bytes of content
+        let most = content.len().max(1);
+        let headers_len = message(most, most, &[])?.formatted().len();
+        let encoded_len = max_message_size.saturating_sub(headers_len);
+        // Each base64 line of 76 characters and its CRLF encode 57 bytes
+        let chunk_len = encoded_len / 78 * 57 + encoded_len % 78 / 4 * 3;
+        if chunk_len == 0 {
+            return Err(error::message_size_too_small());
+        }
+
+        let mut chunks = content.chunks(chunk_len);
+        let total = chunks.len().max(1);
+        (1..=total)
+            .map(|n| message(n, total, chunks.next().unwrap_or_default()))
+            .collect()
+    }
+
     /// Create message from body
-    fn build(self, body: MessageBody) -> Result<Message, EmailError> {
+    fn build(self, mut body: MessageBody) -> Result<Message, EmailError> {
         // Check for missing required headers
         // https://tools.ietf.org/html/rfc5322#section-3.6
 
         // Insert Date if missing
-        let mut res = if self.headers.get::<header::Date>().is_none() {
-            self.date_now()
-        } else {
-            self
+        let mut res = match (self.headers.get::<header::Date>(), &self.rng) {
+            (Some(_), _) => self,
+            (None, Some(_)) => self.date(SystemTime::UNIX_EPOCH),
+            (None, None) => self.date_now(),
         };
 
+        if res.generate_message_id && res.headers.get::<header::MessageId>().is_none() {
+            let id = res.generated_message_id();
+            res.headers.set(id);
+        }
+
+        if let MessageBody::Mime(part) = &body {
+            part.open_lazy_attachments()?;
+        }
+
+        if let MessageBody::Mime(Part::Multi(part)) = &mut body {
+            if let Some(rng) = &mut res.rng {
+                part.regenerate_boundaries(rng);
+            }
+            // The regenerated boundaries never appear in the content,
+            // unlike the random or pinned ones
+            if part.boundary_in_content() {
+                return Err(error::boundary_in_content());
+            }
+        }
+
+        if res.empty_list_unsubscribe {
+            return Err(error::empty_list_unsubscribe());
+        }
+
+        if res.validate_addresses && !res.invalid_addresses.is_empty() {
+            return Err(error::invalid_addresses(res.invalid_addresses));
+        }
+
         // Fail is missing correct originator (Sender or From)
         match res.headers.get::<header::From>() {
             Some(header::From(f)) => {
@@ -432,16 +807,39 @@ impl MessageBuilder {
             None => Envelope::try_from(&res.headers)?,
         };
 
+        if let Some(id) = envelope.envelope_id() {
+            if res.headers.get::<header::XRequestId>().is_none() {
+                res.headers.set(header::XRequestId::from(id.to_owned()));
+            }
+        }
+
         if res.drop_bcc {
             // Remove `Bcc` headers now the envelope is set
             res.headers.remove::<header::Bcc>();
         }
 
-        Ok(Message {
+        let policy = res.dmarc_alignment_policy;
+        let message = Message {
             headers: res.headers,
             body,
             envelope,
-        })
+        };
+        // A null reverse-path has no domain to align
+        if policy != DmarcAlignmentPolicy::Ignore
+            && message.envelope.from().is_some()
+            && message.dmarc_alignment() == DmarcAlignment::None
+        {
+            if policy == DmarcAlignmentPolicy::Reject {
+                return Err(error::dmarc_misaligned());
+            }
+            #[cfg(feature = "tracing")]
+            tracing::warn!("the envelope sender isn't aligned with the From header");
+        }
+        #[cfg(feature = "tracing")]
+        for warning in message.cid_warnings() {
+            tracing::warn!("{warning}");
+        }
+        Ok(message)
     }
 
     /// Create [`Message`] using a [`Vec<u8>`], [`String`], or [`Body`] body
@@ -458,6 +856,9 @@ impl MessageBuilder {
     }
 
     /// Create message using mime body ([`MultiPart`][self::MultiPart])
+    ///
+    /// Fails if the boundary of a multipart appears in the content of its parts,
+    /// which can only happen with a boundary set by [`MultiPartBuilder::boundary`].
     pub fn multipart(self, part: MultiPart) -> Result<Message, EmailError> {
         self.mime_1_0().build(MessageBody::Mime(Part::Multi(part)))
     }
@@ -513,21 +914,202 @@ impl Message {
         &self.envelope
     }
 
+    /// Returns a copy of the message sent with `envelope`
+    pub(crate) fn with_envelope(&self, envelope: Envelope) -> Message {
+        Message {
+            headers: self.headers.clone(),
+            body: self.body.clone(),
+            envelope,
+        }
+    }
+
+    /// Get the raw value of the `Subject` header, if present
+    pub fn subject(&self) -> Option<&str> {
+        self.headers.get_raw(&header::Subject::name())
+    }
+
+    /// Compares the domain of the envelope sender with the one of the `From` header
+    ///
+    /// Uses [`organizational_domain`] to tell whether different domains are
+    /// related, which is wrong for multi-label public suffixes like `co.uk`.
+    /// Returns [`DmarcAlignment::None`] for a message without envelope sender,
+    /// like a bounce.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// use lettre::{
+    ///     address::Envelope,
+    ///     message::{DmarcAlignment, Message},
+    ///     Address,
+    /// };
+    ///
+    /// let m = Message::builder()
+    ///     .from("Brand <marketing@brand.com>".parse()?)
+    ///     .to("Hei <hei@domain.tld>".parse()?)
+    ///     .envelope(Envelope::new(
+    ///         Some("bounces@mail.brand.com".parse()?),
+    ///         vec!["hei@domain.tld".parse::<Address>()?],
+    ///     )?)
+    ///     .body(String::from("Sale!"))?;
+    /// assert_eq!(m.dmarc_alignment(), DmarcAlignment::Relaxed);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn dmarc_alignment(&self) -> DmarcAlignment {
+        self.dmarc_alignment_with(organizational_domain)
+    }
+
+    /// Compares the domain of the envelope sender with the one of the `From`
+    /// header, using `organizational_domain` to tell whether different domains
+    /// are related
+    ///
+    /// The rule can be a closure, for example using a list of public suffixes.
+    /// See [`Message::dmarc_alignment`].
+    pub fn dmarc_alignment_with<F>(&self, organizational_domain: F) -> DmarcAlignment
+    where
+        F: Fn(&str) -> &str,
+    {
+        let from = self
+            .headers
+            .get::<header::From>()
+            .and_then(|header::From(from)| from.into_iter().next());
+        match (from, self.envelope.from()) {
+            (Some(from), Some(sender)) => {
+                dmarc::alignment(from.email.domain(), sender.domain(), &organizational_domain)
+            }
+            _ => DmarcAlignment::None,
+        }
+    }
+
+    /// Compares the `cid:` URLs in the HTML parts of the message
+    /// with the `Content-ID`s of its parts
+    ///
+    /// Returns the references to missing parts, followed by the parts which
+    /// aren't referenced. These are also logged when the message is built,
+    /// with the `tracing` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// use lettre::message::{CidWarning, Message, MultiPart, SinglePart};
+    ///
+    /// let m = Message::builder()
+    ///     .from("NoBody <nobody@domain.tld>".parse()?)
+    ///     .to("Hei <hei@domain.tld>".parse()?)
+    ///     .subject("Happy new year")
+    ///     .multipart(MultiPart::related().singlepart(SinglePart::html(String::from(
+    ///         "<img src=\"cid:logo\">",
+    ///     ))))?;
+    /// assert_eq!(
+    ///     m.cid_warnings(),
+    ///     [CidWarning::MissingPart(String::from("logo"))]
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn cid_warnings(&self) -> Vec<CidWarning> {
+        match &self.body {
+            MessageBody::Mime(part) => part.cid_warnings(),
+            MessageBody::Raw(_) => Vec::new(),
+        }
+    }
+
     /// Get message content formatted for SMTP
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file of a [`LazyAttachment`] can't be read.
+    /// Use [`Message::write_to`] to handle the error instead.
     pub fn formatted(&self) -> Vec<u8> {
         let mut out = Vec::new();
         self.format(&mut out);
         out
     }
 
-    #[cfg(feature = "dkim")]
-    /// Format body for signing
-    pub(crate) fn body_raw(&self) -> Vec<u8> {
+    /// Get message content formatted for SMTP, failing if the file of a
+    /// [`LazyAttachment`] can't be read
+    pub(crate) fn try_formatted(&self) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        self.write_to(&mut out)?;
+        Ok(out)
+    }
+
+    /// Write the message content formatted for SMTP to `out`
+    ///
+    /// Unlike [`Message::formatted`], the content of [`LazyAttachment`]s
+    /// is read and written in small chunks.
+    pub fn write_to<W: Write + ?Sized>(&self, out: &mut W) -> io::Result<()> {
+        io::copy(&mut self.reader(), out).map(drop)
+    }
+
+    /// Get a reader of the message content formatted for SMTP
+    ///
+    /// The message is formatted while being read, so that large
+    /// emails don't have to be held in memory: the content of
+    /// [`LazyAttachment`]s is read and encoded in small chunks.
+    ///
+    /// ```rust
+    /// # use std::{error::Error, io::Read};
+    /// # use lettre::Message;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let message = Message::builder()
+    ///     .from("NoBody <nobody@domain.tld>".parse()?)
+    ///     .to("Hei <hei@domain.tld>".parse()?)
+    ///     .body(String::from("Happy new year!"))?;
+    ///
+    /// let mut formatted = Vec::new();
+    /// message.reader().read_to_end(&mut formatted)?;
+    /// assert_eq!(formatted, message.formatted());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reader(&self) -> MessageReader<'_> {
+        MessageReader::new(self)
+    }
+
+    /// Length of the message content formatted for SMTP, without reading
+    /// the content of lazy attachments
+    #[cfg(feature = "smtp-transport")]
+    pub(crate) fn formatted_len(&self) -> io::Result<u64> {
+        self.reader().formatted_len()
+    }
+
+    /// Whether the formatted message only contains ASCII characters,
+    /// without reading the content of lazy attachments
+    #[cfg(feature = "smtp-transport")]
+    pub(crate) fn is_ascii(&self) -> bool {
+        self.headers.to_string().is_ascii()
+            && match &self.body {
+                MessageBody::Mime(p) => p.is_ascii(),
+                MessageBody::Raw(r) => r.is_ascii(),
+            }
+    }
+
+    /// Get the message body formatted for SMTP, without the message headers
+    ///
+    /// The body is returned already encoded with its `Content-Transfer-Encoding`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file of a [`LazyAttachment`] can't be read.
+    pub fn formatted_body(&self) -> Vec<u8> {
         let mut out = Vec::new();
         match &self.body {
             MessageBody::Mime(p) => p.format(&mut out),
             MessageBody::Raw(r) => out.extend_from_slice(r),
         };
+        out
+    }
+
+    #[cfg(feature = "dkim")]
+    /// Format body for signing
+    pub(crate) fn body_raw(&self) -> Vec<u8> {
+        let mut out = self.formatted_body();
         out.extend_from_slice(b"\r\n");
         out
     }
@@ -594,16 +1176,8 @@ impl Message {
 
 impl EmailFormat for Message {
     fn format(&self, out: &mut Vec<u8>) {
-        write!(out, "{}", self.headers)
-            .expect("A Write implementation panicked while formatting headers");
-
-        match &self.body {
-            MessageBody::Mime(p) => p.format(out),
-            MessageBody::Raw(r) => {
-                out.extend_from_slice(b"\r\n");
-                out.extend_from_slice(r)
-            }
-        }
+        self.write_to(out)
+            .expect("failed to read the content of a lazy attachment");
     }
 }
 
@@ -615,17 +1189,28 @@ impl Default for MessageBuilder {
 
 /// Create a random message id.
 /// (Not cryptographically random)
-fn make_message_id() -> String {
+pub(crate) fn make_message_id() -> String {
     iter::repeat_with(fastrand::alphanumeric).take(36).collect()
 }
 
+/// Create a message id using the provided random number generator.
+fn make_message_id_with(rng: &mut fastrand::Rng) -> String {
+    iter::repeat_with(|| rng.alphanumeric()).take(36).collect()
+}
+
 #[cfg(test)]
 mod test {
     use std::time::{Duration, SystemTime};
 
     use pretty_assertions::assert_eq;
 
-    use super::{header, mailbox::Mailbox, make_message_id, Message, MultiPart, SinglePart};
+    use super::{
+        header::{self, HeaderName, HeaderValue},
+        mailbox::Mailbox,
+        make_message_id, Attachment, CidWarning, DmarcAlignment, DmarcAlignmentPolicy, Message,
+        MultiPart, SinglePart,
+    };
+    use crate::{address::Envelope, Error};
 
     #[test]
     fn email_missing_originator() {
@@ -643,6 +1228,77 @@ mod test {
             .is_ok());
     }
 
+    #[test]
+    fn email_request_id_from_envelope() {
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap()
+        .with_envelope_id("req-42")
+        .unwrap();
+        let builder = || {
+            Message::builder()
+                .from("NoBody <nobody@domain.tld>".parse().unwrap())
+                .to("Hei <hei@domain.tld>".parse().unwrap())
+        };
+
+        let email = builder()
+            .envelope(envelope.clone())
+            .body(String::from("Happy new year!"))
+            .unwrap();
+        let id = email.headers().get::<header::XRequestId>().unwrap();
+        assert_eq!(id.as_ref(), "req-42");
+
+        // An existing header is kept
+        let email = builder()
+            .header(header::XRequestId::from(String::from("other")))
+            .envelope(envelope)
+            .body(String::from("Happy new year!"))
+            .unwrap();
+        let id = email.headers().get::<header::XRequestId>().unwrap();
+        assert_eq!(id.as_ref(), "other");
+
+        // Without an envelope identifier, no header is added
+        let email = builder().body(String::from("Happy new year!")).unwrap();
+        assert!(email.headers().get::<header::XRequestId>().is_none());
+    }
+
+    #[test]
+    fn email_groups() {
+        let team = "Team: Alice <alice@example.com>, bob@example.com;"
+            .parse()
+            .unwrap();
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .to_group(team)
+            .to("carol@example.com".parse().unwrap())
+            .cc_group("undisclosed-recipients:;".parse().unwrap())
+            .body(String::from("Happy new year!"))
+            .unwrap();
+
+        let formatted = String::from_utf8(email.formatted()).unwrap();
+        assert!(
+            formatted.contains(concat!(
+                "To: Hei <hei@domain.tld>,\r\n",
+                " Team: Alice <alice@example.com>, bob@example.com;,\r\n",
+                " carol@example.com\r\n",
+                "Cc: undisclosed-recipients:;\r\n",
+            )),
+            "{formatted}"
+        );
+        assert_eq!(
+            email.envelope().to(),
+            [
+                "hei@domain.tld".parse().unwrap(),
+                "alice@example.com".parse().unwrap(),
+                "bob@example.com".parse().unwrap(),
+                "carol@example.com".parse().unwrap(),
+            ]
+        );
+    }
+
     #[test]
     fn email_missing_sender() {
         assert!(Message::builder()
@@ -652,6 +1308,45 @@ mod test {
             .is_err());
     }
 
+    #[test]
+    fn email_originator_and_recipients() {
+        let builder = || {
+            Message::builder()
+                .from("NoBody <nobody@domain.tld>".parse().unwrap())
+                .to("Hei <hei@domain.tld>".parse().unwrap())
+        };
+
+        let err = Message::builder()
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .body(String::from("Happy new year!"))
+            .unwrap_err();
+        assert!(matches!(err, Error::MissingFrom), "{err:?}");
+
+        let err = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .body(String::from("Happy new year!"))
+            .unwrap_err();
+        assert!(matches!(err, Error::MissingTo), "{err:?}");
+
+        let err = builder()
+            .from("AnyBody <anybody@domain.tld>".parse().unwrap())
+            .body(String::from("Happy new year!"))
+            .unwrap_err();
+        assert!(matches!(err, Error::TooManyFrom), "{err:?}");
+
+        // Several authors are allowed with a single sender, which is the envelope sender
+        let email = builder()
+            .from("AnyBody <anybody@domain.tld>".parse().unwrap())
+            .sender("Secretary <secretary@domain.tld>".parse().unwrap())
+            .body(String::from("Happy new year!"))
+            .unwrap();
+        assert_eq!(
+            email.envelope().from(),
+            Some(&"secretary@domain.tld".parse().unwrap())
+        );
+        assert_eq!(email.envelope().to(), ["hei@domain.tld".parse().unwrap()]);
+    }
+
     #[test]
     fn email_message_no_bcc() {
         // Tue, 15 Nov 1994 08:12:31 GMT
@@ -726,6 +1421,353 @@ mod test {
         );
     }
 
+    #[test]
+    fn email_replace_and_remove_headers() {
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Pony O.P. <pony@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("a")
+            .subject("b")
+            .user_agent(String::from("lettre"))
+            .remove_header::<header::UserAgent>()
+            .body(String::from("Happy new year!"))
+            .unwrap();
+
+        let formatted = String::from_utf8(email.formatted()).unwrap();
+        assert_eq!(formatted.matches("Subject: ").count(), 1);
+        assert!(formatted.contains("Subject: b\r\n"));
+        assert!(!formatted.contains("User-Agent"));
+        assert!(formatted.contains("To: \"Pony O.P.\" <pony@domain.tld>, Hei <hei@domain.tld>\r\n"));
+    }
+
+    #[test]
+    fn email_tls_not_required() {
+        let email = Message::builder()
+            .from("Alice <alice@example.org>".parse().unwrap())
+            .to("Bob <bob@example.org>".parse().unwrap())
+            .date(SystemTime::UNIX_EPOCH)
+            .tls_not_required()
+            .body(String::from("TLS report"))
+            .unwrap();
+
+        assert_eq!(email.headers().get_raw("TLS-Required"), Some("No"),);
+        assert!(String::from_utf8(email.formatted())
+            .unwrap()
+            .contains("\r\nTLS-Required: No\r\n"));
+    }
+
+    #[test]
+    fn email_plus_addressing() {
+        let email = Message::builder()
+            .from("Alice <alice+news@example.org>".parse().unwrap())
+            .to("bob+inbox@example.org".parse().unwrap())
+            .body(String::from("Hello"))
+            .unwrap();
+
+        assert_eq!(
+            email.envelope().to(),
+            &["bob+inbox@example.org".parse().unwrap()]
+        );
+    }
+
+    #[test]
+    fn email_address_with_newline() {
+        assert!("\"bob\r\nbcc\"@example.org".parse::<Mailbox>().is_err());
+        assert!("bob@example.org\r\n".parse::<Mailbox>().is_err());
+        assert!("bob@exam\nple.org".parse::<Mailbox>().is_err());
+    }
+
+    #[test]
+    fn email_invalid_addresses() {
+        // The mailbox parser already rejects those, but not the RFC 5322 address parser
+        let tab = Mailbox::new(None, "\"bob\tbcc\"@example.org".parse().unwrap());
+        let other = Mailbox::new(None, "\"da\tve\"@example.org".parse().unwrap());
+
+        let err = Message::builder()
+            .from("alice@example.org".parse().unwrap())
+            .to(tab.clone())
+            .cc("carol@example.org".parse().unwrap())
+            .bcc(other.clone())
+            .body(String::from("Hello"))
+            .unwrap_err();
+
+        match err.validation() {
+            Some(err) => assert_eq!(
+                err.invalid_addresses(),
+                Some(&[tab.email.clone(), other.email][..])
+            ),
+            None => panic!("unexpected error: {err}"),
+        }
+
+        let email = Message::builder()
+            .from("alice@example.org".parse().unwrap())
+            .to(tab)
+            .cc("carol@example.org".parse().unwrap())
+            .skip_address_validation()
+            .body(String::from("Hello"))
+            .unwrap();
+        assert!(String::from_utf8(email.formatted())
+            .unwrap()
+            .contains("\r\nTo: \"bob\tbcc\"@example.org\r\n"));
+    }
+
+    #[test]
+    fn email_multiple_recipients() {
+        let email = Message::builder()
+            .from("alice@example.org".parse().unwrap())
+            .to("a@example.org".parse().unwrap())
+            .to("B <b@example.org>".parse().unwrap())
+            .cc("e@example.org".parse().unwrap())
+            .to("c@example.org".parse().unwrap())
+            .cc("D <d@example.org>".parse().unwrap())
+            .body(String::from("Hello"))
+            .unwrap();
+
+        let formatted = String::from_utf8(email.formatted()).unwrap();
+        assert_eq!(formatted.matches("\r\nTo: ").count(), 1, "{formatted}");
+        assert_eq!(formatted.matches("\r\nCc: ").count(), 1, "{formatted}");
+        assert!(
+            formatted.contains("\r\nTo: a@example.org, B <b@example.org>, c@example.org\r\n"),
+            "{formatted}"
+        );
+        assert!(
+            formatted.contains("\r\nCc: e@example.org, D <d@example.org>\r\n"),
+            "{formatted}"
+        );
+        assert_eq!(email.envelope().to().len(), 5);
+    }
+
+    #[test]
+    fn email_recipients_merged_with_mailboxes_header() {
+        let email = Message::builder()
+            .from("alice@example.org".parse().unwrap())
+            .mailbox(header::To(
+                "a@example.org, B <b@example.org>".parse().unwrap(),
+            ))
+            .to("c@example.org".parse().unwrap())
+            .bcc("d@example.org".parse().unwrap())
+            .bcc("e@example.org".parse().unwrap())
+            .body(String::from("Hello"))
+            .unwrap();
+
+        let formatted = String::from_utf8(email.formatted()).unwrap();
+        assert_eq!(formatted.matches("\r\nTo: ").count(), 1, "{formatted}");
+        assert!(
+            formatted.contains("\r\nTo: a@example.org, B <b@example.org>, c@example.org\r\n"),
+            "{formatted}"
+        );
+        assert!(!formatted.contains("Bcc:"), "{formatted}");
+
+        let to = email
+            .envelope()
+            .to()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            to,
+            [
+                "a@example.org",
+                "b@example.org",
+                "c@example.org",
+                "d@example.org",
+                "e@example.org"
+            ]
+        );
+    }
+
+    #[test]
+    fn email_header_injection() {
+        let mut email = Message::builder()
+            .from(
+                ("Alice\r\nBcc: evil@example.com", "a@example.org")
+                    .try_into()
+                    .unwrap(),
+            )
+            .to("Bob <bob@example.org>".parse().unwrap())
+            .subject("Hello\r\nBcc: evil@example.com")
+            .body(String::from("Hello"))
+            .unwrap();
+        email.headers_mut().insert_raw(HeaderValue::new(
+            HeaderName::new_from_ascii_str("X-Custom"),
+            "value\nBcc: evil@example.com".to_owned(),
+        ));
+
+        let formatted = String::from_utf8(email.formatted()).unwrap();
+        assert!(!formatted.contains("\nBcc:"), "{formatted}");
+        assert!(formatted.contains("\r\nSubject: =?utf-8?"), "{formatted}");
+        assert!(formatted.contains("\r\nX-Custom: =?utf-8?"), "{formatted}");
+        assert_eq!(
+            email.headers().get::<header::From>().unwrap().0,
+            Mailbox::new(
+                Some("Alice Bcc: evil@example.com".to_owned()),
+                "a@example.org".parse().unwrap()
+            )
+            .into()
+        );
+        assert_eq!(email.envelope().to().len(), 1);
+    }
+
+    #[test]
+    fn email_list_headers() {
+        let build = |mailto, url| {
+            let email = Message::builder()
+                .from("alice@example.org".parse().unwrap())
+                .to("bob@example.org".parse().unwrap())
+                .list_id("newsletter.example.org")
+                .list_unsubscribe(mailto, url)
+                .body(String::from("Hello"))
+                .unwrap();
+            String::from_utf8(email.formatted()).unwrap()
+        };
+
+        let formatted = build(
+            Some("unsubscribe@example.org"),
+            Some("https://example.org/unsubscribe?id=42"),
+        );
+        assert!(
+            formatted.contains("\r\nList-Id: <newsletter.example.org>\r\n"),
+            "{formatted}"
+        );
+        assert!(
+            formatted.contains(concat!(
+                "\r\nList-Unsubscribe: <mailto:unsubscribe@example.org>,\r\n",
+                " <https://example.org/unsubscribe?id=42>\r\n"
+            )),
+            "{formatted}"
+        );
+        assert!(
+            formatted.contains("\r\nList-Unsubscribe-Post: List-Unsubscribe=One-Click\r\n"),
+            "{formatted}"
+        );
+
+        let formatted = build(Some("mailto:unsubscribe@example.org?subject=stop"), None);
+        assert!(
+            formatted.contains(
+                "\r\nList-Unsubscribe: <mailto:unsubscribe@example.org?subject=stop>\r\n"
+            ),
+            "{formatted}"
+        );
+        assert!(!formatted.contains("List-Unsubscribe-Post"), "{formatted}");
+
+        let formatted = build(None, Some("https://example.org/unsubscribe"));
+        assert!(
+            formatted.contains("\r\nList-Unsubscribe: <https://example.org/unsubscribe>\r\n"),
+            "{formatted}"
+        );
+        assert!(formatted.contains("List-Unsubscribe-Post"), "{formatted}");
+
+        assert!(matches!(
+            Message::builder()
+                .from("alice@example.org".parse().unwrap())
+                .to("bob@example.org".parse().unwrap())
+                .list_unsubscribe(None, None)
+                .body(String::from("Hello")),
+            Err(err) if err.validation().is_some_and(|err| err.is_empty_list_unsubscribe())
+        ));
+    }
+
+    #[test]
+    fn email_priority() {
+        let email = Message::builder()
+            .from("alice@example.org".parse().unwrap())
+            .to("bob@example.org".parse().unwrap())
+            .priority(header::Priority::Low)
+            .priority(header::Priority::High)
+            .body(String::from("Hello"))
+            .unwrap();
+
+        let formatted = String::from_utf8(email.formatted()).unwrap();
+        assert!(
+            formatted.contains("\r\nX-Priority: 1 (Highest)\r\n"),
+            "{formatted}"
+        );
+        assert!(
+            formatted.contains("\r\nImportance: High\r\n"),
+            "{formatted}"
+        );
+        assert!(
+            formatted.contains("\r\nPriority: urgent\r\n"),
+            "{formatted}"
+        );
+        assert_eq!(formatted.matches("Priority: ").count(), 2, "{formatted}");
+    }
+
+    #[test]
+    fn email_feedback_id() {
+        let email = Message::builder()
+            .from("Alice <alice@example.org>".parse().unwrap())
+            .to("Bob <bob@example.org>".parse().unwrap())
+            .feedback_id("campaign42:customer7:newsletter:lettre".parse().unwrap())
+            .body(String::from("News"))
+            .unwrap();
+
+        assert!(String::from_utf8(email.formatted())
+            .unwrap()
+            .contains("\r\nFeedback-ID: campaign42:customer7:newsletter:lettre\r\n"));
+    }
+
+    #[test]
+    fn email_accessors() {
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .bcc("hidden@example.com".parse().unwrap())
+            .subject("яңа ел белән!")
+            .body(String::from("Happy new year!"))
+            .unwrap();
+
+        assert_eq!(email.subject(), Some("яңа ел белән!"));
+        assert_eq!(email.formatted_body(), b"Happy new year!");
+        assert_eq!(
+            email.envelope().from(),
+            Some(&"nobody@domain.tld".parse().unwrap())
+        );
+        assert_eq!(
+            email.envelope().to(),
+            [
+                "hei@domain.tld".parse().unwrap(),
+                "hidden@example.com".parse().unwrap()
+            ]
+        );
+        assert_eq!(
+            email.headers().get::<header::To>(),
+            Some(header::To(
+                vec!["Hei <hei@domain.tld>".parse().unwrap()].into()
+            ))
+        );
+
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .body(String::from("Happy new year!"))
+            .unwrap();
+        assert_eq!(email.subject(), None);
+    }
+
+    #[test]
+    fn email_non_ascii_headers() {
+        let email = Message::builder()
+            .from("Jörg <joerg@example.com>".parse().unwrap())
+            .to("Rübezahl <ruebezahl@example.com>".parse().unwrap())
+            .subject("Rübezahl grüßt alle Wanderer im Riesengebirge, die über den Kamm ziehen")
+            .body(String::from("Glück auf!"))
+            .unwrap();
+
+        let formatted = String::from_utf8(email.formatted()).unwrap();
+        let (headers, _) = formatted.split_once("\r\n\r\n").unwrap();
+        assert!(headers.is_ascii());
+        assert!(headers.contains("From: =?utf-8?b?SsO2cmc=?= <joerg@example.com>\r\n"));
+        assert!(headers.contains("To: =?utf-8?b?UsO8YmV6YWhs?= <ruebezahl@example.com>\r\n"));
+        for line in headers.split("\r\n") {
+            assert!(line.len() <= 78, "line too long: {line:?}");
+            for word in line.split(' ').filter(|word| word.starts_with("=?")) {
+                assert!(word.len() <= 75, "encoded-word too long: {word:?}");
+            }
+        }
+    }
+
     #[test]
     fn email_with_png() {
         // Tue, 15 Nov 1994 08:12:31 GMT
@@ -769,6 +1811,391 @@ mod test {
         }
     }
 
+    #[test]
+    fn email_deterministic() {
+        let build = |seed| {
+            Message::builder()
+                .deterministic(seed)
+                .message_id(None)
+                .from("NoBody <nobody@domain.tld>".parse().unwrap())
+                .to("Hei <hei@domain.tld>".parse().unwrap())
+                .subject("Happy new year")
+                .multipart(
+                    MultiPart::mixed()
+                        .multipart(MultiPart::alternative_plain_html(
+                            String::from("Hello, world! :)"),
+                            String::from("<p><b>Hello</b>, <i>world</i>!</p>"),
+                        ))
+                        .singlepart(SinglePart::plain(String::from("Attached"))),
+                )
+                .unwrap()
+                .formatted()
+        };
+
+        let first = build(42);
+        assert_eq!(
+            String::from_utf8(first.clone()).unwrap(),
+            String::from_utf8(build(42)).unwrap()
+        );
+        assert_ne!(first, build(43));
+
+        let first = String::from_utf8(first).unwrap();
+        assert!(first.contains("Date: Thu, 01 Jan 1970 00:00:00 +0000\r\n"));
+        assert!(first.contains("@domain.tld>\r\n"));
+    }
+
+    #[test]
+    fn email_deterministic_boundary_in_content() {
+        let build = |body: &str| {
+            let email = Message::builder()
+                .deterministic(42)
+                .from("NoBody <nobody@domain.tld>".parse().unwrap())
+                .to("Hei <hei@domain.tld>".parse().unwrap())
+                .multipart(MultiPart::mixed().singlepart(SinglePart::plain(body.to_owned())))
+                .unwrap();
+            let formatted = String::from_utf8(email.formatted()).unwrap();
+            let boundary = formatted
+                .split("boundary=\"")
+                .nth(1)
+                .and_then(|rest| rest.split('"').next())
+                .unwrap()
+                .to_owned();
+            (formatted, boundary)
+        };
+
+        let (_, boundary) = build("Hello");
+        assert_eq!(build("Hello").1, boundary);
+
+        let forged = format!("--{boundary}\r\nContent-Type: text/html\r\n\r\nForged");
+        let (formatted, other) = build(&forged);
+        assert_ne!(other, boundary);
+        assert_eq!(formatted.matches(&other).count(), 3, "{formatted}");
+        assert_eq!(build(&forged).1, other);
+    }
+
+    #[test]
+    fn email_fixed_boundary() {
+        let build = |body: &str| {
+            Message::builder()
+                .message_id(Some("<fixed@domain.tld>".to_owned()))
+                .date(SystemTime::UNIX_EPOCH)
+                .from("NoBody <nobody@domain.tld>".parse().unwrap())
+                .to("Hei <hei@domain.tld>".parse().unwrap())
+                .multipart(
+                    MultiPart::mixed()
+                        .boundary("fixed-boundary")
+                        .multipart(
+                            MultiPart::alternative()
+                                .boundary("nested-boundary")
+                                .singlepart(SinglePart::plain(body.to_owned())),
+                        )
+                        .singlepart(SinglePart::plain(String::from("Attached"))),
+                )
+        };
+
+        let first = build("Hello").unwrap().formatted();
+        assert_eq!(first, build("Hello").unwrap().formatted());
+        assert!(String::from_utf8(first)
+            .unwrap()
+            .contains("boundary=\"fixed-boundary\""));
+
+        assert!(matches!(
+            build("--fixed-boundary--"),
+            Err(err) if err.validation().is_some_and(|err| err.is_boundary_in_content())
+        ));
+        assert!(matches!(
+            build("--nested-boundary--"),
+            Err(err) if err.validation().is_some_and(|err| err.is_boundary_in_content())
+        ));
+    }
+
+    #[test]
+    fn email_deterministic_fixed_boundary() {
+        let build = |body: &str| {
+            Message::builder()
+                .deterministic(42)
+                .from("NoBody <nobody@domain.tld>".parse().unwrap())
+                .to("Hei <hei@domain.tld>".parse().unwrap())
+                .multipart(
+                    MultiPart::mixed()
+                        .boundary("fixed")
+                        .multipart(
+                            MultiPart::alternative().singlepart(SinglePart::plain(body.to_owned())),
+                        )
+                        .singlepart(SinglePart::plain(String::from("Attached"))),
+                )
+        };
+
+        let formatted = String::from_utf8(build("Hello").unwrap().formatted()).unwrap();
+        assert!(formatted.contains("boundary=\"fixed\""), "{formatted}");
+        assert_eq!(formatted.matches("--fixed\r\n").count(), 2, "{formatted}");
+        // The nested boundary is still generated from the seed
+        let nested = formatted
+            .split("multipart/alternative;")
+            .nth(1)
+            .and_then(|rest| rest.split("boundary=\"").nth(1))
+            .and_then(|rest| rest.split('"').next())
+            .unwrap();
+        assert_ne!(nested, "fixed");
+        assert_eq!(
+            formatted,
+            String::from_utf8(build("Hello").unwrap().formatted()).unwrap()
+        );
+
+        assert!(matches!(
+            build("--fixed--"),
+            Err(err) if err.validation().is_some_and(|err| err.is_boundary_in_content())
+        ));
+    }
+
+    #[test]
+    fn email_dmarc_alignment() {
+        fn message(sender: &str, policy: DmarcAlignmentPolicy) -> Result<Message, Error> {
+            Message::builder()
+                .from("Brand <marketing@brand.com>".parse().unwrap())
+                .to("Hei <hei@domain.tld>".parse().unwrap())
+                .envelope(
+                    Envelope::new(
+                        Some(sender.parse().unwrap()),
+                        vec!["hei@domain.tld".parse().unwrap()],
+                    )
+                    .unwrap(),
+                )
+                .dmarc_alignment_policy(policy)
+                .body(String::from("Sale!"))
+        }
+
+        let exact = message("bounces@brand.com", DmarcAlignmentPolicy::Reject).unwrap();
+        assert_eq!(exact.dmarc_alignment(), DmarcAlignment::Strict);
+        let subdomain = message("bounces@mail.brand.com", DmarcAlignmentPolicy::Reject).unwrap();
+        assert_eq!(subdomain.dmarc_alignment(), DmarcAlignment::Relaxed);
+
+        let mismatch = message("bounces@esp.net", DmarcAlignmentPolicy::Warn).unwrap();
+        assert_eq!(mismatch.dmarc_alignment(), DmarcAlignment::None);
+        assert!(matches!(
+            message("bounces@esp.net", DmarcAlignmentPolicy::Reject),
+            Err(err) if err.validation().is_some_and(|err| err.is_dmarc_misaligned())
+        ));
+
+        // A rule capturing its configuration
+        fn under(org: String) -> impl Fn(&str) -> &str {
+            move |domain| match domain.strip_suffix(org.as_str()) {
+                Some(sub) if sub.ends_with('.') => &domain[sub.len()..],
+                _ => domain,
+            }
+        }
+        assert_eq!(
+            subdomain.dmarc_alignment_with(under(String::from("brand.com"))),
+            DmarcAlignment::Relaxed
+        );
+        assert_eq!(
+            mismatch.dmarc_alignment_with(under(String::from("brand.com"))),
+            DmarcAlignment::None
+        );
+
+        // A bounce has no envelope sender to align
+        let bounce = Message::builder()
+            .from("Mailer <mailer-daemon@brand.com>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .envelope(Envelope::new(None, vec!["hei@domain.tld".parse().unwrap()]).unwrap())
+            .dmarc_alignment_policy(DmarcAlignmentPolicy::Reject)
+            .body(String::from("Undeliverable"))
+            .unwrap();
+        assert_eq!(bounce.dmarc_alignment(), DmarcAlignment::None);
+
+        // The envelope derived from the headers is always aligned
+        let derived = Message::builder()
+            .from("Brand <marketing@brand.com>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .dmarc_alignment_policy(DmarcAlignmentPolicy::Reject)
+            .body(String::from("Sale!"))
+            .unwrap();
+        assert_eq!(derived.dmarc_alignment(), DmarcAlignment::Strict);
+    }
+
+    #[test]
+    fn email_cid_warnings() {
+        let image = |id: &str| {
+            Attachment::new_inline(id.to_owned())
+                .body(b"<image>".to_vec(), "image/png".parse().unwrap())
+        };
+        let build = |resources: Vec<SinglePart>| {
+            let html = r#"<img src="cid:logo"><div style="background: url(cid:banner)"></div>"#;
+            Message::builder()
+                .from("NoBody <nobody@domain.tld>".parse().unwrap())
+                .to("Hei <hei@domain.tld>".parse().unwrap())
+                .multipart(MultiPart::related_html(html.to_owned(), resources))
+                .unwrap()
+        };
+
+        let m = build(vec![image("banner"), image("footer")]);
+        assert_eq!(
+            m.cid_warnings(),
+            [
+                CidWarning::MissingPart("logo".to_owned()),
+                CidWarning::UnreferencedPart("footer".to_owned()),
+            ]
+        );
+        assert_eq!(
+            m.cid_warnings()[0].to_string(),
+            "no part has the content ID of cid:logo"
+        );
+
+        let m = build(vec![image("banner"), image("logo")]);
+        assert!(m.cid_warnings().is_empty());
+
+        // References are found in encoded HTML
+        let html = SinglePart::builder()
+            .header(header::ContentType::TEXT_HTML)
+            .header(header::ContentTransferEncoding::Base64)
+            .body(String::from(r#"<img src='cid:logo'>"#));
+        let m = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .multipart(MultiPart::related().singlepart(html))
+            .unwrap();
+        assert_eq!(
+            m.cid_warnings(),
+            [CidWarning::MissingPart("logo".to_owned())]
+        );
+    }
+
+    #[test]
+    fn email_split_attachment() {
+        const MB: usize = 1024 * 1024;
+
+        let content: Vec<u8> = (0..3 * MB).map(|i| (i / MB) as u8 + b'a').collect();
+        let content_type = header::ContentType::parse("application/octet-stream").unwrap();
+        let messages = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Backup")
+            .split_attachment(
+                Attachment::new("backup.bin".to_owned()),
+                content.clone(),
+                content_type.clone(),
+                MB,
+            )
+            .unwrap();
+
+        // The chunks are encoded in base64
+        assert_eq!(messages.len(), 5);
+        let mut joined = Vec::new();
+        for (n, message) in messages.iter().enumerate() {
+            let x_part = message.headers().get::<header::XPart>().unwrap();
+            assert_eq!(x_part.as_ref(), format!("{}/5", n + 1));
+            assert_eq!(message.subject(), Some("Backup"));
+
+            let formatted = message.formatted();
+            assert!(formatted.len() <= MB);
+            if n == 0 {
+                assert!(formatted.len() > MB - 80);
+            }
+
+            let formatted = String::from_utf8(formatted).unwrap();
+            let (_, body) = formatted.split_once("\r\n\r\n").unwrap();
+            joined.extend(crate::base64::decode(body.replace("\r\n", "")).unwrap());
+        }
+        assert!(
+            joined == content,
+            "the chunks are different from the content"
+        );
+
+        // An empty attachment still makes a message
+        let messages = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .split_attachment(
+                Attachment::new("empty.bin".to_owned()),
+                Vec::new(),
+                content_type.clone(),
+                MB,
+            )
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            messages[0]
+                .headers()
+                .get::<header::XPart>()
+                .unwrap()
+                .as_ref(),
+            "1/1"
+        );
+
+        // The headers don't fit
+        let err = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .split_attachment(
+                Attachment::new("backup.bin".to_owned()),
+                content,
+                content_type,
+                200,
+            )
+            .unwrap_err();
+        assert!(err
+            .validation()
+            .is_some_and(|err| err.is_message_size_too_small()));
+    }
+
+    #[test]
+    fn email_message_id_domain() {
+        let builder = || {
+            Message::builder()
+                .from("NoBody <nobody@domain.tld>".parse().unwrap())
+                .to("Hei <hei@domain.tld>".parse().unwrap())
+        };
+        let message_id = |message: Message| {
+            message
+                .headers()
+                .get::<header::MessageId>()
+                .map(|id| id.as_ref().to_owned())
+        };
+
+        let id = message_id(
+            builder()
+                .message_id(None)
+                .message_id_domain("mail.example.com")
+                .body(String::new())
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(id.starts_with('<'));
+        assert!(id.ends_with("@mail.example.com>"), "{id}");
+
+        // Defaults to the domain of the sender, even when set afterwards
+        let id = message_id(
+            Message::builder()
+                .message_id(None)
+                .from("NoBody <nobody@Sender.tld>".parse().unwrap())
+                .to("Hei <hei@domain.tld>".parse().unwrap())
+                .body(String::new())
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(id.ends_with("@Sender.tld>"), "{id}");
+
+        // A header set manually isn't overwritten
+        let id = message_id(
+            builder()
+                .message_id(None)
+                .header(header::MessageId::from("<manual@domain.tld>".to_owned()))
+                .message_id_domain("mail.example.com")
+                .body(String::new())
+                .unwrap(),
+        );
+        assert_eq!(id.as_deref(), Some("<manual@domain.tld>"));
+
+        // Nothing is generated unless asked for
+        let id = message_id(
+            builder()
+                .message_id_domain("mail.example.com")
+                .body(String::new())
+                .unwrap(),
+        );
+        assert_eq!(id, None);
+    }
+
     #[test]
     fn test_make_message_id() {
         let mut ids = std::collections::HashSet::with_capacity(10);
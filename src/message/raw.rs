@@ -0,0 +1,251 @@
+use std::{borrow::Cow, fs, path::Path};
+
+use crate::{
+    address::Envelope,
+    message::header::{HeaderName, HeaderValue, Headers},
+    Error as EmailError,
+};
+
+/// An email already formatted by another program, like the content of an `.eml` file
+///
+/// The content is sent as is, except for the line endings which are normalized
+/// to CRLF, and the `Bcc` headers which are removed so that the recipients don't
+/// see the blind copies. The envelope is either given explicitly, or taken from
+/// the headers like for a [`Message`](super::Message).
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::error::Error;
+/// #
+/// # #[cfg(feature = "smtp-transport")]
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// use lettre::{message::RawEmail, SmtpTransport, Transport};
+///
+/// let email = RawEmail::from_file("newsletter.eml")?;
+///
+/// let mailer = SmtpTransport::relay("smtp.example.com")?.build();
+/// mailer.send_raw(email.envelope(), email.formatted())?;
+/// # Ok(())
+/// # }
+/// # #[cfg(not(feature = "smtp-transport"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, Clone)]
+pub struct RawEmail {
+    envelope: Envelope,
+    formatted: Vec<u8>,
+}
+
+impl RawEmail {
+    /// Creates a raw email sent with `envelope`, whatever its headers contain
+    pub fn new(content: &[u8], envelope: Envelope) -> Self {
+        Self {
+            envelope,
+            formatted: remove_bcc(normalize_line_endings(content).into_owned()),
+        }
+    }
+
+    /// Creates a raw email, taking the envelope from its headers
+    ///
+    /// The sender is the `Sender` header, or else the `From` header, and the
+    /// recipients are those of the `To`, `Cc` and `Bcc` headers. Lines of the
+    /// header section which aren't valid headers are ignored.
+    pub fn parse(content: &[u8]) -> Result<Self, EmailError> {
+        let formatted = normalize_line_endings(content).into_owned();
+        let envelope = Envelope::try_from(&parse_headers(&formatted))?;
+        Ok(Self {
+            envelope,
+            formatted: remove_bcc(formatted),
+        })
+    }
+
+    /// Reads the raw email in the file at `path`, taking the envelope from its headers
+    ///
+    /// See [`RawEmail::parse`].
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, EmailError> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    /// Get the envelope of the email
+    pub fn envelope(&self) -> &Envelope {
+        &self.envelope
+    }
+
+    /// Get the content of the email with CRLF line endings, ready to be sent
+    pub fn formatted(&self) -> &[u8] {
+        &self.formatted
+    }
+}
+
+/// Replaces the LF line endings which aren't preceded by a CR with CRLF
+pub(crate) fn normalize_line_endings(content: &[u8]) -> Cow<'_, [u8]> {
+    let bare_lf = content
+        .iter()
+        .enumerate()
+        .any(|(i, &byte)| byte == b'\n' && (i == 0 || content[i - 1] != b'\r'));
+    if !bare_lf {
+        return Cow::Borrowed(content);
+    }
+
+    let mut out = Vec::with_capacity(content.len());
+    let mut prev = None;
+    for &byte in content {
+        if byte == b'\n' && prev != Some(b'\r') {
+            out.push(b'\r');
+        }
+        out.push(byte);
+        prev = Some(byte);
+    }
+    Cow::Owned(out)
+}
+
+/// Returns the end of the header section, including the CRLF of its last line
+fn header_section_len(formatted: &[u8]) -> usize {
+    formatted
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map_or(formatted.len(), |i| i + 2)
+}
+
+/// Removes the `Bcc` headers, with their continuation lines, from the header section
+fn remove_bcc(formatted: Vec<u8>) -> Vec<u8> {
+    let end = header_section_len(&formatted);
+    let mut out = Vec::with_capacity(formatted.len());
+    let mut removing = false;
+    for line in formatted[..end].split_inclusive(|&b| b == b'\n') {
+        if !line.starts_with(b" ") && !line.starts_with(b"\t") {
+            removing = line.split(|&b| b == b':').next().is_some_and(|name| {
+                let len = name.len() - name.iter().rev().take_while(|b| **b == b' ').count();
+                name[..len].eq_ignore_ascii_case(b"Bcc")
+            });
+        }
+        if !removing {
+            out.extend_from_slice(line);
+        }
+    }
+    out.extend_from_slice(&formatted[end..]);
+    out
+}
+
+/// Parses the header section, up to the first empty line
+pub(crate) fn parse_headers(formatted: &[u8]) -> Headers {
+    let end = header_section_len(formatted);
+    let section = String::from_utf8_lossy(&formatted[..end]);
+
+    // Unfold the headers, continuation lines starting with a space or a tab
+    let mut fields: Vec<String> = Vec::new();
+    for line in section.split("\r\n") {
+        match fields.last_mut() {
+            Some(field) if line.starts_with([' ', '\t']) => field.push_str(line),
+            _ => fields.push(line.to_owned()),
+        }
+    }
+
+    let mut headers = Headers::new();
+    for field in fields {
+        let Some((name, value)) = field.split_once(':') else {
+            continue;
+        };
+        if let Ok(name) = HeaderName::new_from_ascii(name.trim_end().to_owned()) {
+            headers.insert_raw(HeaderValue::new(name, value.trim().to_owned()));
+        }
+    }
+    headers
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::RawEmail;
+    use crate::{address::Envelope, Error};
+
+    #[test]
+    fn raw_email_from_file() {
+        let email = RawEmail::from_file("./testdata/raw_email_lf.eml").unwrap();
+
+        assert_eq!(
+            email.envelope(),
+            &Envelope::new(
+                Some("sender@domain.tld".parse().unwrap()),
+                vec![
+                    "hei@domain.tld".parse().unwrap(),
+                    "yuin@domain.tld".parse().unwrap(),
+                    "bob@domain.tld".parse().unwrap(),
+                    "secret@domain.tld".parse().unwrap(),
+                ],
+            )
+            .unwrap()
+        );
+
+        // Only the line endings changed, and the blind copies are hidden
+        let source = std::fs::read_to_string("./testdata/raw_email_lf.eml").unwrap();
+        assert!(!source.contains('\r'));
+        assert_eq!(
+            String::from_utf8(email.formatted().to_vec()).unwrap(),
+            source
+                .replace("Bcc: secret@domain.tld\n", "")
+                .replace('\n', "\r\n")
+        );
+    }
+
+    #[test]
+    fn raw_email_crlf() {
+        let content = b"From: NoBody <nobody@domain.tld>\r\n\
+                        To: Hei <hei@domain.tld>\r\n\
+                        Subject: Hi\r\n\
+                        \r\n\
+                        Mixed\nline\r\nendings\r\n";
+        let email = RawEmail::parse(content).unwrap();
+        assert_eq!(
+            email.formatted(),
+            &b"From: NoBody <nobody@domain.tld>\r\n\
+               To: Hei <hei@domain.tld>\r\n\
+               Subject: Hi\r\n\
+               \r\n\
+               Mixed\r\nline\r\nendings\r\n"[..]
+        );
+        assert_eq!(
+            email.envelope().from(),
+            Some(&"nobody@domain.tld".parse().unwrap())
+        );
+
+        // Headers in the body aren't part of the envelope
+        let content = b"From: nobody@domain.tld\n\nTo: hei@domain.tld\n";
+        assert!(matches!(RawEmail::parse(content), Err(Error::MissingTo)));
+
+        // An explicit envelope is used as is
+        let envelope = Envelope::new(None, vec!["yuin@domain.tld".parse().unwrap()]).unwrap();
+        let email = RawEmail::new(content, envelope.clone());
+        assert_eq!(email.envelope(), &envelope);
+    }
+
+    #[test]
+    fn raw_email_remove_bcc() {
+        let content = b"From: nobody@domain.tld\r\n\
+                        bcc: secret@domain.tld,\r\n\
+                        \tother@domain.tld\r\n\
+                        To: hei@domain.tld\r\n\
+                        X-Bcc: kept\r\n\
+                        \r\n\
+                        Bcc: in the body\r\n";
+        let email = RawEmail::parse(content).unwrap();
+        assert!(email
+            .envelope()
+            .to()
+            .contains(&"secret@domain.tld".parse().unwrap()));
+        assert_eq!(
+            email.formatted(),
+            &b"From: nobody@domain.tld\r\n\
+               To: hei@domain.tld\r\n\
+               X-Bcc: kept\r\n\
+               \r\n\
+               Bcc: in the body\r\n"[..]
+        );
+
+        let formatted = email.formatted().to_vec();
+        let email = RawEmail::new(content, email.envelope().clone());
+        assert_eq!(email.formatted(), formatted);
+    }
+}
@@ -0,0 +1,263 @@
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    io::{self, Cursor, Read},
+};
+
+#[cfg(all(
+    feature = "smtp-transport",
+    any(feature = "tokio1", feature = "async-std1")
+))]
+use futures_io::AsyncRead;
+#[cfg(all(
+    feature = "smtp-transport",
+    any(feature = "tokio1", feature = "async-std1")
+))]
+use futures_util::{stream, TryStreamExt};
+
+use super::{
+    attachment::{LazyAttachment, LazyReader},
+    Message, MessageBody,
+};
+#[cfg(all(
+    feature = "smtp-transport",
+    any(feature = "tokio1", feature = "async-std1")
+))]
+use crate::Executor;
+
+/// Length of the formatted chunks of lazy attachments read at once on a
+/// blocking thread by [`async_reader`]
+#[cfg(all(
+    feature = "smtp-transport",
+    any(feature = "tokio1", feature = "async-std1")
+))]
+const ASYNC_CHUNK_LEN: u64 = 64 * 1024;
+
+/// A piece of a formatted message
+#[derive(Debug)]
+pub(super) enum Segment<'a> {
+    /// Content held in memory
+    Bytes(Cow<'a, [u8]>),
+    /// Attachment read from a file
+    Lazy(&'a LazyAttachment),
+}
+
+impl Segment<'_> {
+    #[cfg(feature = "smtp-transport")]
+    fn formatted_len(&self) -> io::Result<u64> {
+        match self {
+            Segment::Bytes(bytes) => Ok(bytes.len() as u64),
+            Segment::Lazy(part) => part.formatted_len(),
+        }
+    }
+}
+
+/// Reader of a [`Message`] formatted for SMTP, obtained with [`Message::reader`]
+///
+/// The message is formatted while being read: the parts held in memory
+/// aren't copied, and the files of [`LazyAttachment`]s are read and
+/// encoded in small chunks.
+#[derive(Debug)]
+pub struct MessageReader<'a> {
+    segments: VecDeque<Segment<'a>>,
+    current: Option<Current<'a>>,
+}
+
+#[derive(Debug)]
+enum Current<'a> {
+    Bytes(Cursor<Cow<'a, [u8]>>),
+    Lazy(LazyReader),
+}
+
+impl<'a> MessageReader<'a> {
+    pub(super) fn new(message: &'a Message) -> Self {
+        let mut segments = VecDeque::new();
+        segments.push_back(Segment::Bytes(Cow::Owned(
+            message.headers.to_string().into_bytes(),
+        )));
+        match &message.body {
+            MessageBody::Mime(part) => part.segments(&mut segments),
+            MessageBody::Raw(raw) => {
+                segments.push_back(Segment::Bytes(Cow::Borrowed(b"\r\n")));
+                segments.push_back(Segment::Bytes(Cow::Borrowed(raw)));
+            }
+        }
+
+        Self {
+            segments,
+            current: None,
+        }
+    }
+
+    /// Length of the formatted message, from the size of the files of the
+    /// lazy attachments, before anything is read
+    #[cfg(feature = "smtp-transport")]
+    pub(super) fn formatted_len(&self) -> io::Result<u64> {
+        self.segments.iter().map(Segment::formatted_len).sum()
+    }
+}
+
+impl Read for MessageReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let len = match &mut self.current {
+                Some(Current::Bytes(bytes)) => bytes.read(buf)?,
+                Some(Current::Lazy(part)) => part.read(buf)?,
+                None => 0,
+            };
+            if len > 0 || buf.is_empty() {
+                return Ok(len);
+            }
+
+            self.current = match self.segments.pop_front() {
+                Some(Segment::Bytes(bytes)) => Some(Current::Bytes(Cursor::new(bytes))),
+                Some(Segment::Lazy(part)) => Some(Current::Lazy(part.reader()?)),
+                None => return Ok(0),
+            };
+        }
+    }
+}
+
+/// Asynchronous reader of `message` formatted for SMTP
+///
+/// Like [`MessageReader`], except that the files of the lazy attachments
+/// are opened and read on the blocking threads of `E`, so that they don't
+/// block the executor.
+#[cfg(all(
+    feature = "smtp-transport",
+    any(feature = "tokio1", feature = "async-std1")
+))]
+pub(crate) fn async_reader<E: Executor>(message: &Message) -> impl AsyncRead + Unpin + Send + '_ {
+    let segments = MessageReader::new(message).segments;
+    let chunks = stream::try_unfold(
+        (segments, None::<LazyReader>),
+        |(mut segments, mut lazy)| async move {
+            loop {
+                if let Some(mut reader) = lazy.take() {
+                    let (reader, chunk) = E::spawn_blocking(move || {
+                        let mut chunk = Vec::new();
+                        let result = (&mut reader)
+                            .take(ASYNC_CHUNK_LEN)
+                            .read_to_end(&mut chunk)
+                            .map(|_| chunk);
+                        (reader, result)
+                    })
+                    .await;
+                    let chunk = chunk?;
+                    if !chunk.is_empty() {
+                        return Ok(Some((Cow::Owned(chunk), (segments, Some(reader)))));
+                    }
+                }
+
+                match segments.pop_front() {
+                    Some(Segment::Bytes(bytes)) if bytes.is_empty() => {}
+                    Some(Segment::Bytes(bytes)) => return Ok(Some((bytes, (segments, None)))),
+                    Some(Segment::Lazy(part)) => {
+                        let part = part.clone();
+                        lazy = Some(E::spawn_blocking(move || part.reader()).await?);
+                    }
+                    None => return Ok(None),
+                }
+            }
+        },
+    );
+    Box::pin(chunks).into_async_read()
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use crate::message::{header::ContentType, Attachment, Message, MultiPart, SinglePart};
+
+    #[test]
+    fn reader_lazy_attachment() {
+        let path = std::env::temp_dir().join("lettre-reader-lazy-attachment.bin");
+        for size in [0, 1, 56, 57, 58, 7296, 7297, 20000] {
+            let content = (0..size).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+            std::fs::write(&path, content).unwrap();
+
+            let attachment = Attachment::new(String::from("data.bin")).lazy(
+                &path,
+                ContentType::parse("application/octet-stream").unwrap(),
+            );
+            let message = Message::builder()
+                .from("NoBody <nobody@domain.tld>".parse().unwrap())
+                .to("Hei <hei@domain.tld>".parse().unwrap())
+                .multipart(
+                    MultiPart::mixed()
+                        .singlepart(SinglePart::plain(String::from("See attached")))
+                        .lazy_attachment(attachment),
+                )
+                .unwrap();
+
+            // Read a few bytes at a time, across the pieces of the message
+            let mut reader = message.reader();
+            let mut read = Vec::new();
+            let mut buf = [0; 13];
+            loop {
+                let len = reader.read(&mut buf).unwrap();
+                if len == 0 {
+                    break;
+                }
+                read.extend_from_slice(&buf[..len]);
+            }
+
+            let formatted = message.formatted();
+            assert!(read == formatted, "read a different message");
+            #[cfg(feature = "smtp-transport")]
+            assert_eq!(message.formatted_len().unwrap(), formatted.len() as u64);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reader_raw_body() {
+        let message = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .body(String::from("Happy new year!"))
+            .unwrap();
+
+        let mut read = Vec::new();
+        message.reader().read_to_end(&mut read).unwrap();
+        assert_eq!(read, message.formatted());
+        #[cfg(feature = "smtp-transport")]
+        assert_eq!(message.formatted_len().unwrap(), read.len() as u64);
+    }
+
+    #[cfg(all(feature = "smtp-transport", feature = "tokio1"))]
+    #[tokio1_crate::test(crate = "tokio1_crate")]
+    async fn async_reader_lazy_attachment() {
+        use futures_util::AsyncReadExt;
+
+        use crate::Tokio1Executor;
+
+        let path = std::env::temp_dir().join("lettre-async-reader-lazy-attachment.bin");
+        // Larger than a chunk read on a blocking thread
+        let content = (0..200_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        std::fs::write(&path, content).unwrap();
+
+        let attachment = Attachment::new(String::from("data.bin")).lazy(
+            &path,
+            ContentType::parse("application/octet-stream").unwrap(),
+        );
+        let message = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .multipart(
+                MultiPart::mixed()
+                    .singlepart(SinglePart::plain(String::from("Hi")))
+                    .lazy_attachment(attachment),
+            )
+            .unwrap();
+
+        let mut read = Vec::new();
+        super::async_reader::<Tokio1Executor>(&message)
+            .read_to_end(&mut read)
+            .await
+            .unwrap();
+        assert!(read == message.formatted(), "read a different message");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
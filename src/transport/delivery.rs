@@ -0,0 +1,209 @@
+//! Identifiers correlating the logs of the attempts to deliver an email
+//! through the wrapper transports
+//!
+//! With the `tracing` feature, the outermost wrapper transport, like a
+//! [`RetryingTransport`](super::retry::RetryingTransport), opens a `delivery`
+//! span with a `delivery_id` field, and each call to an inner transport an
+//! `attempt` span with an `attempt_id` field. Nested wrappers share the
+//! delivery of the outermost one, so the logs of every underlying attempt
+//! carry both identifiers.
+//!
+//! The identifiers are unique within the process, and unrelated to the
+//! `Message-ID` of the email.
+
+#[cfg(feature = "tracing")]
+use std::{
+    cell::Cell,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+#[cfg(feature = "tracing")]
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+#[cfg(feature = "tracing")]
+thread_local! {
+    /// Identifier of the delivery running on this thread
+    static DELIVERY_ID: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+#[cfg(feature = "tracing")]
+fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Runs `send` as a delivery, unless it is part of the delivery of an
+/// outer wrapper transport
+pub(crate) fn delivery<O>(send: impl FnOnce() -> O) -> O {
+    #[cfg(feature = "tracing")]
+    {
+        /// Ends the delivery of the thread, even if `send` panics
+        struct Guard;
+
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                DELIVERY_ID.with(|id| id.set(None));
+            }
+        }
+
+        if DELIVERY_ID.with(Cell::get).is_some() {
+            return send();
+        }
+
+        let id = next_id();
+        DELIVERY_ID.with(|delivery_id| delivery_id.set(Some(id)));
+        let _guard = Guard;
+        tracing::debug_span!("delivery", delivery_id = id).in_scope(send)
+    }
+    #[cfg(not(feature = "tracing"))]
+    send()
+}
+
+/// Runs `send` as an attempt of the current delivery
+pub(crate) fn attempt<O>(send: impl FnOnce() -> O) -> O {
+    #[cfg(feature = "tracing")]
+    {
+        let delivery_id = DELIVERY_ID.with(Cell::get);
+        tracing::debug_span!("attempt", attempt_id = next_id(), delivery_id).in_scope(send)
+    }
+    #[cfg(not(feature = "tracing"))]
+    send()
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod test {
+    use std::{
+        cell::Cell,
+        io,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use crate::{
+        address::Envelope,
+        transport::{
+            envelope,
+            failover::{FailoverError, FailoverTransport},
+            retry::{RetryingTransport, TransientError},
+            Transport,
+        },
+    };
+
+    #[derive(Debug)]
+    struct Unavailable;
+
+    impl TransientError for Unavailable {
+        fn is_transient(&self) -> bool {
+            true
+        }
+    }
+
+    impl FailoverError for Unavailable {
+        fn should_failover(&self) -> bool {
+            true
+        }
+    }
+
+    /// Fails `failures` times, logging each attempt
+    struct Relay {
+        failures: Cell<u32>,
+    }
+
+    impl Transport for Relay {
+        type Ok = ();
+        type Error = Unavailable;
+
+        fn send_raw(&self, _envelope: &Envelope, _email: &[u8]) -> Result<(), Unavailable> {
+            tracing::debug!("relay attempt");
+            match self.failures.get() {
+                0 => Ok(()),
+                failures => {
+                    self.failures.set(failures - 1);
+                    Err(Unavailable)
+                }
+            }
+        }
+    }
+
+    /// Sink appending the logs to a shared buffer
+    #[derive(Clone)]
+    struct Logs(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Returns the value of the `name` field of the span in `line`
+    fn field<'a>(line: &'a str, name: &str) -> &'a str {
+        let start = line.find(&format!("{name}=")).unwrap() + name.len() + 1;
+        let len = line[start..].find(|c: char| !c.is_ascii_digit()).unwrap();
+        &line[start..start + len]
+    }
+
+    #[test]
+    fn delivery_ids() {
+        let logs = Logs(Arc::new(Mutex::new(Vec::new())));
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+
+        let envelope = envelope();
+        let relay = |failures| Relay {
+            failures: Cell::new(failures),
+        };
+        let transport = RetryingTransport::new(
+            FailoverTransport::new(vec![relay(1), relay(1)]),
+            1,
+            Duration::ZERO,
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            transport.send_raw(&envelope, b"Hi").unwrap();
+            transport.send_raw(&envelope, b"Hi").unwrap();
+        });
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let attempts = logs
+            .lines()
+            .filter(|line| line.contains("relay attempt"))
+            .collect::<Vec<_>>();
+        // Both relays fail, then the retry and the next email go to the
+        // first one
+        assert_eq!(attempts.len(), 4, "{logs}");
+
+        let delivery_ids = attempts
+            .iter()
+            .map(|line| field(line, "delivery_id"))
+            .collect::<Vec<_>>();
+        assert_eq!(delivery_ids[0], delivery_ids[1]);
+        assert_eq!(delivery_ids[0], delivery_ids[2]);
+        assert_ne!(delivery_ids[0], delivery_ids[3]);
+
+        // Each attempt of the retrying transport and of the failover
+        // transport has its own identifier
+        let attempt_ids = attempts
+            .iter()
+            .map(|line| {
+                let (retry, failover) = line
+                    .split_once("attempt{")
+                    .unwrap()
+                    .1
+                    .split_once("attempt{")
+                    .unwrap();
+                (field(retry, "attempt_id"), field(failover, "attempt_id"))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(attempt_ids[0].0, attempt_ids[1].0);
+        assert_ne!(attempt_ids[1].0, attempt_ids[2].0);
+        assert_ne!(attempt_ids[0].1, attempt_ids[1].1);
+        assert_ne!(attempt_ids[1].1, attempt_ids[2].1);
+    }
+}
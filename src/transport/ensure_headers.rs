@@ -0,0 +1,241 @@
+//! The ensure headers transport wraps another transport and adds the `Date`
+//! and `Message-ID` headers to the emails missing them.
+//!
+//! [`Message`]s always get a `Date` header, but emails formatted by other
+//! programs and sent with [`Transport::send_raw`] may lack both. Wrapping
+//! the transport shared by several senders applies the same policy to all
+//! of them, with the `Message-ID`s generated in the chosen domain.
+//!
+//! The headers of the email are left untouched, the missing ones are
+//! added before them. The line endings are normalized to CRLF when headers
+//! are added, like for a [`RawEmail`](crate::message::RawEmail).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use std::error::Error;
+//! #
+//! # #[cfg(feature = "smtp-transport")]
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! use lettre::{
+//!     message::RawEmail, transport::ensure_headers::EnsureHeadersTransport, SmtpTransport,
+//!     Transport,
+//! };
+//!
+//! let email = RawEmail::from_file("newsletter.eml")?;
+//!
+//! let mailer = EnsureHeadersTransport::new(
+//!     SmtpTransport::relay("smtp.example.com")?.build(),
+//!     String::from("example.com"),
+//! );
+//! mailer.send_raw(email.envelope(), email.formatted())?;
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "smtp-transport"))]
+//! # fn main() {}
+//! ```
+//!
+//! [`Message`]: crate::Message
+
+use std::borrow::Cow;
+
+use super::Transport;
+use crate::{
+    address::Envelope,
+    message::{
+        header::{Date, Header, HeaderValue, Headers, MessageId},
+        make_message_id, normalize_line_endings, parse_headers,
+    },
+    Message,
+};
+
+/// A transport adding the `Date` and `Message-ID` headers to the emails
+/// missing them before sending them through the inner transport
+#[derive(Debug, Clone)]
+pub struct EnsureHeadersTransport<T> {
+    inner: T,
+    domain: String,
+}
+
+impl<T> EnsureHeadersTransport<T> {
+    /// Creates a new transport sending emails through `inner`
+    ///
+    /// The generated `Message-ID`s are in `domain`, like
+    /// `<random-id@domain>`.
+    pub fn new(inner: T, domain: String) -> Self {
+        Self { inner, domain }
+    }
+
+    /// Returns the wrapped transport
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the domain of the generated `Message-ID`s
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// Returns the `Date` and `Message-ID` headers missing from `headers`
+    fn missing(&self, headers: &Headers) -> Vec<HeaderValue> {
+        let mut missing = Vec::new();
+        if headers.get_raw("Date").is_none() {
+            missing.push(Date::now().display());
+        }
+        if headers.get_raw("Message-ID").is_none() {
+            missing.push(
+                MessageId::from(format!("<{}@{}>", make_message_id(), self.domain)).display(),
+            );
+        }
+        missing
+    }
+
+    /// Returns `email` with the missing headers added before the others
+    fn complete<'a>(&self, email: &'a [u8]) -> Cow<'a, [u8]> {
+        // The header section of an email with LF line endings is only
+        // found once they are CRLF
+        let normalized = normalize_line_endings(email);
+        let missing = self.missing(&parse_headers(&normalized));
+        if missing.is_empty() {
+            return Cow::Borrowed(email);
+        }
+
+        let mut headers = Headers::new();
+        for value in missing {
+            headers.insert_raw(value);
+        }
+        let missing = headers.to_string();
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(headers = %missing.trim_end(), "adding missing headers");
+        let mut completed = missing.into_bytes();
+        completed.extend_from_slice(&normalized);
+        Cow::Owned(completed)
+    }
+}
+
+impl<T: Transport> Transport for EnsureHeadersTransport<T> {
+    type Ok = T::Ok;
+    type Error = T::Error;
+
+    fn send(&self, message: &Message) -> Result<Self::Ok, Self::Error> {
+        let missing = self.missing(message.headers());
+        if missing.is_empty() {
+            return self.inner.send(message);
+        }
+
+        let mut message = message.clone();
+        for value in missing {
+            message.headers_mut().insert_raw(value);
+        }
+        self.inner.send(&message)
+    }
+
+    fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        self.inner.send_raw(envelope, &self.complete(email))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EnsureHeadersTransport, Transport};
+    use crate::{
+        message::parse_headers,
+        transport::{envelope, stub::StubTransport},
+    };
+
+    #[test]
+    fn ensure_headers_missing() {
+        let transport =
+            EnsureHeadersTransport::new(StubTransport::new_ok(), String::from("example.com"));
+        let email = "From: nobody@domain.tld\r\nTo: hei@domain.tld\r\nSubject: Hi\r\n\r\nHello\r\n";
+        transport.send_raw(&envelope(), email.as_bytes()).unwrap();
+
+        let messages = transport.inner().messages();
+        let sent = &messages[0].1;
+        assert!(sent.ends_with(email), "{sent}");
+
+        let headers = parse_headers(sent.as_bytes());
+        assert!(headers.get_raw("Date").is_some());
+        let message_id = headers.get_raw("Message-ID").unwrap();
+        assert!(message_id.starts_with('<'), "{message_id}");
+        assert!(message_id.ends_with("@example.com>"), "{message_id}");
+    }
+
+    #[test]
+    fn ensure_headers_present() {
+        let transport =
+            EnsureHeadersTransport::new(StubTransport::new_ok(), String::from("example.com"));
+
+        let email = concat!(
+            "date: Tue, 15 Nov 1994 08:12:31 +0000\r\n",
+            "From: nobody@domain.tld\r\n",
+            "Message-Id: <id@domain.tld>\r\n",
+            "\r\n",
+            "Hello\r\n",
+        );
+        transport.send_raw(&envelope(), email.as_bytes()).unwrap();
+        assert_eq!(transport.inner().messages()[0].1, email);
+
+        // Only the missing header is added
+        let email = "Date: Tue, 15 Nov 1994 08:12:31 +0000\r\n\r\nHello\r\n";
+        transport.send_raw(&envelope(), email.as_bytes()).unwrap();
+        let sent = &transport.inner().messages()[1].1;
+        assert!(sent.starts_with("Message-ID: <"), "{sent}");
+        assert_eq!(sent.matches("Date:").count(), 1, "{sent}");
+        assert!(sent.ends_with(email), "{sent}");
+    }
+
+    #[test]
+    fn ensure_headers_lf() {
+        let transport =
+            EnsureHeadersTransport::new(StubTransport::new_ok(), String::from("example.com"));
+
+        // The `Date` of the body isn't a header
+        let email = "From: nobody@domain.tld\nSubject: Hi\n\nDate: tomorrow\n";
+        transport.send_raw(&envelope(), email.as_bytes()).unwrap();
+
+        let sent = &transport.inner().messages()[0].1;
+        assert!(
+            sent.ends_with("From: nobody@domain.tld\r\nSubject: Hi\r\n\r\nDate: tomorrow\r\n"),
+            "{sent}"
+        );
+        assert_eq!(sent.matches("Date: ").count(), 2, "{sent}");
+        assert!(!sent.replace("\r\n", "").contains('\n'), "{sent}");
+    }
+
+    #[test]
+    fn ensure_headers_message() {
+        use crate::Message;
+
+        let transport =
+            EnsureHeadersTransport::new(StubTransport::new_ok(), String::from("example.com"));
+        let message = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap();
+        transport.send(&message).unwrap();
+
+        let sent = &transport.inner().messages()[0].1;
+        assert_eq!(sent.matches("Date: ").count(), 1, "{sent}");
+        assert_eq!(sent.matches("Message-ID: ").count(), 1, "{sent}");
+        assert!(sent.contains("@example.com>\r\n"), "{sent}");
+    }
+
+    #[test]
+    fn ensure_headers_removed_attachment() {
+        use crate::transport::message_with_removed_attachment;
+
+        let transport =
+            EnsureHeadersTransport::new(StubTransport::new_ok(), String::from("example.com"));
+
+        assert!(transport
+            .send(&message_with_removed_attachment(
+                "lettre-ensure-headers-removed-attachment.pdf"
+            ))
+            .is_err());
+        assert!(transport.inner().messages().is_empty());
+    }
+}
@@ -0,0 +1,299 @@
+//! The failover and round-robin transports send emails through the first of
+//! several transports, usually relays, which accepts them.
+//!
+//! A [`FailoverTransport`] always tries its transports in order, so the next
+//! ones are only used when the first ones are unavailable. A
+//! [`RoundRobinTransport`] starts with a different transport for each email,
+//! to spread the load between them, and tries the next ones in turn.
+//!
+//! Only the errors for which [`FailoverError::should_failover`] returns `true`
+//! make the email go to the next transport. For the SMTP transport, these
+//! are all the errors which occur while opening the connection, including
+//! the permanent `5xx` replies to the greeting, `STARTTLS` or `AUTH`, and the
+//! transient `4xx` replies and other errors specific to a server. A permanent
+//! `5xx` reply to `MAIL`, `RCPT` or `DATA`, like for an invalid recipient,
+//! would be the same on any relay, so it is returned immediately. When all
+//! the transports fail, the error of the last one is returned.
+//!
+//! With the `tracing` feature, the logs of each transport tried for an
+//! email are in an `attempt` span, within a `delivery` span identifying the
+//! email, like for the [`RetryingTransport`](super::retry::RetryingTransport).
+//!
+//! Both transports only wrap synchronous [`Transport`]s for now; there is
+//! no `AsyncTransport` implementation yet.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use std::error::Error;
+//! #
+//! # #[cfg(all(feature = "builder", feature = "smtp-transport"))]
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! use lettre::{transport::failover::FailoverTransport, Message, SmtpTransport, Transport};
+//!
+//! let email = Message::builder()
+//!     .from("NoBody <nobody@domain.tld>".parse()?)
+//!     .to("Hei <hei@domain.tld>".parse()?)
+//!     .subject("Happy new year")
+//!     .body(String::from("Be happy!"))?;
+//!
+//! let mailer = FailoverTransport::new(vec![
+//!     SmtpTransport::relay("smtp1.example.com")?.build(),
+//!     SmtpTransport::relay("smtp2.example.com")?.build(),
+//! ]);
+//! mailer.send(&email)?;
+//! # Ok(())
+//! # }
+//! # #[cfg(not(all(feature = "builder", feature = "smtp-transport")))]
+//! # fn main() {}
+//! ```
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{
+    delivery::{attempt, delivery},
+    Transport,
+};
+use crate::address::Envelope;
+#[cfg(feature = "builder")]
+use crate::Message;
+
+/// An error which tells whether another transport could accept the email
+pub trait FailoverError {
+    /// Returns true if sending the email through another transport could succeed
+    fn should_failover(&self) -> bool;
+}
+
+/// Calls `send` on the transports starting at `start`, until one succeeds
+/// or fails with an error which doesn't allow a failover
+fn send_from<T, O, E, F>(transports: &[T], start: usize, mut send: F) -> Result<O, E>
+where
+    E: FailoverError,
+    F: FnMut(&T) -> Result<O, E>,
+{
+    let mut index = start;
+    let mut tried = 1;
+    delivery(|| loop {
+        match attempt(|| send(&transports[index])) {
+            Err(err) if tried < transports.len() && err.should_failover() => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(index, "transport failed, trying the next one");
+                #[cfg(not(feature = "tracing"))]
+                let _ = err;
+                index = (index + 1) % transports.len();
+                tried += 1;
+            }
+            result => return result,
+        }
+    })
+}
+
+/// A transport sending emails through the first of its transports which
+/// accepts them
+#[derive(Debug, Clone)]
+pub struct FailoverTransport<T> {
+    transports: Vec<T>,
+}
+
+impl<T> FailoverTransport<T> {
+    /// Creates a new transport trying `transports` in order
+    ///
+    /// # Panics
+    ///
+    /// Panics if `transports` is empty.
+    pub fn new(transports: Vec<T>) -> Self {
+        assert!(!transports.is_empty(), "at least one transport is required");
+        Self { transports }
+    }
+
+    /// Returns the wrapped transports
+    pub fn transports(&self) -> &[T] {
+        &self.transports
+    }
+}
+
+impl<T> Transport for FailoverTransport<T>
+where
+    T: Transport,
+    T::Error: FailoverError,
+{
+    type Ok = T::Ok;
+    type Error = T::Error;
+
+    #[cfg(feature = "builder")]
+    fn send(&self, message: &Message) -> Result<Self::Ok, Self::Error> {
+        send_from(&self.transports, 0, |transport| transport.send(message))
+    }
+
+    fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        send_from(&self.transports, 0, |transport| {
+            transport.send_raw(envelope, email)
+        })
+    }
+}
+
+/// A transport sending each email through a different one of its
+/// transports in turn, and through the next ones if it fails
+#[derive(Debug)]
+pub struct RoundRobinTransport<T> {
+    transports: Vec<T>,
+    next: AtomicUsize,
+}
+
+impl<T> RoundRobinTransport<T> {
+    /// Creates a new transport rotating between `transports`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `transports` is empty.
+    pub fn new(transports: Vec<T>) -> Self {
+        assert!(!transports.is_empty(), "at least one transport is required");
+        Self {
+            transports,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the wrapped transports
+    pub fn transports(&self) -> &[T] {
+        &self.transports
+    }
+
+    /// Returns the index of the transport to try first for the next email
+    fn start(&self) -> usize {
+        self.next.fetch_add(1, Ordering::Relaxed) % self.transports.len()
+    }
+}
+
+impl<T> Transport for RoundRobinTransport<T>
+where
+    T: Transport,
+    T::Error: FailoverError,
+{
+    type Ok = T::Ok;
+    type Error = T::Error;
+
+    #[cfg(feature = "builder")]
+    fn send(&self, message: &Message) -> Result<Self::Ok, Self::Error> {
+        send_from(&self.transports, self.start(), |transport| {
+            transport.send(message)
+        })
+    }
+
+    fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        send_from(&self.transports, self.start(), |transport| {
+            transport.send_raw(envelope, email)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use super::{FailoverError, FailoverTransport, RoundRobinTransport, Transport};
+    use crate::{address::Envelope, transport::envelope};
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum Reply {
+        Unavailable(usize),
+        Rejected(usize),
+    }
+
+    impl FailoverError for Reply {
+        fn should_failover(&self) -> bool {
+            matches!(self, Reply::Unavailable(_))
+        }
+    }
+
+    /// Replies with `reply`, or accepts the email, counting the attempts
+    struct Relay {
+        id: usize,
+        reply: Option<fn(usize) -> Reply>,
+        attempts: Cell<u32>,
+    }
+
+    impl Relay {
+        fn new(id: usize, reply: Option<fn(usize) -> Reply>) -> Self {
+            Self {
+                id,
+                reply,
+                attempts: Cell::new(0),
+            }
+        }
+    }
+
+    impl Transport for Relay {
+        type Ok = usize;
+        type Error = Reply;
+
+        fn send_raw(&self, _envelope: &Envelope, _email: &[u8]) -> Result<usize, Reply> {
+            self.attempts.set(self.attempts.get() + 1);
+            match self.reply {
+                Some(reply) => Err(reply(self.id)),
+                None => Ok(self.id),
+            }
+        }
+    }
+
+    fn attempts(relays: &[Relay]) -> Vec<u32> {
+        relays.iter().map(|r| r.attempts.get()).collect()
+    }
+
+    #[test]
+    fn failover_in_order() {
+        let transport = FailoverTransport::new(vec![
+            Relay::new(0, Some(Reply::Unavailable)),
+            Relay::new(1, None),
+            Relay::new(2, None),
+        ]);
+
+        assert_eq!(transport.send_raw(&envelope(), b"Hi"), Ok(1));
+        assert_eq!(transport.send_raw(&envelope(), b"Hi"), Ok(1));
+        assert_eq!(attempts(transport.transports()), [2, 2, 0]);
+    }
+
+    #[test]
+    fn failover_all_failed() {
+        let transport = FailoverTransport::new(vec![
+            Relay::new(0, Some(Reply::Unavailable)),
+            Relay::new(1, Some(Reply::Unavailable)),
+        ]);
+
+        assert_eq!(
+            transport.send_raw(&envelope(), b"Hi"),
+            Err(Reply::Unavailable(1))
+        );
+        assert_eq!(attempts(transport.transports()), [1, 1]);
+    }
+
+    #[test]
+    fn no_failover_rejected() {
+        let transport = FailoverTransport::new(vec![
+            Relay::new(0, Some(Reply::Rejected)),
+            Relay::new(1, None),
+        ]);
+
+        assert_eq!(
+            transport.send_raw(&envelope(), b"Hi"),
+            Err(Reply::Rejected(0))
+        );
+        assert_eq!(attempts(transport.transports()), [1, 0]);
+    }
+
+    #[test]
+    fn round_robin() {
+        let transport = RoundRobinTransport::new(vec![
+            Relay::new(0, None),
+            Relay::new(1, Some(Reply::Unavailable)),
+            Relay::new(2, None),
+        ]);
+
+        let sent = (0..6)
+            .map(|_| transport.send_raw(&envelope(), b"Hi").unwrap())
+            .collect::<Vec<_>>();
+        // The emails starting with the unavailable relay go to the next one
+        assert_eq!(sent, [0, 2, 2, 0, 2, 2]);
+        assert_eq!(attempts(transport.transports()), [2, 2, 4]);
+    }
+}
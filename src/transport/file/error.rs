@@ -37,6 +37,11 @@ impl Error {
     pub fn is_envelope(&self) -> bool {
         matches!(self.inner.kind, Kind::Envelope)
     }
+
+    /// Returns true if the message was rejected because the spool is full
+    pub fn is_spool_full(&self) -> bool {
+        matches!(self.inner.kind, Kind::SpoolFull)
+    }
 }
 
 #[derive(Debug)]
@@ -46,6 +51,8 @@ pub(crate) enum Kind {
     /// Envelope serialization/deserialization error
     #[cfg(feature = "file-transport-envelope")]
     Envelope,
+    /// The spool limits would be exceeded by the message
+    SpoolFull,
 }
 
 impl fmt::Debug for Error {
@@ -68,6 +75,7 @@ impl fmt::Display for Error {
             Kind::Io => f.write_str("response error")?,
             #[cfg(feature = "file-transport-envelope")]
             Kind::Envelope => f.write_str("internal client error")?,
+            Kind::SpoolFull => f.write_str("spool is full")?,
         };
 
         if let Some(ref e) = self.inner.source {
@@ -95,3 +103,7 @@ pub(crate) fn io<E: Into<BoxError>>(e: E) -> Error {
 pub(crate) fn envelope<E: Into<BoxError>>(e: E) -> Error {
     Error::new(Kind::Envelope, Some(e))
 }
+
+pub(crate) fn spool_full() -> Error {
+    Error::new(Kind::SpoolFull, None::<BoxError>)
+}
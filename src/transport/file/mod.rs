@@ -2,6 +2,18 @@
 //! `message_id.eml`.
 //! It can be useful for testing purposes, or if you want to keep track of sent messages.
 //!
+//! The `.eml` files contain the raw messages, exactly as they would be sent, so
+//! they can be opened with a mail client or read back with [`RawEmail::from_file`].
+//! The envelope is only written, in a separate `.json` file, with
+//! `with_envelope`.
+//!
+//! Each file is first written to a temporary file, ending in `.tmp`, which is
+//! then renamed, so that the `.eml` and `.json` files are only visible once they are
+//! complete. The envelope is written before the message, so the `.json` file of
+//! a visible `.eml` file is always present. A writer which is killed or fails
+//! midway leaves only `.tmp` files behind, which are ignored by the transport,
+//! and can be removed.
+//!
 //! ## Sync example
 //!
 //! ```rust
@@ -64,6 +76,31 @@
 //! # fn main() {}
 //! ```
 //!
+//! ## Limiting the size of the directory
+//!
+//! By default the directory grows without bounds. The total size and the
+//! number of messages can be limited with `max_spool_bytes` and
+//! `max_spool_files`. Messages which would exceed the limits are rejected,
+//! unless [`SpoolPolicy::EvictOldest`] is set, in which case the oldest
+//! messages are deleted to make room.
+//!
+//! ```rust
+//! # #[cfg(feature = "file-transport")]
+//! # fn main() {
+//! use std::env::temp_dir;
+//!
+//! use lettre::transport::file::{FileTransport, SpoolPolicy};
+//!
+//! let sender = FileTransport::new(temp_dir())
+//!     .max_spool_bytes(64 * 1024 * 1024)
+//!     .max_spool_files(1000)
+//!     .spool_policy(SpoolPolicy::EvictOldest);
+//! # let _ = sender;
+//! # }
+//! # #[cfg(not(feature = "file-transport"))]
+//! # fn main() {}
+//! ```
+//!
 //! ## Async tokio 1.x
 //!
 //! ```rust,no_run
@@ -135,27 +172,39 @@
 //! ```json
 //! {"forward_path":["hei@domain.tld"],"reverse_path":"nobody@domain.tld"}
 //! ```
+//!
+//! [`RawEmail::from_file`]: crate::message::RawEmail::from_file
 
 #[cfg(any(feature = "async-std1", feature = "tokio1"))]
 use std::marker::PhantomData;
 use std::{
     path::{Path, PathBuf},
-    str,
+    process, str,
+    sync::atomic::{AtomicU64, Ordering},
 };
 
 #[cfg(any(feature = "async-std1", feature = "tokio1"))]
 use async_trait::async_trait;
 use uuid::Uuid;
 
-pub use self::error::Error;
+pub use self::{
+    error::Error,
+    spool::{SpoolPolicy, SpoolUsage},
+};
+#[cfg(feature = "builder")]
+use crate::Message;
 use crate::{address::Envelope, Transport};
 #[cfg(any(feature = "async-std1", feature = "tokio1"))]
 use crate::{AsyncTransport, Executor};
 
 mod error;
+mod spool;
 
 type Id = String;
 
+/// Makes the names of the temporary files unique within the process
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
 /// Writes the content and the envelope information to a file
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -164,6 +213,8 @@ pub struct FileTransport {
     path: PathBuf,
     #[cfg(feature = "file-transport-envelope")]
     save_envelope: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    spool: spool::Spool,
 }
 
 /// Asynchronously writes the content and the envelope information to a file
@@ -185,6 +236,7 @@ impl FileTransport {
             path: PathBuf::from(path.as_ref()),
             #[cfg(feature = "file-transport-envelope")]
             save_envelope: false,
+            spool: spool::Spool::default(),
         }
     }
 
@@ -198,9 +250,45 @@ impl FileTransport {
             path: PathBuf::from(path.as_ref()),
             #[cfg(feature = "file-transport-envelope")]
             save_envelope: true,
+            spool: spool::Spool::default(),
         }
     }
 
+    /// Limits the total size in bytes of the messages and envelopes
+    /// in the directory
+    ///
+    /// What happens to messages which would exceed the limit is
+    /// controlled by [`FileTransport::spool_policy`].
+    pub fn max_spool_bytes(mut self, bytes: u64) -> Self {
+        self.spool.max_bytes = Some(bytes);
+        self
+    }
+
+    /// Limits the number of messages in the directory
+    ///
+    /// What happens to messages which would exceed the limit is
+    /// controlled by [`FileTransport::spool_policy`].
+    pub fn max_spool_files(mut self, files: u64) -> Self {
+        self.spool.max_files = Some(files);
+        self
+    }
+
+    /// Sets what happens when a message would exceed the spool limits
+    ///
+    /// Defaults to [`SpoolPolicy::Reject`].
+    pub fn spool_policy(mut self, policy: SpoolPolicy) -> Self {
+        self.spool.policy = policy;
+        self
+    }
+
+    /// Returns the current size of the directory
+    ///
+    /// The directory is scanned the first time the usage is needed,
+    /// afterwards the counters are updated as messages are written.
+    pub fn spool_usage(&self) -> Result<SpoolUsage, Error> {
+        self.spool.usage(&self.path)
+    }
+
     /// Read a message that was written using the file transport.
     ///
     /// Reads the envelope and the raw message content.
@@ -221,6 +309,34 @@ impl FileTransport {
     fn path(&self, email_id: &Uuid, extension: &str) -> PathBuf {
         self.path.join(format!("{email_id}.{extension}"))
     }
+
+    /// Path of a temporary file, renamed to `file` once written
+    fn tmp_path(file: &Path) -> PathBuf {
+        let counter = TMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let mut tmp = file.as_os_str().to_owned();
+        tmp.push(format!(".{}.{counter}.tmp", process::id()));
+        tmp.into()
+    }
+
+    /// Writes `contents` to a new temporary file, and then renames it to `file`
+    fn write(file: &Path, contents: &[u8]) -> std::io::Result<()> {
+        use std::{fs, io::Write};
+
+        let tmp = Self::tmp_path(file);
+        let result = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp)
+            .and_then(|mut f| {
+                f.write_all(contents)?;
+                f.sync_all()
+            })
+            .and_then(|()| fs::rename(&tmp, file));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        result
+    }
 }
 
 #[cfg(any(feature = "async-std1", feature = "tokio1"))]
@@ -250,6 +366,51 @@ where
         }
     }
 
+    /// Limits the total size in bytes of the messages and envelopes
+    /// in the directory
+    ///
+    /// See [`FileTransport::max_spool_bytes`].
+    pub fn max_spool_bytes(mut self, bytes: u64) -> Self {
+        self.inner = self.inner.max_spool_bytes(bytes);
+        self
+    }
+
+    /// Limits the number of messages in the directory
+    ///
+    /// See [`FileTransport::max_spool_files`].
+    pub fn max_spool_files(mut self, files: u64) -> Self {
+        self.inner = self.inner.max_spool_files(files);
+        self
+    }
+
+    /// Sets what happens when a message would exceed the spool limits
+    ///
+    /// Defaults to [`SpoolPolicy::Reject`].
+    pub fn spool_policy(mut self, policy: SpoolPolicy) -> Self {
+        self.inner = self.inner.spool_policy(policy);
+        self
+    }
+
+    /// Returns the current size of the directory
+    ///
+    /// See [`FileTransport::spool_usage`].
+    pub fn spool_usage(&self) -> Result<SpoolUsage, Error> {
+        self.inner.spool_usage()
+    }
+
+    /// Writes `contents` to a new temporary file, and then renames it to `file`
+    async fn write(file: &Path, contents: &[u8]) -> std::io::Result<()> {
+        let tmp = FileTransport::tmp_path(file);
+        let mut result = E::fs_write_new(&tmp, contents).await;
+        if result.is_ok() {
+            result = E::fs_rename(&tmp, file).await;
+        }
+        if result.is_err() {
+            let _ = E::fs_remove_file(&tmp).await;
+        }
+        result
+    }
+
     /// Read a message that was written using the file transport.
     ///
     /// Reads the envelope and the raw message content.
@@ -270,27 +431,52 @@ impl Transport for FileTransport {
     type Ok = Id;
     type Error = Error;
 
+    #[cfg(feature = "builder")]
+    fn send(&self, message: &Message) -> Result<Self::Ok, Self::Error> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!("starting to send an email");
+
+        let raw = message.try_formatted().map_err(error::io)?;
+        self.send_raw(message.envelope(), &raw)
+    }
+
     fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
         use std::fs;
 
         let email_id = Uuid::new_v4();
 
-        let file = self.path(&email_id, "eml");
-        #[cfg(feature = "tracing")]
-        tracing::debug!(?file, "writing email to");
-        fs::write(file, email).map_err(error::io)?;
-
         #[cfg(feature = "file-transport-envelope")]
-        {
-            if self.save_envelope {
-                let file = self.path(&email_id, "json");
-                let buf = serde_json::to_string(&envelope).map_err(error::envelope)?;
-                fs::write(file, buf).map_err(error::io)?;
-            }
-        }
+        let envelope_buf = if self.save_envelope {
+            Some(serde_json::to_string(&envelope).map_err(error::envelope)?)
+        } else {
+            None
+        };
+        #[cfg(not(feature = "file-transport-envelope"))]
+        let envelope_buf: Option<String> = None;
         // use envelope anyway
         let _ = envelope;
 
+        let size = (email.len() + envelope_buf.as_ref().map_or(0, String::len)) as u64;
+        self.spool.reserve(&self.path, size)?;
+
+        let json_file = self.path(&email_id, "json");
+        let mut result = match envelope_buf {
+            Some(buf) => Self::write(&json_file, buf.as_bytes()),
+            None => Ok(()),
+        };
+
+        if result.is_ok() {
+            let file = self.path(&email_id, "eml");
+            #[cfg(feature = "tracing")]
+            tracing::debug!(?file, "writing email to");
+            result = Self::write(&file, email);
+        }
+        if let Err(err) = result {
+            let _ = fs::remove_file(json_file);
+            self.spool.release(size);
+            return Err(error::io(err));
+        }
+
         Ok(email_id.to_string())
     }
 }
@@ -304,25 +490,53 @@ where
     type Ok = Id;
     type Error = Error;
 
+    #[cfg(feature = "builder")]
+    async fn send(&self, message: Message) -> Result<Self::Ok, Self::Error> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!("starting to send an email");
+
+        let raw = message.try_formatted().map_err(error::io)?;
+        self.send_raw(message.envelope(), &raw).await
+    }
+
     async fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
         let email_id = Uuid::new_v4();
 
-        let file = self.inner.path(&email_id, "eml");
-        #[cfg(feature = "tracing")]
-        tracing::debug!(?file, "writing email to");
-        E::fs_write(&file, email).await.map_err(error::io)?;
-
         #[cfg(feature = "file-transport-envelope")]
-        {
-            if self.inner.save_envelope {
-                let file = self.inner.path(&email_id, "json");
-                let buf = serde_json::to_vec(&envelope).map_err(error::envelope)?;
-                E::fs_write(&file, &buf).await.map_err(error::io)?;
-            }
-        }
+        let envelope_buf = if self.inner.save_envelope {
+            Some(serde_json::to_vec(&envelope).map_err(error::envelope)?)
+        } else {
+            None
+        };
+        #[cfg(not(feature = "file-transport-envelope"))]
+        let envelope_buf: Option<Vec<u8>> = None;
         // use envelope anyway
         let _ = envelope;
 
+        let size = (email.len() + envelope_buf.as_ref().map_or(0, Vec::len)) as u64;
+        // Reserving may scan the directory and evict older messages
+        let spool = self.inner.spool.clone();
+        let path = self.inner.path.clone();
+        E::spawn_blocking(move || spool.reserve(&path, size)).await?;
+
+        let json_file = self.inner.path(&email_id, "json");
+        let mut result = match envelope_buf {
+            Some(buf) => Self::write(&json_file, &buf).await,
+            None => Ok(()),
+        };
+
+        if result.is_ok() {
+            let file = self.inner.path(&email_id, "eml");
+            #[cfg(feature = "tracing")]
+            tracing::debug!(?file, "writing email to");
+            result = Self::write(&file, email).await;
+        }
+        if let Err(err) = result {
+            let _ = E::fs_remove_file(&json_file).await;
+            self.inner.spool.release(size);
+            return Err(error::io(err));
+        }
+
         Ok(email_id.to_string())
     }
 }
@@ -0,0 +1,188 @@
+//! Size accounting for the directory used by the file transport
+
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use super::{error, Error};
+
+/// What to do when writing a message would exceed the spool limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SpoolPolicy {
+    /// Refuse the new message with an error for which
+    /// [`Error::is_spool_full`] returns `true`
+    #[default]
+    Reject,
+    /// Delete the oldest messages in the spool until the new one fits
+    EvictOldest,
+}
+
+/// Current size of a file transport spool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpoolUsage {
+    bytes: u64,
+    files: u64,
+}
+
+impl SpoolUsage {
+    /// Total size in bytes of the messages and envelopes in the spool
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Number of messages in the spool
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+}
+
+/// Configured limits and the incrementally maintained usage of a spool
+///
+/// The usage is shared between clones of the transport.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(super) struct Spool {
+    pub(super) max_bytes: Option<u64>,
+    pub(super) max_files: Option<u64>,
+    pub(super) policy: SpoolPolicy,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    usage: Arc<Mutex<Option<SpoolUsage>>>,
+}
+
+impl Spool {
+    fn is_bounded(&self) -> bool {
+        self.max_bytes.is_some() || self.max_files.is_some()
+    }
+
+    /// Whether a message of `bytes` bytes fits in an empty spool
+    fn can_ever_fit(&self, bytes: u64) -> bool {
+        self.max_bytes.map_or(true, |max| bytes <= max) && self.max_files != Some(0)
+    }
+
+    fn fits(&self, usage: SpoolUsage, bytes: u64) -> bool {
+        self.max_bytes
+            .map_or(true, |max| usage.bytes + bytes <= max)
+            && self.max_files.map_or(true, |max| usage.files < max)
+    }
+
+    /// Returns the usage of the spool, scanning the directory on first use
+    pub(super) fn usage(&self, dir: &Path) -> Result<SpoolUsage, Error> {
+        let mut usage = self.usage.lock().unwrap();
+        match *usage {
+            Some(u) => Ok(u),
+            None => {
+                let u = scan(dir)?;
+                *usage = Some(u);
+                Ok(u)
+            }
+        }
+    }
+
+    /// Makes room for a message of `bytes` bytes and accounts for it
+    ///
+    /// The counters are reconciled with a directory scan before a message
+    /// is rejected or older ones are evicted, so files removed by
+    /// something else than the transport are taken into account.
+    /// A message larger than the whole spool is rejected without evicting
+    /// anything.
+    pub(super) fn reserve(&self, dir: &Path, bytes: u64) -> Result<(), Error> {
+        if !self.is_bounded() {
+            return Ok(());
+        }
+        if !self.can_ever_fit(bytes) {
+            return Err(error::spool_full());
+        }
+
+        let mut usage = self.usage.lock().unwrap();
+        let mut current = match *usage {
+            Some(u) => u,
+            None => scan(dir)?,
+        };
+
+        if !self.fits(current, bytes) {
+            current = scan(dir)?;
+        }
+        if !self.fits(current, bytes) && self.policy == SpoolPolicy::EvictOldest {
+            current = self.evict(dir, current, bytes)?;
+        }
+        if !self.fits(current, bytes) {
+            *usage = Some(current);
+            return Err(error::spool_full());
+        }
+
+        current.bytes += bytes;
+        current.files += 1;
+        *usage = Some(current);
+        Ok(())
+    }
+
+    /// Gives back a reservation made for a message which could not be written
+    pub(super) fn release(&self, bytes: u64) {
+        if let Some(usage) = self.usage.lock().unwrap().as_mut() {
+            usage.bytes = usage.bytes.saturating_sub(bytes);
+            usage.files = usage.files.saturating_sub(1);
+        }
+    }
+
+    fn evict(&self, dir: &Path, mut usage: SpoolUsage, bytes: u64) -> Result<SpoolUsage, Error> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dir).map_err(error::io)? {
+            let path = entry.map_err(error::io)?.path();
+            if path.extension().is_some_and(|ext| ext == "eml") {
+                let metadata = fs::metadata(&path).map_err(error::io)?;
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                entries.push((modified, path, metadata.len()));
+            }
+        }
+        entries.sort();
+
+        for (_, eml, len) in entries {
+            if self.fits(usage, bytes) {
+                break;
+            }
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!(file = ?eml, "evicting email from spool");
+            fs::remove_file(&eml).map_err(error::io)?;
+            usage.bytes = usage.bytes.saturating_sub(len);
+            usage.files = usage.files.saturating_sub(1);
+
+            let json = eml.with_extension("json");
+            if let Ok(metadata) = fs::metadata(&json) {
+                fs::remove_file(&json).map_err(error::io)?;
+                usage.bytes = usage.bytes.saturating_sub(metadata.len());
+            }
+        }
+
+        Ok(usage)
+    }
+}
+
+fn scan(dir: &Path) -> Result<SpoolUsage, Error> {
+    let mut usage = SpoolUsage { bytes: 0, files: 0 };
+
+    for entry in fs::read_dir(dir).map_err(error::io)? {
+        let entry = entry.map_err(error::io)?;
+        let path = entry.path();
+        let is_eml = path.extension().is_some_and(|ext| ext == "eml");
+        let is_json = path.extension().is_some_and(|ext| ext == "json");
+        if !is_eml && !is_json {
+            continue;
+        }
+
+        let metadata = entry.metadata().map_err(error::io)?;
+        if !metadata.is_file() {
+            continue;
+        }
+        usage.bytes += metadata.len();
+        if is_eml {
+            usage.files += 1;
+        }
+    }
+
+    Ok(usage)
+}
@@ -34,6 +34,17 @@
 //! | [`file`]     | File     | [`FileTransport`]     | [`AsyncFileTransport`]     | Saves the email as an `.eml` file                       |
 //! | [`stub`]     | Debug    | [`StubTransport`]     | [`StubTransport`]          | Drops the email - Useful for debugging                  |
 //!
+//! Transports which can fail with transient errors, like [`smtp`], can be wrapped in
+//! a [`RetryingTransport`] to send the email again after such errors. A
+//! [`TeeTransport`] copies the emails sent through a transport to another one, and a
+//! [`RoutingTransport`] picks the transport depending on the recipient domains. A
+//! [`FailoverTransport`] or a [`RoundRobinTransport`] sends the emails through the
+//! first of several relays which is available. An
+//! [`OverflowTransport`] handles the emails which are too large for a transport. An
+//! [`EnsureHeadersTransport`] adds the `Date` and `Message-ID` headers to the emails
+//! missing them, and a [`RateLimitedTransport`] limits the number of emails sent
+//! over time.
+//!
 //! ## Building an email
 //!
 //! Emails can either be built though [`Message`], which is a typed API for constructing emails
@@ -97,6 +108,14 @@
 //! [`FileTransport`]: crate::FileTransport
 //! [`AsyncFileTransport`]: crate::AsyncFileTransport
 //! [`StubTransport`]: crate::transport::stub::StubTransport
+//! [`RetryingTransport`]: crate::transport::retry::RetryingTransport
+//! [`TeeTransport`]: crate::transport::tee::TeeTransport
+//! [`RoutingTransport`]: crate::transport::routing::RoutingTransport
+//! [`OverflowTransport`]: crate::transport::overflow::OverflowTransport
+//! [`FailoverTransport`]: crate::transport::failover::FailoverTransport
+//! [`RoundRobinTransport`]: crate::transport::failover::RoundRobinTransport
+//! [`EnsureHeadersTransport`]: crate::transport::ensure_headers::EnsureHeadersTransport
+//! [`RateLimitedTransport`]: crate::transport::rate_limit::RateLimitedTransport
 
 #[cfg(any(feature = "async-std1", feature = "tokio1"))]
 use async_trait::async_trait;
@@ -105,9 +124,18 @@ use crate::Envelope;
 #[cfg(feature = "builder")]
 use crate::Message;
 
+mod delivery;
+#[cfg(feature = "builder")]
+#[cfg_attr(docsrs, doc(cfg(feature = "builder")))]
+pub mod ensure_headers;
+pub mod failover;
 #[cfg(feature = "file-transport")]
 #[cfg_attr(docsrs, doc(cfg(feature = "file-transport")))]
 pub mod file;
+pub mod overflow;
+pub mod rate_limit;
+pub mod retry;
+pub mod routing;
 #[cfg(feature = "sendmail-transport")]
 #[cfg_attr(docsrs, doc(cfg(feature = "sendmail-transport")))]
 pub mod sendmail;
@@ -115,6 +143,7 @@ pub mod sendmail;
 #[cfg_attr(docsrs, doc(cfg(feature = "smtp-transport")))]
 pub mod smtp;
 pub mod stub;
+pub mod tee;
 
 /// Blocking Transport method for emails
 pub trait Transport {
@@ -124,6 +153,14 @@ pub trait Transport {
     type Error;
 
     /// Sends the email
+    ///
+    /// The default implementation formats the message with [`Message::formatted`],
+    /// which panics if the file of a [`LazyAttachment`] can't be read anymore.
+    /// The transports of this crate override it to return an error instead,
+    /// and the ones wrapping other transports pass the message on to their
+    /// `send`.
+    ///
+    /// [`LazyAttachment`]: crate::message::LazyAttachment
     #[cfg(feature = "builder")]
     #[cfg_attr(docsrs, doc(cfg(feature = "builder")))]
     fn send(&self, message: &Message) -> Result<Self::Ok, Self::Error> {
@@ -148,6 +185,12 @@ pub trait AsyncTransport {
     type Error;
 
     /// Sends the email
+    ///
+    /// The default implementation formats the message with [`Message::formatted`],
+    /// which panics if the file of a [`LazyAttachment`] can't be read anymore.
+    /// The transports of this crate override it to return an error instead.
+    ///
+    /// [`LazyAttachment`]: crate::message::LazyAttachment
     #[cfg(feature = "builder")]
     #[cfg_attr(docsrs, doc(cfg(feature = "builder")))]
     // TODO take &Message
@@ -162,3 +205,38 @@ pub trait AsyncTransport {
 
     async fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error>;
 }
+
+/// Returns the envelope of the emails sent in the tests of the transports
+#[cfg(test)]
+pub(crate) fn envelope() -> Envelope {
+    Envelope::new(
+        Some("nobody@domain.tld".parse().unwrap()),
+        vec!["hei@domain.tld".parse().unwrap()],
+    )
+    .unwrap()
+}
+
+/// Returns a message whose lazy attachment was removed after building it
+///
+/// `name` is the name of the file of the attachment, unique to each test.
+#[cfg(all(test, feature = "builder"))]
+pub(crate) fn message_with_removed_attachment(name: &str) -> Message {
+    use crate::message::{header::ContentType, Attachment, MultiPart, SinglePart};
+
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, b"%PDF-1.7").unwrap();
+    let attachment = Attachment::new(String::from("report.pdf"))
+        .lazy(&path, ContentType::parse("application/pdf").unwrap());
+    let message = Message::builder()
+        .from("NoBody <nobody@domain.tld>".parse().unwrap())
+        .to("Hei <hei@domain.tld>".parse().unwrap())
+        .subject("Report")
+        .multipart(
+            MultiPart::mixed()
+                .singlepart(SinglePart::plain(String::from("The report is attached")))
+                .lazy_attachment(attachment),
+        )
+        .unwrap();
+    std::fs::remove_file(path).unwrap();
+    message
+}
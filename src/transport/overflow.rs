@@ -0,0 +1,499 @@
+//! The overflow transport sends emails through a primary transport, and
+//! decides what to do with the emails it rejects because they are too large.
+//!
+//! An email is considered too large when the primary transport fails with an
+//! error for which [`SizeLimitError::is_message_too_large`] returns `true`. For
+//! the SMTP transport, this happens before the message is sent when it is larger
+//! than the limit advertised by the server with the `SIZE` extension, so the
+//! decision is based on the exact size of the formatted message.
+//!
+//! What happens then depends on the [`SizeOverflowPolicy`]:
+//!
+//! * [`Reject`](SizeOverflowPolicy::Reject), the default, returns the error
+//! * [`RouteTo`](SizeOverflowPolicy::RouteTo) sends the email through an alternate transport
+//! * [`Transform`](SizeOverflowPolicy::Transform) sends a smaller replacement, for
+//!   example with a link to the original, through the primary transport, and then the
+//!   email through an alternate transport
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use std::error::Error;
+//! #
+//! # #[cfg(all(feature = "builder", feature = "smtp-transport"))]
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! use lettre::{
+//!     transport::overflow::{OverflowTransport, SizeOverflowPolicy},
+//!     Message, SmtpTransport, Transport,
+//! };
+//!
+//! let email = Message::builder()
+//!     .from("NoBody <nobody@domain.tld>".parse()?)
+//!     .to("Hei <hei@domain.tld>".parse()?)
+//!     .subject("Happy new year")
+//!     .body(String::from("Be happy!"))?;
+//!
+//! let mailer = OverflowTransport::new(
+//!     SmtpTransport::relay("smtp.example.com")?.build(),
+//!     SizeOverflowPolicy::RouteTo(SmtpTransport::relay("bulk.example.com")?.build()),
+//! );
+//! mailer.send(&email)?;
+//! # Ok(())
+//! # }
+//! # #[cfg(not(all(feature = "builder", feature = "smtp-transport")))]
+//! # fn main() {}
+//! ```
+
+use std::{error::Error as StdError, fmt};
+
+use super::Transport;
+use crate::address::Envelope;
+#[cfg(feature = "builder")]
+use crate::Message;
+
+/// An error which tells whether the email was rejected because of its size
+pub trait SizeLimitError {
+    /// Returns true if the email is larger than what the transport accepts
+    fn is_message_too_large(&self) -> bool;
+}
+
+/// What an [`OverflowTransport`] does with the emails which are too large
+/// for its primary transport
+#[derive(Default)]
+#[non_exhaustive]
+pub enum SizeOverflowPolicy<A> {
+    /// Return the error of the primary transport
+    #[default]
+    Reject,
+    /// Send the email through the alternate transport
+    RouteTo(A),
+    /// Send the message returned by `transform` through the primary transport,
+    /// and then the email through the alternate transport
+    ///
+    /// The replacement is sent first, so nothing was delivered when the primary
+    /// transport fails with [`OverflowError::Primary`], while the replacement was
+    /// already delivered when the alternate transport fails with
+    /// [`OverflowError::Alternate`].
+    ///
+    /// Only emails sent with [`Transport::send`] can be transformed. Emails
+    /// sent with [`Transport::send_raw`] are only sent through the alternate
+    /// transport, like with [`SizeOverflowPolicy::RouteTo`].
+    #[cfg(feature = "builder")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "builder")))]
+    Transform {
+        /// The transport the original email is sent through
+        alternate: A,
+        /// Creates the message sent through the primary transport instead of the original
+        transform: Box<dyn Fn(&Message) -> Message + Send + Sync>,
+    },
+}
+
+impl<A> SizeOverflowPolicy<A> {
+    /// Returns the alternate transport, if any
+    pub fn alternate(&self) -> Option<&A> {
+        match self {
+            Self::Reject => None,
+            Self::RouteTo(alternate) => Some(alternate),
+            #[cfg(feature = "builder")]
+            Self::Transform { alternate, .. } => Some(alternate),
+        }
+    }
+}
+
+impl<A: fmt::Debug> fmt::Debug for SizeOverflowPolicy<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reject => f.write_str("Reject"),
+            Self::RouteTo(alternate) => f.debug_tuple("RouteTo").field(alternate).finish(),
+            #[cfg(feature = "builder")]
+            Self::Transform { alternate, .. } => f
+                .debug_struct("Transform")
+                .field("alternate", alternate)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+/// How an email was sent by an [`OverflowTransport`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Delivery<P, A> {
+    /// The email was sent through the primary transport
+    Primary(P),
+    /// The email was too large and was sent through the alternate transport
+    Alternate(A),
+    /// The email was too large and was sent through the alternate transport,
+    /// and its replacement through the primary transport
+    Transformed {
+        /// Result of sending the replacement through the primary transport
+        primary: P,
+        /// Result of sending the original email through the alternate transport
+        alternate: A,
+    },
+}
+
+/// The error of an [`OverflowTransport`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OverflowError<P, A> {
+    /// The primary transport failed
+    Primary(P),
+    /// The alternate transport failed
+    Alternate(A),
+}
+
+impl<P: fmt::Display, A: fmt::Display> fmt::Display for OverflowError<P, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Primary(err) => write!(f, "primary transport: {err}"),
+            Self::Alternate(err) => write!(f, "alternate transport: {err}"),
+        }
+    }
+}
+
+impl<P, A> StdError for OverflowError<P, A>
+where
+    P: StdError + 'static,
+    A: StdError + 'static,
+{
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Primary(err) => Some(err),
+            Self::Alternate(err) => Some(err),
+        }
+    }
+}
+
+/// A transport sending emails through a primary transport, and handling
+/// the emails which are too large for it with a [`SizeOverflowPolicy`]
+#[derive(Debug)]
+pub struct OverflowTransport<P, A> {
+    primary: P,
+    policy: SizeOverflowPolicy<A>,
+}
+
+impl<P, A> OverflowTransport<P, A> {
+    /// Creates a new transport sending emails through `primary`,
+    /// and handling those which are too large with `policy`
+    pub fn new(primary: P, policy: SizeOverflowPolicy<A>) -> Self {
+        Self { primary, policy }
+    }
+
+    /// Returns the primary transport
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    /// Returns the policy for the emails which are too large
+    pub fn policy(&self) -> &SizeOverflowPolicy<A> {
+        &self.policy
+    }
+}
+
+impl<P, A> Transport for OverflowTransport<P, A>
+where
+    P: Transport,
+    P::Error: SizeLimitError,
+    A: Transport,
+{
+    type Ok = Delivery<P::Ok, A::Ok>;
+    type Error = OverflowError<P::Error, A::Error>;
+
+    #[cfg(feature = "builder")]
+    fn send(&self, message: &Message) -> Result<Self::Ok, Self::Error> {
+        let err = match self.primary.send(message) {
+            Ok(ok) => return Ok(Delivery::Primary(ok)),
+            Err(err) if err.is_message_too_large() => err,
+            Err(err) => return Err(OverflowError::Primary(err)),
+        };
+
+        match &self.policy {
+            SizeOverflowPolicy::Transform {
+                alternate,
+                transform,
+            } => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    "email too large, sending its replacement through the primary transport"
+                );
+                let primary = self
+                    .primary
+                    .send(&transform(message))
+                    .map_err(OverflowError::Primary)?;
+                let alternate = alternate.send(message).map_err(OverflowError::Alternate)?;
+                Ok(Delivery::Transformed { primary, alternate })
+            }
+            SizeOverflowPolicy::RouteTo(alternate) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("email too large, sending it through the alternate transport");
+                alternate
+                    .send(message)
+                    .map(Delivery::Alternate)
+                    .map_err(OverflowError::Alternate)
+            }
+            SizeOverflowPolicy::Reject => Err(OverflowError::Primary(err)),
+        }
+    }
+
+    fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        match self.primary.send_raw(envelope, email) {
+            Ok(ok) => Ok(Delivery::Primary(ok)),
+            Err(err) if err.is_message_too_large() => self.overflow(err, envelope, email),
+            Err(err) => Err(OverflowError::Primary(err)),
+        }
+    }
+}
+
+impl<P, A> OverflowTransport<P, A>
+where
+    P: Transport,
+    P::Error: SizeLimitError,
+    A: Transport,
+{
+    /// Sends an email which is too large for the primary transport
+    /// through the alternate transport, if any
+    fn overflow(
+        &self,
+        err: P::Error,
+        envelope: &Envelope,
+        email: &[u8],
+    ) -> Result<<Self as Transport>::Ok, <Self as Transport>::Error> {
+        let Some(alternate) = self.policy.alternate() else {
+            return Err(OverflowError::Primary(err));
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!("email too large, sending it through the alternate transport");
+        alternate
+            .send_raw(envelope, email)
+            .map(Delivery::Alternate)
+            .map_err(OverflowError::Alternate)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+
+    use super::{
+        Delivery, OverflowError, OverflowTransport, SizeLimitError, SizeOverflowPolicy, Transport,
+    };
+    #[cfg(feature = "builder")]
+    use crate::Message;
+    use crate::{
+        address::Envelope,
+        transport::{envelope, stub::StubTransport},
+    };
+
+    const LIMIT: usize = 1024 * 1024;
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum Reply {
+        TooLarge,
+        Other,
+    }
+
+    impl SizeLimitError for Reply {
+        fn is_message_too_large(&self) -> bool {
+            matches!(self, Reply::TooLarge)
+        }
+    }
+
+    /// Accepts the emails up to 1 MB, like a server advertising `SIZE 1048576`
+    #[derive(Default)]
+    struct Limited {
+        sent: RefCell<Vec<Vec<u8>>>,
+        fail: bool,
+    }
+
+    impl Transport for Limited {
+        type Ok = usize;
+        type Error = Reply;
+
+        fn send_raw(&self, _envelope: &Envelope, email: &[u8]) -> Result<usize, Reply> {
+            if email.len() > LIMIT {
+                return Err(Reply::TooLarge);
+            }
+            if self.fail {
+                return Err(Reply::Other);
+            }
+            self.sent.borrow_mut().push(email.to_vec());
+            Ok(email.len())
+        }
+    }
+
+    fn large_email() -> Vec<u8> {
+        vec![b'a'; 2 * LIMIT]
+    }
+
+    #[cfg(feature = "builder")]
+    fn message(body: String) -> Message {
+        Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Report")
+            .body(body)
+            .unwrap()
+    }
+
+    /// Records whether the emails were sent with `send` or `send_raw`
+    #[cfg(feature = "builder")]
+    #[derive(Default)]
+    struct Recorder {
+        calls: RefCell<Vec<&'static str>>,
+    }
+
+    #[cfg(feature = "builder")]
+    impl Transport for Recorder {
+        type Ok = ();
+        type Error = Reply;
+
+        fn send(&self, _message: &Message) -> Result<(), Reply> {
+            self.calls.borrow_mut().push("send");
+            Ok(())
+        }
+
+        fn send_raw(&self, _envelope: &Envelope, _email: &[u8]) -> Result<(), Reply> {
+            self.calls.borrow_mut().push("send_raw");
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn overflow_reject() {
+        let transport =
+            OverflowTransport::<_, StubTransport>::new(Limited::default(), Default::default());
+
+        assert!(matches!(
+            transport.send_raw(&envelope(), &large_email()),
+            Err(OverflowError::Primary(Reply::TooLarge))
+        ));
+        assert!(matches!(
+            transport.send_raw(&envelope(), b"Hi"),
+            Ok(Delivery::Primary(2))
+        ));
+    }
+
+    #[test]
+    fn overflow_route_to() {
+        let transport = OverflowTransport::new(
+            Limited::default(),
+            SizeOverflowPolicy::RouteTo(StubTransport::new_ok()),
+        );
+        let alternate = transport.policy().alternate().unwrap();
+
+        assert!(matches!(
+            transport.send_raw(&envelope(), &large_email()),
+            Ok(Delivery::Alternate(()))
+        ));
+        assert!(transport.primary().sent.borrow().is_empty());
+        assert_eq!(alternate.messages().len(), 1);
+        assert_eq!(alternate.messages()[0].1.len(), 2 * LIMIT);
+
+        // Small emails and other errors don't use the alternate transport
+        assert!(matches!(
+            transport.send_raw(&envelope(), b"Hi"),
+            Ok(Delivery::Primary(2))
+        ));
+        let transport = OverflowTransport::new(
+            Limited {
+                fail: true,
+                ..Limited::default()
+            },
+            SizeOverflowPolicy::RouteTo(StubTransport::new_ok()),
+        );
+        assert!(matches!(
+            transport.send_raw(&envelope(), b"Hi"),
+            Err(OverflowError::Primary(Reply::Other))
+        ));
+        assert!(transport
+            .policy()
+            .alternate()
+            .unwrap()
+            .messages()
+            .is_empty());
+    }
+
+    #[cfg(feature = "builder")]
+    #[test]
+    fn overflow_message() {
+        let original = message("a".repeat(2 * LIMIT));
+
+        let transport =
+            OverflowTransport::<_, StubTransport>::new(Limited::default(), Default::default());
+        assert!(matches!(
+            transport.send(&original),
+            Err(OverflowError::Primary(Reply::TooLarge))
+        ));
+
+        // The alternate transport gets the message, not the formatted email
+        let transport = OverflowTransport::new(
+            Limited::default(),
+            SizeOverflowPolicy::RouteTo(Recorder::default()),
+        );
+        assert!(matches!(
+            transport.send(&original),
+            Ok(Delivery::Alternate(()))
+        ));
+        assert_eq!(
+            *transport.policy().alternate().unwrap().calls.borrow(),
+            ["send"]
+        );
+    }
+
+    #[cfg(feature = "builder")]
+    #[test]
+    fn overflow_transform() {
+        let transport = OverflowTransport::new(
+            Limited::default(),
+            SizeOverflowPolicy::Transform {
+                alternate: StubTransport::new_ok(),
+                transform: Box::new(|_| {
+                    message("The report is available on the intranet".to_owned())
+                }),
+            },
+        );
+        let alternate = transport.policy().alternate().unwrap();
+
+        let original = message("a".repeat(2 * LIMIT));
+        let Ok(Delivery::Transformed { primary, .. }) = transport.send(&original) else {
+            panic!("the email wasn't transformed");
+        };
+
+        let sent = transport.primary().sent.borrow();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(primary, sent[0].len());
+        assert!(String::from_utf8_lossy(&sent[0]).contains("available on the intranet"));
+        assert_eq!(alternate.messages().len(), 1);
+        assert_eq!(alternate.messages()[0].0, envelope());
+        assert!(alternate.messages()[0].1.len() > 2 * LIMIT);
+
+        // Without a message, the email can only be sent as is
+        drop(sent);
+        assert!(matches!(
+            transport.send_raw(&envelope(), &large_email()),
+            Ok(Delivery::Alternate(()))
+        ));
+        assert_eq!(transport.primary().sent.borrow().len(), 1);
+
+        // When the replacement can't be sent, the original isn't sent either
+        let transport = OverflowTransport::new(
+            Limited {
+                fail: true,
+                ..Limited::default()
+            },
+            SizeOverflowPolicy::Transform {
+                alternate: StubTransport::new_ok(),
+                transform: Box::new(|_| message("Too large".to_owned())),
+            },
+        );
+        assert!(matches!(
+            transport.send(&original),
+            Err(OverflowError::Primary(Reply::Other))
+        ));
+        assert!(transport
+            .policy()
+            .alternate()
+            .unwrap()
+            .messages()
+            .is_empty());
+    }
+}
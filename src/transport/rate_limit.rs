@@ -0,0 +1,333 @@
+//! The rate limited transport wraps another transport and limits the number
+//! of emails sent through it over time.
+//!
+//! Providers often reject the emails of clients sending too many of them per
+//! minute. The rate limited transport sends at most `max_per_window` emails
+//! per `window` on average, with a token bucket: the bucket holds up to
+//! `max_per_window` tokens, each email takes one, and tokens are added back
+//! continuously at the allowed rate. Bursts of up to `max_per_window` emails
+//! are sent at once after an idle period.
+//!
+//! When the bucket is empty, the [`RateLimitPolicy`] decides whether to wait
+//! for the next token, the default, or to return an error immediately.
+//!
+//! The transport can be shared between threads, the limit applies to all the
+//! emails sent through it.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use std::error::Error;
+//! #
+//! # #[cfg(all(feature = "builder", feature = "smtp-transport"))]
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! use std::time::Duration;
+//!
+//! use lettre::{transport::rate_limit::RateLimitedTransport, Message, SmtpTransport, Transport};
+//!
+//! let email = Message::builder()
+//!     .from("NoBody <nobody@domain.tld>".parse()?)
+//!     .to("Hei <hei@domain.tld>".parse()?)
+//!     .subject("Happy new year")
+//!     .body(String::from("Be happy!"))?;
+//!
+//! // At most 30 emails per minute
+//! let mailer = RateLimitedTransport::new(
+//!     SmtpTransport::relay("smtp.example.com")?.build(),
+//!     30,
+//!     Duration::from_secs(60),
+//! );
+//! mailer.send(&email)?;
+//! # Ok(())
+//! # }
+//! # #[cfg(not(all(feature = "builder", feature = "smtp-transport")))]
+//! # fn main() {}
+//! ```
+
+use std::{
+    error::Error as StdError,
+    fmt,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{retry::TransientError, Transport};
+use crate::address::Envelope;
+#[cfg(feature = "builder")]
+use crate::Message;
+
+/// What a [`RateLimitedTransport`] does when the limit is reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RateLimitPolicy {
+    /// Block the calling thread until the email can be sent
+    #[default]
+    Wait,
+    /// Return [`RateLimitError::Limited`] without sending the email
+    Reject,
+}
+
+/// The error of a [`RateLimitedTransport`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RateLimitError<E> {
+    /// The limit was reached, and the email wasn't sent
+    ///
+    /// Only returned with [`RateLimitPolicy::Reject`].
+    Limited {
+        /// The delay after which the email could be sent
+        retry_after: Duration,
+    },
+    /// The inner transport failed
+    Inner(E),
+}
+
+impl<E: fmt::Display> fmt::Display for RateLimitError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Limited { retry_after } => {
+                write!(f, "rate limit reached, retry after {retry_after:?}")
+            }
+            Self::Inner(err) => err.fmt(f),
+        }
+    }
+}
+
+impl<E: StdError + 'static> StdError for RateLimitError<E> {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Limited { .. } => None,
+            Self::Inner(err) => Some(err),
+        }
+    }
+}
+
+impl<E: TransientError> TransientError for RateLimitError<E> {
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Limited { .. } => true,
+            Self::Inner(err) => err.is_transient(),
+        }
+    }
+}
+
+/// The tokens available to send emails
+#[derive(Debug)]
+struct Bucket {
+    /// Negative when threads are waiting for the next tokens
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// A transport sending at most a number of emails per time window
+/// through the inner transport
+#[derive(Debug)]
+pub struct RateLimitedTransport<T> {
+    inner: T,
+    max_per_window: u32,
+    window: Duration,
+    policy: RateLimitPolicy,
+    bucket: Mutex<Bucket>,
+}
+
+impl<T> RateLimitedTransport<T> {
+    /// Creates a new transport sending at most `max_per_window` emails
+    /// per `window` through `inner`
+    ///
+    /// The bucket starts full, so the first `max_per_window` emails are
+    /// sent without waiting.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_per_window` is zero or `window` is zero.
+    pub fn new(inner: T, max_per_window: u32, window: Duration) -> Self {
+        assert!(max_per_window > 0, "max_per_window must be positive");
+        assert!(!window.is_zero(), "window must be positive");
+
+        Self {
+            inner,
+            max_per_window,
+            window,
+            policy: RateLimitPolicy::default(),
+            bucket: Mutex::new(Bucket {
+                tokens: f64::from(max_per_window),
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Sets what happens when the limit is reached
+    pub fn on_limit(mut self, policy: RateLimitPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Returns the wrapped transport
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Takes a token, waiting for it or failing depending on the policy
+    fn acquire<E>(&self) -> Result<(), RateLimitError<E>> {
+        let capacity = f64::from(self.max_per_window);
+        let per_second = capacity / self.window.as_secs_f64();
+
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * per_second).min(capacity);
+            bucket.last_refill = now;
+
+            if bucket.tokens >= 1.0 {
+                bucket.tokens -= 1.0;
+                return Ok(());
+            }
+
+            let wait = Duration::from_secs_f64((1.0 - bucket.tokens) / per_second);
+            if self.policy == RateLimitPolicy::Reject {
+                return Err(RateLimitError::Limited { retry_after: wait });
+            }
+            // Reserve the token now, so that waiting threads are served in order
+            bucket.tokens -= 1.0;
+            wait
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(?wait, "rate limit reached, waiting");
+        thread::sleep(wait);
+        Ok(())
+    }
+}
+
+impl<T: Transport> Transport for RateLimitedTransport<T> {
+    type Ok = T::Ok;
+    type Error = RateLimitError<T::Error>;
+
+    #[cfg(feature = "builder")]
+    fn send(&self, message: &Message) -> Result<Self::Ok, Self::Error> {
+        self.acquire()?;
+        self.inner.send(message).map_err(RateLimitError::Inner)
+    }
+
+    fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        self.acquire()?;
+        self.inner
+            .send_raw(envelope, email)
+            .map_err(RateLimitError::Inner)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{RateLimitError, RateLimitPolicy, RateLimitedTransport, Transport};
+    use crate::{
+        address::Envelope,
+        transport::{envelope, retry::TransientError, stub::StubTransport},
+    };
+
+    #[derive(Default)]
+    struct Counting(AtomicUsize);
+
+    impl Transport for Counting {
+        type Ok = ();
+        type Error = ();
+
+        fn send_raw(&self, _envelope: &Envelope, _email: &[u8]) -> Result<(), ()> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rate_limit_reject() {
+        let transport = RateLimitedTransport::new(Counting::default(), 3, Duration::from_secs(60))
+            .on_limit(RateLimitPolicy::Reject);
+
+        // A burst up to the size of the bucket is allowed
+        for _ in 0..3 {
+            transport.send_raw(&envelope(), b"Hi").unwrap();
+        }
+        let Err(err) = transport.send_raw(&envelope(), b"Hi") else {
+            panic!("the limit wasn't enforced");
+        };
+        let RateLimitError::Limited { retry_after } = err else {
+            panic!("unexpected error {err:?}");
+        };
+        assert!(retry_after > Duration::from_secs(19), "{retry_after:?}");
+        assert!(retry_after <= Duration::from_secs(20), "{retry_after:?}");
+        assert_eq!(transport.inner().0.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn rate_limit_wait() {
+        let transport =
+            RateLimitedTransport::new(StubTransport::new_ok(), 2, Duration::from_millis(200));
+
+        let start = Instant::now();
+        for _ in 0..2 {
+            transport.send_raw(&envelope(), b"Hi").unwrap();
+        }
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        // The next tokens are added every 100ms
+        for _ in 0..2 {
+            transport.send_raw(&envelope(), b"Hi").unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(transport.inner().messages().len(), 4);
+    }
+
+    #[test]
+    fn rate_limit_threads() {
+        let transport = Arc::new(
+            RateLimitedTransport::new(Counting::default(), 5, Duration::from_secs(3600))
+                .on_limit(RateLimitPolicy::Reject),
+        );
+
+        let threads = (0..4)
+            .map(|_| {
+                let transport = Arc::clone(&transport);
+                thread::spawn(move || {
+                    (0..5)
+                        .filter(|_| transport.send_raw(&envelope(), b"Hi").is_ok())
+                        .count()
+                })
+            })
+            .collect::<Vec<_>>();
+        let sent = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .sum::<usize>();
+
+        assert_eq!(sent, 5);
+        assert_eq!(transport.inner().0.load(Ordering::SeqCst), 5);
+    }
+
+    #[derive(Debug)]
+    struct Permanent;
+
+    impl TransientError for Permanent {
+        fn is_transient(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn rate_limit_error_transient() {
+        // Retrying after the limit was reached can succeed
+        assert!(RateLimitError::<Permanent>::Limited {
+            retry_after: Duration::from_secs(1)
+        }
+        .is_transient());
+        assert!(!RateLimitError::Inner(Permanent).is_transient());
+    }
+}
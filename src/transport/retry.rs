@@ -0,0 +1,216 @@
+//! The retrying transport wraps another transport and sends the email again
+//! when it fails with a transient error.
+//!
+//! Transient errors, like the `4xx` replies of an SMTP server, mean that the same
+//! message may be accepted later. The retrying transport retries the sending after
+//! an exponentially increasing delay, up to a maximum number of retries. Other
+//! errors, like permanent `5xx` SMTP replies, are returned immediately.
+//!
+//! With the `tracing` feature, the logs of all the tries are in a `delivery`
+//! span with a `delivery_id` field, and those of each try in an `attempt`
+//! span with its own `attempt_id` field. When retrying transports and
+//! failover transports are nested, they share the `delivery_id` of the
+//! outermost one.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use std::error::Error;
+//! #
+//! # #[cfg(all(feature = "builder", feature = "smtp-transport"))]
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! use std::time::Duration;
+//!
+//! use lettre::{transport::retry::RetryingTransport, Message, SmtpTransport, Transport};
+//!
+//! let email = Message::builder()
+//!     .from("NoBody <nobody@domain.tld>".parse()?)
+//!     .to("Hei <hei@domain.tld>".parse()?)
+//!     .subject("Happy new year")
+//!     .body(String::from("Be happy!"))?;
+//!
+//! // Waits 1, 2 and then 4 seconds before each of the 3 retries
+//! let mailer = RetryingTransport::new(
+//!     SmtpTransport::relay("smtp.example.com")?.build(),
+//!     3,
+//!     Duration::from_secs(1),
+//! );
+//! mailer.send(&email)?;
+//! # Ok(())
+//! # }
+//! # #[cfg(not(all(feature = "builder", feature = "smtp-transport")))]
+//! # fn main() {}
+//! ```
+
+use std::{thread, time::Duration};
+
+use super::{
+    delivery::{attempt, delivery},
+    Transport,
+};
+use crate::address::Envelope;
+#[cfg(feature = "builder")]
+use crate::Message;
+
+/// An error which tells whether sending the same email again could succeed
+pub trait TransientError {
+    /// Returns true if sending the email again later could succeed
+    fn is_transient(&self) -> bool;
+}
+
+/// A transport retrying to send emails when the inner transport
+/// fails with a transient error
+#[derive(Debug, Clone)]
+pub struct RetryingTransport<T> {
+    inner: T,
+    max_retries: u32,
+    base_delay: Duration,
+}
+
+impl<T> RetryingTransport<T> {
+    /// Creates a new transport sending emails through `inner`
+    ///
+    /// After a transient error, the email is sent again up to `max_retries` times.
+    /// The delay before the first retry is `base_delay`, and is doubled for each
+    /// subsequent retry.
+    pub fn new(inner: T, max_retries: u32, base_delay: Duration) -> Self {
+        Self {
+            inner,
+            max_retries,
+            base_delay,
+        }
+    }
+
+    /// Returns the wrapped transport
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Calls `send` until it succeeds, fails with a non transient error,
+    /// or the retries are exhausted, returning the last result
+    fn retry<O, E, F>(&self, mut send: F) -> Result<O, E>
+    where
+        E: TransientError,
+        F: FnMut() -> Result<O, E>,
+    {
+        let mut delay = self.base_delay;
+        let mut retries = 0;
+        delivery(|| loop {
+            match attempt(&mut send) {
+                Err(err) if err.is_transient() && retries < self.max_retries => {
+                    retries += 1;
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(retry = retries, ?delay, "transient error, retrying");
+                    thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                }
+                result => return result,
+            }
+        })
+    }
+}
+
+impl<T> Transport for RetryingTransport<T>
+where
+    T: Transport,
+    T::Error: TransientError,
+{
+    type Ok = T::Ok;
+    type Error = T::Error;
+
+    #[cfg(feature = "builder")]
+    fn send(&self, message: &Message) -> Result<Self::Ok, Self::Error> {
+        self.retry(|| self.inner.send(message))
+    }
+
+    fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        self.retry(|| self.inner.send_raw(envelope, email))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        cell::Cell,
+        time::{Duration, Instant},
+    };
+
+    use super::{RetryingTransport, TransientError, Transport};
+    use crate::{address::Envelope, transport::envelope};
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum Reply {
+        Transient(u32),
+        Permanent(u32),
+    }
+
+    impl TransientError for Reply {
+        fn is_transient(&self) -> bool {
+            matches!(self, Reply::Transient(_))
+        }
+    }
+
+    /// Fails with the given replies, then succeeds
+    struct Flaky {
+        replies: Vec<fn(u32) -> Reply>,
+        attempts: Cell<u32>,
+    }
+
+    impl Flaky {
+        fn new<const N: usize>(replies: [fn(u32) -> Reply; N]) -> Self {
+            Self {
+                replies: replies.to_vec(),
+                attempts: Cell::new(0),
+            }
+        }
+    }
+
+    impl Transport for Flaky {
+        type Ok = u32;
+        type Error = Reply;
+
+        fn send_raw(&self, _envelope: &Envelope, _email: &[u8]) -> Result<u32, Reply> {
+            let attempt = self.attempts.get();
+            self.attempts.set(attempt + 1);
+            match self.replies.get(attempt as usize) {
+                Some(reply) => Err(reply(attempt)),
+                None => Ok(attempt),
+            }
+        }
+    }
+
+    #[test]
+    fn retry_transient() {
+        let inner = Flaky::new([Reply::Transient; 2]);
+        let transport = RetryingTransport::new(inner, 3, Duration::from_millis(10));
+
+        let start = Instant::now();
+        assert_eq!(transport.send_raw(&envelope(), b"Hi"), Ok(2));
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert_eq!(transport.inner().attempts.get(), 3);
+    }
+
+    #[test]
+    fn retry_exhausted() {
+        let inner = Flaky::new([Reply::Transient; 5]);
+        let transport = RetryingTransport::new(inner, 2, Duration::ZERO);
+
+        assert_eq!(
+            transport.send_raw(&envelope(), b"Hi"),
+            Err(Reply::Transient(2))
+        );
+        assert_eq!(transport.inner().attempts.get(), 3);
+    }
+
+    #[test]
+    fn no_retry_permanent() {
+        let inner = Flaky::new([Reply::Transient, Reply::Permanent, Reply::Transient]);
+        let transport = RetryingTransport::new(inner, 5, Duration::ZERO);
+
+        assert_eq!(
+            transport.send_raw(&envelope(), b"Hi"),
+            Err(Reply::Permanent(1))
+        );
+        assert_eq!(transport.inner().attempts.get(), 2);
+    }
+}
@@ -0,0 +1,510 @@
+//! The routing transport sends emails through different transports depending
+//! on the domains of the recipients.
+//!
+//! Routes match a recipient domain either exactly, like `example.com`, or with
+//! all its subdomains, like `*.example.com`, ignoring the case. The recipients
+//! of an email are split by the first route matching their domain, and each
+//! group is sent with its own envelope through the transport of the route.
+//! Recipients matched by no route are sent through the default transport.
+//!
+//! A route can also rewrite the addresses of its recipients in the envelope,
+//! for example to strip subaddresses. The headers of the email are never
+//! modified.
+//!
+//! The email is sent through every route of its recipients, even when one
+//! of them fails. The [`RoutingReport`] tells which recipients each route
+//! got and what its transport returned, so that only the failed groups are
+//! sent again.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use std::error::Error;
+//! #
+//! # #[cfg(all(feature = "builder", feature = "smtp-transport"))]
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! use lettre::{
+//!     transport::routing::RoutingTransport, Address, Message, SmtpTransport, Transport,
+//! };
+//!
+//! fn strip_subaddress(address: &Address) -> Address {
+//!     let user = address.user().split('+').next().unwrap_or_default();
+//!     Address::new(user, address.domain()).unwrap_or_else(|_| address.clone())
+//! }
+//!
+//! let email = Message::builder()
+//!     .from("NoBody <nobody@domain.tld>".parse()?)
+//!     .to("Hei <hei+news@internal.example>".parse()?)
+//!     .to("Yuin <yuin@domain.tld>".parse()?)
+//!     .subject("Happy new year")
+//!     .body(String::from("Be happy!"))?;
+//!
+//! let mailer = RoutingTransport::new(SmtpTransport::relay("smtp.example.com")?.build())
+//!     .route_with_rewrite(
+//!         "internal.example",
+//!         SmtpTransport::builder_dangerous("mx.internal.example").build(),
+//!         strip_subaddress,
+//!     );
+//! mailer.send(&email)?;
+//! # Ok(())
+//! # }
+//! # #[cfg(not(all(feature = "builder", feature = "smtp-transport")))]
+//! # fn main() {}
+//! ```
+
+use std::{error::Error as StdError, fmt};
+
+use super::Transport;
+use crate::address::{Address, Envelope};
+#[cfg(feature = "builder")]
+use crate::Message;
+
+/// A route of a [`RoutingTransport`]
+#[derive(Debug, Clone)]
+struct Route<T> {
+    domain: String,
+    transport: T,
+    rewrite: Option<fn(&Address) -> Address>,
+}
+
+impl<T> Route<T> {
+    fn matches(&self, domain: &str) -> bool {
+        match self.domain.strip_prefix("*.") {
+            Some(parent) => domain
+                .len()
+                .checked_sub(parent.len() + 1)
+                .and_then(|dot| domain.get(dot..))
+                .and_then(|suffix| suffix.strip_prefix('.'))
+                .is_some_and(|suffix| suffix.eq_ignore_ascii_case(parent)),
+            None => domain.eq_ignore_ascii_case(&self.domain),
+        }
+    }
+}
+
+/// The result of sending an email through one route of a [`RoutingTransport`]
+#[derive(Debug)]
+pub struct RouteResult<O, E> {
+    route: Option<String>,
+    recipients: Vec<Address>,
+    result: Result<O, E>,
+}
+
+impl<O, E> RouteResult<O, E> {
+    /// Returns the domain of the route, or `None` for the default transport
+    pub fn route(&self) -> Option<&str> {
+        self.route.as_deref()
+    }
+
+    /// Returns the recipients of the envelope sent through the route,
+    /// after the rewriting of their addresses
+    pub fn recipients(&self) -> &[Address] {
+        &self.recipients
+    }
+
+    /// Returns the result of the transport of the route
+    pub fn result(&self) -> &Result<O, E> {
+        &self.result
+    }
+
+    /// Returns the result of the transport of the route
+    pub fn into_result(self) -> Result<O, E> {
+        self.result
+    }
+}
+
+/// The results of the routes an email was sent through, in the order of
+/// the routes, the default transport last
+#[derive(Debug)]
+pub struct RoutingReport<O, E> {
+    routes: Vec<RouteResult<O, E>>,
+}
+
+impl<O, E> RoutingReport<O, E> {
+    /// Returns the results of the routes
+    pub fn routes(&self) -> &[RouteResult<O, E>] {
+        &self.routes
+    }
+
+    /// Returns the results of the routes
+    pub fn into_routes(self) -> Vec<RouteResult<O, E>> {
+        self.routes
+    }
+
+    /// Returns the results of the routes which failed
+    pub fn failures(&self) -> impl Iterator<Item = &RouteResult<O, E>> {
+        self.routes.iter().filter(|route| route.result.is_err())
+    }
+}
+
+/// The error of a [`RoutingTransport`], returned when the transport of at
+/// least one route failed
+///
+/// The email may have been sent through the other routes, as told by
+/// its [`RoutingReport`].
+#[derive(Debug)]
+pub struct RoutingError<O, E> {
+    report: RoutingReport<O, E>,
+}
+
+impl<O, E> RoutingError<O, E> {
+    /// Returns the results of all the routes
+    pub fn report(&self) -> &RoutingReport<O, E> {
+        &self.report
+    }
+
+    /// Returns the results of all the routes
+    pub fn into_report(self) -> RoutingReport<O, E> {
+        self.report
+    }
+
+    fn first_error(&self) -> Option<&E> {
+        self.report
+            .failures()
+            .find_map(|route| route.result.as_ref().err())
+    }
+}
+
+impl<O, E: fmt::Display> fmt::Display for RoutingError<O, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of the {} routes failed",
+            self.report.failures().count(),
+            self.report.routes.len()
+        )?;
+        if let Some(err) = self.first_error() {
+            write!(f, ": {err}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<O: fmt::Debug, E: StdError + 'static> StdError for RoutingError<O, E> {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        let err: &(dyn StdError + 'static) = self.first_error()?;
+        Some(err)
+    }
+}
+
+/// A transport sending emails through different transports depending
+/// on the domains of the recipients
+#[derive(Debug, Clone)]
+pub struct RoutingTransport<T> {
+    routes: Vec<Route<T>>,
+    default: T,
+}
+
+impl<T> RoutingTransport<T> {
+    /// Creates a new transport sending all emails through `default`
+    /// until routes are added
+    pub fn new(default: T) -> Self {
+        Self {
+            routes: Vec::new(),
+            default,
+        }
+    }
+
+    /// Sends the emails to recipients of `domain` through `transport`
+    ///
+    /// `domain` is either a domain, or `*.` followed by a domain to match all
+    /// of its subdomains. Routes are tried in the order they were added.
+    pub fn route<D: Into<String>>(self, domain: D, transport: T) -> Self {
+        self.add_route(domain.into(), transport, None)
+    }
+
+    /// Sends the emails to recipients of `domain` through `transport`,
+    /// rewriting their addresses in the envelope with `rewrite`
+    ///
+    /// See [`RoutingTransport::route`].
+    pub fn route_with_rewrite<D: Into<String>>(
+        self,
+        domain: D,
+        transport: T,
+        rewrite: fn(&Address) -> Address,
+    ) -> Self {
+        self.add_route(domain.into(), transport, Some(rewrite))
+    }
+
+    fn add_route(
+        mut self,
+        domain: String,
+        transport: T,
+        rewrite: Option<fn(&Address) -> Address>,
+    ) -> Self {
+        self.routes.push(Route {
+            domain,
+            transport,
+            rewrite,
+        });
+        self
+    }
+
+    /// Returns the transport used for recipients matched by no route
+    pub fn default_transport(&self) -> &T {
+        &self.default
+    }
+
+    /// Splits the recipients of `envelope` by route, the last group
+    /// being the one of the default transport
+    fn split(&self, envelope: &Envelope) -> Vec<Vec<Address>> {
+        let mut groups = vec![Vec::new(); self.routes.len() + 1];
+        for recipient in envelope.to() {
+            match self
+                .routes
+                .iter()
+                .position(|route| route.matches(recipient.domain()))
+            {
+                Some(i) => {
+                    let route = &self.routes[i];
+                    let recipient = route
+                        .rewrite
+                        .map_or_else(|| recipient.clone(), |f| f(recipient));
+                    if !groups[i].contains(&recipient) {
+                        groups[i].push(recipient);
+                    }
+                }
+                None => groups[self.routes.len()].push(recipient.clone()),
+            }
+        }
+        groups
+    }
+
+    /// Sends an envelope for each group of recipients through the
+    /// transport of its route with `send`
+    fn dispatch<O, E>(
+        &self,
+        envelope: &Envelope,
+        mut send: impl FnMut(&T, &Envelope) -> Result<O, E>,
+    ) -> Result<RoutingReport<O, E>, RoutingError<O, E>> {
+        let transports = self
+            .routes
+            .iter()
+            .map(|route| (Some(&route.domain), &route.transport))
+            .chain([(None, &self.default)]);
+
+        let mut routes = Vec::new();
+        for ((route, transport), recipients) in transports.zip(self.split(envelope)) {
+            if recipients.is_empty() {
+                continue;
+            }
+
+            let mut group = Envelope::new(envelope.from().cloned(), recipients)
+                .expect("the recipients of a group are not empty");
+            if let Some(id) = envelope.envelope_id() {
+                group = group
+                    .with_envelope_id(id)
+                    .expect("the envelope id is already valid");
+            }
+            let result = send(transport, &group);
+            #[cfg(feature = "tracing")]
+            if result.is_err() {
+                tracing::warn!(route = route.map(String::as_str), "route failed");
+            }
+            routes.push(RouteResult {
+                route: route.cloned(),
+                recipients: group.to().to_vec(),
+                result,
+            });
+        }
+
+        let report = RoutingReport { routes };
+        if report.failures().next().is_none() {
+            Ok(report)
+        } else {
+            Err(RoutingError { report })
+        }
+    }
+}
+
+impl<T> Transport for RoutingTransport<T>
+where
+    T: Transport,
+{
+    type Ok = RoutingReport<T::Ok, T::Error>;
+    type Error = RoutingError<T::Ok, T::Error>;
+
+    /// Sends the email through the transports of the recipients
+    ///
+    /// The email is sent through all of them, even when one fails.
+    #[cfg(feature = "builder")]
+    fn send(&self, message: &Message) -> Result<Self::Ok, Self::Error> {
+        self.dispatch(message.envelope(), |transport, group| {
+            transport.send(&message.with_envelope(group.clone()))
+        })
+    }
+
+    /// Sends the email through the transports of the recipients
+    ///
+    /// The email is sent through all of them, even when one fails.
+    fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        self.dispatch(envelope, |transport, group| {
+            transport.send_raw(group, email)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RoutingTransport, Transport};
+    use crate::{
+        address::{Address, Envelope},
+        transport::stub::StubTransport,
+    };
+
+    fn strip_subaddress(address: &Address) -> Address {
+        let user = address.user().split('+').next().unwrap_or_default();
+        Address::new(user, address.domain()).unwrap()
+    }
+
+    fn envelope(to: &[&str]) -> Envelope {
+        Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            to.iter().map(|to| to.parse().unwrap()).collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn routing_groups() {
+        let internal = StubTransport::new_ok();
+        let partner = StubTransport::new_ok();
+        let default = StubTransport::new_ok();
+        let transport = RoutingTransport::new(default.clone())
+            .route_with_rewrite("internal.example", internal.clone(), strip_subaddress)
+            .route("*.partner.example", partner.clone());
+
+        let report = transport
+            .send_raw(
+                &envelope(&[
+                    "hei+news@internal.example",
+                    "yuin@MX.Partner.example",
+                    "bob@domain.tld",
+                    "hei@internal.example",
+                ]),
+                b"Hi",
+            )
+            .unwrap();
+        let routes = report
+            .routes()
+            .iter()
+            .map(|route| (route.route(), route.recipients().len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            routes,
+            [
+                (Some("internal.example"), 1),
+                (Some("*.partner.example"), 1),
+                (None, 1)
+            ]
+        );
+
+        assert_eq!(
+            internal.messages(),
+            [(envelope(&["hei@internal.example"]), "Hi".to_owned())]
+        );
+        assert_eq!(
+            partner.messages(),
+            [(envelope(&["yuin@MX.Partner.example"]), "Hi".to_owned())]
+        );
+        assert_eq!(
+            default.messages(),
+            [(envelope(&["bob@domain.tld"]), "Hi".to_owned())]
+        );
+    }
+
+    #[test]
+    fn routing_failed_route() {
+        let internal = StubTransport::new_ok();
+        let partner = StubTransport::new_error();
+        let default = StubTransport::new_ok();
+        let transport = RoutingTransport::new(default.clone())
+            .route("internal.example", internal.clone())
+            .route("partner.example", partner.clone());
+
+        let err = transport
+            .send_raw(
+                &envelope(&[
+                    "hei@internal.example",
+                    "yuin@partner.example",
+                    "bob@domain.tld",
+                ]),
+                b"Hi",
+            )
+            .unwrap_err();
+        assert_eq!(err.to_string(), "1 of the 3 routes failed: stub error");
+
+        // The email is still sent through the routes after the failed one
+        assert_eq!(internal.messages().len(), 1);
+        assert_eq!(partner.messages().len(), 1);
+        assert_eq!(default.messages().len(), 1);
+
+        let failures = err.report().failures().collect::<Vec<_>>();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].route(), Some("partner.example"));
+        assert_eq!(
+            failures[0].recipients(),
+            ["yuin@partner.example".parse::<Address>().unwrap()]
+        );
+        assert!(err.report().routes()[0].result().is_ok());
+        assert!(err.report().routes()[2].result().is_ok());
+    }
+
+    #[cfg(feature = "builder")]
+    #[test]
+    fn routing_removed_attachment() {
+        use crate::transport::message_with_removed_attachment;
+
+        let default = StubTransport::new_ok();
+        let transport = RoutingTransport::new(default.clone());
+
+        let err = transport
+            .send(&message_with_removed_attachment(
+                "lettre-routing-removed-attachment.pdf",
+            ))
+            .unwrap_err();
+        assert_eq!(err.report().failures().count(), 1);
+        assert!(default.messages().is_empty());
+    }
+
+    #[test]
+    fn routing_envelope_id() {
+        let internal = StubTransport::new_ok();
+        let default = StubTransport::new_ok();
+        let transport =
+            RoutingTransport::new(default.clone()).route("internal.example", internal.clone());
+
+        let envelope = envelope(&["hei@internal.example", "bob@domain.tld"])
+            .with_envelope_id("request-42")
+            .unwrap();
+        transport.send_raw(&envelope, b"Hi").unwrap();
+        assert_eq!(internal.messages()[0].0.envelope_id(), Some("request-42"));
+        assert_eq!(default.messages()[0].0.envelope_id(), Some("request-42"));
+    }
+
+    #[test]
+    fn routing_unused_routes() {
+        let internal = StubTransport::new_error();
+        let default = StubTransport::new_ok();
+        let transport =
+            RoutingTransport::new(default.clone()).route("internal.example", internal.clone());
+
+        let report = transport
+            .send_raw(&envelope(&["bob@partner.example"]), b"Hi")
+            .unwrap();
+        assert_eq!(report.routes().len(), 1);
+        assert!(internal.messages().is_empty());
+        assert_eq!(default.messages().len(), 1);
+
+        // Subdomain patterns don't match the domain itself
+        let transport = RoutingTransport::new(default.clone()).route("*.partner.example", internal);
+        assert!(transport
+            .send_raw(&envelope(&["bob@partner.example"]), b"Hi")
+            .is_ok());
+        assert!(transport
+            .send_raw(&envelope(&["bob@evilpartner.example"]), b"Hi")
+            .is_ok());
+        assert!(transport
+            .send_raw(&envelope(&["bob@mx.partner.example"]), b"Hi")
+            .is_err());
+    }
+}
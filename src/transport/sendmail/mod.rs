@@ -77,7 +77,7 @@
 #[cfg(any(feature = "async-std1", feature = "tokio1"))]
 use std::marker::PhantomData;
 use std::{
-    ffi::OsString,
+    ffi::{OsStr, OsString},
     io::Write,
     process::{Command, Stdio},
 };
@@ -88,6 +88,8 @@ use async_trait::async_trait;
 pub use self::error::Error;
 #[cfg(feature = "async-std1")]
 use crate::AsyncStd1Executor;
+#[cfg(feature = "builder")]
+use crate::Message;
 #[cfg(feature = "tokio1")]
 use crate::Tokio1Executor;
 use crate::{address::Envelope, Transport};
@@ -97,6 +99,8 @@ use crate::{AsyncTransport, Executor};
 mod error;
 
 const DEFAULT_SENDMAIL: &str = "sendmail";
+/// Options of sendmail whose value is the rest of their argument, or the next argument
+const OPTIONS_WITH_VALUE: &str = "BbCDdFfhLNOopqRrVX";
 
 /// Sends emails using the `sendmail` command
 #[derive(Debug, Clone)]
@@ -104,6 +108,10 @@ const DEFAULT_SENDMAIL: &str = "sendmail";
 #[cfg_attr(docsrs, doc(cfg(feature = "sendmail-transport")))]
 pub struct SendmailTransport {
     command: OsString,
+    #[cfg_attr(feature = "serde", serde(default))]
+    args: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    recipients_from_headers: bool,
 }
 
 /// Asynchronously sends emails using the `sendmail` command
@@ -122,33 +130,125 @@ impl SendmailTransport {
     /// Note: This uses the `sendmail` command in the current `PATH`. To use another command,
     /// use [SendmailTransport::new_with_command].
     pub fn new() -> SendmailTransport {
-        SendmailTransport {
-            command: DEFAULT_SENDMAIL.into(),
-        }
+        Self::new_with_command(DEFAULT_SENDMAIL)
     }
 
     /// Creates a new transport to the given sendmail command
     pub fn new_with_command<S: Into<OsString>>(command: S) -> SendmailTransport {
+        Self::new_with_command_and_args(command, Vec::new())
+    }
+
+    /// Creates a new transport to the given sendmail command, passing it
+    /// the extra arguments `args`
+    ///
+    /// See [`SendmailTransport::args`].
+    pub fn new_with_command_and_args<S: Into<OsString>>(
+        command: S,
+        args: Vec<String>,
+    ) -> SendmailTransport {
         SendmailTransport {
             command: command.into(),
+            args,
+            recipients_from_headers: false,
         }
     }
 
-    fn command(&self, envelope: &Envelope) -> Command {
-        let mut c = Command::new(&self.command);
-        c.arg("-i");
+    /// Set extra arguments for the sendmail command, like `-N` to request
+    /// delivery status notifications
+    ///
+    /// They are passed after `-i` and before `-f` and the recipients.
+    pub fn args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Let sendmail read the recipients from the `To`, `Cc` and `Bcc` headers
+    /// of the email with `-t`, instead of passing the envelope recipients
+    ///
+    /// The two are mutually exclusive: sendmail can't be given the recipients
+    /// both ways. Sending fails if `-t` is among the extra [`args`], alone or
+    /// in a group of flags like `-ti`, while this isn't enabled, as the
+    /// envelope recipients would be passed too.
+    ///
+    /// Lettre removes the `Bcc` header of [`Message`]s by default, so their
+    /// blind copy recipients are only sent the email if the message keeps it
+    /// with [`MessageBuilder::keep_bcc`].
+    ///
+    /// [`args`]: SendmailTransport::args
+    /// [`Message`]: crate::Message
+    /// [`MessageBuilder::keep_bcc`]: crate::message::MessageBuilder::keep_bcc
+    pub fn recipients_from_headers(mut self, enabled: bool) -> Self {
+        self.recipients_from_headers = enabled;
+        self
+    }
+
+    /// The arguments of the sendmail command to send an email to `envelope`
+    fn arguments(&self, envelope: &Envelope) -> Result<Vec<OsString>, Error> {
+        if !self.recipients_from_headers && has_t_flag(&self.args) {
+            return Err(error::client(
+                "`-t` and the envelope recipients are mutually exclusive, \
+                 use `recipients_from_headers` to read the recipients from the headers",
+            ));
+        }
+
+        let mut args = vec![OsString::from("-i")];
+        args.extend(self.args.iter().map(OsString::from));
         if let Some(from) = envelope.from() {
-            c.arg("-f").arg(from);
+            args.push("-f".into());
+            args.push(AsRef::<OsStr>::as_ref(from).to_owned());
+        }
+        if self.recipients_from_headers {
+            if !has_t_flag(&self.args) {
+                args.push("-t".into());
+            }
+        } else {
+            args.push("--".into());
+            args.extend(
+                envelope
+                    .to()
+                    .iter()
+                    .map(|to| AsRef::<OsStr>::as_ref(to).to_owned()),
+            );
         }
-        c.arg("--")
-            .args(envelope.to())
+        Ok(args)
+    }
+
+    fn command(&self, envelope: &Envelope) -> Result<Command, Error> {
+        let mut c = Command::new(&self.command);
+        c.args(self.arguments(envelope)?)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
-        c
+        Ok(c)
     }
 }
 
+/// Returns whether `args` contain the `-t` flag, alone or in a group like `-ti`
+fn has_t_flag(args: &[String]) -> bool {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        // Long options and `--` don't contain flags
+        let Some(flags) = arg
+            .strip_prefix('-')
+            .filter(|flags| !flags.starts_with('-'))
+        else {
+            continue;
+        };
+        for (i, flag) in flags.char_indices() {
+            if flag == 't' {
+                return true;
+            }
+            if OPTIONS_WITH_VALUE.contains(flag) {
+                if i + 1 == flags.len() {
+                    args.next();
+                }
+                break;
+            }
+        }
+    }
+    false
+}
+
 #[cfg(any(feature = "async-std1", feature = "tokio1"))]
 impl<E> AsyncSendmailTransport<E>
 where
@@ -167,47 +267,61 @@ where
 
     /// Creates a new transport to the given sendmail command
     pub fn new_with_command<S: Into<OsString>>(command: S) -> Self {
+        Self::new_with_command_and_args(command, Vec::new())
+    }
+
+    /// Creates a new transport to the given sendmail command, passing it
+    /// the extra arguments `args`
+    ///
+    /// See [`SendmailTransport::args`].
+    pub fn new_with_command_and_args<S: Into<OsString>>(command: S, args: Vec<String>) -> Self {
         Self {
-            inner: SendmailTransport::new_with_command(command),
+            inner: SendmailTransport::new_with_command_and_args(command, args),
             marker_: PhantomData,
         }
     }
 
+    /// Set extra arguments for the sendmail command
+    ///
+    /// See [`SendmailTransport::args`].
+    pub fn args(mut self, args: Vec<String>) -> Self {
+        self.inner = self.inner.args(args);
+        self
+    }
+
+    /// Let sendmail read the recipients from the headers of the email
+    ///
+    /// See [`SendmailTransport::recipients_from_headers`].
+    pub fn recipients_from_headers(mut self, enabled: bool) -> Self {
+        self.inner = self.inner.recipients_from_headers(enabled);
+        self
+    }
+
     #[cfg(feature = "tokio1")]
-    fn tokio1_command(&self, envelope: &Envelope) -> tokio1_crate::process::Command {
+    fn tokio1_command(&self, envelope: &Envelope) -> Result<tokio1_crate::process::Command, Error> {
         use tokio1_crate::process::Command;
 
         let mut c = Command::new(&self.inner.command);
         c.kill_on_drop(true);
-        c.arg("-i");
-        if let Some(from) = envelope.from() {
-            c.arg("-f").arg(from);
-        }
-        c.arg("--")
-            .args(envelope.to())
+        c.args(self.inner.arguments(envelope)?)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
-        c
+        Ok(c)
     }
 
     #[cfg(feature = "async-std1")]
-    fn async_std_command(&self, envelope: &Envelope) -> async_std::process::Command {
+    fn async_std_command(&self, envelope: &Envelope) -> Result<async_std::process::Command, Error> {
         use async_std::process::Command;
 
         let mut c = Command::new(&self.inner.command);
         // TODO: figure out why enabling this kills it earlier
         // c.kill_on_drop(true);
-        c.arg("-i");
-        if let Some(from) = envelope.from() {
-            c.arg("-f").arg(from);
-        }
-        c.arg("--")
-            .args(envelope.to())
+        c.args(self.inner.arguments(envelope)?)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
-        c
+        Ok(c)
     }
 }
 
@@ -231,12 +345,21 @@ impl Transport for SendmailTransport {
     type Ok = ();
     type Error = Error;
 
+    #[cfg(feature = "builder")]
+    fn send(&self, message: &Message) -> Result<Self::Ok, Self::Error> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!("starting to send an email");
+
+        let raw = message.try_formatted().map_err(error::client)?;
+        self.send_raw(message.envelope(), &raw)
+    }
+
     fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
         #[cfg(feature = "tracing")]
         tracing::debug!(command = ?self.command, "sending email with");
 
         // Spawn the sendmail command
-        let mut process = self.command(envelope).spawn().map_err(error::client)?;
+        let mut process = self.command(envelope)?.spawn().map_err(error::client)?;
 
         process
             .stdin
@@ -261,13 +384,22 @@ impl AsyncTransport for AsyncSendmailTransport<AsyncStd1Executor> {
     type Ok = ();
     type Error = Error;
 
+    #[cfg(feature = "builder")]
+    async fn send(&self, message: Message) -> Result<Self::Ok, Self::Error> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!("starting to send an email");
+
+        let raw = message.try_formatted().map_err(error::client)?;
+        self.send_raw(message.envelope(), &raw).await
+    }
+
     async fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
         use async_std::io::prelude::WriteExt;
 
         #[cfg(feature = "tracing")]
         tracing::debug!(command = ?self.inner.command, "sending email with");
 
-        let mut command = self.async_std_command(envelope);
+        let mut command = self.async_std_command(envelope)?;
 
         // Spawn the sendmail command
         let mut process = command.spawn().map_err(error::client)?;
@@ -296,13 +428,22 @@ impl AsyncTransport for AsyncSendmailTransport<Tokio1Executor> {
     type Ok = ();
     type Error = Error;
 
+    #[cfg(feature = "builder")]
+    async fn send(&self, message: Message) -> Result<Self::Ok, Self::Error> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!("starting to send an email");
+
+        let raw = message.try_formatted().map_err(error::client)?;
+        self.send_raw(message.envelope(), &raw).await
+    }
+
     async fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
         use tokio1_crate::io::AsyncWriteExt;
 
         #[cfg(feature = "tracing")]
         tracing::debug!(command = ?self.inner.command, "sending email with");
 
-        let mut command = self.tokio1_command(envelope);
+        let mut command = self.tokio1_command(envelope)?;
 
         // Spawn the sendmail command
         let mut process = command.spawn().map_err(error::client)?;
@@ -324,3 +465,123 @@ impl AsyncTransport for AsyncSendmailTransport<Tokio1Executor> {
         }
     }
 }
+
+#[cfg(test)]
+#[cfg(all(unix, feature = "builder"))]
+mod test {
+    use std::{
+        fs,
+        os::unix::fs::PermissionsExt,
+        path::{Path, PathBuf},
+    };
+
+    use super::SendmailTransport;
+    use crate::{Message, Transport};
+
+    /// Creates a sendmail script in a new directory named after `test`,
+    /// saving its arguments and the email in the `args` and `email` files
+    fn fake_sendmail(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lettre-sendmail-{}-{test}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("sendmail");
+        fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\nprintf '%s\\n' \"$@\" > '{0}/args'\ncat > '{0}/email'\n",
+                dir.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        dir
+    }
+
+    /// Returns the arguments and the email received by the script, and removes it
+    fn received(dir: &Path) -> (String, String) {
+        let args = fs::read_to_string(dir.join("args")).unwrap();
+        let email = fs::read_to_string(dir.join("email")).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        (args, email)
+    }
+
+    fn email() -> Message {
+        Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .bcc("hidden@domain.tld".parse().unwrap())
+            .subject("Happy new year")
+            .body(String::from("Be happy!"))
+            .unwrap()
+    }
+
+    #[test]
+    fn bcc_only_in_envelope() {
+        let dir = fake_sendmail("bcc");
+        SendmailTransport::new_with_command(dir.join("sendmail"))
+            .send(&email())
+            .unwrap();
+        let (args, piped) = received(&dir);
+
+        assert_eq!(
+            args,
+            "-i\n-f\nnobody@domain.tld\n--\nhei@domain.tld\nhidden@domain.tld\n"
+        );
+        assert!(piped.contains("To: Hei <hei@domain.tld>\r\n"), "{piped}");
+        assert!(!piped.contains("hidden@domain.tld"), "{piped}");
+        assert!(!piped.contains("Bcc:"), "{piped}");
+    }
+
+    #[test]
+    fn extra_args() {
+        let dir = fake_sendmail("args");
+        SendmailTransport::new_with_command_and_args(
+            dir.join("sendmail"),
+            vec!["-N".to_owned(), "failure,delay".to_owned()],
+        )
+        .send(&email())
+        .unwrap();
+        let (args, _) = received(&dir);
+
+        assert_eq!(
+            args,
+            "-i\n-N\nfailure,delay\n-f\nnobody@domain.tld\n--\nhei@domain.tld\nhidden@domain.tld\n"
+        );
+    }
+
+    #[test]
+    fn recipients_from_headers() {
+        let dir = fake_sendmail("headers");
+        let sendmail = SendmailTransport::new_with_command(dir.join("sendmail"))
+            .args(vec!["-oi".to_owned()])
+            .recipients_from_headers(true);
+        sendmail.send(&email()).unwrap();
+        let (args, _) = received(&dir);
+        assert_eq!(args, "-i\n-oi\n-f\nnobody@domain.tld\n-t\n");
+
+        // Passing `-t` explicitly would also pass the envelope recipients
+        let dir = fake_sendmail("explicit-t");
+        let err = SendmailTransport::new_with_command(dir.join("sendmail"))
+            .args(vec!["-t".to_owned()])
+            .send(&email())
+            .unwrap_err();
+        assert!(err.is_client());
+        assert!(!dir.join("args").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn t_flag() {
+        use super::has_t_flag;
+
+        let args = |args: &[&str]| args.iter().map(|&arg| arg.to_owned()).collect::<Vec<_>>();
+        assert!(has_t_flag(&args(&["-t"])));
+        assert!(has_t_flag(&args(&["-ti"])));
+        assert!(has_t_flag(&args(&["-N", "never", "-it"])));
+
+        // Values of options and long options aren't flags
+        assert!(!has_t_flag(&args(&["-Ftest"])));
+        assert!(!has_t_flag(&args(&["-F", "-t"])));
+        assert!(!has_t_flag(&args(&["-oi", "--test"])));
+    }
+}
@@ -1,4 +1,11 @@
-#[cfg(feature = "pool")]
+#[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+use std::sync::atomic::AtomicBool;
+#[cfg(any(
+    feature = "pool",
+    feature = "native-tls",
+    feature = "rustls-tls",
+    feature = "boring-tls"
+))]
 use std::sync::Arc;
 use std::{
     fmt::{self, Debug},
@@ -7,21 +14,29 @@ use std::{
 };
 
 use async_trait::async_trait;
+use futures_util::io::AsyncRead;
 
+#[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+use super::check_starttls_advertised;
 #[cfg(feature = "pool")]
 use super::pool::async_impl::Pool;
 #[cfg(feature = "pool")]
 use super::PoolConfig;
 use super::{
-    client::AsyncSmtpConnection, ClientId, Credentials, Error, Mechanism, Response, SmtpInfo,
+    client::AsyncSmtpConnection,
+    error,
+    extension::{DsnNotify, DsnReturn},
+    ClientId, Credentials, Error, Mechanism, Response, SmtpInfo,
 };
 #[cfg(feature = "async-std1")]
 use crate::AsyncStd1Executor;
 #[cfg(any(feature = "tokio1", feature = "async-std1"))]
 use crate::AsyncTransport;
+#[cfg(feature = "builder")]
+use crate::Message;
 #[cfg(feature = "tokio1")]
 use crate::Tokio1Executor;
-use crate::{Envelope, Executor};
+use crate::{Address, Envelope, Executor};
 
 /// Asynchronously sends emails using the SMTP protocol
 #[cfg_attr(docsrs, doc(cfg(any(feature = "tokio1", feature = "async-std1"))))]
@@ -38,9 +53,27 @@ impl AsyncTransport for AsyncSmtpTransport<Tokio1Executor> {
     type Ok = Response;
     type Error = Error;
 
+    /// Sends an email
+    ///
+    /// The content is formatted while being transmitted, from [`Message::reader`].
+    /// The files of [`LazyAttachment`]s are read with blocking calls, in small chunks.
+    ///
+    /// [`LazyAttachment`]: crate::message::LazyAttachment
+    #[cfg(feature = "builder")]
+    async fn send(&self, message: Message) -> Result<Self::Ok, Self::Error> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!("starting to send an email");
+
+        self.send_message(&message).await
+    }
+
     /// Sends an email
     async fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
-        let mut conn = self.inner.connection().await?;
+        let mut conn = self
+            .inner
+            .connection()
+            .await
+            .map_err(Error::while_connecting)?;
 
         let result = conn.send(envelope, email).await?;
 
@@ -57,9 +90,27 @@ impl AsyncTransport for AsyncSmtpTransport<AsyncStd1Executor> {
     type Ok = Response;
     type Error = Error;
 
+    /// Sends an email
+    ///
+    /// The content is formatted while being transmitted, from [`Message::reader`].
+    /// The files of [`LazyAttachment`]s are read with blocking calls, in small chunks.
+    ///
+    /// [`LazyAttachment`]: crate::message::LazyAttachment
+    #[cfg(feature = "builder")]
+    async fn send(&self, message: Message) -> Result<Self::Ok, Self::Error> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!("starting to send an email");
+
+        self.send_message(&message).await
+    }
+
     /// Sends an email
     async fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
-        let mut conn = self.inner.connection().await?;
+        let mut conn = self
+            .inner
+            .connection()
+            .await
+            .map_err(Error::while_connecting)?;
 
         let result = conn.send(envelope, email).await?;
 
@@ -255,6 +306,57 @@ where
         super::connection_url::from_connection_url(connection_url)
     }
 
+    #[cfg(feature = "builder")]
+    async fn send_message(&self, message: &Message) -> Result<Response, Error> {
+        let size = message.formatted_len().map_err(error::client)?;
+        let mut conn = self
+            .inner
+            .connection()
+            .await
+            .map_err(Error::while_connecting)?;
+        conn.check_size(size)?;
+
+        let mut reader = crate::message::async_reader::<E>(message);
+        let result = conn
+            .send_from_reader(message.envelope(), message.is_ascii(), &mut reader)
+            .await?;
+
+        #[cfg(not(feature = "pool"))]
+        conn.quit().await?;
+
+        Ok(result)
+    }
+
+    /// Sends an email whose formatted content is read from `reader`
+    ///
+    /// The content is streamed to the server, so it never has to be held in
+    /// memory in full. `is_ascii` tells whether it only contains ASCII characters.
+    /// See [`AsyncSmtpConnection::send_from_reader`].
+    ///
+    /// As the length of the content isn't known in advance, it isn't checked
+    /// against the size limit advertised by the server with the `SIZE`
+    /// extension: a server enforcing it rejects the email only once it was
+    /// sent in full.
+    pub async fn send_from_reader<R: AsyncRead + Unpin + Send + ?Sized>(
+        &self,
+        envelope: &Envelope,
+        is_ascii: bool,
+        reader: &mut R,
+    ) -> Result<Response, Error> {
+        let mut conn = self
+            .inner
+            .connection()
+            .await
+            .map_err(Error::while_connecting)?;
+
+        let result = conn.send_from_reader(envelope, is_ascii, reader).await?;
+
+        #[cfg(not(feature = "pool"))]
+        conn.quit().await?;
+
+        Ok(result)
+    }
+
     /// Tests the SMTP connection
     ///
     /// `test_connection()` tests the connection by using the SMTP NOOP command.
@@ -349,6 +451,63 @@ impl AsyncSmtpTransportBuilder {
         self
     }
 
+    /// Require the messages to only be relayed over TLS
+    ///
+    /// Sends the `REQUIRETLS` parameter defined in
+    /// [RFC 8689](https://tools.ietf.org/html/rfc8689) with each message.
+    /// Sending fails with an error for which [`Error::is_require_tls_unsupported`]
+    /// returns `true` if the server doesn't support it. Defaults to `false`.
+    ///
+    /// To signal that a message may be delivered even if TLS can't be used,
+    /// set the `TLS-Required: No` header with [`MessageBuilder::tls_not_required`].
+    ///
+    /// [`MessageBuilder::tls_not_required`]: crate::message::MessageBuilder::tls_not_required
+    pub fn require_tls_relay(mut self, require: bool) -> Self {
+        self.info.require_tls_relay = require;
+        self
+    }
+
+    /// Set the sender used when the server permanently rejects the sender of an email
+    ///
+    /// See [`SmtpTransportBuilder::mail_from_fallback`](super::SmtpTransportBuilder::mail_from_fallback).
+    pub fn mail_from_fallback(mut self, fallback: Option<Address>) -> Self {
+        self.info.mail_from_fallback = fallback;
+        self
+    }
+
+    /// Request delivery status notifications for the `notify` events
+    ///
+    /// Sends the `NOTIFY` parameter defined in [RFC 3461](https://tools.ietf.org/html/rfc3461)
+    /// with each recipient, along with its address as `ORCPT`. Multiple events are
+    /// combined, unless [`DsnNotify::Never`] is given. Nothing is requested from servers
+    /// which don't advertise the `DSN` extension. Defaults to no parameter, leaving the
+    /// server's default behavior.
+    pub fn dsn_notify(mut self, notify: &[DsnNotify]) -> Self {
+        self.info.dsn_notify = notify.to_vec();
+        self
+    }
+
+    /// Set the part of the message returned with failure notifications
+    ///
+    /// Sends the `RET` parameter defined in [RFC 3461](https://tools.ietf.org/html/rfc3461)
+    /// to servers advertising the `DSN` extension.
+    pub fn dsn_return(mut self, ret: DsnReturn) -> Self {
+        self.info.dsn_return = Some(ret);
+        self
+    }
+
+    /// Send the content with `BDAT` when the server supports it
+    ///
+    /// When the server advertises the `CHUNKING` extension defined in
+    /// [RFC 3030](https://tools.ietf.org/html/rfc3030), the content is sent
+    /// in `BDAT` chunks, which some servers prefer over `DATA` and which
+    /// doesn't require dot-stuffing. `DATA` is used with the other servers,
+    /// or for all of them when set to `false`. Defaults to `true`.
+    pub fn prefer_chunking(mut self, prefer: bool) -> Self {
+        self.info.prefer_chunking = prefer;
+        self
+    }
+
     /// Set the TLS settings to use
     #[cfg(any(
         feature = "tokio1-native-tls",
@@ -384,21 +543,40 @@ impl AsyncSmtpTransportBuilder {
     where
         E: Executor,
     {
-        let client = AsyncSmtpClient {
-            info: self.info,
-            marker_: PhantomData,
-        };
+        let client = AsyncSmtpClient::new(self.info);
 
         #[cfg(feature = "pool")]
         let client = Pool::new(self.pool_config, client);
 
         AsyncSmtpTransport { inner: client }
     }
+
+    /// Build the transport and check that it can be used
+    ///
+    /// Connects to the server, authenticates if credentials were provided and
+    /// sends a `NOOP` command, like [`AsyncSmtpTransport::test_connection`], so that
+    /// an unreachable server or invalid credentials are reported at startup
+    /// instead of when sending the first email.
+    pub async fn build_and_verify<E>(self) -> Result<AsyncSmtpTransport<E>, Error>
+    where
+        E: Executor,
+    {
+        let transport = self.build();
+
+        if transport.test_connection().await? {
+            Ok(transport)
+        } else {
+            Err(error::client("The server did not accept the NOOP command"))
+        }
+    }
 }
 
 /// Build client
 pub struct AsyncSmtpClient<E> {
     info: SmtpInfo,
+    /// Whether the server advertised `STARTTLS`, shared between clones
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    starttls_seen: Arc<AtomicBool>,
     marker_: PhantomData<E>,
 }
 
@@ -406,22 +584,56 @@ impl<E> AsyncSmtpClient<E>
 where
     E: Executor,
 {
+    fn new(info: SmtpInfo) -> Self {
+        Self {
+            info,
+            #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+            starttls_seen: Arc::new(AtomicBool::new(false)),
+            marker_: PhantomData,
+        }
+    }
+
     /// Creates a new connection directly usable to send emails
     ///
     /// Handles encryption and authentication
     pub async fn connection(&self) -> Result<AsyncSmtpConnection, Error> {
-        let mut conn = E::connect(
+        let conn = E::connect(
             &self.info.server,
             self.info.port,
             self.info.timeout,
             &self.info.hello_name,
             &self.info.tls,
         )
-        .await?;
+        .await;
+
+        // The executor sends `STARTTLS` when the server advertises it, the
+        // connection is then encrypted, unless the upgrade failed
+        #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+        let conn = match (&self.info.tls, conn) {
+            (super::Tls::Opportunistic(_), Ok(mut conn)) => {
+                let advertised = conn.is_encrypted() || conn.can_starttls();
+                if let Err(err) = check_starttls_advertised(&self.starttls_seen, advertised) {
+                    conn.abort().await;
+                    return Err(err);
+                }
+                Ok(conn)
+            }
+            (super::Tls::Opportunistic(_), Err(err)) if err.is_starttls() => {
+                check_starttls_advertised(&self.starttls_seen, true)?;
+                Err(err)
+            }
+            (_, conn) => conn,
+        };
+        let mut conn = conn?;
 
         if let Some(credentials) = &self.info.credentials {
             conn.auth(&self.info.authentication, credentials).await?;
         }
+        conn.require_tls_relay(self.info.require_tls_relay);
+        conn.mail_from_fallback(self.info.mail_from_fallback.clone());
+        conn.prefer_chunking(self.info.prefer_chunking);
+        conn.dsn_notify(&self.info.dsn_notify);
+        conn.dsn_return(self.info.dsn_return);
         Ok(conn)
     }
 }
@@ -443,7 +655,70 @@ where
     fn clone(&self) -> Self {
         Self {
             info: self.info.clone(),
+            #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+            starttls_seen: Arc::clone(&self.starttls_seen),
             marker_: PhantomData,
         }
     }
 }
+
+#[cfg(all(
+    test,
+    any(feature = "tokio1-native-tls", feature = "tokio1-rustls-tls")
+))]
+mod test {
+    use super::{AsyncSmtpClient, AsyncSmtpTransport};
+    use crate::{
+        transport::smtp::client::{serve, MockStep, Tls, TlsParameters},
+        Tokio1Executor,
+    };
+
+    #[tokio1_crate::test(crate = "tokio1_crate")]
+    async fn starttls_downgrade() {
+        let greet = |ehlo: &str| {
+            vec![
+                MockStep::send("220 localhost ESMTP\r\n"),
+                MockStep::ExpectLine("EHLO ".to_owned()),
+                MockStep::send(ehlo),
+            ]
+        };
+        let tls = Tls::Opportunistic(TlsParameters::new("localhost".to_owned()).unwrap());
+        let builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous("127.0.0.1").tls(tls);
+
+        // The first connection advertises STARTTLS, but the upgrade fails
+        let mut steps = greet("250-localhost\r\n250 STARTTLS\r\n");
+        steps.push(MockStep::expect("STARTTLS\r\n"));
+        steps.push(MockStep::send("454 TLS not available\r\n"));
+        let (addr, server) = serve(steps);
+        let mut info = builder.info.clone();
+        info.port = addr.port();
+        let client = AsyncSmtpClient::<Tokio1Executor>::new(info);
+        let err = client.connection().await.err().unwrap();
+        assert!(err.is_starttls());
+        assert!(!err.is_tls_downgrade());
+        server.join().unwrap();
+
+        // STARTTLS was stripped, clones share what was seen before
+        let (addr, server) = serve(greet("250 localhost\r\n"));
+        let mut clone = client.clone();
+        clone.info.port = addr.port();
+        let err = clone.connection().await.err().unwrap();
+        assert!(err.is_tls_downgrade());
+        server.join().unwrap();
+
+        // A server which never advertised STARTTLS is used in plaintext
+        let mut steps = greet("250 localhost\r\n");
+        steps.push(MockStep::expect("QUIT\r\n"));
+        steps.push(MockStep::send("221 Bye\r\n"));
+        let (addr, server) = serve(steps);
+        let mut info = builder.info;
+        info.port = addr.port();
+        let mut conn = AsyncSmtpClient::<Tokio1Executor>::new(info)
+            .connection()
+            .await
+            .unwrap();
+        assert!(!conn.is_encrypted());
+        conn.quit().await.unwrap();
+        server.join().unwrap();
+    }
+}
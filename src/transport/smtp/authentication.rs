@@ -2,6 +2,8 @@
 
 use std::fmt::{self, Debug, Display, Formatter};
 
+use sha2::{Digest, Sha256};
+
 use crate::transport::smtp::error::{self, Error};
 
 /// Accepted authentication mechanisms
@@ -25,6 +27,14 @@ impl Credentials {
             secret: password,
         }
     }
+
+    pub(crate) fn username(&self) -> &str {
+        &self.authentication_identity
+    }
+
+    pub(crate) fn secret(&self) -> &str {
+        &self.secret
+    }
 }
 
 impl<S, T> From<(S, T)> for Credentials
@@ -46,6 +56,7 @@ impl Debug for Credentials {
 /// Represents authentication mechanisms
 #[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum Mechanism {
     /// PLAIN authentication mechanism, defined in
     /// [RFC 4616](https://tools.ietf.org/html/rfc4616)
@@ -58,6 +69,17 @@ pub enum Mechanism {
     /// Non-standard XOAUTH2 mechanism, defined in
     /// [xoauth2-protocol](https://developers.google.com/gmail/imap/xoauth2-protocol)
     Xoauth2,
+    /// SCRAM-SHA-256 authentication mechanism, defined in
+    /// [RFC 5802](https://tools.ietf.org/html/rfc5802) and
+    /// [RFC 7677](https://tools.ietf.org/html/rfc7677)
+    ///
+    /// The password is never sent to the server, and the server proves that
+    /// it knows it too. The exchange takes several steps, so it can only be
+    /// driven by a [`SaslSession`], not by [`Mechanism::response`].
+    ///
+    /// The username and the password are used as is, without the SASLprep
+    /// normalization, which only matters for non-ASCII credentials.
+    ScramSha256,
 }
 
 impl Display for Mechanism {
@@ -66,6 +88,7 @@ impl Display for Mechanism {
             Mechanism::Plain => "PLAIN",
             Mechanism::Login => "LOGIN",
             Mechanism::Xoauth2 => "XOAUTH2",
+            Mechanism::ScramSha256 => "SCRAM-SHA-256",
         })
     }
 }
@@ -74,13 +97,15 @@ impl Mechanism {
     /// Does the mechanism support initial response?
     pub fn supports_initial_response(self) -> bool {
         match self {
-            Mechanism::Plain | Mechanism::Xoauth2 => true,
+            Mechanism::Plain | Mechanism::Xoauth2 | Mechanism::ScramSha256 => true,
             Mechanism::Login => false,
         }
     }
 
     /// Returns the string to send to the server, using the provided username, password and
     /// challenge in some cases
+    ///
+    /// Fails for [`Mechanism::ScramSha256`], which needs a [`SaslSession`].
     pub fn response(
         self,
         credentials: &Credentials,
@@ -115,13 +140,282 @@ impl Mechanism {
                     credentials.authentication_identity, credentials.secret
                 )),
             },
+            Mechanism::ScramSha256 => Err(error::client(
+                "This mechanism needs a SaslSession to keep its state",
+            )),
         }
     }
 }
 
+/// Maximum iteration count accepted from the server for SCRAM mechanisms,
+/// to bound the time spent deriving the key
+const SCRAM_MAX_ITERATIONS: u32 = 1_000_000;
+
+/// An authentication exchange with the server, made of one or more steps
+///
+/// Most mechanisms answer each challenge independently, with
+/// [`Mechanism::response`], while SCRAM mechanisms carry state across the
+/// challenges of the exchange.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// use lettre::transport::smtp::authentication::{Credentials, Mechanism, SaslSession};
+///
+/// let credentials = Credentials::new("user".to_owned(), "pencil".to_owned());
+/// let mut session = SaslSession::new(Mechanism::ScramSha256, credentials)?;
+///
+/// let client_first = session.initial_response()?.unwrap();
+/// assert!(client_first.starts_with(b"n,,n=user,r="));
+/// # Ok(())
+/// # }
+/// ```
+pub struct SaslSession {
+    mechanism: Mechanism,
+    credentials: Credentials,
+    scram: ScramState,
+}
+
+/// The progress of a SCRAM exchange
+enum ScramState {
+    /// The client-first message wasn't sent yet
+    Start { nonce: String },
+    /// Waiting for the server-first message
+    ClientFirst {
+        client_first_bare: String,
+        nonce: String,
+    },
+    /// Waiting for the server-final message
+    ClientFinal { server_signature: [u8; 32] },
+    /// The server proved that it knows the password
+    Verified,
+}
+
+impl SaslSession {
+    /// Starts an authentication exchange with `mechanism`
+    ///
+    /// SCRAM mechanisms draw their nonce from the random generator of the
+    /// operating system, and fail if it is unavailable.
+    pub fn new(mechanism: Mechanism, credentials: Credentials) -> Result<Self, Error> {
+        let nonce = if mechanism == Mechanism::ScramSha256 {
+            let mut nonce = [0; 24];
+            getrandom::getrandom(&mut nonce).map_err(|err| error::client(err.to_string()))?;
+            crate::base64::encode(nonce)
+        } else {
+            String::new()
+        };
+        Ok(Self::with_nonce(mechanism, credentials, nonce))
+    }
+
+    fn with_nonce(mechanism: Mechanism, credentials: Credentials, nonce: String) -> Self {
+        Self {
+            mechanism,
+            credentials,
+            scram: ScramState::Start { nonce },
+        }
+    }
+
+    /// Returns the mechanism of the exchange
+    pub fn mechanism(&self) -> Mechanism {
+        self.mechanism
+    }
+
+    pub(crate) fn credentials(&self) -> &Credentials {
+        &self.credentials
+    }
+
+    /// Returns the response sent along with the `AUTH` command, if the
+    /// mechanism supports it
+    pub fn initial_response(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        match self.mechanism {
+            Mechanism::ScramSha256 => {
+                let ScramState::Start { nonce } = &self.scram else {
+                    return Err(error::client("The exchange has already started"));
+                };
+                let nonce = nonce.clone();
+                let client_first_bare =
+                    format!("n={},r={nonce}", scram_escape(self.credentials.username()));
+                let response = format!("n,,{client_first_bare}").into_bytes();
+                self.scram = ScramState::ClientFirst {
+                    client_first_bare,
+                    nonce,
+                };
+                Ok(Some(response))
+            }
+            mechanism if mechanism.supports_initial_response() => mechanism
+                .response(&self.credentials, None)
+                .map(|response| Some(response.into_bytes())),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the response to a decoded challenge of the server
+    pub fn step(&mut self, challenge: &[u8]) -> Result<Vec<u8>, Error> {
+        let challenge = std::str::from_utf8(challenge).map_err(error::response)?;
+        if self.mechanism != Mechanism::ScramSha256 {
+            return self
+                .mechanism
+                .response(&self.credentials, Some(challenge))
+                .map(String::into_bytes);
+        }
+
+        match &self.scram {
+            ScramState::ClientFirst {
+                client_first_bare,
+                nonce,
+            } => {
+                let (response, server_signature) = scram_client_final(
+                    client_first_bare,
+                    nonce,
+                    challenge,
+                    self.credentials.secret(),
+                )?;
+                self.scram = ScramState::ClientFinal { server_signature };
+                Ok(response.into_bytes())
+            }
+            ScramState::ClientFinal { server_signature } => {
+                if let Some(err) = challenge.strip_prefix("e=") {
+                    return Err(error::response(format!("Authentication failed: {err}")));
+                }
+                let verifier = challenge
+                    .strip_prefix("v=")
+                    .and_then(|v| crate::base64::decode(v).ok())
+                    .ok_or_else(|| error::response("Invalid server-final message"))?;
+                if verifier != server_signature {
+                    return Err(error::response("Invalid server signature"));
+                }
+                self.scram = ScramState::Verified;
+                Ok(Vec::new())
+            }
+            ScramState::Start { .. } | ScramState::Verified => {
+                Err(error::client("Unexpected challenge"))
+            }
+        }
+    }
+
+    /// Checks that the exchange went far enough for the server to be
+    /// authenticated too
+    pub(crate) fn finish(&self) -> Result<(), Error> {
+        match (self.mechanism, &self.scram) {
+            (Mechanism::ScramSha256, ScramState::Verified) => Ok(()),
+            (Mechanism::ScramSha256, _) => {
+                Err(error::response("The server didn't prove its identity"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Debug for SaslSession {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SaslSession")
+            .field("mechanism", &self.mechanism)
+            .finish()
+    }
+}
+
+/// Escapes `,` and `=` in a SCRAM username
+fn scram_escape(name: &str) -> String {
+    name.replace('=', "=3D").replace(',', "=2C")
+}
+
+/// Returns the client-final message answering `server_first`, and the
+/// signature expected from the server
+fn scram_client_final(
+    client_first_bare: &str,
+    nonce: &str,
+    server_first: &str,
+    password: &str,
+) -> Result<(String, [u8; 32]), Error> {
+    let mut server_nonce = None;
+    let mut salt = None;
+    let mut iterations = None;
+    for attribute in server_first.split(',') {
+        match attribute.split_once('=') {
+            Some(("m", _)) => return Err(error::response("Unsupported SCRAM extension")),
+            Some(("r", value)) => server_nonce = Some(value),
+            Some(("s", value)) => salt = crate::base64::decode(value).ok(),
+            Some(("i", value)) => iterations = value.parse::<u32>().ok(),
+            _ => {}
+        }
+    }
+    let (Some(server_nonce), Some(salt), Some(iterations)) = (server_nonce, salt, iterations)
+    else {
+        return Err(error::response("Invalid server-first message"));
+    };
+    if !server_nonce.starts_with(nonce) || server_nonce.len() == nonce.len() {
+        return Err(error::response("Invalid server nonce"));
+    }
+    if iterations == 0 || iterations > SCRAM_MAX_ITERATIONS {
+        return Err(error::response("Invalid iteration count"));
+    }
+
+    // The channel binding header is `n,,`, without channel binding
+    let client_final_without_proof = format!("c=biws,r={server_nonce}");
+    let auth_message = format!("{client_first_bare},{server_first},{client_final_without_proof}");
+
+    let salted_password = pbkdf2_hmac_sha256(password.as_bytes(), &salt, iterations);
+    let client_key = hmac_sha256(&salted_password, &[b"Client Key"]);
+    let stored_key = Sha256::digest(client_key);
+    let client_signature = hmac_sha256(&stored_key, &[auth_message.as_bytes()]);
+    let mut proof = client_key;
+    for (p, s) in proof.iter_mut().zip(client_signature) {
+        *p ^= s;
+    }
+    let server_key = hmac_sha256(&salted_password, &[b"Server Key"]);
+    let server_signature = hmac_sha256(&server_key, &[auth_message.as_bytes()]);
+
+    Ok((
+        format!(
+            "{client_final_without_proof},p={}",
+            crate::base64::encode(proof)
+        ),
+        server_signature,
+    ))
+}
+
+/// HMAC-SHA-256 of the concatenation of `data`, defined in
+/// [RFC 2104](https://tools.ietf.org/html/rfc2104)
+fn hmac_sha256(key: &[u8], data: &[&[u8]]) -> [u8; 32] {
+    const BLOCK_LEN: usize = 64;
+
+    let mut block = [0; BLOCK_LEN];
+    if key.len() > BLOCK_LEN {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    for data in data {
+        inner.update(data);
+    }
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+/// PBKDF2 with HMAC-SHA-256, for a key as long as the hash, defined in
+/// [RFC 8018](https://tools.ietf.org/html/rfc8018)
+fn pbkdf2_hmac_sha256(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut u = hmac_sha256(password, &[salt, &1u32.to_be_bytes()]);
+    let mut key = u;
+    for _ in 1..iterations {
+        u = hmac_sha256(password, &[&u]);
+        for (k, u) in key.iter_mut().zip(u) {
+            *k ^= u;
+        }
+    }
+    key
+}
+
 #[cfg(test)]
 mod test {
-    use super::{Credentials, Mechanism};
+    use super::{hmac_sha256, Credentials, Mechanism, SaslSession};
 
     #[test]
     fn test_plain() {
@@ -169,6 +463,114 @@ mod test {
         assert!(mechanism.response(&credentials, Some("test")).is_err());
     }
 
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231, test cases 2 and 6
+        assert_eq!(
+            hmac_sha256(b"Jefe", &[b"what do ya want ", b"for nothing?"]),
+            *b"\x5b\xdc\xc1\x46\xbf\x60\x75\x4e\x6a\x04\x24\x26\x08\x95\x75\xc7\
+               \x5a\x00\x3f\x08\x9d\x27\x39\x83\x9d\xec\x58\xb9\x64\xec\x38\x43"
+        );
+        assert_eq!(
+            hmac_sha256(
+                &[0xaa; 131],
+                &[b"Test Using Larger Than Block-Size Key - Hash Key First"]
+            ),
+            *b"\x60\xe4\x31\x59\x1e\xe0\xb6\x7f\x0d\x8a\x26\xaa\xcb\xf5\xb7\x7f\
+               \x8e\x0b\xc6\x21\x37\x28\xc5\x14\x05\x46\x04\x0f\x0e\xe3\x7f\x54"
+        );
+    }
+
+    #[test]
+    fn test_scram_sha256() {
+        // RFC 7677, section 3
+        let credentials = Credentials::new("user".to_owned(), "pencil".to_owned());
+        let mut session = SaslSession::with_nonce(
+            Mechanism::ScramSha256,
+            credentials,
+            "rOprNGfwEbeRWgbNEkqO".to_owned(),
+        );
+
+        assert_eq!(
+            session.initial_response().unwrap().unwrap(),
+            b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO"
+        );
+        assert!(session.finish().is_err());
+        assert_eq!(
+            session
+                .step(b"r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096")
+                .unwrap(),
+            b"c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ="
+        );
+        assert!(session.finish().is_err());
+        assert_eq!(
+            session
+                .step(b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+                .unwrap(),
+            b""
+        );
+        session.finish().unwrap();
+        assert!(session.step(b"").is_err());
+    }
+
+    #[test]
+    fn test_scram_sha256_invalid_server() {
+        let credentials = Credentials::new("us=er,".to_owned(), "pencil".to_owned());
+        let session = || {
+            let mut session = SaslSession::with_nonce(
+                Mechanism::ScramSha256,
+                credentials.clone(),
+                "abc".to_owned(),
+            );
+            assert_eq!(
+                session.initial_response().unwrap().unwrap(),
+                b"n,,n=us=3Der=2C,r=abc"
+            );
+            session
+        };
+
+        // The server nonce must extend the client nonce
+        for server_first in [
+            "r=abc,s=c2FsdA==,i=4096",
+            "r=xyzabc,s=c2FsdA==,i=4096",
+            "r=abcdef,s=c2FsdA==,i=0",
+            "r=abcdef,s=c2FsdA==",
+            "m=ext,r=abcdef,s=c2FsdA==,i=4096",
+        ] {
+            assert!(
+                session().step(server_first.as_bytes()).is_err(),
+                "{server_first}"
+            );
+        }
+
+        // The server must prove it knows the password
+        let mut session = session();
+        session.step(b"r=abcdef,s=c2FsdA==,i=1").unwrap();
+        let err = session
+            .step(b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "response error: Invalid server signature");
+        assert!(session.finish().is_err());
+    }
+
+    #[test]
+    fn test_sasl_session_stateless() {
+        let credentials = Credentials::new("alice".to_owned(), "wonderland".to_owned());
+
+        let mut session = SaslSession::new(Mechanism::Login, credentials.clone()).unwrap();
+        assert_eq!(session.initial_response().unwrap(), None);
+        assert_eq!(session.step(b"Username").unwrap(), b"alice");
+        assert_eq!(session.step(b"Password").unwrap(), b"wonderland");
+        session.finish().unwrap();
+
+        let mut session = SaslSession::new(Mechanism::Plain, credentials.clone()).unwrap();
+        assert_eq!(
+            session.initial_response().unwrap().unwrap(),
+            b"\0alice\0wonderland"
+        );
+        assert!(Mechanism::ScramSha256.response(&credentials, None).is_err());
+    }
+
     #[test]
     fn test_from_user_pass_for_credentials() {
         assert_eq!(
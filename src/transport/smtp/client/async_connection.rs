@@ -1,22 +1,34 @@
-use std::{fmt::Display, net::IpAddr, time::Duration};
+use std::{
+    fmt::Display,
+    future::Future,
+    io,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
 
-use futures_util::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use futures_util::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
 
 #[cfg(feature = "tokio1")]
 use super::async_net::AsyncTokioStream;
 #[cfg(feature = "tracing")]
 use super::escape_crlf;
-use super::{AsyncNetworkStream, ClientCodec, TlsParameters};
+use super::{
+    rejects_sender, sent, with_header, AsyncNetworkStream, ClientCodec, ContentEncoder,
+    LineCallback, NegotiatedFeatures, TlsParameters, DATA_BUFFER_LEN,
+};
 use crate::{
     transport::smtp::{
-        authentication::{Credentials, Mechanism},
-        commands::{Auth, Data, Ehlo, Mail, Noop, Quit, Rcpt, Starttls},
+        authentication::{Credentials, Mechanism, SaslSession},
+        commands::{Auth, Bdat, Data, Ehlo, Mail, Noop, Quit, Rcpt, Rset, Starttls},
         error,
         error::Error,
-        extension::{ClientId, Extension, MailBodyParameter, MailParameter, ServerInfo},
-        response::{parse_response, Response},
+        extension::{
+            dsn_envid_parameter, dsn_mail_parameter, dsn_rcpt_parameters, ClientId, DsnNotify,
+            DsnReturn, Extension, MailBodyParameter, MailParameter, ServerInfo,
+        },
+        response::{check_reply_line, parse_response, Code, Response},
     },
-    Envelope,
+    Address, Envelope,
 };
 
 macro_rules! try_smtp (
@@ -24,22 +36,75 @@ macro_rules! try_smtp (
         match $err {
             Ok(val) => val,
             Err(err) => {
-                $client.abort().await;
+                $client.abort_with(&err).await;
                 return Err(From::from(err))
             },
         }
     })
 );
 
+/// Runtime of the stream of a connection, whose timer bounds the reception of responses
+#[derive(Debug, Clone, Copy)]
+enum Runtime {
+    #[cfg(feature = "tokio1")]
+    Tokio1,
+    #[cfg(feature = "async-std1")]
+    AsyncStd1,
+}
+
+impl Runtime {
+    /// Waits for `future`, failing with a `TimedOut` error once `deadline` has passed
+    async fn timeout_at<T, F>(self, deadline: Instant, future: F) -> io::Result<T>
+    where
+        F: Future<Output = io::Result<T>>,
+    {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let timed_out =
+            || io::Error::new(io::ErrorKind::TimedOut, "timed out receiving the response");
+        match self {
+            #[cfg(feature = "tokio1")]
+            Runtime::Tokio1 => tokio1_crate::time::timeout(remaining, future)
+                .await
+                .unwrap_or_else(|_| Err(timed_out())),
+            #[cfg(feature = "async-std1")]
+            Runtime::AsyncStd1 => async_std::future::timeout(remaining, future)
+                .await
+                .unwrap_or_else(|_| Err(timed_out())),
+        }
+    }
+}
+
 /// Structure that implements the SMTP client
 pub struct AsyncSmtpConnection {
     /// TCP stream between client and server
     /// Value is None before connection
     stream: BufReader<AsyncNetworkStream>,
-    /// Panic state
+    /// Whether the session is broken, in which case `QUIT` isn't sent anymore
     panic: bool,
+    /// Message of the error which broke the session
+    failure: Option<String>,
+    /// Runtime providing the timer for `timeout`
+    runtime: Runtime,
+    /// Maximum time to receive each whole response
+    timeout: Option<Duration>,
     /// Information about the server
     server_info: ServerInfo,
+    /// Callback invoked for each line received from the server
+    on_line: Option<LineCallback>,
+    /// Whether the `REQUIRETLS` parameter is sent with `MAIL FROM`
+    require_tls_relay: bool,
+    /// Sender used when the server permanently rejects the one of the envelope
+    mail_from_fallback: Option<Address>,
+    /// Whether the content is sent with `BDAT` when the server supports it
+    prefer_chunking: bool,
+    /// Events for which delivery status notifications are requested
+    dsn_notify: Vec<DsnNotify>,
+    /// Part of the message returned with failure notifications
+    dsn_return: Option<DsnReturn>,
+    /// Mechanism used to authenticate, if any
+    auth_mechanism: Option<Mechanism>,
+    /// Features used to send the last email
+    negotiated: Option<NegotiatedFeatures>,
 }
 
 impl AsyncSmtpConnection {
@@ -57,7 +122,7 @@ impl AsyncSmtpConnection {
         hello_name: &ClientId,
     ) -> Result<AsyncSmtpConnection, Error> {
         let stream = AsyncNetworkStream::use_existing_tokio1(stream);
-        Self::connect_impl(stream, hello_name).await
+        Self::connect_impl(stream, Runtime::Tokio1, None, hello_name).await
     }
 
     /// Connects to the configured server
@@ -102,7 +167,7 @@ impl AsyncSmtpConnection {
         let stream =
             AsyncNetworkStream::connect_tokio1(server, timeout, tls_parameters, local_address)
                 .await?;
-        Self::connect_impl(stream, hello_name).await
+        Self::connect_impl(stream, Runtime::Tokio1, timeout, hello_name).await
     }
 
     /// Connects to the configured server
@@ -116,18 +181,31 @@ impl AsyncSmtpConnection {
         tls_parameters: Option<TlsParameters>,
     ) -> Result<AsyncSmtpConnection, Error> {
         let stream = AsyncNetworkStream::connect_asyncstd1(server, timeout, tls_parameters).await?;
-        Self::connect_impl(stream, hello_name).await
+        Self::connect_impl(stream, Runtime::AsyncStd1, timeout, hello_name).await
     }
 
     async fn connect_impl(
         stream: AsyncNetworkStream,
+        runtime: Runtime,
+        timeout: Option<Duration>,
         hello_name: &ClientId,
     ) -> Result<AsyncSmtpConnection, Error> {
         let stream = BufReader::new(stream);
         let mut conn = AsyncSmtpConnection {
             stream,
             panic: false,
+            failure: None,
+            runtime,
+            timeout,
             server_info: ServerInfo::default(),
+            on_line: None,
+            require_tls_relay: false,
+            mail_from_fallback: None,
+            prefer_chunking: false,
+            dsn_notify: Vec::new(),
+            dsn_return: None,
+            auth_mechanism: None,
+            negotiated: None,
         };
         // TODO log
         let _response = conn.read_response().await?;
@@ -141,8 +219,118 @@ impl AsyncSmtpConnection {
     }
 
     pub async fn send(&mut self, envelope: &Envelope, email: &[u8]) -> Result<Response, Error> {
+        self.check_size(email.len() as u64)?;
+        let header = self.start_data(envelope, email.is_ascii()).await?;
+        let email = with_header(header, email);
+        let email = email.as_ref();
+
+        // Message content
+        let result = if self.uses_chunking() {
+            try_smtp!(self.chunk(email, true).await, self)
+        } else {
+            try_smtp!(self.command(Data).await, self);
+            try_smtp!(self.message(email).await, self)
+        };
+        Ok(sent(envelope.envelope_id(), result))
+    }
+
+    /// Sends an email whose content is read from `reader`
+    ///
+    /// The content is read and sent to the server in small chunks, so
+    /// large emails don't have to be loaded in memory. Bare `LF` line
+    /// endings are converted to `CRLF`. `is_ascii` tells whether the
+    /// content only contains ASCII characters.
+    ///
+    /// If reading fails the connection is aborted, so that the partially
+    /// sent message is discarded by the server.
+    pub async fn send_from_reader<R: AsyncRead + Unpin + ?Sized>(
+        &mut self,
+        envelope: &Envelope,
+        is_ascii: bool,
+        reader: &mut R,
+    ) -> Result<Response, Error> {
+        let header = self.start_data(envelope, is_ascii).await?;
+        let chunking = self.uses_chunking();
+        if !chunking {
+            try_smtp!(self.command(Data).await, self);
+        }
+
+        let mut encoder = ContentEncoder::new(chunking);
+        let mut block = vec![0; DATA_BUFFER_LEN];
+        let mut buf = Vec::with_capacity(2 * DATA_BUFFER_LEN);
+        if let Some(header) = header {
+            encoder.encode(header.as_bytes(), &mut buf);
+        }
+        loop {
+            let len = match reader.read(&mut block).await {
+                Ok(len) => len,
+                Err(err) => {
+                    // Anything sent now would be part of the message content
+                    self.panic = true;
+                    let err = error::client(err);
+                    self.abort_with(&err).await;
+                    return Err(err);
+                }
+            };
+            if len == 0 {
+                break;
+            }
+
+            encoder.encode(&block[..len], &mut buf);
+            if buf.len() >= DATA_BUFFER_LEN {
+                if chunking {
+                    try_smtp!(self.chunk(&buf, false).await, self);
+                } else {
+                    self.write(&buf).await?;
+                    self.count_wire_bytes(buf.len());
+                }
+                buf.clear();
+            }
+        }
+
+        let result = if chunking {
+            try_smtp!(self.chunk(&buf, true).await, self)
+        } else {
+            encoder.finish(&mut buf);
+            self.write(&buf).await?;
+            self.count_wire_bytes(buf.len());
+            try_smtp!(self.read_response().await, self)
+        };
+        Ok(sent(envelope.envelope_id(), result))
+    }
+
+    /// Fails if an email of `size` octets is larger than the limit
+    /// advertised by the server, so that it isn't sent for nothing
+    pub(crate) fn check_size(&self, size: u64) -> Result<(), Error> {
+        match self.server_info().max_message_size() {
+            Some(limit) if size > limit => Err(error::message_too_large(size, limit)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the content is sent with `BDAT` instead of `DATA`
+    fn uses_chunking(&self) -> bool {
+        self.prefer_chunking && self.server_info().supports_feature(Extension::Chunking)
+    }
+
+    /// Sends the `MAIL` and `RCPT` commands for `envelope`
+    ///
+    /// Returns the header to add before the content when the sender
+    /// was replaced by the fallback.
+    async fn start_data(
+        &mut self,
+        envelope: &Envelope,
+        is_ascii: bool,
+    ) -> Result<Option<String>, Error> {
         // Mail
         let mut mail_options = vec![];
+        self.negotiated = None;
+        let mut negotiated = NegotiatedFeatures {
+            chunking: self.uses_chunking(),
+            encrypted: self.is_encrypted(),
+            auth_mechanism: self.auth_mechanism,
+            ..NegotiatedFeatures::default()
+        };
 
         // Internationalization handling
         //
@@ -158,44 +346,120 @@ impl AsyncSmtpConnection {
                 ));
             }
             mail_options.push(MailParameter::SmtpUtfEight);
+            negotiated.smtp_utf8 = true;
         }
 
         // Check for non-ascii content in the message
-        if !email.is_ascii() {
+        if !is_ascii {
             if !self.server_info().supports_feature(Extension::EightBitMime) {
                 return Err(error::client(
                     "Message contains non-ascii chars but server does not support 8BITMIME",
                 ));
             }
             mail_options.push(MailParameter::Body(MailBodyParameter::EightBitMime));
+            negotiated.eight_bit_mime = true;
         }
 
-        try_smtp!(
-            self.command(Mail::new(envelope.from().cloned(), mail_options))
-                .await,
-            self
-        );
+        // Require the message to only be relayed over TLS
+        //
+        // * REQUIRETLS: https://tools.ietf.org/html/rfc8689
+        if self.require_tls_relay {
+            if !self.server_info().supports_feature(Extension::RequireTls) {
+                return Err(error::require_tls_unsupported());
+            }
+            mail_options.push(MailParameter::Other {
+                keyword: "REQUIRETLS".to_owned(),
+                value: None,
+            });
+            negotiated.require_tls = true;
+        }
+
+        // Delivery status notifications, not requested if the server can't send them
+        //
+        // * DSN: https://tools.ietf.org/html/rfc3461
+        let dsn = self.server_info().supports_feature(Extension::Dsn);
+        if let Some(ret) = self.dsn_return.filter(|_| dsn) {
+            mail_options.push(dsn_mail_parameter(ret));
+        }
+        if let Some(id) = envelope.envelope_id() {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(envelope_id = id, "sending the email");
+            if dsn {
+                mail_options.push(dsn_envid_parameter(id));
+            }
+        }
+        negotiated.dsn = dsn
+            && (self.dsn_return.is_some()
+                || !self.dsn_notify.is_empty()
+                || envelope.envelope_id().is_some());
+        self.negotiated = Some(negotiated);
+
+        let mut header = None;
+        let mail = self
+            .command(Mail::new(envelope.from().cloned(), mail_options.clone()))
+            .await;
+        let mail = match (mail, envelope.from(), self.mail_from_fallback.clone()) {
+            (Err(err), Some(from), Some(fallback)) if rejects_sender(&err) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    envelope_id = envelope.envelope_id(),
+                    "sender {from} rejected ({err}), retrying with {fallback}"
+                );
+                #[cfg(not(feature = "tracing"))]
+                let _ = err;
+
+                try_smtp!(self.command(Rset).await, self);
+                if let Some(negotiated) = &mut self.negotiated {
+                    negotiated.mail_from_fallback = true;
+                }
+                header = Some(format!("X-Original-From: {from}\r\n"));
+                self.command(Mail::new(Some(fallback), mail_options)).await
+            }
+            (mail, _, _) => mail,
+        };
+        try_smtp!(mail, self);
 
         // Recipient
         for to_address in envelope.to() {
+            let rcpt_options = if dsn {
+                dsn_rcpt_parameters(&self.dsn_notify, to_address)
+            } else {
+                vec![]
+            };
             try_smtp!(
-                self.command(Rcpt::new(to_address.clone(), vec![])).await,
+                self.command(Rcpt::new(to_address.clone(), rcpt_options))
+                    .await,
                 self
             );
         }
+        Ok(header)
+    }
 
-        // Data
-        try_smtp!(self.command(Data).await, self);
-
-        // Message content
-        let result = try_smtp!(self.message(email).await, self);
-        Ok(result)
+    /// Returns the features of the server used to send the last email
+    ///
+    /// Returns `None` if no email was sent over this connection, or
+    /// if sending the last one failed before the `MAIL` command.
+    pub fn negotiated_features(&self) -> Option<NegotiatedFeatures> {
+        self.negotiated
     }
 
     pub fn has_broken(&self) -> bool {
         self.panic
     }
 
+    /// Returns the message of the error which broke the session, if any
+    #[cfg(feature = "pool")]
+    pub(crate) fn failure(&self) -> Option<&str> {
+        self.failure.as_deref()
+    }
+
+    /// Returns the address of the server, or of the proxy, if the
+    /// connection has one
+    #[cfg(feature = "pool")]
+    pub(crate) fn peer_addr(&self) -> Option<std::net::SocketAddr> {
+        self.stream.get_ref().peer_addr().ok()
+    }
+
     pub fn can_starttls(&self) -> bool {
         !self.is_encrypted() && self.server_info.supports_feature(Extension::StartTls)
     }
@@ -203,9 +467,11 @@ impl AsyncSmtpConnection {
     /// Upgrade the connection using `STARTTLS`.
     ///
     /// As described in [rfc3207]. Note that this mechanism has been deprecated in [rfc8314].
+    /// Fails with [`StarttlsStage::NotAdvertised`] if the server doesn't advertise `STARTTLS`.
     ///
     /// [rfc3207]: https://www.rfc-editor.org/rfc/rfc3207
     /// [rfc8314]: https://www.rfc-editor.org/rfc/rfc8314
+    /// [`StarttlsStage::NotAdvertised`]: crate::transport::smtp::StarttlsStage::NotAdvertised
     #[allow(unused_variables)]
     pub async fn starttls(
         &mut self,
@@ -213,15 +479,42 @@ impl AsyncSmtpConnection {
         hello_name: &ClientId,
     ) -> Result<(), Error> {
         if self.server_info.supports_feature(Extension::StartTls) {
-            try_smtp!(self.command(Starttls).await, self);
-            self.stream.get_mut().upgrade_tls(tls_parameters).await?;
+            let response = try_smtp!(
+                self.command(Starttls)
+                    .await
+                    .map_err(|e| error::starttls(error::StarttlsStage::CommandRejected, e)),
+                self
+            );
+            // Only a `220` reply lets the handshake start
+            if response.code() != Code::SERVICE_READY {
+                let err =
+                    error::response(format!("Unexpected reply to STARTTLS: {}", response.code()));
+                return Err(
+                    self.corrupted(error::starttls(error::StarttlsStage::CommandRejected, err))
+                );
+            }
+            // Anything sent before the handshake, still buffered, is unprotected
+            // and must not be read as a reply over TLS (RFC 3207 section 6)
+            if !self.stream.buffer().is_empty() {
+                let err = error::response("Unexpected data after the STARTTLS reply");
+                return Err(
+                    self.corrupted(error::starttls(error::StarttlsStage::UnexpectedData, err))
+                );
+            }
+            // The capabilities advertised in plain text can't be trusted either
+            self.server_info = ServerInfo::default();
+            self.stream
+                .get_mut()
+                .upgrade_tls(tls_parameters)
+                .await
+                .map_err(error::starttls_upgrade)?;
             #[cfg(feature = "tracing")]
             tracing::debug!("connection encrypted");
             // Send EHLO again
             try_smtp!(self.ehlo(hello_name).await, self);
             Ok(())
         } else {
-            Err(error::client("STARTTLS is not supported on this server"))
+            Err(error::starttls_not_advertised())
         }
     }
 
@@ -237,12 +530,75 @@ impl AsyncSmtpConnection {
     }
 
     pub async fn abort(&mut self) {
-        // Only try to quit if we are not already broken
+        self.close().await;
+    }
+
+    /// Closes the connection like [`abort`](Self::abort), returning whether
+    /// `QUIT` was written
+    pub(crate) async fn close(&mut self) -> bool {
+        let mut quit_sent = false;
+        // Only try to quit if the session is still in a known state, otherwise
+        // the server may not be waiting for a command and never reply
         if !self.panic {
             self.panic = true;
-            let _ = self.command(Quit).await;
+            quit_sent = self.write(Quit.to_string().as_bytes()).await.is_ok();
+            if quit_sent {
+                let _ = self.read_response().await;
+            }
         }
         let _ = self.stream.close().await;
+        quit_sent
+    }
+
+    /// Sets a callback invoked with each raw line received from the server
+    ///
+    /// The line is passed as received, including the reply code and the trailing CRLF,
+    /// before the complete response is parsed.
+    pub fn on_line(&mut self, callback: LineCallback) {
+        self.on_line = Some(callback);
+    }
+
+    /// Sets whether the messages must only be relayed over TLS
+    ///
+    /// When enabled, the `REQUIRETLS` parameter defined in
+    /// [RFC 8689](https://tools.ietf.org/html/rfc8689) is sent with `MAIL FROM`,
+    /// and sending fails if the server doesn't support it.
+    pub fn require_tls_relay(&mut self, require: bool) {
+        self.require_tls_relay = require;
+    }
+
+    /// Sets the sender used when the server rejects the sender of the envelope
+    ///
+    /// See [`SmtpConnection::mail_from_fallback`](super::SmtpConnection::mail_from_fallback).
+    pub fn mail_from_fallback(&mut self, fallback: Option<Address>) {
+        self.mail_from_fallback = fallback;
+    }
+
+    /// Requests delivery status notifications for the `notify` events
+    ///
+    /// Sends the `NOTIFY` and `ORCPT` parameters defined in
+    /// [RFC 3461](https://tools.ietf.org/html/rfc3461) with each `RCPT TO`.
+    /// They are only sent to servers supporting the `DSN` extension.
+    pub fn dsn_notify(&mut self, notify: &[DsnNotify]) {
+        self.dsn_notify = notify.to_vec();
+    }
+
+    /// Sets the part of the message returned with failure notifications
+    ///
+    /// Sends the `RET` parameter defined in [RFC 3461](https://tools.ietf.org/html/rfc3461)
+    /// with `MAIL FROM`. It is only sent to servers supporting the `DSN` extension.
+    pub fn dsn_return(&mut self, ret: Option<DsnReturn>) {
+        self.dsn_return = ret;
+    }
+
+    /// Sets whether the content is sent with `BDAT` when possible
+    ///
+    /// When enabled and the server advertises the `CHUNKING` extension
+    /// defined in [RFC 3030](https://tools.ietf.org/html/rfc3030), the content
+    /// is sent with `BDAT` instead of after a `DATA` command, without
+    /// dot-stuffing. `DATA` is still used with servers which don't support it.
+    pub fn prefer_chunking(&mut self, prefer: bool) {
+        self.prefer_chunking = prefer;
     }
 
     /// Sets the underlying stream
@@ -260,6 +616,19 @@ impl AsyncSmtpConnection {
         self.command(Noop).await.is_ok()
     }
 
+    /// Resets the session using the RSET SMTP command
+    ///
+    /// Fails unless the server replied with `250`, in which case the state
+    /// of the session is unknown and the connection shouldn't be reused.
+    pub async fn reset(&mut self) -> Result<Response, Error> {
+        let response = self.command(Rset).await?;
+        if response.code() == Code::OK {
+            Ok(response)
+        } else {
+            Err(error::response("the server didn't reply to RSET with 250"))
+        }
+    }
+
     /// Sends an AUTH command with the given mechanism, and handles the challenge if needed
     pub async fn auth(
         &mut self,
@@ -273,26 +642,22 @@ impl AsyncSmtpConnection {
 
         // Limit challenges to avoid blocking
         let mut challenges: u8 = 10;
+        let mut session = SaslSession::new(mechanism, credentials.clone())?;
         let mut response = self
-            .command(Auth::new(mechanism, credentials.clone(), None)?)
+            .command(Auth::from_session(&mut session, None)?)
             .await?;
 
-        while challenges > 0 && response.has_code(334) {
+        while challenges > 0 && response.code() == Code::AUTH_CHALLENGE {
             challenges -= 1;
-            response = try_smtp!(
-                self.command(Auth::new_from_response(
-                    mechanism,
-                    credentials.clone(),
-                    &response,
-                )?)
-                .await,
-                self
-            );
+            let auth = try_smtp!(Auth::from_session(&mut session, Some(&response)), self);
+            response = try_smtp!(self.command(auth).await, self);
         }
 
         if challenges == 0 {
             Err(error::response("Unexpected number of challenges"))
         } else {
+            try_smtp!(session.finish(), self);
+            self.auth_mechanism = Some(mechanism);
             Ok(response)
         }
     }
@@ -304,9 +669,26 @@ impl AsyncSmtpConnection {
         codec.encode(message, &mut out_buf);
         self.write(out_buf.as_slice()).await?;
         self.write(b"\r\n.\r\n").await?;
+        self.count_wire_bytes(out_buf.len() + 5);
         self.read_response().await
     }
 
+    /// Sends a chunk of the message content with `BDAT`
+    async fn chunk(&mut self, chunk: &[u8], last: bool) -> Result<Response, Error> {
+        let mut out_buf = Bdat::new(chunk.len(), last).to_string().into_bytes();
+        out_buf.extend_from_slice(chunk);
+        self.write(&out_buf).await?;
+        self.count_wire_bytes(out_buf.len());
+        self.read_response().await
+    }
+
+    /// Adds `len` octets of content to the ones of the email being sent
+    fn count_wire_bytes(&mut self, len: usize) {
+        if let Some(negotiated) = &mut self.negotiated {
+            negotiated.wire_bytes += len as u64;
+        }
+    }
+
     /// Sends an SMTP command
     pub async fn command<C: Display>(&mut self, command: C) -> Result<Response, Error> {
         self.write(command.to_string().as_bytes()).await?;
@@ -319,29 +701,71 @@ impl AsyncSmtpConnection {
             .get_mut()
             .write_all(string)
             .await
-            .map_err(error::network)?;
+            .map_err(|err| self.corrupted(error::network(err)))?;
         self.stream
             .get_mut()
             .flush()
             .await
-            .map_err(error::network)?;
+            .map_err(|err| self.corrupted(error::network(err)))?;
 
         #[cfg(feature = "tracing")]
         tracing::debug!("Wrote: {}", escape_crlf(&String::from_utf8_lossy(string)));
         Ok(())
     }
 
+    /// Marks the session as broken after a network or protocol error
+    ///
+    /// The state of the session is unknown, so `QUIT` won't be sent on it.
+    fn corrupted(&mut self, err: Error) -> Error {
+        self.panic = true;
+        self.failure.get_or_insert_with(|| err.to_string());
+        err
+    }
+
+    /// Aborts the connection because of `err`, which is kept as the reason
+    /// why the session broke unless an earlier error already broke it
+    async fn abort_with(&mut self, err: &(dyn Display + Sync)) {
+        self.failure.get_or_insert_with(|| err.to_string());
+        self.abort().await;
+    }
+
+    /// Set the maximum time to receive each whole response
+    ///
+    /// It bounds the reception of all the lines of a response, so that a
+    /// server sending its responses very slowly can't stall the client.
+    pub fn set_timeout(&mut self, duration: Option<Duration>) {
+        self.timeout = duration;
+    }
+
     /// Gets the SMTP response
     pub async fn read_response(&mut self) -> Result<Response, Error> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
         let mut buffer = String::with_capacity(100);
+        let mut line_start = 0;
+
+        loop {
+            let read = self.stream.read_line(&mut buffer);
+            let read = match deadline {
+                Some(deadline) => self.runtime.timeout_at(deadline, read).await,
+                None => read.await,
+            };
+            if read.map_err(|err| self.corrupted(error::network(err)))? == 0 {
+                break;
+            }
+
+            if let Some(on_line) = &mut self.on_line {
+                on_line(&buffer[line_start..]);
+            }
+            if let Err(err) = check_reply_line(&buffer[line_start..]) {
+                return Err(self.corrupted(err));
+            }
+            // Only the last line of a response doesn't have a `-` after the code
+            let last_line = buffer.as_bytes().get(line_start + 3) != Some(&b'-');
+            line_start = buffer.len();
+            if !last_line {
+                continue;
+            }
 
-        while self
-            .stream
-            .read_line(&mut buffer)
-            .await
-            .map_err(error::network)?
-            > 0
-        {
             #[cfg(feature = "tracing")]
             tracing::debug!("<< {}", escape_crlf(&buffer));
             match parse_response(&buffer) {
@@ -349,23 +773,20 @@ impl AsyncSmtpConnection {
                     return if response.is_positive() {
                         Ok(response)
                     } else {
-                        Err(error::code(
-                            response.code(),
-                            Some(response.message().collect()),
-                        ))
+                        Err(error::from_response(response))
                     }
                 }
                 Err(nom::Err::Failure(e)) => {
-                    return Err(error::response(e.to_string()));
+                    return Err(self.corrupted(error::response(e.to_string())));
                 }
                 Err(nom::Err::Incomplete(_)) => { /* read more */ }
                 Err(nom::Err::Error(e)) => {
-                    return Err(error::response(e.to_string()));
+                    return Err(self.corrupted(error::response(e.to_string())));
                 }
             }
         }
 
-        Err(error::response("incomplete response"))
+        Err(self.corrupted(error::response("incomplete response")))
     }
 
     /// The X509 certificate of the server (DER encoded)
@@ -374,3 +795,144 @@ impl AsyncSmtpConnection {
         self.stream.get_ref().peer_certificate()
     }
 }
+
+#[cfg(all(test, feature = "tokio1"))]
+mod test {
+    use std::time::Duration;
+
+    use futures_util::io::Cursor;
+
+    use super::AsyncSmtpConnection;
+    use crate::transport::{
+        envelope,
+        smtp::{
+            client::{serve, MockStep},
+            extension::ClientId,
+        },
+    };
+
+    #[tokio1_crate::test(crate = "tokio1_crate")]
+    async fn send_from_reader() {
+        let (addr, server) = serve(vec![
+            MockStep::send("220 localhost ESMTP\r\n"),
+            MockStep::ExpectLine("EHLO ".to_owned()),
+            MockStep::send("250-localhost\r\n250 SIZE 100\r\n"),
+            MockStep::ExpectLine("MAIL FROM:".to_owned()),
+            MockStep::send("250 OK\r\n"),
+            MockStep::ExpectLine("RCPT TO:".to_owned()),
+            MockStep::send("250 OK\r\n"),
+            MockStep::expect("DATA\r\n"),
+            MockStep::send("354 Go ahead\r\n"),
+            MockStep::expect("Subject: Hi\r\n\r\n..Hi\r\nthere\r\n.\r\n"),
+            MockStep::send("250 Queued\r\n"),
+            MockStep::expect("QUIT\r\n"),
+            MockStep::send("221 Bye\r\n"),
+        ]);
+
+        let hello = ClientId::Domain("localhost".to_owned());
+        let mut conn = AsyncSmtpConnection::connect_tokio1(addr, None, &hello, None, None)
+            .await
+            .unwrap();
+
+        // Bare LF line endings are converted and the leading dot is escaped
+        let mut reader = Cursor::new(b"Subject: Hi\n\n.Hi\nthere".to_vec());
+        let response = conn
+            .send_from_reader(&envelope(), true, &mut reader)
+            .await
+            .unwrap();
+        assert_eq!(response.first_line(), Some("Queued"));
+        assert_eq!(conn.negotiated_features().unwrap().wire_bytes(), 31);
+
+        // The size of a content read from a reader isn't known beforehand
+        assert!(conn.check_size(101).unwrap_err().is_message_too_large());
+        conn.quit().await.unwrap();
+
+        server.join().unwrap();
+    }
+
+    #[tokio1_crate::test(crate = "tokio1_crate")]
+    async fn slow_server() {
+        let hello = ClientId::Domain("localhost".to_owned());
+        // A server sending its greeting one byte at a time
+        let dripping_server = |delay| {
+            serve(vec![
+                MockStep::SendBytesSlowly {
+                    bytes: b"220 localhost ESMTP\r\n".to_vec(),
+                    chunk: 1,
+                    delay,
+                },
+                MockStep::ExpectLine("EHLO ".to_owned()),
+                MockStep::send("250-localhost\r\n250 8BITMIME\r\n"),
+                MockStep::expect("QUIT\r\n"),
+                MockStep::send("221 Bye\r\n"),
+            ])
+        };
+
+        let (addr, server) = dripping_server(Duration::from_millis(1));
+        let timeout = Some(Duration::from_secs(30));
+        let mut conn = AsyncSmtpConnection::connect_tokio1(addr, timeout, &hello, None, None)
+            .await
+            .unwrap();
+        conn.quit().await.unwrap();
+        server.join().unwrap();
+
+        // Each byte arrives within the timeout, but the whole greeting
+        // can't arrive in less than 2 seconds
+        let (addr, server) = dripping_server(Duration::from_millis(100));
+        let timeout = Some(Duration::from_millis(300));
+        let err = AsyncSmtpConnection::connect_tokio1(addr, timeout, &hello, None, None)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.is_timeout(), "{err}");
+        // The server stops once the client disconnected
+        assert!(server.join().unwrap().is_empty());
+    }
+
+    #[tokio1_crate::test(crate = "tokio1_crate")]
+    async fn mail_from_fallback() {
+        let (addr, server) = serve(vec![
+            MockStep::send("220 localhost ESMTP\r\n"),
+            MockStep::ExpectLine("EHLO ".to_owned()),
+            MockStep::send("250 localhost\r\n"),
+            MockStep::expect("MAIL FROM:<nobody@domain.tld>\r\n"),
+            MockStep::send("550 5.7.1 Sender domain not verified\r\n"),
+            MockStep::expect("RSET\r\n"),
+            MockStep::send("250 OK\r\n"),
+            MockStep::expect("MAIL FROM:<noreply@verified.tld>\r\n"),
+            MockStep::send("250 OK\r\n"),
+            MockStep::ExpectLine("RCPT TO:".to_owned()),
+            MockStep::send("250 OK\r\n"),
+            MockStep::expect("DATA\r\n"),
+            MockStep::send("354 Go ahead\r\n"),
+            MockStep::expect(
+                "X-Original-From: nobody@domain.tld\r\nSubject: Hi\r\n\r\nHi\r\n.\r\n",
+            ),
+            MockStep::send("250 Queued\r\n"),
+            MockStep::expect("MAIL FROM:<nobody@domain.tld>\r\n"),
+            MockStep::send("552 5.3.4 Message too big\r\n"),
+            MockStep::expect("QUIT\r\n"),
+            MockStep::send("221 Bye\r\n"),
+        ]);
+
+        let hello = ClientId::Domain("localhost".to_owned());
+        let mut conn = AsyncSmtpConnection::connect_tokio1(addr, None, &hello, None, None)
+            .await
+            .unwrap();
+        conn.mail_from_fallback(Some("noreply@verified.tld".parse().unwrap()));
+
+        conn.send(&envelope(), b"Subject: Hi\r\n\r\nHi")
+            .await
+            .unwrap();
+        assert!(conn.negotiated_features().unwrap().mail_from_fallback());
+
+        // Size errors aren't retried
+        let err = conn
+            .send(&envelope(), b"Subject: Hi\r\n\r\nHi")
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Some(552));
+
+        server.join().unwrap();
+    }
+}
@@ -1,22 +1,32 @@
+#[cfg(unix)]
+use std::path::Path;
 use std::{
     fmt::Display,
-    io::{self, BufRead, BufReader, Write},
+    io::{self, BufRead, BufReader, Read, Write},
     net::{IpAddr, ToSocketAddrs},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 #[cfg(feature = "tracing")]
 use super::escape_crlf;
-use super::{ClientCodec, NetworkStream, TlsParameters};
+use super::{
+    rejects_sender, sent, with_header, ClientCodec, ContentEncoder, LineCallback,
+    NegotiatedFeatures, NetworkStream, ProxyConfig, TlsParameters, DATA_BUFFER_LEN,
+};
+#[cfg(feature = "compression")]
+use crate::transport::smtp::commands::Compress;
 use crate::{
-    address::Envelope,
+    address::{Address, Envelope},
     transport::smtp::{
-        authentication::{Credentials, Mechanism},
-        commands::{Auth, Data, Ehlo, Mail, Noop, Quit, Rcpt, Starttls},
+        authentication::{Credentials, Mechanism, SaslSession},
+        commands::{Auth, Bdat, Data, Ehlo, Lhlo, Mail, Noop, Quit, Rcpt, Rset, Starttls},
         error,
         error::Error,
-        extension::{ClientId, Extension, MailBodyParameter, MailParameter, ServerInfo},
-        response::{parse_response, Response},
+        extension::{
+            dsn_envid_parameter, dsn_mail_parameter, dsn_rcpt_parameters, ClientId, DsnNotify,
+            DsnReturn, Extension, MailBodyParameter, MailParameter, ServerInfo,
+        },
+        response::{check_reply_line, parse_response, Code, Response},
     },
 };
 
@@ -25,22 +35,151 @@ macro_rules! try_smtp (
         match $err {
             Ok(val) => val,
             Err(err) => {
-                $client.abort();
+                $client.abort_with(&err);
                 return Err(From::from(err))
             },
         }
     })
 );
 
+/// Writer for the content of an email, obtained from [`SmtpConnection::data_writer`]
+///
+/// The content is written to the server in small chunks as it's written.
+/// Bare `LF` line endings are converted to `CRLF`. Lines starting with a
+/// `.` are escaped, unless the content is sent in `BDAT` chunks.
+///
+/// [`DataWriter::finish`] must be called once the whole content has been
+/// written. If the writer is dropped without being finished, the
+/// connection is shut down so that the server discards the partial message.
+pub struct DataWriter<'a> {
+    conn: &'a mut SmtpConnection,
+    encoder: ContentEncoder,
+    buf: Vec<u8>,
+    /// Whether the content is sent with `BDAT` rather than `DATA`
+    chunking: bool,
+    /// Whether writing to the server failed, as opposed to producing the content
+    network_failed: bool,
+    /// Error returned by the server for a `BDAT` chunk
+    chunk_error: Option<Error>,
+    /// Identifier of the envelope of the email
+    envelope_id: Option<String>,
+    finished: bool,
+}
+
+impl DataWriter<'_> {
+    /// Writes the end of the content and reads the response of the server
+    pub fn finish(mut self) -> Result<Response, Error> {
+        if self.chunking {
+            self.finished = true;
+            let chunk = std::mem::take(&mut self.buf);
+            let conn = &mut *self.conn;
+            let result = try_smtp!(conn.chunk(&chunk, true), conn);
+            return Ok(sent(self.envelope_id.as_deref(), result));
+        }
+
+        self.encoder.finish(&mut self.buf);
+        Write::flush(&mut self).map_err(error::network)?;
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!("Wrote message content");
+
+        self.finished = true;
+        let conn = &mut *self.conn;
+        let result = try_smtp!(conn.read_response(), conn);
+        Ok(sent(self.envelope_id.as_deref(), result))
+    }
+
+    fn write_buf(&mut self) -> io::Result<()> {
+        if self.chunking {
+            if self.buf.is_empty() {
+                return Ok(());
+            }
+            return match self.conn.chunk(&self.buf, false) {
+                Ok(_) => {
+                    self.buf.clear();
+                    Ok(())
+                }
+                Err(err) => {
+                    self.chunk_error = Some(err);
+                    Err(io::Error::new(io::ErrorKind::Other, "BDAT chunk failed"))
+                }
+            };
+        }
+
+        let stream = self.conn.stream.get_mut();
+        if let Err(err) = stream.write_all(&self.buf) {
+            self.network_failed = true;
+            return Err(err);
+        }
+        self.conn.count_wire_bytes(self.buf.len());
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl Write for DataWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder.encode(buf, &mut self.buf);
+        if self.buf.len() >= DATA_BUFFER_LEN {
+            self.write_buf()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_buf()?;
+        self.conn.stream.get_mut().flush().map_err(|err| {
+            self.network_failed = true;
+            err
+        })
+    }
+}
+
+impl Drop for DataWriter<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("message content not finished, aborting the connection");
+
+            // Anything sent now would be part of the message content
+            self.conn.panic = true;
+            self.conn
+                .abort_with(&"the content of the email wasn't finished");
+        }
+    }
+}
+
 /// Structure that implements the SMTP client
 pub struct SmtpConnection {
     /// TCP stream between client and server
     /// Value is None before connection
     stream: BufReader<NetworkStream>,
-    /// Panic state
+    /// Whether the session is broken, in which case `QUIT` isn't sent anymore
     panic: bool,
+    /// Message of the error which broke the session
+    failure: Option<String>,
     /// Information about the server
     server_info: ServerInfo,
+    /// Callback invoked for each line received from the server
+    on_line: Option<LineCallback>,
+    /// Whether the `REQUIRETLS` parameter is sent with `MAIL FROM`
+    require_tls_relay: bool,
+    /// Sender used when the server permanently rejects the one of the envelope
+    mail_from_fallback: Option<Address>,
+    /// Whether the content is sent with `BDAT` when the server supports it
+    prefer_chunking: bool,
+    /// Events for which delivery status notifications are requested
+    dsn_notify: Vec<DsnNotify>,
+    /// Part of the message returned with failure notifications
+    dsn_return: Option<DsnReturn>,
+    /// Mechanism used to authenticate, if any
+    auth_mechanism: Option<Mechanism>,
+    /// Features used to send the last email
+    negotiated: Option<NegotiatedFeatures>,
+    /// Whether the server speaks LMTP instead of SMTP
+    lmtp: bool,
+    /// Timeout of IO operations, which also bounds the time to receive a whole response
+    timeout: Option<Duration>,
 }
 
 impl SmtpConnection {
@@ -62,12 +201,54 @@ impl SmtpConnection {
         local_address: Option<IpAddr>,
     ) -> Result<SmtpConnection, Error> {
         let stream = NetworkStream::connect(server, timeout, tls_parameters, local_address)?;
-        let stream = BufReader::new(stream);
-        let mut conn = SmtpConnection {
-            stream,
-            panic: false,
-            server_info: ServerInfo::default(),
-        };
+        Self::greet(stream, timeout, hello_name)
+    }
+
+    /// Connects to the SMTP server at `host:port` through `proxy`
+    ///
+    /// `host` is resolved by the proxy. When `tls_parameters` are given,
+    /// TLS is negotiated with the server, not with the proxy.
+    pub fn connect_through_proxy(
+        proxy: &ProxyConfig,
+        host: &str,
+        port: u16,
+        timeout: Option<Duration>,
+        hello_name: &ClientId,
+        tls_parameters: Option<&TlsParameters>,
+        local_address: Option<IpAddr>,
+    ) -> Result<SmtpConnection, Error> {
+        let stream = NetworkStream::connect_through_proxy(
+            proxy,
+            host,
+            port,
+            timeout,
+            tls_parameters,
+            local_address,
+        )?;
+        Self::greet(stream, timeout, hello_name)
+    }
+
+    /// Speaks SMTP over a stream already connected to the server
+    ///
+    /// Reads the greeting of the server and sends EHLO. This allows using
+    /// another transport than TCP, or scripting the replies of a server
+    /// in tests. The connection can't be upgraded to TLS.
+    ///
+    /// See [`MockStream`](super::MockStream) for an example.
+    pub fn from_stream<S: Read + Write + Send + 'static>(
+        stream: S,
+        hello_name: &ClientId,
+    ) -> Result<SmtpConnection, Error> {
+        Self::greet(NetworkStream::from_stream(stream), None, hello_name)
+    }
+
+    /// Reads the greeting of the server and sends EHLO
+    pub(crate) fn greet(
+        stream: NetworkStream,
+        timeout: Option<Duration>,
+        hello_name: &ClientId,
+    ) -> Result<SmtpConnection, Error> {
+        let mut conn = SmtpConnection::new(stream);
         conn.set_timeout(timeout).map_err(error::network)?;
         // TODO log
         let _response = conn.read_response()?;
@@ -80,9 +261,245 @@ impl SmtpConnection {
         Ok(conn)
     }
 
+    /// Connects to an LMTP server listening on the unix domain socket at `path`
+    ///
+    /// Sends LHLO and parses server information. Emails must then be
+    /// sent with [`SmtpConnection::send_lmtp`].
+    #[cfg(unix)]
+    pub fn connect_lmtp_unix<P: AsRef<Path>>(
+        path: P,
+        timeout: Option<Duration>,
+        hello_name: &ClientId,
+    ) -> Result<SmtpConnection, Error> {
+        let stream = NetworkStream::connect_unix(path, timeout)?;
+        let mut conn = SmtpConnection::new(stream);
+        conn.lmtp = true;
+        conn.timeout = timeout;
+        let _response = conn.read_response()?;
+
+        let lhlo_response = try_smtp!(conn.command(Lhlo::new(hello_name.clone())), conn);
+        conn.server_info = try_smtp!(ServerInfo::from_response(&lhlo_response), conn);
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!("server {}", conn.server_info);
+        Ok(conn)
+    }
+
+    fn new(stream: NetworkStream) -> SmtpConnection {
+        SmtpConnection {
+            stream: BufReader::new(stream),
+            panic: false,
+            failure: None,
+            server_info: ServerInfo::default(),
+            on_line: None,
+            require_tls_relay: false,
+            mail_from_fallback: None,
+            prefer_chunking: false,
+            dsn_notify: Vec::new(),
+            dsn_return: None,
+            auth_mechanism: None,
+            negotiated: None,
+            lmtp: false,
+            timeout: None,
+        }
+    }
+
+    /// Sends an email to an LMTP server, returning the result of
+    /// the delivery to each recipient
+    ///
+    /// The server replies once for each recipient after receiving the message,
+    /// and the results are in the order of the recipients of the envelope.
+    /// Errors preventing the delivery to all recipients, like a rejected
+    /// recipient, are returned directly.
+    pub fn send_lmtp(
+        &mut self,
+        envelope: &Envelope,
+        email: &[u8],
+    ) -> Result<Vec<Result<Response, Error>>, Error> {
+        if !self.lmtp {
+            return Err(error::client("Not connected to an LMTP server"));
+        }
+        self.check_size(email.len() as u64)?;
+        let header = self.start_data(envelope, email.is_ascii())?;
+        let email = with_header(header, email);
+
+        let mut out_buf = Vec::with_capacity(email.len());
+        ClientCodec::new().encode(&email, &mut out_buf);
+        try_smtp!(self.write(&out_buf), self);
+        try_smtp!(self.write(b"\r\n.\r\n"), self);
+        self.count_wire_bytes(out_buf.len() + 5);
+
+        let mut results = Vec::with_capacity(envelope.to().len());
+        for _ in envelope.to() {
+            match self.read_response() {
+                // The remaining replies can't be read
+                Err(err) if !err.is_transient() && !err.is_permanent() => {
+                    self.abort_with(&err);
+                    return Err(err);
+                }
+                result => {
+                    results.push(result.map(|response| sent(envelope.envelope_id(), response)))
+                }
+            }
+        }
+        Ok(results)
+    }
+
     pub fn send(&mut self, envelope: &Envelope, email: &[u8]) -> Result<Response, Error> {
+        if self.lmtp {
+            return Err(error::client("LMTP emails must be sent with send_lmtp"));
+        }
+        self.check_size(email.len() as u64)?;
+        let header = self.start_data(envelope, email.is_ascii())?;
+        let email = with_header(header, email);
+
+        // Message content
+        let result = if self.uses_chunking() {
+            try_smtp!(self.chunk(&email, true), self)
+        } else {
+            try_smtp!(self.message(&email), self)
+        };
+        Ok(sent(envelope.envelope_id(), result))
+    }
+
+    /// Sends an email whose content is read from `reader`
+    ///
+    /// The content is read and sent to the server in fixed-size chunks, so
+    /// large emails, like one with an attachment in a file, don't have to be
+    /// loaded in memory. Bare `LF` line endings are converted to `CRLF`. See
+    /// [`SmtpConnection::send_with`].
+    pub fn send_from_reader(
+        &mut self,
+        envelope: &Envelope,
+        is_ascii: bool,
+        reader: &mut dyn Read,
+    ) -> Result<Response, Error> {
+        self.send_with(envelope, is_ascii, |out| io::copy(reader, out).map(drop))
+    }
+
+    /// Sends an email whose content is written by `write`
+    ///
+    /// The content is dot-stuffed and sent to the server in small chunks as it's
+    /// written, so it never has to be held in memory in full. `is_ascii` tells
+    /// whether the content only contains ASCII characters.
+    ///
+    /// If `write` fails the connection is aborted, so that the partially
+    /// written message is discarded by the server.
+    pub fn send_with<F>(
+        &mut self,
+        envelope: &Envelope,
+        is_ascii: bool,
+        write: F,
+    ) -> Result<Response, Error>
+    where
+        F: FnOnce(&mut dyn Write) -> io::Result<()>,
+    {
+        let mut writer = self.data_writer(envelope, is_ascii)?;
+        if let Err(err) = write(&mut writer) {
+            if let Some(err) = writer.chunk_error.take() {
+                return Err(err);
+            }
+            return Err(if writer.network_failed {
+                error::network(err)
+            } else {
+                error::client(err)
+            });
+        }
+        writer.finish()
+    }
+
+    /// Starts sending an email, returning a writer for its content
+    ///
+    /// Sends the `MAIL`, `RCPT` and `DATA` commands for `envelope`, or
+    /// sends the content in `BDAT` chunks if chunking is preferred and
+    /// supported by the server. The message, headers included, is then written to the returned
+    /// [`DataWriter`], and [`DataWriter::finish`] returns the response of
+    /// the server. `is_ascii` tells whether the content only contains ASCII
+    /// characters.
+    ///
+    /// ```rust,no_run
+    /// # use std::{error::Error, io::Write};
+    /// # use lettre::{address::Envelope, transport::smtp::{client::SmtpConnection, extension::ClientId}};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let hello = ClientId::Domain("localhost".to_owned());
+    /// let mut conn = SmtpConnection::connect(("localhost", 25), None, &hello, None, None)?;
+    ///
+    /// let envelope = Envelope::new(
+    ///     Some("nobody@domain.tld".parse()?),
+    ///     vec!["hei@domain.tld".parse()?],
+    /// )?;
+    /// let mut writer = conn.data_writer(&envelope, true)?;
+    /// writer.write_all(b"Subject: Report\r\n\r\n")?;
+    /// for i in 0..1000 {
+    ///     writeln!(writer, "line {i}")?;
+    /// }
+    /// writer.finish()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn data_writer(
+        &mut self,
+        envelope: &Envelope,
+        is_ascii: bool,
+    ) -> Result<DataWriter<'_>, Error> {
+        if self.lmtp {
+            return Err(error::client("LMTP emails must be sent with send_lmtp"));
+        }
+        let header = self.start_data(envelope, is_ascii)?;
+
+        let chunking = self.uses_chunking();
+        let mut writer = DataWriter {
+            chunking,
+            conn: self,
+            encoder: ContentEncoder::new(chunking),
+            buf: Vec::with_capacity(DATA_BUFFER_LEN),
+            network_failed: false,
+            chunk_error: None,
+            envelope_id: envelope.envelope_id().map(ToOwned::to_owned),
+            finished: false,
+        };
+        if let Some(header) = header {
+            if let Err(err) = writer.write_all(header.as_bytes()) {
+                return Err(writer
+                    .chunk_error
+                    .take()
+                    .unwrap_or_else(|| error::network(err)));
+            }
+        }
+        Ok(writer)
+    }
+
+    /// Whether the content is sent with `BDAT` instead of `DATA`
+    fn uses_chunking(&self) -> bool {
+        self.prefer_chunking
+            && !self.lmtp
+            && self.server_info().supports_feature(Extension::Chunking)
+    }
+
+    /// Fails if an email of `size` octets is larger than the limit
+    /// advertised by the server, so that it isn't sent for nothing
+    pub(crate) fn check_size(&self, size: u64) -> Result<(), Error> {
+        match self.server_info().max_message_size() {
+            Some(limit) if size > limit => Err(error::message_too_large(size, limit)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sends the `MAIL` and `RCPT` commands for `envelope`, followed
+    /// by `DATA` unless the content is sent in `BDAT` chunks
+    ///
+    /// Returns the header to add before the content when the sender
+    /// was replaced by the fallback.
+    fn start_data(&mut self, envelope: &Envelope, is_ascii: bool) -> Result<Option<String>, Error> {
         // Mail
         let mut mail_options = vec![];
+        self.negotiated = None;
+        let mut negotiated = NegotiatedFeatures {
+            chunking: self.uses_chunking(),
+            encrypted: self.is_encrypted(),
+            auth_mechanism: self.auth_mechanism,
+            ..NegotiatedFeatures::default()
+        };
 
         // Internationalization handling
         //
@@ -98,44 +515,133 @@ impl SmtpConnection {
                 ));
             }
             mail_options.push(MailParameter::SmtpUtfEight);
+            negotiated.smtp_utf8 = true;
         }
 
         // Check for non-ascii content in the message
-        if !email.is_ascii() {
+        if !is_ascii {
             if !self.server_info().supports_feature(Extension::EightBitMime) {
                 return Err(error::client(
                     "Message contains non-ascii chars but server does not support 8BITMIME",
                 ));
             }
             mail_options.push(MailParameter::Body(MailBodyParameter::EightBitMime));
+            negotiated.eight_bit_mime = true;
         }
 
-        try_smtp!(
-            self.command(Mail::new(envelope.from().cloned(), mail_options)),
-            self
-        );
+        // Require the message to only be relayed over TLS
+        //
+        // * REQUIRETLS: https://tools.ietf.org/html/rfc8689
+        if self.require_tls_relay {
+            if !self.server_info().supports_feature(Extension::RequireTls) {
+                return Err(error::require_tls_unsupported());
+            }
+            mail_options.push(MailParameter::Other {
+                keyword: "REQUIRETLS".to_owned(),
+                value: None,
+            });
+            negotiated.require_tls = true;
+        }
+
+        // Delivery status notifications, not requested if the server can't send them
+        //
+        // * DSN: https://tools.ietf.org/html/rfc3461
+        let dsn = self.server_info().supports_feature(Extension::Dsn);
+        if let Some(ret) = self.dsn_return.filter(|_| dsn) {
+            mail_options.push(dsn_mail_parameter(ret));
+        }
+        if let Some(id) = envelope.envelope_id() {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(envelope_id = id, "sending the email");
+            if dsn {
+                mail_options.push(dsn_envid_parameter(id));
+            }
+        }
+        negotiated.dsn = dsn
+            && (self.dsn_return.is_some()
+                || !self.dsn_notify.is_empty()
+                || envelope.envelope_id().is_some());
+        self.negotiated = Some(negotiated);
+
+        let mut header = None;
+        let mail = self.command(Mail::new(envelope.from().cloned(), mail_options.clone()));
+        let mail = match (mail, envelope.from(), self.mail_from_fallback.clone()) {
+            (Err(err), Some(from), Some(fallback)) if rejects_sender(&err) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    envelope_id = envelope.envelope_id(),
+                    "sender {from} rejected ({err}), retrying with {fallback}"
+                );
+                #[cfg(not(feature = "tracing"))]
+                let _ = err;
+
+                try_smtp!(self.command(Rset), self);
+                if let Some(negotiated) = &mut self.negotiated {
+                    negotiated.mail_from_fallback = true;
+                }
+                header = Some(format!("X-Original-From: {from}\r\n"));
+                self.command(Mail::new(Some(fallback), mail_options))
+            }
+            (mail, _, _) => mail,
+        };
+        try_smtp!(mail, self);
 
         // Recipient
         for to_address in envelope.to() {
-            try_smtp!(self.command(Rcpt::new(to_address.clone(), vec![])), self);
+            let rcpt_options = if dsn {
+                dsn_rcpt_parameters(&self.dsn_notify, to_address)
+            } else {
+                vec![]
+            };
+            try_smtp!(
+                self.command(Rcpt::new(to_address.clone(), rcpt_options)),
+                self
+            );
         }
 
         // Data
-        try_smtp!(self.command(Data), self);
+        if !self.uses_chunking() {
+            try_smtp!(self.command(Data), self);
+        }
+        Ok(header)
+    }
 
-        // Message content
-        let result = try_smtp!(self.message(email), self);
-        Ok(result)
+    /// Returns the features of the server used to send the last email
+    ///
+    /// Returns `None` if no email was sent over this connection, or
+    /// if sending the last one failed before the `MAIL` command.
+    pub fn negotiated_features(&self) -> Option<NegotiatedFeatures> {
+        self.negotiated
     }
 
     pub fn has_broken(&self) -> bool {
         self.panic
     }
 
+    /// Returns the message of the error which broke the session, if any
+    #[cfg(feature = "pool")]
+    pub(crate) fn failure(&self) -> Option<&str> {
+        self.failure.as_deref()
+    }
+
+    /// Returns the address of the server, or of the proxy, if the
+    /// connection has one
+    #[cfg(feature = "pool")]
+    pub(crate) fn peer_addr(&self) -> Option<std::net::SocketAddr> {
+        self.stream.get_ref().peer_addr().ok()
+    }
+
     pub fn can_starttls(&self) -> bool {
         !self.is_encrypted() && self.server_info.supports_feature(Extension::StartTls)
     }
 
+    /// Upgrade the connection using `STARTTLS`, and send `EHLO` again over TLS
+    ///
+    /// As described in [rfc3207]. Fails with [`StarttlsStage::NotAdvertised`]
+    /// if the server doesn't advertise `STARTTLS`.
+    ///
+    /// [rfc3207]: https://www.rfc-editor.org/rfc/rfc3207
+    /// [`StarttlsStage::NotAdvertised`]: crate::transport::smtp::StarttlsStage::NotAdvertised
     #[allow(unused_variables)]
     pub fn starttls(
         &mut self,
@@ -145,8 +651,35 @@ impl SmtpConnection {
         if self.server_info.supports_feature(Extension::StartTls) {
             #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
             {
-                try_smtp!(self.command(Starttls), self);
-                self.stream.get_mut().upgrade_tls(tls_parameters)?;
+                let response = try_smtp!(
+                    self.command(Starttls)
+                        .map_err(|e| error::starttls(error::StarttlsStage::CommandRejected, e)),
+                    self
+                );
+                // Only a `220` reply lets the handshake start
+                if response.code() != Code::SERVICE_READY {
+                    let err = error::response(format!(
+                        "Unexpected reply to STARTTLS: {}",
+                        response.code()
+                    ));
+                    return Err(
+                        self.corrupted(error::starttls(error::StarttlsStage::CommandRejected, err))
+                    );
+                }
+                // Anything sent before the handshake, still buffered, is unprotected
+                // and must not be read as a reply over TLS (RFC 3207 section 6)
+                if !self.stream.buffer().is_empty() {
+                    let err = error::response("Unexpected data after the STARTTLS reply");
+                    return Err(
+                        self.corrupted(error::starttls(error::StarttlsStage::UnexpectedData, err))
+                    );
+                }
+                // The capabilities advertised in plain text can't be trusted either
+                self.server_info = ServerInfo::default();
+                self.stream
+                    .get_mut()
+                    .upgrade_tls(tls_parameters)
+                    .map_err(error::starttls_upgrade)?;
                 #[cfg(feature = "tracing")]
                 tracing::debug!("connection encrypted");
                 // Send EHLO again
@@ -162,8 +695,38 @@ impl SmtpConnection {
             // when a TLS library is enabled
             unreachable!("TLS support required but not supported");
         } else {
-            Err(error::client("STARTTLS is not supported on this server"))
+            Err(error::starttls_not_advertised())
+        }
+    }
+
+    /// Compresses the connection with DEFLATE, using the `COMPRESS` extension
+    ///
+    /// All the following commands and responses are compressed. Fails if the
+    /// server doesn't advertise `COMPRESS DEFLATE`. TLS, if any, must be
+    /// negotiated before.
+    #[cfg(feature = "compression")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+    pub fn compress(&mut self) -> Result<(), Error> {
+        if !self
+            .server_info
+            .supports_feature(Extension::CompressDeflate)
+        {
+            return Err(error::client(
+                "COMPRESS DEFLATE is not supported on this server",
+            ));
+        }
+
+        try_smtp!(self.command(Compress), self);
+        if !self.stream.buffer().is_empty() {
+            // Data sent by the server before the compression would be lost
+            let err = error::response("Unexpected data after the COMPRESS reply");
+            self.abort_with(&err);
+            return Err(err);
         }
+        self.stream.get_mut().compress()?;
+        #[cfg(feature = "tracing")]
+        tracing::debug!("connection compressed");
+        Ok(())
     }
 
     /// Send EHLO and update server info
@@ -178,12 +741,81 @@ impl SmtpConnection {
     }
 
     pub fn abort(&mut self) {
-        // Only try to quit if we are not already broken
+        self.close();
+    }
+
+    /// Closes the connection like [`abort`](Self::abort), returning whether
+    /// `QUIT` was written
+    pub(crate) fn close(&mut self) -> bool {
+        let mut quit_sent = false;
+        // Only try to quit if the session is still in a known state, otherwise
+        // the server may not be waiting for a command and never reply
         if !self.panic {
             self.panic = true;
-            let _ = self.command(Quit);
+            quit_sent = self.write(Quit.to_string().as_bytes()).is_ok();
+            if quit_sent {
+                let _ = self.read_response();
+            }
         }
         let _ = self.stream.get_mut().shutdown(std::net::Shutdown::Both);
+        quit_sent
+    }
+
+    /// Sets a callback invoked with each raw line received from the server
+    ///
+    /// The line is passed as received, including the reply code and the trailing CRLF,
+    /// before the complete response is parsed.
+    pub fn on_line(&mut self, callback: LineCallback) {
+        self.on_line = Some(callback);
+    }
+
+    /// Sets whether the messages must only be relayed over TLS
+    ///
+    /// When enabled, the `REQUIRETLS` parameter defined in
+    /// [RFC 8689](https://tools.ietf.org/html/rfc8689) is sent with `MAIL FROM`,
+    /// and sending fails if the server doesn't support it.
+    pub fn require_tls_relay(&mut self, require: bool) {
+        self.require_tls_relay = require;
+    }
+
+    /// Sets the sender used when the server rejects the sender of the envelope
+    ///
+    /// Some relays reject senders whose domain they can't verify. When the
+    /// `MAIL FROM` command gets a `550` or `553` reply, or a `5.1.x` or `5.7.x`
+    /// enhanced status code, the session is reset and `MAIL FROM` is sent
+    /// again once with `fallback`. The original sender is then added to the
+    /// content in an `X-Original-From` header. Other errors, like a `552`
+    /// size error, and emails with a null sender, like bounces, are never
+    /// retried.
+    pub fn mail_from_fallback(&mut self, fallback: Option<Address>) {
+        self.mail_from_fallback = fallback;
+    }
+
+    /// Requests delivery status notifications for the `notify` events
+    ///
+    /// Sends the `NOTIFY` and `ORCPT` parameters defined in
+    /// [RFC 3461](https://tools.ietf.org/html/rfc3461) with each `RCPT TO`.
+    /// They are only sent to servers supporting the `DSN` extension.
+    pub fn dsn_notify(&mut self, notify: &[DsnNotify]) {
+        self.dsn_notify = notify.to_vec();
+    }
+
+    /// Sets the part of the message returned with failure notifications
+    ///
+    /// Sends the `RET` parameter defined in [RFC 3461](https://tools.ietf.org/html/rfc3461)
+    /// with `MAIL FROM`. It is only sent to servers supporting the `DSN` extension.
+    pub fn dsn_return(&mut self, ret: Option<DsnReturn>) {
+        self.dsn_return = ret;
+    }
+
+    /// Sets whether the content is sent with `BDAT` when possible
+    ///
+    /// When enabled and the server advertises the `CHUNKING` extension
+    /// defined in [RFC 3030](https://tools.ietf.org/html/rfc3030), the content
+    /// is sent in `BDAT` chunks instead of after a `DATA` command, without
+    /// dot-stuffing. `DATA` is still used with servers which don't support it.
+    pub fn prefer_chunking(&mut self, prefer: bool) {
+        self.prefer_chunking = prefer;
     }
 
     /// Sets the underlying stream
@@ -197,7 +829,12 @@ impl SmtpConnection {
     }
 
     /// Set timeout
+    ///
+    /// It applies to each read and write, and to the reception of each whole
+    /// response, so that a server sending its responses very slowly can't
+    /// stall the client.
     pub fn set_timeout(&mut self, duration: Option<Duration>) -> io::Result<()> {
+        self.timeout = duration;
         self.stream.get_mut().set_read_timeout(duration)?;
         self.stream.get_mut().set_write_timeout(duration)
     }
@@ -207,6 +844,19 @@ impl SmtpConnection {
         self.command(Noop).is_ok()
     }
 
+    /// Resets the session using the RSET SMTP command
+    ///
+    /// Fails unless the server replied with `250`, in which case the state
+    /// of the session is unknown and the connection shouldn't be reused.
+    pub fn reset(&mut self) -> Result<Response, Error> {
+        let response = self.command(Rset)?;
+        if response.code() == Code::OK {
+            Ok(response)
+        } else {
+            Err(error::response("the server didn't reply to RSET with 250"))
+        }
+    }
+
     /// Sends an AUTH command with the given mechanism, and handles the challenge if needed
     pub fn auth(
         &mut self,
@@ -220,23 +870,20 @@ impl SmtpConnection {
 
         // Limit challenges to avoid blocking
         let mut challenges = 10;
-        let mut response = self.command(Auth::new(mechanism, credentials.clone(), None)?)?;
+        let mut session = SaslSession::new(mechanism, credentials.clone())?;
+        let mut response = self.command(Auth::from_session(&mut session, None)?)?;
 
-        while challenges > 0 && response.has_code(334) {
+        while challenges > 0 && response.code() == Code::AUTH_CHALLENGE {
             challenges -= 1;
-            response = try_smtp!(
-                self.command(Auth::new_from_response(
-                    mechanism,
-                    credentials.clone(),
-                    &response,
-                )?),
-                self
-            );
+            let auth = try_smtp!(Auth::from_session(&mut session, Some(&response)), self);
+            response = try_smtp!(self.command(auth), self);
         }
 
         if challenges == 0 {
             Err(error::response("Unexpected number of challenges"))
         } else {
+            try_smtp!(session.finish(), self);
+            self.auth_mechanism = Some(mechanism);
             Ok(response)
         }
     }
@@ -248,10 +895,27 @@ impl SmtpConnection {
         codec.encode(message, &mut out_buf);
         self.write(out_buf.as_slice())?;
         self.write(b"\r\n.\r\n")?;
+        self.count_wire_bytes(out_buf.len() + 5);
 
         self.read_response()
     }
 
+    /// Sends a chunk of the message content with `BDAT`
+    fn chunk(&mut self, chunk: &[u8], last: bool) -> Result<Response, Error> {
+        let mut out_buf = Bdat::new(chunk.len(), last).to_string().into_bytes();
+        out_buf.extend_from_slice(chunk);
+        self.write(&out_buf)?;
+        self.count_wire_bytes(out_buf.len());
+        self.read_response()
+    }
+
+    /// Adds `len` octets of content to the ones of the email being sent
+    fn count_wire_bytes(&mut self, len: usize) {
+        if let Some(negotiated) = &mut self.negotiated {
+            negotiated.wire_bytes += len as u64;
+        }
+    }
+
     /// Sends an SMTP command
     pub fn command<C: Display>(&mut self, command: C) -> Result<Response, Error> {
         self.write(command.to_string().as_bytes())?;
@@ -263,43 +927,132 @@ impl SmtpConnection {
         self.stream
             .get_mut()
             .write_all(string)
-            .map_err(error::network)?;
-        self.stream.get_mut().flush().map_err(error::network)?;
+            .map_err(|err| self.corrupted(error::network(err)))?;
+        self.stream
+            .get_mut()
+            .flush()
+            .map_err(|err| self.corrupted(error::network(err)))?;
 
         #[cfg(feature = "tracing")]
         tracing::debug!("Wrote: {}", escape_crlf(&String::from_utf8_lossy(string)));
         Ok(())
     }
 
+    /// Marks the session as broken after a network or protocol error
+    ///
+    /// The state of the session is unknown, so `QUIT` won't be sent on it.
+    fn corrupted(&mut self, err: Error) -> Error {
+        self.panic = true;
+        self.failure.get_or_insert_with(|| err.to_string());
+        err
+    }
+
+    /// Aborts the connection because of `err`, which is kept as the reason
+    /// why the session broke unless an earlier error already broke it
+    fn abort_with(&mut self, err: &dyn Display) {
+        self.failure.get_or_insert_with(|| err.to_string());
+        self.abort();
+    }
+
     /// Gets the SMTP response
     pub fn read_response(&mut self) -> Result<Response, Error> {
-        let mut buffer = String::with_capacity(100);
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let result = self.read_response_until(deadline);
+        if deadline.is_some() && !self.panic {
+            // Restore the timeout shortened for the last reads
+            self.stream
+                .get_mut()
+                .set_read_timeout(self.timeout)
+                .map_err(|err| self.corrupted(error::network(err)))?;
+        }
+        result
+    }
+
+    fn read_response_until(&mut self, deadline: Option<Instant>) -> Result<Response, Error> {
+        let mut buffer = Vec::with_capacity(100);
+        // Start of the line being received, only the bytes after it are
+        // scanned when more data arrives
+        let mut line_start = 0;
+
+        loop {
+            if let (Some(deadline), true) = (deadline, self.stream.buffer().is_empty()) {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(self.corrupted(error::network(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "timed out receiving the response",
+                    ))));
+                }
+                self.stream
+                    .get_mut()
+                    .set_read_timeout(Some(remaining))
+                    .map_err(|err| self.corrupted(error::network(err)))?;
+            }
+
+            let available = match self.stream.fill_buf() {
+                Ok(available) => available,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                // The read timeout is reported as `WouldBlock` on Unix
+                Err(err) if deadline.is_some() && err.kind() == io::ErrorKind::WouldBlock => {
+                    return Err(self.corrupted(error::network(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "timed out receiving the response",
+                    ))));
+                }
+                Err(err) => return Err(self.corrupted(error::network(err))),
+            };
+            if available.is_empty() {
+                break;
+            }
+            let (len, line_end) = match available.iter().position(|&b| b == b'\n') {
+                Some(i) => (i + 1, true),
+                None => (available.len(), false),
+            };
+            buffer.extend_from_slice(&available[..len]);
+            self.stream.consume(len);
+            if !line_end {
+                continue;
+            }
+
+            let line = match std::str::from_utf8(&buffer[line_start..]) {
+                Ok(line) => line,
+                Err(err) => return Err(self.corrupted(error::response(err.to_string()))),
+            };
+            if let Some(on_line) = &mut self.on_line {
+                on_line(line);
+            }
+            if let Err(err) = check_reply_line(line) {
+                return Err(self.corrupted(err));
+            }
+            // Only the last line of a response doesn't have a `-` after the code
+            let last_line = line.as_bytes().get(3) != Some(&b'-');
+            line_start = buffer.len();
+            if !last_line {
+                continue;
+            }
 
-        while self.stream.read_line(&mut buffer).map_err(error::network)? > 0 {
+            let response = std::str::from_utf8(&buffer).expect("all the lines are valid UTF-8");
             #[cfg(feature = "tracing")]
-            tracing::debug!("<< {}", escape_crlf(&buffer));
-            match parse_response(&buffer) {
+            tracing::debug!("<< {}", escape_crlf(response));
+            match parse_response(response) {
                 Ok((_remaining, response)) => {
                     return if response.is_positive() {
                         Ok(response)
                     } else {
-                        Err(error::code(
-                            response.code(),
-                            Some(response.message().collect()),
-                        ))
+                        Err(error::from_response(response))
                     };
                 }
                 Err(nom::Err::Failure(e)) => {
-                    return Err(error::response(e.to_string()));
+                    return Err(self.corrupted(error::response(e.to_string())));
                 }
                 Err(nom::Err::Incomplete(_)) => { /* read more */ }
                 Err(nom::Err::Error(e)) => {
-                    return Err(error::response(e.to_string()));
+                    return Err(self.corrupted(error::response(e.to_string())));
                 }
             }
         }
 
-        Err(error::response("incomplete response"))
+        Err(self.corrupted(error::response("incomplete response")))
     }
 
     /// The X509 certificate of the server (DER encoded)
@@ -308,3 +1061,1103 @@ impl SmtpConnection {
         self.stream.get_ref().peer_certificate()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::{SocketAddr, TcpListener},
+        sync::{Arc, Mutex},
+        thread::{self, JoinHandle},
+        time::{Duration, Instant},
+    };
+
+    use super::{NegotiatedFeatures, ProxyConfig, SmtpConnection, DATA_BUFFER_LEN};
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    use crate::transport::smtp::client::TlsParameters;
+    use crate::{
+        address::Envelope,
+        transport::smtp::{
+            authentication::Credentials,
+            client::{serve, serve_session, MockStep, MockStream},
+            commands::Noop,
+            extension::{ClientId, DsnNotify, DsnReturn, Extension},
+        },
+    };
+
+    #[test]
+    fn on_line_callback() {
+        let (addr, server) = serve(vec![
+            MockStep::send("220 localhost ESMTP\r\n"),
+            MockStep::ExpectLine("EHLO ".to_owned()),
+            MockStep::send("250 localhost\r\n"),
+            MockStep::expect("NOOP\r\n"),
+            MockStep::send("250-first\r\n250-second\r\n250 third\r\n"),
+        ]);
+
+        let hello = ClientId::Domain("localhost".to_owned());
+        let mut conn = SmtpConnection::connect(addr, None, &hello, None, None).unwrap();
+
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let lines_ = Arc::clone(&lines);
+        conn.on_line(Box::new(move |line| {
+            lines_.lock().unwrap().push(line.to_owned())
+        }));
+
+        let response = conn.command(Noop).unwrap();
+        assert_eq!(server.join().unwrap().len(), 7);
+
+        assert_eq!(response.message().count(), 3);
+        assert_eq!(
+            *lines.lock().unwrap(),
+            ["250-first\r\n", "250-second\r\n", "250 third\r\n"]
+        );
+    }
+
+    #[test]
+    fn require_tls_relay() {
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+        let hello = ClientId::Domain("localhost".to_owned());
+
+        let (addr, server) = serve_session("250-localhost\r\n250 REQUIRETLS\r\n");
+        let mut conn = SmtpConnection::connect(addr, None, &hello, None, None).unwrap();
+        conn.require_tls_relay(true);
+        conn.send(&envelope, b"Subject: Hi\r\n\r\nHi").unwrap();
+        conn.quit().unwrap();
+        let commands = server.join().unwrap();
+        assert_eq!(commands[1], "MAIL FROM:<nobody@domain.tld> REQUIRETLS\r\n");
+
+        let (addr, server) = serve_session("250 localhost\r\n");
+        let mut conn = SmtpConnection::connect(addr, None, &hello, None, None).unwrap();
+        conn.require_tls_relay(true);
+        let err = conn.send(&envelope, b"Subject: Hi\r\n\r\nHi").unwrap_err();
+        assert!(err.is_require_tls_unsupported());
+        conn.quit().unwrap();
+        let commands = server.join().unwrap();
+        assert!(!commands.iter().any(|command| command.starts_with("MAIL")));
+    }
+
+    #[test]
+    fn data_writer() {
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+        let hello = ClientId::Domain("localhost".to_owned());
+
+        let mut content = String::from("Subject: Report\r\n\r\n");
+        let mut i = 0;
+        while content.len() < 1024 * 1024 {
+            match i % 4 {
+                0 => content.push_str(&format!(".line {i}\r\n")),
+                1 => content.push_str(&format!("line {i}\n")),
+                2 => content.push_str(".\n"),
+                _ => content.push_str(&format!("line {i}\r\n\r\n")),
+            }
+            i += 1;
+        }
+        let expected = content
+            .replace("\r\n", "\n")
+            .lines()
+            .map(|line| {
+                if line.starts_with('.') {
+                    format!(".{line}\r\n")
+                } else {
+                    format!("{line}\r\n")
+                }
+            })
+            .collect::<String>();
+
+        let (addr, server) = serve_session("250 localhost\r\n");
+        let mut conn = SmtpConnection::connect(addr, None, &hello, None, None).unwrap();
+        let mut writer = conn.data_writer(&envelope, true).unwrap();
+        let mut chunks = [7, 1, 13, 4093, 2].iter().cycle();
+        let mut remaining = content.as_bytes();
+        while !remaining.is_empty() {
+            let len = (*chunks.next().unwrap()).min(remaining.len());
+            writer.write_all(&remaining[..len]).unwrap();
+            remaining = &remaining[len..];
+        }
+        let response = writer.finish().unwrap();
+        assert_eq!(response.first_line(), Some("Queued"));
+        conn.quit().unwrap();
+
+        let commands = server.join().unwrap();
+        let data = commands.iter().position(|c| c == "DATA\r\n").unwrap();
+        let end = commands.iter().rposition(|c| c == ".\r\n").unwrap();
+        assert!(commands[data + 1..end].concat() == expected);
+    }
+
+    /// Returns at most `chunk` bytes of `content` per read
+    struct Trickle<'a> {
+        content: &'a [u8],
+        chunk: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = self.chunk.min(buf.len()).min(self.content.len());
+            buf[..len].copy_from_slice(&self.content[..len]);
+            self.content = &self.content[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn send_from_reader() {
+        /// Pads `content` with lines of `x` up to `len` bytes
+        fn pad(content: &mut Vec<u8>, len: usize) {
+            while content.len() + 80 < len {
+                content.extend_from_slice(&[b'x'; 78]);
+                content.extend_from_slice(b"\r\n");
+            }
+            content.resize(len, b'x');
+        }
+
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+        let hello = ClientId::Domain("localhost".to_owned());
+
+        // Dots and line endings falling on the chunk boundaries
+        let mut content = b"Subject: Dots\r\n\r\n".to_vec();
+        pad(&mut content, DATA_BUFFER_LEN - 1);
+        content.extend_from_slice(b"\n.after a bare LF\r\n");
+        pad(&mut content, 2 * DATA_BUFFER_LEN - 1);
+        content.extend_from_slice(b"\r\n.after a split CRLF\r\n");
+        pad(&mut content, 3 * DATA_BUFFER_LEN - 3);
+        content.extend_from_slice(b"\r\n.\r\nend\r\n");
+        assert_eq!(content[DATA_BUFFER_LEN], b'.');
+        assert_eq!(content[2 * DATA_BUFFER_LEN], b'\n');
+        assert_eq!(content[3 * DATA_BUFFER_LEN - 1], b'.');
+
+        let expected = String::from_utf8(content.clone())
+            .unwrap()
+            .replace("\r\n", "\n")
+            .lines()
+            .map(|line| {
+                if line.starts_with('.') {
+                    format!(".{line}\r\n")
+                } else {
+                    format!("{line}\r\n")
+                }
+            })
+            .collect::<String>();
+
+        for chunk in [1, DATA_BUFFER_LEN] {
+            let (addr, server) = serve_session("250 localhost\r\n");
+            let mut conn = SmtpConnection::connect(addr, None, &hello, None, None).unwrap();
+            let mut reader = Trickle {
+                content: &content,
+                chunk,
+            };
+            let response = conn.send_from_reader(&envelope, true, &mut reader).unwrap();
+            assert_eq!(response.first_line(), Some("Queued"));
+            conn.quit().unwrap();
+
+            let commands = server.join().unwrap();
+            let data = commands.iter().position(|c| c == "DATA\r\n").unwrap();
+            let end = commands.iter().rposition(|c| c == ".\r\n").unwrap();
+            assert!(commands[data + 1..end].concat() == expected);
+        }
+    }
+
+    #[test]
+    fn data_writer_dropped() {
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+        let hello = ClientId::Domain("localhost".to_owned());
+
+        let (addr, server) = serve_session("250 localhost\r\n");
+        let mut conn = SmtpConnection::connect(addr, None, &hello, None, None).unwrap();
+        let mut writer = conn.data_writer(&envelope, true).unwrap();
+        writer.write_all(b"Subject: Unfinished\r\n").unwrap();
+        drop(writer);
+        assert!(conn.has_broken());
+
+        let commands = server.join().unwrap();
+        assert!(!commands.iter().any(|c| c == ".\r\n" || c == "QUIT\r\n"));
+    }
+
+    #[test]
+    fn prefer_chunking() {
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+        let hello = ClientId::Domain("localhost".to_owned());
+        let email = b"Subject: Hi\r\n\r\n.Hi\r\n";
+
+        let (addr, server) = serve_session("250-localhost\r\n250 CHUNKING\r\n");
+        let mut conn = SmtpConnection::connect(addr, None, &hello, None, None).unwrap();
+        conn.prefer_chunking(true);
+        let response = conn.send(&envelope, email).unwrap();
+        assert_eq!(response.first_line(), Some("Queued"));
+
+        let mut writer = conn.data_writer(&envelope, true).unwrap();
+        writer.write_all(&[b'.'; 10000]).unwrap();
+        writer.write_all(b"\n").unwrap();
+        writer.finish().unwrap();
+        conn.quit().unwrap();
+
+        let commands = server.join().unwrap();
+        assert!(!commands.iter().any(|c| c == "DATA\r\n"));
+        let bdat = commands.iter().position(|c| c.starts_with("BDAT")).unwrap();
+        assert_eq!(
+            commands[bdat..bdat + 2],
+            [
+                format!("BDAT {} LAST\r\n", email.len()),
+                "Subject: Hi\r\n\r\n.Hi\r\n".to_owned()
+            ]
+        );
+        let chunks = commands[bdat + 2..]
+            .iter()
+            .filter(|c| c.starts_with("BDAT"))
+            .collect::<Vec<_>>();
+        assert_eq!(chunks, ["BDAT 10000\r\n", "BDAT 2 LAST\r\n"]);
+
+        // DATA is used when chunking isn't preferred
+        let (addr, server) = serve_session("250-localhost\r\n250 CHUNKING\r\n");
+        let mut conn = SmtpConnection::connect(addr, None, &hello, None, None).unwrap();
+        conn.send(&envelope, email).unwrap();
+        conn.quit().unwrap();
+        let commands = server.join().unwrap();
+        assert!(commands.iter().any(|c| c == "DATA\r\n"));
+        assert!(commands.iter().any(|c| c == "..Hi\r\n"));
+        assert!(!commands.iter().any(|c| c.starts_with("BDAT")));
+    }
+
+    /// Starts a server accepting a connection and not answering
+    /// for much longer than the client waits
+    fn silent_server() -> (SocketAddr, JoinHandle<Vec<String>>) {
+        serve(vec![MockStep::Sleep(Duration::from_secs(2))])
+    }
+
+    #[test]
+    fn connect_timeout_greeting() {
+        let hello = ClientId::Domain("localhost".to_owned());
+        let (addr, server) = silent_server();
+
+        let start = Instant::now();
+        assert!(SmtpConnection::connect(
+            addr,
+            Some(Duration::from_millis(200)),
+            &hello,
+            None,
+            None,
+        )
+        .is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+        server.join().unwrap();
+    }
+
+    /// A server sending its greeting and `EHLO` reply one byte at a time,
+    /// waiting `delay` before each byte
+    fn dripping_server(delay: Duration) -> Vec<MockStep> {
+        let drip = |bytes: &[u8]| MockStep::SendBytesSlowly {
+            bytes: bytes.to_vec(),
+            chunk: 1,
+            delay,
+        };
+        vec![
+            drip(b"220 localhost ESMTP\r\n"),
+            MockStep::ExpectLine("EHLO ".to_owned()),
+            drip(b"250-localhost\r\n250-PIPELINING\r\n250 8BITMIME\r\n"),
+            MockStep::expect("QUIT\r\n"),
+            drip(b"221 Bye\r\n"),
+        ]
+    }
+
+    #[test]
+    fn slow_server() {
+        let hello = ClientId::Domain("localhost".to_owned());
+
+        let (addr, server) = serve(dripping_server(Duration::from_millis(1)));
+        let mut conn =
+            SmtpConnection::connect(addr, Some(Duration::from_secs(30)), &hello, None, None)
+                .unwrap();
+        assert!(conn.server_info().supports_feature(Extension::EightBitMime));
+        conn.quit().unwrap();
+        server.join().unwrap();
+
+        // Each byte arrives within the timeout, but the whole greeting
+        // can't arrive in less than 2 seconds
+        let (addr, server) = serve(dripping_server(Duration::from_millis(100)));
+        let err =
+            SmtpConnection::connect(addr, Some(Duration::from_millis(300)), &hello, None, None)
+                .err()
+                .unwrap();
+        assert!(err.is_timeout(), "{err}");
+        // The server stops once the client disconnected
+        assert!(server.join().unwrap().is_empty());
+    }
+
+    #[test]
+    fn not_an_smtp_server() {
+        let hello = ClientId::Domain("localhost".to_owned());
+        let greet = |greeting: &str| {
+            let stream = MockStream::with_steps(vec![MockStep::send(greeting)]);
+            SmtpConnection::from_stream(stream, &hello).err().unwrap()
+        };
+
+        // An HTTP server
+        let err = greet(concat!(
+            "HTTP/1.1 400 Bad Request\r\n",
+            "Content-Type: text/html\r\n",
+            "\r\n",
+            "<html><body>Bad Request</body></html>\r\n",
+        ));
+        assert!(err.is_not_an_smtp_server());
+        assert_eq!(
+            err.to_string(),
+            "not an SMTP server, received \"HTTP/1.1 400 Bad Request\""
+        );
+
+        // A proxy sending a PROXY protocol header, the line is truncated
+        let err = greet(concat!(
+            "PROXY TCP6 2001:db8:85a3::8a2e:370:7334 2001:db8:85a3::8a2e:370:7335 56324 25\r\n",
+            "220 localhost ESMTP\r\n",
+        ));
+        assert!(err.is_not_an_smtp_server());
+        assert_eq!(
+            err.to_string(),
+            "not an SMTP server, received \"PROXY TCP6 2001:db8:85a3::8a2e:370:7334 2001:db8:85a3::8a2e:370:...\""
+        );
+
+        // A reply code which doesn't exist
+        let err = greet("999 localhost ESMTP\r\n");
+        assert!(!err.is_not_an_smtp_server());
+        assert_eq!(err.to_string(), "response error: invalid reply code 999");
+    }
+
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    #[test]
+    fn connect_timeout_tls_handshake() {
+        let hello = ClientId::Domain("localhost".to_owned());
+        let tls = TlsParameters::new("localhost".to_owned()).unwrap();
+        let (addr, server) = silent_server();
+
+        let start = Instant::now();
+        assert!(SmtpConnection::connect(
+            addr,
+            Some(Duration::from_millis(200)),
+            &hello,
+            Some(&tls),
+            None,
+        )
+        .is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn chunking_empty_message() {
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+        let hello = ClientId::Domain("localhost".to_owned());
+
+        let (addr, server) = serve_session("250-localhost\r\n250 CHUNKING\r\n");
+        let mut conn = SmtpConnection::connect(addr, None, &hello, None, None).unwrap();
+        conn.prefer_chunking(true);
+        let response = conn.send(&envelope, b"").unwrap();
+        assert_eq!(response.first_line(), Some("Queued"));
+        let response = conn.data_writer(&envelope, true).unwrap().finish().unwrap();
+        assert_eq!(response.first_line(), Some("Queued"));
+        conn.quit().unwrap();
+
+        let commands = server.join().unwrap();
+        let chunks = commands
+            .iter()
+            .filter(|c| c.starts_with("BDAT"))
+            .collect::<Vec<_>>();
+        assert_eq!(chunks, ["BDAT 0 LAST\r\n", "BDAT 0 LAST\r\n"]);
+    }
+
+    #[test]
+    fn dsn() {
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+        let hello = ClientId::Domain("localhost".to_owned());
+
+        let (addr, server) = serve_session("250-localhost\r\n250 DSN\r\n");
+        let mut conn = SmtpConnection::connect(addr, None, &hello, None, None).unwrap();
+        conn.dsn_notify(&[DsnNotify::Success, DsnNotify::Failure]);
+        conn.dsn_return(Some(DsnReturn::Full));
+        conn.send(&envelope, b"Subject: Hi\r\n\r\nHi").unwrap();
+        conn.quit().unwrap();
+        let commands = server.join().unwrap();
+        assert_eq!(commands[1], "MAIL FROM:<nobody@domain.tld> RET=FULL\r\n");
+        assert_eq!(
+            commands[2],
+            "RCPT TO:<hei@domain.tld> NOTIFY=SUCCESS,FAILURE ORCPT=rfc822;hei@domain.tld\r\n"
+        );
+
+        // Not sent to servers which don't support it
+        let (addr, server) = serve_session("250 localhost\r\n");
+        let mut conn = SmtpConnection::connect(addr, None, &hello, None, None).unwrap();
+        conn.dsn_notify(&[DsnNotify::Success, DsnNotify::Failure]);
+        conn.dsn_return(Some(DsnReturn::Full));
+        conn.send(&envelope, b"Subject: Hi\r\n\r\nHi").unwrap();
+        conn.quit().unwrap();
+        let commands = server.join().unwrap();
+        assert_eq!(commands[1], "MAIL FROM:<nobody@domain.tld>\r\n");
+        assert_eq!(commands[2], "RCPT TO:<hei@domain.tld>\r\n");
+    }
+
+    #[test]
+    fn dsn_envelope_id() {
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap()
+        .with_envelope_id("req=1+2 a")
+        .unwrap();
+        let hello = ClientId::Domain("localhost".to_owned());
+        let script = |ehlo: &str, mail: &str| {
+            MockStream::with_steps(vec![
+                MockStep::send("220 localhost ESMTP\r\n"),
+                MockStep::expect("EHLO localhost\r\n"),
+                MockStep::send(ehlo),
+                MockStep::expect(mail),
+                MockStep::send("250 OK\r\n"),
+                MockStep::expect("RCPT TO:<hei@domain.tld>\r\n"),
+                MockStep::send("250 OK\r\n"),
+                MockStep::expect("DATA\r\n"),
+                MockStep::send("354 Go ahead\r\n"),
+                MockStep::expect("Subject: Hi\r\n\r\nHi\r\n.\r\n"),
+                MockStep::send("250 Queued\r\n"),
+            ])
+        };
+
+        let stream = script(
+            "250-localhost\r\n250 DSN\r\n",
+            "MAIL FROM:<nobody@domain.tld> ENVID=req+3D1+2B2+20a\r\n",
+        );
+        let mut conn = SmtpConnection::from_stream(stream.clone(), &hello).unwrap();
+        let response = conn.send(&envelope, b"Subject: Hi\r\n\r\nHi").unwrap();
+        assert_eq!(response.envelope_id(), Some("req=1+2 a"));
+        assert!(conn.negotiated_features().unwrap().dsn);
+        assert_eq!(stream.errors(), Vec::<String>::new());
+        assert!(stream.is_finished());
+
+        // Not sent to servers which don't support DSN
+        let stream = script("250 localhost\r\n", "MAIL FROM:<nobody@domain.tld>\r\n");
+        let mut conn = SmtpConnection::from_stream(stream.clone(), &hello).unwrap();
+        let response = conn.send(&envelope, b"Subject: Hi\r\n\r\nHi").unwrap();
+        assert_eq!(response.envelope_id(), Some("req=1+2 a"));
+        assert_eq!(stream.errors(), Vec::<String>::new());
+        assert!(stream.is_finished());
+    }
+
+    #[test]
+    fn reset() {
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+        let hello = ClientId::Domain("localhost".to_owned());
+
+        let (addr, server) = serve_session("250 localhost\r\n");
+        let mut conn = SmtpConnection::connect(addr, None, &hello, None, None).unwrap();
+        conn.send(&envelope, b"Subject: Hi\r\n\r\nHi").unwrap();
+        conn.reset().unwrap();
+        conn.send(&envelope, b"Subject: Hi\r\n\r\nHi").unwrap();
+        conn.quit().unwrap();
+        let commands = server.join().unwrap();
+        assert_eq!(commands.iter().filter(|c| *c == "RSET\r\n").count(), 1);
+
+        // The server closes the connection instead of replying
+        let (addr, server) = serve(vec![
+            MockStep::send("220 localhost ESMTP\r\n"),
+            MockStep::ExpectLine("EHLO ".to_owned()),
+            MockStep::send("250 localhost\r\n"),
+            MockStep::expect("RSET\r\n"),
+            MockStep::CloseConnection,
+        ]);
+
+        let mut conn = SmtpConnection::connect(addr, None, &hello, None, None).unwrap();
+        assert!(conn.reset().is_err());
+        assert_eq!(server.join().unwrap().last().unwrap(), "C: RSET");
+    }
+
+    #[test]
+    fn abort_after_protocol_error() {
+        /// Starts a server replying `noop` to `NOOP`, then expecting `QUIT`
+        fn noop_server(noop: &'static str) -> (SocketAddr, JoinHandle<Vec<String>>) {
+            serve(vec![
+                MockStep::send("220 localhost ESMTP\r\n"),
+                MockStep::ExpectLine("EHLO ".to_owned()),
+                MockStep::send("250 localhost\r\n"),
+                MockStep::expect("NOOP\r\n"),
+                MockStep::send(noop),
+                MockStep::expect("QUIT\r\n"),
+            ])
+        }
+
+        let hello = ClientId::Domain("localhost".to_owned());
+
+        let (addr, server) = noop_server("garbage\r\n");
+        let mut conn =
+            SmtpConnection::connect(addr, Some(Duration::from_secs(5)), &hello, None, None)
+                .unwrap();
+        assert!(conn.command(Noop).unwrap_err().is_response());
+        assert!(conn.has_broken());
+        let start = Instant::now();
+        conn.abort();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(server.join().unwrap().last().unwrap(), "S: garbage");
+
+        // The session is still in a known state after a rejection
+        let (addr, server) = noop_server("550 No\r\n");
+        let mut conn =
+            SmtpConnection::connect(addr, Some(Duration::from_secs(5)), &hello, None, None)
+                .unwrap();
+        assert!(conn.command(Noop).unwrap_err().is_permanent());
+        assert!(!conn.has_broken());
+        conn.abort();
+        assert_eq!(server.join().unwrap().last().unwrap(), "C: QUIT");
+    }
+
+    #[test]
+    fn negotiated_features() {
+        let hello = ClientId::Domain("localhost".to_owned());
+
+        let (addr, server) = serve_session(
+            "250-localhost\r\n250-8BITMIME\r\n250-SMTPUTF8\r\n250-CHUNKING\r\n250 DSN\r\n",
+        );
+        let mut conn = SmtpConnection::connect(addr, None, &hello, None, None).unwrap();
+        assert_eq!(conn.negotiated_features(), None);
+        conn.prefer_chunking(true);
+        conn.dsn_notify(&[DsnNotify::Failure]);
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["jörg@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+        conn.send(&envelope, "Subject: Grüße\r\n\r\nHallo".as_bytes())
+            .unwrap();
+        let features = conn.negotiated_features().unwrap();
+        assert!(features.smtp_utf8());
+        assert!(features.eight_bit_mime());
+        assert!(features.dsn());
+        assert!(features.chunking());
+        assert!(!features.require_tls());
+        assert!(!features.encrypted());
+        assert_eq!(features.auth_mechanism(), None);
+        conn.quit().unwrap();
+        server.join().unwrap();
+
+        // The same preferences with a server supporting none of the extensions
+        let (addr, server) = serve_session("250 localhost\r\n");
+        let mut conn = SmtpConnection::connect(addr, None, &hello, None, None).unwrap();
+        conn.prefer_chunking(true);
+        conn.dsn_notify(&[DsnNotify::Failure]);
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+        conn.send(&envelope, b"Subject: Hi\r\n\r\nHi").unwrap();
+        assert_eq!(
+            conn.negotiated_features(),
+            Some(NegotiatedFeatures {
+                wire_bytes: 22,
+                ..NegotiatedFeatures::default()
+            })
+        );
+        conn.quit().unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn wire_bytes() {
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+        let hello = ClientId::Domain("localhost".to_owned());
+        let email = b"Subject: Hi\r\n\r\n.Hi\r\n.\r\n";
+        let wire_bytes = |conn: &SmtpConnection| conn.negotiated_features().unwrap().wire_bytes();
+
+        let (addr, server) = serve_session("250-localhost\r\n250 CHUNKING\r\n");
+        let mut conn = SmtpConnection::connect(addr, None, &hello, None, None).unwrap();
+        // Both lines starting with a dot are escaped, and the content is followed by `\r\n.\r\n`
+        conn.send(&envelope, email).unwrap();
+        assert_eq!(wire_bytes(&conn), email.len() as u64 + 2 + 5);
+
+        // The count is reset for each email, only the final `.` line is added here
+        let mut writer = conn.data_writer(&envelope, true).unwrap();
+        writer.write_all(email).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(wire_bytes(&conn), email.len() as u64 + 2 + 3);
+
+        // Chunks aren't escaped but each one has its `BDAT` command
+        conn.prefer_chunking(true);
+        conn.send(&envelope, email).unwrap();
+        assert_eq!(
+            wire_bytes(&conn),
+            (format!("BDAT {} LAST\r\n", email.len()).len() + email.len()) as u64
+        );
+        conn.quit().unwrap();
+        server.join().unwrap();
+    }
+
+    /// Starts a SOCKS5 proxy requiring the `user` and `pass` credentials, which
+    /// answers as an SMTP server itself, and returns the requested destination
+    fn mock_socks5_proxy() -> (SocketAddr, JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut methods = [0; 4];
+            stream.read_exact(&mut methods).unwrap();
+            assert_eq!(methods, [5, 2, 0, 2]);
+            stream.write_all(&[5, 2]).unwrap();
+
+            let mut auth = [0; 11];
+            stream.read_exact(&mut auth).unwrap();
+            let valid = &auth == b"\x01\x04user\x04pass";
+            stream.write_all(&[1, u8::from(!valid)]).unwrap();
+            if !valid {
+                return Vec::new();
+            }
+
+            let mut request = [0; 5];
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(request[..4], [5, 1, 0, 3]);
+            let mut destination = vec![0; usize::from(request[4]) + 2];
+            stream.read_exact(&mut destination).unwrap();
+            stream
+                .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0x1f, 0x90])
+                .unwrap();
+
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            stream.write_all(b"220 localhost ESMTP\r\n").unwrap();
+            reader.read_line(&mut line).unwrap();
+            stream.write_all(b"250 localhost\r\n").unwrap();
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            stream.write_all(b"221 Bye\r\n").unwrap();
+            destination
+        });
+        (addr, server)
+    }
+
+    #[test]
+    fn socks5_proxy() {
+        let hello = ClientId::Domain("localhost".to_owned());
+
+        let (addr, proxy) = mock_socks5_proxy();
+        let config = ProxyConfig::Socks5 {
+            addr: addr.to_string(),
+            auth: Some(Credentials::new("user".to_owned(), "pass".to_owned())),
+        };
+        let mut conn = SmtpConnection::connect_through_proxy(
+            &config,
+            "smtp.example.org",
+            587,
+            Some(Duration::from_secs(5)),
+            &hello,
+            None,
+            None,
+        )
+        .unwrap();
+        conn.quit().unwrap();
+        assert_eq!(proxy.join().unwrap(), b"smtp.example.org\x02\x4b");
+
+        let (addr, proxy) = mock_socks5_proxy();
+        let config = ProxyConfig::Socks5 {
+            addr: addr.to_string(),
+            auth: Some(Credentials::new("user".to_owned(), "wrong".to_owned())),
+        };
+        let err = SmtpConnection::connect_through_proxy(
+            &config,
+            "smtp.example.org",
+            587,
+            Some(Duration::from_secs(5)),
+            &hello,
+            None,
+            None,
+        )
+        .err()
+        .unwrap();
+        assert!(err
+            .to_string()
+            .contains("SOCKS5 proxy authentication failed"));
+        proxy.join().unwrap();
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compress() {
+        use crate::transport::smtp::client::deflate::DeflateStream;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            stream.write_all(b"220 localhost ESMTP\r\n").unwrap();
+            reader.read_line(&mut line).unwrap();
+            stream
+                .write_all(b"250-localhost\r\n250 COMPRESS DEFLATE\r\n")
+                .unwrap();
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(line, "COMPRESS DEFLATE\r\n");
+            stream.write_all(b"250 OK\r\n").unwrap();
+
+            // Compressed from now on
+            let mut stream = DeflateStream::new(stream);
+            let mut reader = BufReader::new(DeflateStream::new(reader.into_inner()));
+            let mut commands = Vec::new();
+            let mut data = false;
+            loop {
+                line.clear();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                commands.push(line.clone());
+                let reply: &[u8] = match line.as_str() {
+                    ".\r\n" if data => {
+                        data = false;
+                        b"250 Queued\r\n"
+                    }
+                    _ if data => continue,
+                    "DATA\r\n" => {
+                        data = true;
+                        b"354 Go ahead\r\n"
+                    }
+                    "QUIT\r\n" => b"221 Bye\r\n",
+                    _ => b"250 OK\r\n",
+                };
+                stream.write_all(reply).unwrap();
+                if line == "QUIT\r\n" {
+                    break;
+                }
+            }
+            commands
+        });
+
+        let hello = ClientId::Domain("localhost".to_owned());
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+        let mut conn = SmtpConnection::connect(addr, None, &hello, None, None).unwrap();
+        assert!(conn
+            .server_info()
+            .supports_feature(Extension::CompressDeflate));
+        conn.compress().unwrap();
+        assert!(conn.test_connected());
+        let response = conn.send(&envelope, b"Subject: Hi\r\n\r\nHi").unwrap();
+        assert_eq!(response.first_line(), Some("Queued"));
+        conn.quit().unwrap();
+
+        assert_eq!(
+            server.join().unwrap(),
+            [
+                "NOOP\r\n",
+                "MAIL FROM:<nobody@domain.tld>\r\n",
+                "RCPT TO:<hei@domain.tld>\r\n",
+                "DATA\r\n",
+                "Subject: Hi\r\n",
+                "\r\n",
+                "Hi\r\n",
+                ".\r\n",
+                "QUIT\r\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_stream() {
+        let stream = MockStream::with_script(vec![
+            ("", "220 localhost ESMTP\r\n"),
+            ("EHLO localhost\r\n", "250-localhost\r\n250 8BITMIME\r\n"),
+            ("QUIT\r\n", "221 Bye\r\n"),
+        ]);
+
+        let mut conn =
+            SmtpConnection::from_stream(stream.clone(), &ClientId::Domain("localhost".to_owned()))
+                .unwrap();
+        assert!(!conn.is_encrypted());
+        assert!(conn.server_info().supports_feature(Extension::EightBitMime));
+        conn.quit().unwrap();
+        assert!(stream.errors().is_empty());
+    }
+
+    #[test]
+    fn message_too_large() {
+        // The message isn't sent at all
+        let stream = MockStream::with_script(vec![
+            ("", "220 localhost ESMTP\r\n"),
+            (
+                "EHLO localhost\r\n",
+                "250-localhost\r\n250-8BITMIME\r\n250 SIZE 1048576\r\n",
+            ),
+            ("QUIT\r\n", "221 Bye\r\n"),
+        ]);
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+
+        let mut conn =
+            SmtpConnection::from_stream(stream.clone(), &ClientId::Domain("localhost".to_owned()))
+                .unwrap();
+        assert_eq!(conn.server_info().max_message_size(), Some(1_048_576));
+        let email = vec![b'a'; 2 * 1024 * 1024];
+        let err = conn.send(&envelope, &email).err().unwrap();
+        assert!(err.is_message_too_large());
+        assert!(!err.is_transient());
+        conn.quit().unwrap();
+        assert!(stream.errors().is_empty());
+        assert!(stream.is_finished());
+    }
+
+    #[test]
+    fn reply_grammar() {
+        let stream = MockStream::with_script(vec![
+            ("", "220-localhost ESMTP\r\n220 ready\r\n"),
+            (
+                "EHLO localhost\r\n",
+                "250-mx.domain.tld at your service, [192.0.2.1]\r\n250-SIZE 35882577\r\n\
+                 250-8BITMIME\r\n250-STARTTLS\r\n250-ENHANCEDSTATUSCODES\r\n250-PIPELINING\r\n\
+                 250-CHUNKING\r\n250 SMTPUTF8\r\n",
+            ),
+            ("NOOP\r\n", "250\r\n"),
+            ("NOOP\r\n", "250 \r\n"),
+            ("NOOP\r\n", "250-\r\n250 OK\r\n"),
+            ("QUIT\r\n", "221 Bye\r\n"),
+        ]);
+        let mut conn =
+            SmtpConnection::from_stream(stream.clone(), &ClientId::Domain("localhost".to_owned()))
+                .unwrap();
+        let info = conn.server_info();
+        assert_eq!(info.name(), "mx.domain.tld");
+        assert_eq!(info.max_message_size(), Some(35_882_577));
+        assert!(info.supports_feature(Extension::SmtpUtfEight));
+        assert!(info.supports("chunking"));
+
+        // Empty texts are allowed
+        let response = conn.command(Noop).unwrap();
+        assert_eq!(response.message().count(), 0);
+        let response = conn.command(Noop).unwrap();
+        assert_eq!(response.message().collect::<Vec<_>>(), [""]);
+        let response = conn.command(Noop).unwrap();
+        assert_eq!(response.message().collect::<Vec<_>>(), ["", "OK"]);
+        conn.quit().unwrap();
+        assert!(stream.errors().is_empty());
+
+        // Malformed replies break the connection instead of panicking
+        for reply in [
+            "250-first\r\n251 second\r\n",
+            "25\r\n",
+            "\r\n",
+            "250first\r\n",
+        ] {
+            let stream = MockStream::with_script(vec![
+                ("", "220 localhost ESMTP\r\n"),
+                ("EHLO localhost\r\n", "250 localhost\r\n"),
+                ("NOOP\r\n", reply),
+            ]);
+            let mut conn = SmtpConnection::from_stream(
+                stream.clone(),
+                &ClientId::Domain("localhost".to_owned()),
+            )
+            .unwrap();
+            let err = conn.command(Noop).unwrap_err();
+            assert!(err.is_response(), "{reply:?}: {err}");
+            assert!(conn.has_broken(), "{reply:?}");
+        }
+    }
+
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    #[test]
+    fn starttls_injection() {
+        use crate::transport::smtp::StarttlsStage;
+
+        let hello = ClientId::Domain("localhost".to_owned());
+        let tls = TlsParameters::new("localhost".to_owned()).unwrap();
+
+        // A reply appended to the STARTTLS go-ahead is never read
+        let stream = MockStream::with_script(vec![
+            ("", "220 localhost ESMTP\r\n"),
+            ("EHLO localhost\r\n", "250-localhost\r\n250 STARTTLS\r\n"),
+            (
+                "STARTTLS\r\n",
+                "220 Go ahead\r\n250-localhost\r\n250 AUTH PLAIN\r\n",
+            ),
+        ]);
+        let mut conn = SmtpConnection::from_stream(stream.clone(), &hello).unwrap();
+        let err = conn.starttls(&tls, &hello).unwrap_err();
+        assert_eq!(err.starttls_stage(), Some(StarttlsStage::UnexpectedData));
+        assert!(conn.has_broken());
+        conn.abort();
+        assert!(stream.errors().is_empty());
+        assert!(stream.is_finished());
+
+        // Only a `220` reply starts the handshake, not one injected before it
+        for reply in ["250 OK\r\n", "250 Injected\r\n220 Go ahead\r\n"] {
+            let stream = MockStream::with_script(vec![
+                ("", "220 localhost ESMTP\r\n"),
+                ("EHLO localhost\r\n", "250-localhost\r\n250 STARTTLS\r\n"),
+                ("STARTTLS\r\n", reply),
+            ]);
+            let mut conn = SmtpConnection::from_stream(stream.clone(), &hello).unwrap();
+            let err = conn.starttls(&tls, &hello).unwrap_err();
+            assert_eq!(err.starttls_stage(), Some(StarttlsStage::CommandRejected));
+            assert_eq!(
+                err.to_string(),
+                "starttls error (command rejected): response error: Unexpected reply to STARTTLS: 250"
+            );
+            assert!(conn.has_broken());
+            assert!(!conn.is_encrypted());
+            assert!(stream.errors().is_empty());
+        }
+
+        // The capabilities advertised before are forgotten, even if the upgrade fails
+        let stream = MockStream::with_script(vec![
+            ("", "220 localhost ESMTP\r\n"),
+            ("EHLO localhost\r\n", "250-localhost\r\n250 STARTTLS\r\n"),
+            ("STARTTLS\r\n", "220 Go ahead\r\n"),
+        ]);
+        let mut conn = SmtpConnection::from_stream(stream, &hello).unwrap();
+        assert!(conn.starttls(&tls, &hello).is_err());
+        assert!(!conn.server_info().supports_feature(Extension::StartTls));
+        assert_eq!(conn.server_info().name(), "");
+    }
+
+    #[test]
+    fn mail_from_fallback() {
+        let envelope = Envelope::new(
+            Some("nobody@unverified.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+        let hello = ClientId::Domain("localhost".to_owned());
+        let connect = |script: Vec<(&str, &str)>| {
+            let mut script = script;
+            script.insert(0, ("", "220 localhost ESMTP\r\n"));
+            script.insert(1, ("EHLO localhost\r\n", "250 localhost\r\n"));
+            let stream = MockStream::with_script(script);
+            let mut conn = SmtpConnection::from_stream(stream.clone(), &hello).unwrap();
+            conn.mail_from_fallback(Some("noreply@domain.tld".parse().unwrap()));
+            (conn, stream)
+        };
+
+        // The fallback is accepted
+        let (mut conn, stream) = connect(vec![
+            (
+                "MAIL FROM:<nobody@unverified.tld>\r\n",
+                "550 5.7.1 Sender domain not verified\r\n",
+            ),
+            ("RSET\r\n", "250 OK\r\n"),
+            ("MAIL FROM:<noreply@domain.tld>\r\n", "250 OK\r\n"),
+            ("RCPT TO:<hei@domain.tld>\r\n", "250 OK\r\n"),
+            ("DATA\r\n", "354 Go ahead\r\n"),
+            (
+                "X-Original-From: nobody@unverified.tld\r\nSubject: Hi\r\n\r\nHi\r\n.\r\n",
+                "250 Queued\r\n",
+            ),
+            ("QUIT\r\n", "221 Bye\r\n"),
+        ]);
+        conn.send(&envelope, b"Subject: Hi\r\n\r\nHi").unwrap();
+        assert!(conn.negotiated_features().unwrap().mail_from_fallback());
+        conn.quit().unwrap();
+        assert!(stream.errors().is_empty(), "{:?}", stream.errors());
+        assert!(stream.is_finished());
+
+        // The fallback is rejected too
+        let (mut conn, stream) = connect(vec![
+            (
+                "MAIL FROM:<nobody@unverified.tld>\r\n",
+                "550 5.7.1 Sender domain not verified\r\n",
+            ),
+            ("RSET\r\n", "250 OK\r\n"),
+            (
+                "MAIL FROM:<noreply@domain.tld>\r\n",
+                "553 5.7.1 Sender not allowed\r\n",
+            ),
+            ("QUIT\r\n", "221 Bye\r\n"),
+        ]);
+        let err = conn.send(&envelope, b"Subject: Hi\r\n\r\nHi").unwrap_err();
+        assert!(err.is_permanent());
+        assert_eq!(err.status().map(u16::from), Some(553));
+        assert!(stream.errors().is_empty(), "{:?}", stream.errors());
+        assert!(stream.is_finished());
+
+        // Transient errors aren't retried
+        let (mut conn, stream) = connect(vec![
+            (
+                "MAIL FROM:<nobody@unverified.tld>\r\n",
+                "451 4.7.1 Try again later\r\n",
+            ),
+            ("QUIT\r\n", "221 Bye\r\n"),
+        ]);
+        let err = conn.send(&envelope, b"Subject: Hi\r\n\r\nHi").unwrap_err();
+        assert!(err.is_transient());
+        assert!(stream.errors().is_empty(), "{:?}", stream.errors());
+        assert!(stream.is_finished());
+
+        // Neither are the errors which aren't about the sender
+        for reply in [
+            "552 5.3.4 Message too big\r\n",
+            "555 5.5.4 Unsupported parameter\r\n",
+            "501 Syntax error\r\n",
+        ] {
+            let (mut conn, stream) = connect(vec![
+                ("MAIL FROM:<nobody@unverified.tld>\r\n", reply),
+                ("QUIT\r\n", "221 Bye\r\n"),
+            ]);
+            let err = conn.send(&envelope, b"Subject: Hi\r\n\r\nHi").unwrap_err();
+            assert!(err.is_permanent());
+            assert!(stream.errors().is_empty(), "{:?}", stream.errors());
+            assert!(stream.is_finished());
+        }
+
+        // A sender rejection without a 550 or 553 reply
+        let (mut conn, stream) = connect(vec![
+            (
+                "MAIL FROM:<nobody@unverified.tld>\r\n",
+                "554 5.7.1 Sender blocked\r\n",
+            ),
+            ("RSET\r\n", "250 OK\r\n"),
+            ("MAIL FROM:<noreply@domain.tld>\r\n", "250 OK\r\n"),
+            ("RCPT TO:<hei@domain.tld>\r\n", "250 OK\r\n"),
+            ("DATA\r\n", "354 Go ahead\r\n"),
+            (
+                "X-Original-From: nobody@unverified.tld\r\nSubject: Hi\r\n\r\nHi\r\n.\r\n",
+                "250 Queued\r\n",
+            ),
+        ]);
+        conn.send(&envelope, b"Subject: Hi\r\n\r\nHi").unwrap();
+        assert!(stream.errors().is_empty(), "{:?}", stream.errors());
+        assert!(stream.is_finished());
+    }
+}
@@ -0,0 +1,248 @@
+use std::io::{self, Read, Write};
+
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+
+/// Size of the buffers of compressed data
+const BUFFER_LEN: usize = 8 * 1024;
+
+/// A stream compressed with raw DEFLATE in both directions, as negotiated
+/// with the `COMPRESS DEFLATE` command
+///
+/// Each write is compressed and flushed right away, so that the server
+/// receives whole commands.
+pub(super) struct DeflateStream<S> {
+    inner: S,
+    compress: Compress,
+    decompress: Decompress,
+    /// Compressed data read from `inner`, from `input_pos`
+    input: Vec<u8>,
+    input_pos: usize,
+    /// Compressed data not yet written to `inner`
+    output: Vec<u8>,
+}
+
+impl<S> DeflateStream<S> {
+    pub(super) fn new(inner: S) -> Self {
+        Self {
+            inner,
+            compress: Compress::new(Compression::default(), false),
+            decompress: Decompress::new(false),
+            input: Vec::with_capacity(BUFFER_LEN),
+            input_pos: 0,
+            output: Vec::with_capacity(BUFFER_LEN),
+        }
+    }
+
+    pub(super) fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub(super) fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+}
+
+impl<S: Read> Read for DeflateStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            // The decompressor may still have output for the input it consumed
+            let total_in = self.decompress.total_in();
+            let total_out = self.decompress.total_out();
+            let status = self
+                .decompress
+                .decompress(&self.input[self.input_pos..], buf, FlushDecompress::None)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            let consumed = (self.decompress.total_in() - total_in) as usize;
+            self.input_pos += consumed;
+            let read = (self.decompress.total_out() - total_out) as usize;
+
+            if read > 0 || status == Status::StreamEnd {
+                return Ok(read);
+            }
+            if self.input_pos < self.input.len() {
+                if consumed == 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "the DEFLATE stream doesn't progress",
+                    ));
+                }
+                continue;
+            }
+
+            self.input.resize(BUFFER_LEN, 0);
+            let len = self.inner.read(&mut self.input)?;
+            self.input.truncate(len);
+            self.input_pos = 0;
+            if len == 0 {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+impl<S: Write> DeflateStream<S> {
+    /// Writes the pending compressed data to `inner`
+    ///
+    /// The data is only dropped once written, as losing part of it would
+    /// corrupt the rest of the stream.
+    fn write_output(&mut self) -> io::Result<()> {
+        while !self.output.is_empty() {
+            match self.inner.write(&self.output) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write the compressed data",
+                    ))
+                }
+                Ok(len) => {
+                    self.output.drain(..len);
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: Write> Write for DeflateStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // `buf` isn't consumed if the data of the previous writes can't be written
+        self.write_output()?;
+
+        let mut input = buf;
+        loop {
+            self.output.reserve(BUFFER_LEN);
+            let total_in = self.compress.total_in();
+            self.compress
+                .compress_vec(input, &mut self.output, FlushCompress::Sync)
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+            input = &input[(self.compress.total_in() - total_in) as usize..];
+
+            // The flush is complete once the output isn't full anymore
+            if input.is_empty() && self.output.len() < self.output.capacity() {
+                break;
+            }
+        }
+
+        // `buf` is consumed now, so an error is returned by the next write
+        // or flush, which write the data again
+        let _ = self.write_output();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_output()?;
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, Cursor, Read, Write};
+
+    use flate2::read::DeflateDecoder;
+    use pretty_assertions::assert_eq;
+
+    use super::DeflateStream;
+
+    #[test]
+    fn deflate_round_trip() {
+        let commands = [
+            "EHLO localhost\r\n".to_owned(),
+            "MAIL FROM:<nobody@domain.tld>\r\n".to_owned(),
+            "RCPT TO:<hei@domain.tld>\r\n".to_owned(),
+            "DATA\r\n".to_owned(),
+            format!("Subject: Report\r\n\r\n{}", "All good.\r\n".repeat(10_000)),
+            ".\r\n".to_owned(),
+        ];
+
+        let mut stream = DeflateStream::new(Vec::new());
+        for command in &commands {
+            stream.write_all(command.as_bytes()).unwrap();
+        }
+        let compressed = stream.get_ref().clone();
+        let plain = commands.concat();
+        assert!(compressed.len() < plain.len() / 10);
+
+        // Readable by another DEFLATE implementation, the stream is never finished
+        let mut decoded = vec![0; plain.len()];
+        DeflateDecoder::new(&compressed[..])
+            .read_exact(&mut decoded)
+            .unwrap();
+        assert_eq!(String::from_utf8(decoded).unwrap(), plain);
+
+        // Each write is flushed, so that it can be read on its own
+        let mut stream = DeflateStream::new(Vec::new());
+        stream.write_all(b"EHLO localhost\r\n").unwrap();
+        let mut reader = DeflateStream::new(Cursor::new(stream.get_ref().clone()));
+        let mut buf = [0; 64];
+        let len = reader.read(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"EHLO localhost\r\n");
+
+        // Read back in small pieces
+        let mut reader = DeflateStream::new(Cursor::new(compressed));
+        let mut decoded = Vec::new();
+        let mut buf = [0; 7];
+        loop {
+            let len = reader.read(&mut buf).unwrap();
+            if len == 0 {
+                break;
+            }
+            decoded.extend_from_slice(&buf[..len]);
+        }
+        assert_eq!(String::from_utf8(decoded).unwrap(), plain);
+    }
+
+    #[test]
+    fn deflate_write_failures() {
+        /// Sink accepting a few bytes at a time, and failing every other write
+        struct Flaky {
+            written: Vec<u8>,
+            fail: bool,
+        }
+
+        impl Write for Flaky {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.fail = !self.fail;
+                if self.fail {
+                    return Err(io::Error::new(io::ErrorKind::Other, "flaky"));
+                }
+                let len = buf.len().min(5);
+                self.written.extend_from_slice(&buf[..len]);
+                Ok(len)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let commands = [
+            "EHLO localhost\r\n",
+            "MAIL FROM:<nobody@domain.tld>\r\n",
+            "RCPT TO:<hei@domain.tld>\r\n",
+        ];
+
+        let mut stream = DeflateStream::new(Flaky {
+            written: Vec::new(),
+            fail: false,
+        });
+        for command in commands {
+            // The failed writes and flushes are retried
+            while stream.write_all(command.as_bytes()).is_err() {}
+            while stream.flush().is_err() {}
+        }
+
+        let plain = commands.concat();
+        let mut decoded = vec![0; plain.len()];
+        DeflateDecoder::new(&stream.get_ref().written[..])
+            .read_exact(&mut decoded)
+            .unwrap();
+        assert_eq!(String::from_utf8(decoded).unwrap(), plain);
+    }
+}
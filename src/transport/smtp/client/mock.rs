@@ -0,0 +1,673 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// A step of the scripted SMTP conversation of a [`MockStream`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MockStep {
+    /// Expect exactly these bytes from the client
+    Expect(Vec<u8>),
+    /// Expect a line from the client starting with this text, like
+    /// `MAIL FROM:` for a command whose parameters don't matter
+    ExpectLine(String),
+    /// Send these bytes to the client
+    Send(Vec<u8>),
+    /// Send these bytes to the client `chunk` bytes at a time,
+    /// waiting `delay` before each chunk
+    SendBytesSlowly {
+        /// The bytes to send
+        bytes: Vec<u8>,
+        /// The maximum number of bytes read by the client at once
+        chunk: usize,
+        /// The delay before each chunk
+        delay: Duration,
+    },
+    /// Wait before sending what follows
+    Sleep(Duration),
+    /// Close the connection, the client reading the end of the stream
+    /// once it received everything sent before
+    CloseConnection,
+}
+
+impl MockStep {
+    /// Expect exactly `bytes` from the client
+    pub fn expect<B: AsRef<[u8]>>(bytes: B) -> Self {
+        Self::Expect(bytes.as_ref().to_vec())
+    }
+
+    /// Send `bytes` to the client
+    pub fn send<B: AsRef<[u8]>>(bytes: B) -> Self {
+        Self::Send(bytes.as_ref().to_vec())
+    }
+}
+
+/// A stream replaying a scripted SMTP conversation, to test code
+/// using an [`SmtpConnection`](super::SmtpConnection) without a server
+///
+/// The script is made of [`MockStep`]s, played in order: the bytes of the
+/// server are readable by the client as soon as all the expected writes
+/// before them were made. Writes which don't match the script are recorded,
+/// and nothing is replied to them, so that the client sees the connection
+/// as closed.
+///
+/// Clones share the same conversation, so that the errors and the transcript
+/// can be checked after the stream was given to the connection.
+///
+/// # Examples
+///
+/// ```rust
+/// use lettre::transport::smtp::{
+///     client::{MockStep, MockStream, SmtpConnection},
+///     extension::ClientId,
+/// };
+///
+/// let stream = MockStream::with_steps(vec![
+///     MockStep::send("220 localhost ESMTP\r\n"),
+///     MockStep::ExpectLine("EHLO ".to_owned()),
+///     MockStep::send("250-localhost\r\n250 8BITMIME\r\n"),
+///     MockStep::expect("QUIT\r\n"),
+///     MockStep::send("221 Bye\r\n"),
+/// ]);
+/// let mut conn =
+///     SmtpConnection::from_stream(stream.clone(), &ClientId::Domain("localhost".to_owned()))?;
+/// conn.quit()?;
+///
+/// assert!(stream.errors().is_empty());
+/// assert!(stream.is_finished());
+/// assert_eq!(stream.transcript()[1], "C: EHLO localhost");
+/// # Ok::<(), lettre::transport::smtp::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockStream {
+    inner: Arc<Mutex<Conversation>>,
+}
+
+/// Bytes sent by the server and not read yet by the client
+#[derive(Debug)]
+struct Pending {
+    bytes: VecDeque<u8>,
+    /// The maximum number of bytes returned by each read
+    chunk: usize,
+    /// The delay before each read
+    delay: Duration,
+}
+
+#[derive(Debug)]
+struct Conversation {
+    steps: VecDeque<MockStep>,
+    /// Bytes written by the client for the current step
+    written: Vec<u8>,
+    readable: VecDeque<Pending>,
+    closed: bool,
+    errors: Vec<String>,
+    transcript: Vec<String>,
+}
+
+impl Conversation {
+    /// Plays the steps until one waits for the client
+    fn advance(&mut self) {
+        while let Some(step) = self.steps.front() {
+            match step {
+                MockStep::Expect(expected) => {
+                    if self.written.len() < expected.len() {
+                        if !expected.starts_with(&self.written) {
+                            self.mismatch();
+                        }
+                        return;
+                    }
+                    if !self.written.starts_with(expected) {
+                        self.mismatch();
+                        return;
+                    }
+
+                    let written = self.written.drain(..expected.len()).collect::<Vec<_>>();
+                    record(&mut self.transcript, "C: ", &written);
+                }
+                MockStep::ExpectLine(prefix) => {
+                    let Some(end) = self.written.iter().position(|&b| b == b'\n') else {
+                        if !self.written.starts_with(prefix.as_bytes())
+                            && !prefix.as_bytes().starts_with(&self.written)
+                        {
+                            self.mismatch();
+                        }
+                        return;
+                    };
+                    if !self.written.starts_with(prefix.as_bytes()) {
+                        self.mismatch();
+                        return;
+                    }
+
+                    let written = self.written.drain(..=end).collect::<Vec<_>>();
+                    record(&mut self.transcript, "C: ", &written);
+                }
+                MockStep::Send(bytes) => {
+                    record(&mut self.transcript, "S: ", bytes);
+                    self.readable.push_back(Pending {
+                        bytes: bytes.iter().copied().collect(),
+                        chunk: usize::MAX,
+                        delay: Duration::ZERO,
+                    });
+                }
+                MockStep::SendBytesSlowly {
+                    bytes,
+                    chunk,
+                    delay,
+                } => {
+                    record(&mut self.transcript, "S: ", bytes);
+                    self.readable.push_back(Pending {
+                        bytes: bytes.iter().copied().collect(),
+                        chunk: (*chunk).max(1),
+                        delay: *delay,
+                    });
+                }
+                MockStep::Sleep(delay) => {
+                    self.readable.push_back(Pending {
+                        bytes: VecDeque::new(),
+                        chunk: usize::MAX,
+                        delay: *delay,
+                    });
+                }
+                MockStep::CloseConnection => {
+                    self.closed = true;
+                    self.steps.clear();
+                }
+            }
+            self.steps.pop_front();
+        }
+
+        if !self.written.is_empty() {
+            let written = String::from_utf8_lossy(&self.written);
+            self.errors.push(format!(
+                "unexpected write after the end of the script: {written:?}"
+            ));
+            self.written.clear();
+        }
+    }
+
+    /// Records a write which doesn't match the current step, and ends the conversation
+    fn mismatch(&mut self) {
+        let expected = match self.steps.front() {
+            Some(MockStep::Expect(expected)) => String::from_utf8_lossy(expected).into_owned(),
+            Some(MockStep::ExpectLine(prefix)) => format!("{prefix}..."),
+            _ => unreachable!("the current step expects a write"),
+        };
+        let written = String::from_utf8_lossy(&self.written);
+        self.errors
+            .push(format!("expected {expected:?}, got {written:?}"));
+        self.steps.clear();
+        self.written.clear();
+    }
+}
+
+/// Adds the lines of `bytes` to `transcript`, after `prefix`
+fn record(transcript: &mut Vec<String>, prefix: &str, bytes: &[u8]) {
+    transcript.extend(
+        String::from_utf8_lossy(bytes)
+            .lines()
+            .map(|line| format!("{prefix}{line}")),
+    );
+}
+
+impl MockStream {
+    /// Creates a stream following `script`, a list of expected writes and their reply
+    ///
+    /// A step expecting nothing replies immediately, like the greeting of a server.
+    pub fn with_script<E, R>(script: Vec<(E, R)>) -> Self
+    where
+        E: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        let steps = script
+            .into_iter()
+            .flat_map(|(expected, reply)| {
+                let expected = (!expected.as_ref().is_empty()).then(|| MockStep::expect(expected));
+                let reply = (!reply.as_ref().is_empty()).then(|| MockStep::send(reply));
+                expected.into_iter().chain(reply)
+            })
+            .collect();
+        Self::with_steps(steps)
+    }
+
+    /// Creates a stream following `steps`
+    pub fn with_steps(steps: Vec<MockStep>) -> Self {
+        let mut conversation = Conversation {
+            steps: steps.into(),
+            written: Vec::new(),
+            readable: VecDeque::new(),
+            closed: false,
+            errors: Vec::new(),
+            transcript: Vec::new(),
+        };
+        conversation.advance();
+
+        Self {
+            inner: Arc::new(Mutex::new(conversation)),
+        }
+    }
+
+    /// Get the writes which didn't match the script
+    pub fn errors(&self) -> Vec<String> {
+        self.conversation().errors.clone()
+    }
+
+    /// Whether all the steps of the script were played
+    pub fn is_finished(&self) -> bool {
+        self.conversation().steps.is_empty()
+    }
+
+    /// The lines written by the client and by the server so far,
+    /// after `C: ` and `S: ` respectively
+    ///
+    /// The lines of the server are added when the steps sending them
+    /// are reached, even if the client didn't read them yet.
+    pub fn transcript(&self) -> Vec<String> {
+        self.conversation().transcript.clone()
+    }
+
+    fn conversation(&self) -> std::sync::MutexGuard<'_, Conversation> {
+        self.inner.lock().expect("the mock stream isn't poisoned")
+    }
+}
+
+impl Read for MockStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut conversation = self.conversation();
+        while let Some(pending) = conversation.readable.front_mut() {
+            if !pending.delay.is_zero() {
+                thread::sleep(pending.delay);
+            }
+            let len = buf.len().min(pending.chunk).min(pending.bytes.len());
+            for (byte, read) in buf.iter_mut().zip(pending.bytes.drain(..len)) {
+                *byte = read;
+            }
+            if pending.bytes.is_empty() {
+                conversation.readable.pop_front();
+            }
+            if len > 0 {
+                return Ok(len);
+            }
+        }
+        Ok(0)
+    }
+}
+
+impl Write for MockStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut conversation = self.conversation();
+        if conversation.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        conversation.written.extend_from_slice(buf);
+        conversation.advance();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Starts a server playing `steps` with the first client connecting to it,
+/// and returning the transcript of the conversation
+///
+/// The server thread panics when the client doesn't write what a step
+/// expects, and stops when the client disconnects.
+#[cfg(test)]
+pub(crate) fn serve(
+    steps: Vec<MockStep>,
+) -> (std::net::SocketAddr, thread::JoinHandle<Vec<String>>) {
+    use std::{
+        io::{BufRead, BufReader},
+        net::{Shutdown, TcpListener},
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.set_nodelay(true).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+        let mut transcript = Vec::new();
+        for step in steps {
+            let played = match step {
+                MockStep::Expect(expected) => {
+                    let mut written = vec![0; expected.len()];
+                    reader.read_exact(&mut written).map(|()| {
+                        assert_eq!(
+                            String::from_utf8_lossy(&written),
+                            String::from_utf8_lossy(&expected)
+                        );
+                        record(&mut transcript, "C: ", &written);
+                    })
+                }
+                MockStep::ExpectLine(prefix) => {
+                    let mut written = Vec::new();
+                    match reader.read_until(b'\n', &mut written) {
+                        Ok(0) => Err(io::ErrorKind::UnexpectedEof.into()),
+                        Ok(_) => {
+                            let line = String::from_utf8_lossy(&written);
+                            assert!(
+                                line.starts_with(&prefix),
+                                "expected {prefix:?}, got {line:?}"
+                            );
+                            record(&mut transcript, "C: ", &written);
+                            Ok(())
+                        }
+                        Err(err) => Err(err),
+                    }
+                }
+                MockStep::Send(bytes) => stream
+                    .write_all(&bytes)
+                    .map(|()| record(&mut transcript, "S: ", &bytes)),
+                MockStep::SendBytesSlowly {
+                    bytes,
+                    chunk,
+                    delay,
+                } => bytes
+                    .chunks(chunk.max(1))
+                    .try_for_each(|chunk| {
+                        thread::sleep(delay);
+                        stream.write_all(chunk)
+                    })
+                    .map(|()| record(&mut transcript, "S: ", &bytes)),
+                MockStep::Sleep(delay) => {
+                    thread::sleep(delay);
+                    Ok(())
+                }
+                MockStep::CloseConnection => {
+                    let _ = stream.shutdown(Shutdown::Both);
+                    break;
+                }
+            };
+            // The client disconnected
+            if played.is_err() {
+                break;
+            }
+        }
+        transcript
+    });
+
+    (addr, server)
+}
+
+/// Lines received by a server started with [`serve_sessions`], for each connection
+#[cfg(test)]
+pub(crate) type Sessions = Arc<Mutex<Vec<Vec<String>>>>;
+
+/// Starts a server answering the first client connecting to it like an SMTP
+/// server accepting everything, `EHLO` being answered with `ehlo`
+///
+/// The lines received, those of the messages included, are returned once
+/// the client quits or disconnects. The content of a `BDAT` chunk is
+/// returned as one line.
+#[cfg(test)]
+pub(crate) fn serve_session(
+    ehlo: &'static str,
+) -> (std::net::SocketAddr, thread::JoinHandle<Vec<String>>) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut lines = Vec::new();
+        play_session(stream, ehlo, "235 Authentication successful\r\n", |line| {
+            lines.push(line)
+        });
+        lines
+    });
+
+    (addr, server)
+}
+
+/// Starts a server like [`serve_session`], accepting every connection, as
+/// a connection pool may open idle connections in the background
+///
+/// `ehlo` gives the reply to `EHLO` from the index of the connection, and
+/// `AUTH` is answered with `auth`. The received lines are recorded as they
+/// arrive.
+#[cfg(test)]
+pub(crate) fn serve_sessions<F>(ehlo: F, auth: &'static str) -> (std::net::SocketAddr, Sessions)
+where
+    F: Fn(usize) -> String + Send + 'static,
+{
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let sessions = Sessions::default();
+    let sessions_ = Arc::clone(&sessions);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { break };
+            let i = {
+                let mut sessions = sessions_.lock().unwrap();
+                sessions.push(Vec::new());
+                sessions.len() - 1
+            };
+            let ehlo = ehlo(i);
+            let sessions = Arc::clone(&sessions_);
+            thread::spawn(move || {
+                play_session(stream, &ehlo, auth, |line| {
+                    sessions.lock().unwrap()[i].push(line);
+                });
+            });
+        }
+    });
+
+    (addr, sessions)
+}
+
+/// Answers the client of `stream` like an SMTP server accepting everything,
+/// passing each received line to `record` before replying to it
+#[cfg(test)]
+fn play_session<F>(mut stream: std::net::TcpStream, ehlo: &str, auth: &str, mut record: F)
+where
+    F: FnMut(String),
+{
+    use std::io::{BufRead, BufReader};
+
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    if stream.write_all(b"220 localhost ESMTP\r\n").is_err() {
+        return;
+    }
+
+    let mut data = false;
+    let mut line = String::new();
+    while reader.read_line(&mut line).unwrap_or(0) > 0 {
+        let mut words = line.split_whitespace();
+        let reply = match words.next().unwrap_or_default() {
+            _ if data && line != ".\r\n" => "",
+            "." if data => {
+                data = false;
+                "250 Queued\r\n"
+            }
+            "EHLO" => ehlo,
+            "AUTH" => auth,
+            "MAIL" | "RCPT" | "RSET" | "NOOP" => "250 OK\r\n",
+            "DATA" => {
+                data = true;
+                "354 Go ahead\r\n"
+            }
+            "BDAT" => {
+                let size = words.next().unwrap().parse().unwrap();
+                let last = words.next() == Some("LAST");
+                let mut chunk = vec![0; size];
+                if reader.read_exact(&mut chunk).is_err() {
+                    break;
+                }
+                record(std::mem::take(&mut line));
+                line = String::from_utf8(chunk).unwrap();
+                if last {
+                    "250 Queued\r\n"
+                } else {
+                    "250 Chunk received\r\n"
+                }
+            }
+            "STARTTLS" => "454 TLS not available\r\n",
+            "QUIT" => "221 Bye\r\n",
+            _ => "500 Unknown command\r\n",
+        };
+        record(std::mem::take(&mut line));
+        if stream.write_all(reply.as_bytes()).is_err() || reply.starts_with("221") {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use pretty_assertions::assert_eq;
+
+    use super::{serve, MockStep, MockStream};
+    use crate::transport::{
+        envelope,
+        smtp::{client::SmtpConnection, extension::ClientId},
+    };
+
+    fn hello() -> ClientId {
+        ClientId::Domain("localhost".to_owned())
+    }
+
+    /// A conversation sending an email, with the EHLO reply sent slowly
+    fn send_script() -> Vec<MockStep> {
+        vec![
+            MockStep::send("220 localhost ESMTP\r\n"),
+            MockStep::ExpectLine("EHLO ".to_owned()),
+            MockStep::SendBytesSlowly {
+                bytes: b"250-localhost\r\n250-PIPELINING\r\n250 8BITMIME\r\n".to_vec(),
+                chunk: 5,
+                delay: Duration::from_millis(1),
+            },
+            MockStep::ExpectLine("MAIL FROM:<nobody@domain.tld>".to_owned()),
+            MockStep::send("250 OK\r\n"),
+            MockStep::expect("RCPT TO:<hei@domain.tld>\r\n"),
+            MockStep::send("250 OK\r\n"),
+            MockStep::expect("DATA\r\n"),
+            MockStep::send("354 Go ahead\r\n"),
+            MockStep::expect("Subject: Hi\r\n\r\n..Hi\r\n.\r\n"),
+            MockStep::Sleep(Duration::from_millis(50)),
+            MockStep::send("250 Queued\r\n"),
+            MockStep::expect("QUIT\r\n"),
+            MockStep::send("221 Bye\r\n"),
+        ]
+    }
+
+    const SEND_TRANSCRIPT: [&str; 18] = [
+        "S: 220 localhost ESMTP",
+        "C: EHLO localhost",
+        "S: 250-localhost",
+        "S: 250-PIPELINING",
+        "S: 250 8BITMIME",
+        "C: MAIL FROM:<nobody@domain.tld>",
+        "S: 250 OK",
+        "C: RCPT TO:<hei@domain.tld>",
+        "S: 250 OK",
+        "C: DATA",
+        "S: 354 Go ahead",
+        "C: Subject: Hi",
+        "C: ",
+        "C: ..Hi",
+        "C: .",
+        "S: 250 Queued",
+        "C: QUIT",
+        "S: 221 Bye",
+    ];
+
+    #[test]
+    fn mock_stream_send() {
+        let stream = MockStream::with_steps(send_script());
+
+        let mut conn = SmtpConnection::from_stream(stream.clone(), &hello()).unwrap();
+        let start = Instant::now();
+        let response = conn.send(&envelope(), b"Subject: Hi\r\n\r\n.Hi").unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(response.first_line(), Some("Queued"));
+        conn.quit().unwrap();
+
+        assert_eq!(stream.errors(), Vec::<String>::new());
+        assert!(stream.is_finished());
+        assert_eq!(stream.transcript(), SEND_TRANSCRIPT);
+    }
+
+    #[test]
+    fn server_send() {
+        let (addr, server) = serve(send_script());
+
+        let mut conn = SmtpConnection::connect(addr, None, &hello(), None, None).unwrap();
+        let start = Instant::now();
+        let response = conn.send(&envelope(), b"Subject: Hi\r\n\r\n.Hi").unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(response.first_line(), Some("Queued"));
+        conn.quit().unwrap();
+
+        assert_eq!(server.join().unwrap(), SEND_TRANSCRIPT);
+    }
+
+    #[test]
+    fn mock_stream_script() {
+        let stream = MockStream::with_script(vec![
+            ("", "220 localhost ESMTP\r\n"),
+            ("EHLO localhost\r\n", "250 localhost\r\n"),
+            ("QUIT\r\n", "221 Bye\r\n"),
+        ]);
+
+        let mut conn = SmtpConnection::from_stream(stream.clone(), &hello()).unwrap();
+        conn.quit().unwrap();
+        assert_eq!(stream.errors(), Vec::<String>::new());
+        assert!(stream.is_finished());
+    }
+
+    #[test]
+    fn mock_stream_mismatch() {
+        let stream = MockStream::with_script(vec![
+            ("", "220 localhost ESMTP\r\n"),
+            ("HELO localhost\r\n", "250 localhost\r\n"),
+        ]);
+
+        assert!(SmtpConnection::from_stream(stream.clone(), &hello()).is_err());
+        assert_eq!(
+            stream.errors(),
+            ["expected \"HELO localhost\\r\\n\", got \"EHLO localhost\\r\\n\""]
+        );
+        assert!(stream.is_finished());
+
+        let stream = MockStream::with_steps(vec![
+            MockStep::send("220 localhost ESMTP\r\n"),
+            MockStep::ExpectLine("LHLO ".to_owned()),
+        ]);
+        assert!(SmtpConnection::from_stream(stream.clone(), &hello()).is_err());
+        assert_eq!(
+            stream.errors(),
+            ["expected \"LHLO ...\", got \"EHLO localhost\\r\\n\""]
+        );
+    }
+
+    #[test]
+    fn mock_stream_close() {
+        let stream = MockStream::with_steps(vec![
+            MockStep::send("220 localhost ESMTP\r\n"),
+            MockStep::ExpectLine("EHLO ".to_owned()),
+            MockStep::send("421 Shutting down\r\n"),
+            MockStep::CloseConnection,
+        ]);
+
+        let err = SmtpConnection::from_stream(stream.clone(), &hello())
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), Some(421));
+        assert!(stream.is_finished());
+        assert_eq!(
+            stream.transcript(),
+            [
+                "S: 220 localhost ESMTP",
+                "C: EHLO localhost",
+                "S: 421 Shutting down"
+            ]
+        );
+    }
+}
@@ -22,22 +22,29 @@
 //! # }
 //! ```
 
+use std::borrow::Cow;
 #[cfg(feature = "serde")]
 use std::fmt::Debug;
 
+use super::{authentication::Mechanism, response::Response, Error};
+
 #[cfg(any(feature = "tokio1", feature = "async-std1"))]
 pub use self::async_connection::AsyncSmtpConnection;
 #[cfg(any(feature = "tokio1", feature = "async-std1"))]
 pub use self::async_net::AsyncNetworkStream;
 #[cfg(feature = "tokio1")]
 pub use self::async_net::AsyncTokioStream;
-use self::net::NetworkStream;
+#[cfg(test)]
+pub(super) use self::mock::{serve, serve_session, serve_sessions, Sessions};
+pub(super) use self::net::{NetworkStream, ProtocolTap};
 #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
 pub(super) use self::tls::InnerTlsParameters;
 #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
 pub use self::tls::TlsVersion;
 pub use self::{
-    connection::SmtpConnection,
+    connection::{DataWriter, SmtpConnection},
+    mock::{MockStep, MockStream},
+    proxy::ProxyConfig,
     tls::{Certificate, CertificateStore, Tls, TlsParameters, TlsParametersBuilder},
 };
 
@@ -46,9 +53,87 @@ mod async_connection;
 #[cfg(any(feature = "tokio1", feature = "async-std1"))]
 mod async_net;
 mod connection;
+#[cfg(feature = "compression")]
+mod deflate;
+mod mock;
 mod net;
+mod proxy;
 mod tls;
 
+/// Amount of message content buffered before being written to the server
+const DATA_BUFFER_LEN: usize = 8 * 1024;
+
+/// A callback invoked with each raw line received from the server
+pub type LineCallback = Box<dyn FnMut(&str) + Send>;
+
+/// The features of the server used to send an email
+///
+/// Recorded while the email is sent, see [`SmtpConnection::negotiated_features`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NegotiatedFeatures {
+    smtp_utf8: bool,
+    eight_bit_mime: bool,
+    require_tls: bool,
+    dsn: bool,
+    chunking: bool,
+    encrypted: bool,
+    auth_mechanism: Option<Mechanism>,
+    mail_from_fallback: bool,
+    wire_bytes: u64,
+}
+
+impl NegotiatedFeatures {
+    /// Whether the `SMTPUTF8` parameter was sent for non-ASCII addresses
+    pub fn smtp_utf8(&self) -> bool {
+        self.smtp_utf8
+    }
+
+    /// Whether the content was declared as `8BITMIME`
+    pub fn eight_bit_mime(&self) -> bool {
+        self.eight_bit_mime
+    }
+
+    /// Whether the `REQUIRETLS` parameter was sent
+    pub fn require_tls(&self) -> bool {
+        self.require_tls
+    }
+
+    /// Whether delivery status notifications were requested
+    pub fn dsn(&self) -> bool {
+        self.dsn
+    }
+
+    /// Whether the content was sent in `BDAT` chunks instead of after `DATA`
+    pub fn chunking(&self) -> bool {
+        self.chunking
+    }
+
+    /// Whether the connection was encrypted
+    pub fn encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    /// The mechanism used to authenticate on the connection, if any
+    pub fn auth_mechanism(&self) -> Option<Mechanism> {
+        self.auth_mechanism
+    }
+
+    /// Whether the sender of the envelope was rejected and replaced by the
+    /// fallback set with [`SmtpConnection::mail_from_fallback`]
+    pub fn mail_from_fallback(&self) -> bool {
+        self.mail_from_fallback
+    }
+
+    /// The number of octets of the content written to the server
+    ///
+    /// Unlike the size of the email, this includes the dot-stuffing and
+    /// the framing of the content: the final `.` line after `DATA`, or
+    /// the `BDAT` commands of the chunks.
+    pub fn wire_bytes(&self) -> u64 {
+        self.wire_bytes
+    }
+}
+
 /// The codec used for transparency
 #[derive(Debug)]
 struct ClientCodec {
@@ -63,8 +148,18 @@ impl ClientCodec {
         }
     }
 
+    /// Whether the data encoded so far ends with a complete line
+    fn is_start_of_line(&self) -> bool {
+        matches!(self.status, CodecStatus::StartOfNewLine)
+    }
+
     /// Adds transparency
+    ///
+    /// `frame` can be any chunk of the content, the state of the
+    /// current line is kept between calls.
     fn encode(&mut self, frame: &[u8], buf: &mut Vec<u8>) {
+        // A few more bytes for the escaped dots
+        buf.reserve(frame.len() + frame.len() / 64 + 1);
         for &b in frame {
             buf.push(b);
             match (b, self.status) {
@@ -90,6 +185,97 @@ impl ClientCodec {
     }
 }
 
+/// Whether a `MAIL FROM` error rejects the sender itself, with a `550`
+/// or `553` reply or a `5.1.x` or `5.7.x` enhanced status code, so that
+/// sending from the fallback sender may succeed
+///
+/// Errors about the size or the parameters of the email, like `552`
+/// or `555`, are not sender rejections.
+fn rejects_sender(err: &Error) -> bool {
+    err.is_permanent()
+        && (matches!(err.code(), Some(550 | 553))
+            || err
+                .server_response()
+                .and_then(|response| response.enhanced_code())
+                .is_some_and(|code| matches!(code.subject, 1 | 7)))
+}
+
+/// Marks `response` as accepting the email sent with `envelope`
+fn sent(envelope_id: Option<&str>, response: Response) -> Response {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(envelope_id, code = %response.code(), "email sent");
+    response.with_envelope_id(envelope_id)
+}
+
+/// Prepends the header added for the fallback sender to `email`
+fn with_header(header: Option<String>, email: &[u8]) -> Cow<'_, [u8]> {
+    match header {
+        Some(header) => Cow::Owned([header.as_bytes(), email].concat()),
+        None => Cow::Borrowed(email),
+    }
+}
+
+/// Encodes the content of an email written to the server in pieces
+///
+/// Bare `LF` line endings are converted to `CRLF`. Lines starting with a
+/// `.` are escaped, unless the content is sent in `BDAT` chunks.
+#[derive(Debug)]
+struct ContentEncoder {
+    codec: ClientCodec,
+    /// Whether the content is sent with `BDAT` rather than `DATA`
+    chunking: bool,
+    /// Whether the last encoded byte was a `CR`
+    last_cr: bool,
+}
+
+impl ContentEncoder {
+    fn new(chunking: bool) -> Self {
+        Self {
+            codec: ClientCodec::new(),
+            chunking,
+            last_cr: false,
+        }
+    }
+
+    /// Appends `content` to `buf`, the state of the current line
+    /// is kept between calls
+    fn encode(&mut self, content: &[u8], buf: &mut Vec<u8>) {
+        let mut start = 0;
+        for (i, &b) in content.iter().enumerate() {
+            let after_cr = if i == 0 {
+                self.last_cr
+            } else {
+                content[i - 1] == b'\r'
+            };
+            if b == b'\n' && !after_cr {
+                self.push(&content[start..i], buf);
+                self.push(b"\r", buf);
+                start = i;
+            }
+        }
+        self.push(&content[start..], buf);
+        if let Some(&last) = content.last() {
+            self.last_cr = last == b'\r';
+        }
+    }
+
+    fn push(&mut self, content: &[u8], buf: &mut Vec<u8>) {
+        if self.chunking {
+            buf.extend_from_slice(content);
+        } else {
+            self.codec.encode(content, buf);
+        }
+    }
+
+    /// Appends the end of the content sent with `DATA` to `buf`
+    fn finish(&self, buf: &mut Vec<u8>) {
+        if !self.codec.is_start_of_line() {
+            buf.extend_from_slice(b"\r\n");
+        }
+        buf.extend_from_slice(b".\r\n");
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 #[allow(clippy::enum_variant_names)]
 enum CodecStatus {
@@ -138,6 +324,33 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_codec_chunk_boundaries() {
+        fn encode(chunks: &[&[u8]]) -> String {
+            let mut buf = Vec::new();
+            let mut codec = ClientCodec::new();
+            for chunk in chunks {
+                codec.encode(chunk, &mut buf);
+            }
+            String::from_utf8(buf).unwrap()
+        }
+
+        // The message is exactly a dot
+        assert_eq!(encode(&[b"."]), "..");
+        assert_eq!(encode(&[b"", b"."]), "..");
+
+        // The line ending and the dot are in different chunks
+        assert_eq!(encode(&[b"a\r\n", b".b"]), "a\r\n..b");
+        assert_eq!(encode(&[b"a\r", b"\n", b".b"]), "a\r\n..b");
+        assert_eq!(encode(&[b"a\r", b"\n.", b"b"]), "a\r\n..b");
+        assert_eq!(encode(&[b"a\r", b"x.b"]), "a\rx.b");
+
+        // One byte at a time
+        let content = b".a\r\n.\r\nb.\r\n..\r\n";
+        let chunks: Vec<&[u8]> = content.chunks(1).collect();
+        assert_eq!(encode(&chunks), "..a\r\n..\r\nb.\r\n...\r\n");
+    }
+
     #[test]
     #[cfg(feature = "log")]
     fn test_escape_crlf() {
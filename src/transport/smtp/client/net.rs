@@ -1,11 +1,13 @@
-#[cfg(feature = "rustls-tls")]
-use std::sync::Arc;
 use std::{
+    fmt::{self, Debug},
     io::{self, Read, Write},
     mem,
     net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, SocketAddrV4, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
     time::Duration,
 };
+#[cfg(unix)]
+use std::{os::unix::net::UnixStream, path::Path};
 
 #[cfg(feature = "boring-tls")]
 use boring::ssl::SslStream;
@@ -15,14 +17,53 @@ use native_tls::TlsStream;
 use rustls::{pki_types::ServerName, ClientConnection, StreamOwned};
 use socket2::{Domain, Protocol, Type};
 
+#[cfg(feature = "compression")]
+use super::deflate::DeflateStream;
 #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
 use super::InnerTlsParameters;
-use super::TlsParameters;
+use super::{
+    proxy::{socks5_connect, ProxyConfig},
+    TlsParameters,
+};
 use crate::transport::smtp::{error, Error};
 
+/// A stream which can be read and written, implemented for any such type
+trait ReadWrite: Read + Write + Send {}
+
+impl<T: Read + Write + Send> ReadWrite for T {}
+
+/// Sink receiving a copy of the bytes sent and received, shared between connections
+#[derive(Clone)]
+pub(crate) struct ProtocolTap(Arc<Mutex<Box<dyn Write + Send>>>);
+
+impl ProtocolTap {
+    pub(crate) fn new(sink: Box<dyn Write + Send>) -> Self {
+        Self(Arc::new(Mutex::new(sink)))
+    }
+
+    /// Copies `bytes` to the sink, after `marker`
+    fn record(&self, marker: &[u8], bytes: &[u8]) {
+        // The tap is only used for debugging, its errors don't break the session
+        if let Ok(mut sink) = self.0.lock() {
+            let _ = sink
+                .write_all(marker)
+                .and_then(|()| sink.write_all(bytes))
+                .and_then(|()| sink.flush());
+        }
+    }
+}
+
+impl Debug for ProtocolTap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProtocolTap")
+    }
+}
+
 /// A network stream
 pub struct NetworkStream {
     inner: InnerNetworkStream,
+    /// Receives the plain bytes read and written, after decryption and decompression
+    tap: Option<ProtocolTap>,
 }
 
 /// Represents the different types of underlying network streams
@@ -32,6 +73,11 @@ pub struct NetworkStream {
 enum InnerNetworkStream {
     /// Plain TCP stream
     Tcp(TcpStream),
+    /// Unix domain socket
+    #[cfg(unix)]
+    Unix(UnixStream),
+    /// Stream provided by the user
+    Custom(Box<dyn ReadWrite>),
     /// Encrypted TCP stream
     #[cfg(feature = "native-tls")]
     NativeTls(TlsStream<TcpStream>),
@@ -40,6 +86,9 @@ enum InnerNetworkStream {
     RustlsTls(StreamOwned<ClientConnection, TcpStream>),
     #[cfg(feature = "boring-tls")]
     BoringTls(SslStream<TcpStream>),
+    /// Stream compressed with `COMPRESS DEFLATE`
+    #[cfg(feature = "compression")]
+    Deflate(Box<DeflateStream<NetworkStream>>),
     /// Can't be built
     None,
 }
@@ -50,19 +99,35 @@ impl NetworkStream {
             debug_assert!(false, "InnerNetworkStream::None must never be built");
         }
 
-        NetworkStream { inner }
+        NetworkStream { inner, tap: None }
+    }
+
+    /// Copies the bytes read and written to `tap`, preceded by `<< ` and `>> `
+    pub(crate) fn set_protocol_tap(&mut self, tap: Option<ProtocolTap>) {
+        self.tap = tap;
     }
 
     /// Returns peer's address
     pub fn peer_addr(&self) -> io::Result<SocketAddr> {
         match self.inner {
             InnerNetworkStream::Tcp(ref s) => s.peer_addr(),
+            #[cfg(unix)]
+            InnerNetworkStream::Unix(_) => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "unix domain sockets don't have a socket address",
+            )),
+            InnerNetworkStream::Custom(_) => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "custom streams don't have a socket address",
+            )),
             #[cfg(feature = "native-tls")]
             InnerNetworkStream::NativeTls(ref s) => s.get_ref().peer_addr(),
             #[cfg(feature = "rustls-tls")]
             InnerNetworkStream::RustlsTls(ref s) => s.get_ref().peer_addr(),
             #[cfg(feature = "boring-tls")]
             InnerNetworkStream::BoringTls(ref s) => s.get_ref().peer_addr(),
+            #[cfg(feature = "compression")]
+            InnerNetworkStream::Deflate(ref s) => s.get_ref().peer_addr(),
             InnerNetworkStream::None => {
                 debug_assert!(false, "InnerNetworkStream::None must never be built");
                 Ok(SocketAddr::V4(SocketAddrV4::new(
@@ -77,12 +142,17 @@ impl NetworkStream {
     pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
         match self.inner {
             InnerNetworkStream::Tcp(ref s) => s.shutdown(how),
+            #[cfg(unix)]
+            InnerNetworkStream::Unix(ref s) => s.shutdown(how),
+            InnerNetworkStream::Custom(_) => Ok(()),
             #[cfg(feature = "native-tls")]
             InnerNetworkStream::NativeTls(ref s) => s.get_ref().shutdown(how),
             #[cfg(feature = "rustls-tls")]
             InnerNetworkStream::RustlsTls(ref s) => s.get_ref().shutdown(how),
             #[cfg(feature = "boring-tls")]
             InnerNetworkStream::BoringTls(ref s) => s.get_ref().shutdown(how),
+            #[cfg(feature = "compression")]
+            InnerNetworkStream::Deflate(ref s) => s.get_ref().shutdown(how),
             InnerNetworkStream::None => {
                 debug_assert!(false, "InnerNetworkStream::None must never be built");
                 Ok(())
@@ -96,47 +166,49 @@ impl NetworkStream {
         tls_parameters: Option<&TlsParameters>,
         local_addr: Option<IpAddr>,
     ) -> Result<NetworkStream, Error> {
-        fn try_connect<T: ToSocketAddrs>(
-            server: T,
-            timeout: Option<Duration>,
-            local_addr: Option<IpAddr>,
-        ) -> Result<TcpStream, Error> {
-            let addrs = server
-                .to_socket_addrs()
-                .map_err(error::connection)?
-                .filter(|resolved_addr| resolved_address_filter(resolved_addr, local_addr));
-
-            let mut last_err = None;
-
-            for addr in addrs {
-                let socket = socket2::Socket::new(
-                    Domain::for_address(addr),
-                    Type::STREAM,
-                    Some(Protocol::TCP),
-                )
-                .map_err(error::connection)?;
-                bind_local_address(&socket, &addr, local_addr)?;
-
-                if let Some(timeout) = timeout {
-                    match socket.connect_timeout(&addr.into(), timeout) {
-                        Ok(_) => return Ok(socket.into()),
-                        Err(err) => last_err = Some(err),
-                    }
-                } else {
-                    match socket.connect(&addr.into()) {
-                        Ok(_) => return Ok(socket.into()),
-                        Err(err) => last_err = Some(err),
-                    }
-                }
-            }
+        let tcp_stream = try_connect(server, timeout, local_addr)?;
+        Self::from_tcp(tcp_stream, timeout, tls_parameters)
+    }
 
-            Err(match last_err {
-                Some(last_err) => error::connection(last_err),
-                None => error::connection("could not resolve to any address"),
-            })
+    /// Connects to `host:port` through `proxy`
+    ///
+    /// TLS is negotiated with the server, not with the proxy.
+    pub fn connect_through_proxy(
+        proxy: &ProxyConfig,
+        host: &str,
+        port: u16,
+        timeout: Option<Duration>,
+        tls_parameters: Option<&TlsParameters>,
+        local_addr: Option<IpAddr>,
+    ) -> Result<NetworkStream, Error> {
+        match proxy {
+            ProxyConfig::Socks5 { addr, auth } => {
+                let mut tcp_stream = try_connect(addr.as_str(), timeout, local_addr)?;
+                tcp_stream
+                    .set_read_timeout(timeout)
+                    .map_err(error::connection)?;
+                tcp_stream
+                    .set_write_timeout(timeout)
+                    .map_err(error::connection)?;
+                socks5_connect(&mut tcp_stream, host, port, auth.as_ref())?;
+                Self::from_tcp(tcp_stream, timeout, tls_parameters)
+            }
         }
+    }
 
-        let tcp_stream = try_connect(server, timeout, local_addr)?;
+    fn from_tcp(
+        tcp_stream: TcpStream,
+        timeout: Option<Duration>,
+        tls_parameters: Option<&TlsParameters>,
+    ) -> Result<NetworkStream, Error> {
+        // Also bounds the TLS handshake, which happens before
+        // the caller gets a chance to set the timeouts
+        tcp_stream
+            .set_read_timeout(timeout)
+            .map_err(error::connection)?;
+        tcp_stream
+            .set_write_timeout(timeout)
+            .map_err(error::connection)?;
         let mut stream = NetworkStream::new(InnerNetworkStream::Tcp(tcp_stream));
         if let Some(tls_parameters) = tls_parameters {
             stream.upgrade_tls(tls_parameters)?;
@@ -144,6 +216,29 @@ impl NetworkStream {
         Ok(stream)
     }
 
+    /// Connects to the unix domain socket at `path`
+    #[cfg(unix)]
+    pub fn connect_unix<P: AsRef<Path>>(
+        path: P,
+        timeout: Option<Duration>,
+    ) -> Result<NetworkStream, Error> {
+        let stream = UnixStream::connect(path).map_err(error::connection)?;
+        stream
+            .set_read_timeout(timeout)
+            .map_err(error::connection)?;
+        stream
+            .set_write_timeout(timeout)
+            .map_err(error::connection)?;
+        Ok(NetworkStream::new(InnerNetworkStream::Unix(stream)))
+    }
+
+    /// Wraps a stream already connected to the server, like an in-memory pipe
+    ///
+    /// The stream can't be upgraded to TLS and timeouts are ignored.
+    pub fn from_stream<S: Read + Write + Send + 'static>(stream: S) -> NetworkStream {
+        NetworkStream::new(InnerNetworkStream::Custom(Box::new(stream)))
+    }
+
     pub fn upgrade_tls(&mut self, tls_parameters: &TlsParameters) -> Result<(), Error> {
         match &self.inner {
             #[cfg(not(any(
@@ -168,6 +263,17 @@ impl NetworkStream {
                 self.inner = Self::upgrade_tls_impl(tcp_stream, tls_parameters)?;
                 Ok(())
             }
+            #[cfg(unix)]
+            InnerNetworkStream::Unix(_) => Err(error::client(
+                "TLS isn't supported over unix domain sockets",
+            )),
+            InnerNetworkStream::Custom(_) => {
+                Err(error::client("TLS isn't supported over custom streams"))
+            }
+            #[cfg(feature = "compression")]
+            InnerNetworkStream::Deflate(_) => Err(error::client(
+                "TLS can't be negotiated over a compressed stream",
+            )),
             _ => Ok(()),
         }
     }
@@ -189,8 +295,17 @@ impl NetworkStream {
             InnerTlsParameters::RustlsTls(connector) => {
                 let domain = ServerName::try_from(tls_parameters.domain())
                     .map_err(|_| error::connection("domain isn't a valid DNS name"))?;
-                let connection = ClientConnection::new(Arc::clone(connector), domain.to_owned())
-                    .map_err(error::connection)?;
+                let mut connection =
+                    ClientConnection::new(Arc::clone(connector), domain.to_owned())
+                        .map_err(error::connection)?;
+                let mut tcp_stream = tcp_stream;
+                // Complete the handshake now, so that its errors aren't
+                // reported by the first read or write
+                while connection.is_handshaking() {
+                    connection
+                        .complete_io(&mut tcp_stream)
+                        .map_err(error::connection)?;
+                }
                 let stream = StreamOwned::new(connection, tcp_stream);
                 InnerNetworkStream::RustlsTls(stream)
             }
@@ -207,15 +322,36 @@ impl NetworkStream {
         })
     }
 
+    /// Compresses the stream with DEFLATE in both directions, once the
+    /// server accepted the `COMPRESS DEFLATE` command
+    #[cfg(feature = "compression")]
+    pub fn compress(&mut self) -> Result<(), Error> {
+        if let InnerNetworkStream::Deflate(_) = self.inner {
+            return Err(error::client("The stream is already compressed"));
+        }
+
+        let inner = mem::replace(&mut self.inner, InnerNetworkStream::None);
+        self.inner = InnerNetworkStream::Deflate(Box::new(DeflateStream::new(NetworkStream {
+            inner,
+            tap: None,
+        })));
+        Ok(())
+    }
+
     pub fn is_encrypted(&self) -> bool {
         match self.inner {
             InnerNetworkStream::Tcp(_) => false,
+            #[cfg(unix)]
+            InnerNetworkStream::Unix(_) => false,
+            InnerNetworkStream::Custom(_) => false,
             #[cfg(feature = "native-tls")]
             InnerNetworkStream::NativeTls(_) => true,
             #[cfg(feature = "rustls-tls")]
             InnerNetworkStream::RustlsTls(_) => true,
             #[cfg(feature = "boring-tls")]
             InnerNetworkStream::BoringTls(_) => true,
+            #[cfg(feature = "compression")]
+            InnerNetworkStream::Deflate(ref s) => s.get_ref().is_encrypted(),
             InnerNetworkStream::None => {
                 debug_assert!(false, "InnerNetworkStream::None must never be built");
                 false
@@ -227,6 +363,9 @@ impl NetworkStream {
     pub fn peer_certificate(&self) -> Result<Vec<u8>, Error> {
         match &self.inner {
             InnerNetworkStream::Tcp(_) => Err(error::client("Connection is not encrypted")),
+            #[cfg(unix)]
+            InnerNetworkStream::Unix(_) => Err(error::client("Connection is not encrypted")),
+            InnerNetworkStream::Custom(_) => Err(error::client("Connection is not encrypted")),
             #[cfg(feature = "native-tls")]
             InnerNetworkStream::NativeTls(stream) => Ok(stream
                 .peer_certificate()
@@ -249,6 +388,8 @@ impl NetworkStream {
                 .unwrap()
                 .to_der()
                 .map_err(error::tls)?),
+            #[cfg(feature = "compression")]
+            InnerNetworkStream::Deflate(stream) => stream.get_ref().peer_certificate(),
             InnerNetworkStream::None => panic!("InnerNetworkStream::None must never be built"),
         }
     }
@@ -256,6 +397,9 @@ impl NetworkStream {
     pub fn set_read_timeout(&mut self, duration: Option<Duration>) -> io::Result<()> {
         match self.inner {
             InnerNetworkStream::Tcp(ref mut stream) => stream.set_read_timeout(duration),
+            #[cfg(unix)]
+            InnerNetworkStream::Unix(ref mut stream) => stream.set_read_timeout(duration),
+            InnerNetworkStream::Custom(_) => Ok(()),
             #[cfg(feature = "native-tls")]
             InnerNetworkStream::NativeTls(ref mut stream) => {
                 stream.get_ref().set_read_timeout(duration)
@@ -268,6 +412,10 @@ impl NetworkStream {
             InnerNetworkStream::BoringTls(ref mut stream) => {
                 stream.get_ref().set_read_timeout(duration)
             }
+            #[cfg(feature = "compression")]
+            InnerNetworkStream::Deflate(ref mut stream) => {
+                stream.get_mut().set_read_timeout(duration)
+            }
             InnerNetworkStream::None => {
                 debug_assert!(false, "InnerNetworkStream::None must never be built");
                 Ok(())
@@ -279,6 +427,9 @@ impl NetworkStream {
     pub fn set_write_timeout(&mut self, duration: Option<Duration>) -> io::Result<()> {
         match self.inner {
             InnerNetworkStream::Tcp(ref mut stream) => stream.set_write_timeout(duration),
+            #[cfg(unix)]
+            InnerNetworkStream::Unix(ref mut stream) => stream.set_write_timeout(duration),
+            InnerNetworkStream::Custom(_) => Ok(()),
 
             #[cfg(feature = "native-tls")]
             InnerNetworkStream::NativeTls(ref mut stream) => {
@@ -292,6 +443,10 @@ impl NetworkStream {
             InnerNetworkStream::BoringTls(ref mut stream) => {
                 stream.get_ref().set_write_timeout(duration)
             }
+            #[cfg(feature = "compression")]
+            InnerNetworkStream::Deflate(ref mut stream) => {
+                stream.get_mut().set_write_timeout(duration)
+            }
             InnerNetworkStream::None => {
                 debug_assert!(false, "InnerNetworkStream::None must never be built");
                 Ok(())
@@ -302,48 +457,71 @@ impl NetworkStream {
 
 impl Read for NetworkStream {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        match self.inner {
+        let len = match self.inner {
             InnerNetworkStream::Tcp(ref mut s) => s.read(buf),
+            #[cfg(unix)]
+            InnerNetworkStream::Unix(ref mut s) => s.read(buf),
+            InnerNetworkStream::Custom(ref mut s) => s.read(buf),
             #[cfg(feature = "native-tls")]
             InnerNetworkStream::NativeTls(ref mut s) => s.read(buf),
             #[cfg(feature = "rustls-tls")]
             InnerNetworkStream::RustlsTls(ref mut s) => s.read(buf),
             #[cfg(feature = "boring-tls")]
             InnerNetworkStream::BoringTls(ref mut s) => s.read(buf),
+            #[cfg(feature = "compression")]
+            InnerNetworkStream::Deflate(ref mut s) => s.read(buf),
             InnerNetworkStream::None => {
                 debug_assert!(false, "InnerNetworkStream::None must never be built");
                 Ok(0)
             }
+        }?;
+        if let (Some(tap), true) = (&self.tap, len > 0) {
+            tap.record(b"<< ", &buf[..len]);
         }
+        Ok(len)
     }
 }
 
 impl Write for NetworkStream {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        match self.inner {
+        let len = match self.inner {
             InnerNetworkStream::Tcp(ref mut s) => s.write(buf),
+            #[cfg(unix)]
+            InnerNetworkStream::Unix(ref mut s) => s.write(buf),
+            InnerNetworkStream::Custom(ref mut s) => s.write(buf),
             #[cfg(feature = "native-tls")]
             InnerNetworkStream::NativeTls(ref mut s) => s.write(buf),
             #[cfg(feature = "rustls-tls")]
             InnerNetworkStream::RustlsTls(ref mut s) => s.write(buf),
             #[cfg(feature = "boring-tls")]
             InnerNetworkStream::BoringTls(ref mut s) => s.write(buf),
+            #[cfg(feature = "compression")]
+            InnerNetworkStream::Deflate(ref mut s) => s.write(buf),
             InnerNetworkStream::None => {
                 debug_assert!(false, "InnerNetworkStream::None must never be built");
                 Ok(0)
             }
+        }?;
+        if let (Some(tap), true) = (&self.tap, len > 0) {
+            tap.record(b">> ", &buf[..len]);
         }
+        Ok(len)
     }
 
     fn flush(&mut self) -> io::Result<()> {
         match self.inner {
             InnerNetworkStream::Tcp(ref mut s) => s.flush(),
+            #[cfg(unix)]
+            InnerNetworkStream::Unix(ref mut s) => s.flush(),
+            InnerNetworkStream::Custom(ref mut s) => s.flush(),
             #[cfg(feature = "native-tls")]
             InnerNetworkStream::NativeTls(ref mut s) => s.flush(),
             #[cfg(feature = "rustls-tls")]
             InnerNetworkStream::RustlsTls(ref mut s) => s.flush(),
             #[cfg(feature = "boring-tls")]
             InnerNetworkStream::BoringTls(ref mut s) => s.flush(),
+            #[cfg(feature = "compression")]
+            InnerNetworkStream::Deflate(ref mut s) => s.flush(),
             InnerNetworkStream::None => {
                 debug_assert!(false, "InnerNetworkStream::None must never be built");
                 Ok(())
@@ -352,6 +530,44 @@ impl Write for NetworkStream {
     }
 }
 
+/// Connects to the first address `server` resolves to
+fn try_connect<T: ToSocketAddrs>(
+    server: T,
+    timeout: Option<Duration>,
+    local_addr: Option<IpAddr>,
+) -> Result<TcpStream, Error> {
+    let addrs = server
+        .to_socket_addrs()
+        .map_err(error::connection)?
+        .filter(|resolved_addr| resolved_address_filter(resolved_addr, local_addr));
+
+    let mut last_err = None;
+
+    for addr in addrs {
+        let socket =
+            socket2::Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))
+                .map_err(error::connection)?;
+        bind_local_address(&socket, &addr, local_addr)?;
+
+        if let Some(timeout) = timeout {
+            match socket.connect_timeout(&addr.into(), timeout) {
+                Ok(_) => return Ok(socket.into()),
+                Err(err) => last_err = Some(err),
+            }
+        } else {
+            match socket.connect(&addr.into()) {
+                Ok(_) => return Ok(socket.into()),
+                Err(err) => last_err = Some(err),
+            }
+        }
+    }
+
+    Err(match last_err {
+        Some(last_err) => error::connection(last_err),
+        None => error::connection("could not resolve to any address"),
+    })
+}
+
 /// If the local address is set, binds the socket to this address.
 /// If local address is not set, then destination address is required to determine the default
 /// local address on some platforms.
@@ -0,0 +1,142 @@
+use std::{
+    io::{Read, Write},
+    net::{IpAddr, TcpStream},
+};
+
+use crate::transport::smtp::{authentication::Credentials, error, Error};
+
+/// A proxy through which the connections to the SMTP server are established
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProxyConfig {
+    /// A SOCKS5 proxy, defined in [RFC 1928](https://tools.ietf.org/html/rfc1928)
+    ///
+    /// The name of the SMTP server is resolved by the proxy, and TLS
+    /// certificates are still verified against it.
+    Socks5 {
+        /// Address of the proxy, as `host:port`
+        addr: String,
+        /// Username and password authentication, defined in
+        /// [RFC 1929](https://tools.ietf.org/html/rfc1929)
+        auth: Option<Credentials>,
+    },
+}
+
+const SOCKS_VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const USERNAME_PASSWORD: u8 = 2;
+const NO_ACCEPTABLE_METHOD: u8 = 0xff;
+const CONNECT: u8 = 1;
+const IPV4: u8 = 1;
+const DOMAIN_NAME: u8 = 3;
+const IPV6: u8 = 4;
+
+/// Asks the SOCKS5 proxy at the other end of `stream` to connect to `host:port`
+pub(super) fn socks5_connect(
+    stream: &mut TcpStream,
+    host: &str,
+    port: u16,
+    auth: Option<&Credentials>,
+) -> Result<(), Error> {
+    // Method selection
+    let methods: &[u8] = match auth {
+        Some(_) => &[SOCKS_VERSION, 2, NO_AUTHENTICATION, USERNAME_PASSWORD],
+        None => &[SOCKS_VERSION, 1, NO_AUTHENTICATION],
+    };
+    stream.write_all(methods).map_err(error::connection)?;
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply).map_err(error::connection)?;
+    match (reply, auth) {
+        ([SOCKS_VERSION, NO_AUTHENTICATION], _) => {}
+        ([SOCKS_VERSION, USERNAME_PASSWORD], Some(credentials)) => {
+            authenticate(stream, credentials)?;
+        }
+        ([SOCKS_VERSION, NO_ACCEPTABLE_METHOD], _) => {
+            return Err(error::connection(
+                "SOCKS5 proxy accepted none of the authentication methods",
+            ));
+        }
+        _ => return Err(error::connection("invalid reply from the SOCKS5 proxy")),
+    }
+
+    // Connection to the SMTP server
+    let mut request = vec![SOCKS_VERSION, CONNECT, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            let len = u8::try_from(host.len())
+                .map_err(|_| error::connection("server name too long for SOCKS5"))?;
+            request.extend_from_slice(&[DOMAIN_NAME, len]);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).map_err(error::connection)?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply).map_err(error::connection)?;
+    if reply[0] != SOCKS_VERSION {
+        return Err(error::connection("invalid reply from the SOCKS5 proxy"));
+    }
+    if reply[1] != 0 {
+        return Err(error::connection(format!(
+            "SOCKS5 proxy couldn't connect to the server: {}",
+            reply_message(reply[1])
+        )));
+    }
+
+    // Skip the address bound by the proxy, followed by its port
+    let len = match reply[3] {
+        IPV4 => 4,
+        IPV6 => 16,
+        DOMAIN_NAME => {
+            let mut len = [0];
+            stream.read_exact(&mut len).map_err(error::connection)?;
+            usize::from(len[0])
+        }
+        _ => return Err(error::connection("invalid reply from the SOCKS5 proxy")),
+    };
+    let mut bound = vec![0; len + 2];
+    stream.read_exact(&mut bound).map_err(error::connection)?;
+    Ok(())
+}
+
+/// Username and password authentication, from RFC 1929
+fn authenticate(stream: &mut TcpStream, credentials: &Credentials) -> Result<(), Error> {
+    let mut request = vec![1];
+    for field in [credentials.username(), credentials.secret()] {
+        let len = u8::try_from(field.len())
+            .map_err(|_| error::connection("SOCKS5 credentials too long"))?;
+        request.push(len);
+        request.extend_from_slice(field.as_bytes());
+    }
+    stream.write_all(&request).map_err(error::connection)?;
+
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply).map_err(error::connection)?;
+    if reply[1] != 0 {
+        return Err(error::connection("SOCKS5 proxy authentication failed"));
+    }
+    Ok(())
+}
+
+fn reply_message(code: u8) -> &'static str {
+    match code {
+        1 => "general failure",
+        2 => "connection not allowed by ruleset",
+        3 => "network unreachable",
+        4 => "host unreachable",
+        5 => "connection refused",
+        6 => "TTL expired",
+        7 => "command not supported",
+        8 => "address type not supported",
+        _ => "unknown error",
+    }
+}
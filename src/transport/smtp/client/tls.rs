@@ -63,6 +63,15 @@ pub enum Tls {
     /// Insecure connection only (for testing purposes)
     None,
     /// Start with insecure connection and use `STARTTLS` when available
+    ///
+    /// The email is sent without encryption when the server doesn't
+    /// advertise `STARTTLS`. A failed TLS handshake is still an error.
+    ///
+    /// Once a server advertised `STARTTLS`, connecting to it without `STARTTLS`
+    /// fails with an error for which [`Error::is_tls_downgrade`] is true, as
+    /// this can be caused by an attacker stripping it from the server replies.
+    ///
+    /// [`Error::is_tls_downgrade`]: crate::transport::smtp::Error::is_tls_downgrade
     #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
     #[cfg_attr(
         docsrs,
@@ -70,6 +79,11 @@ pub enum Tls {
     )]
     Opportunistic(TlsParameters),
     /// Start with insecure connection and require `STARTTLS`
+    ///
+    /// Connecting to a server which doesn't advertise `STARTTLS` fails with
+    /// [`StarttlsStage::NotAdvertised`], before authenticating or sending anything.
+    ///
+    /// [`StarttlsStage::NotAdvertised`]: crate::transport::smtp::StarttlsStage::NotAdvertised
     #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
     #[cfg_attr(
         docsrs,
@@ -5,10 +5,10 @@ use std::fmt::{self, Display, Formatter};
 use crate::{
     address::Address,
     transport::smtp::{
-        authentication::{Credentials, Mechanism},
+        authentication::{Credentials, Mechanism, SaslSession},
         error::{self, Error},
         extension::{ClientId, MailParameter, RcptParameter},
-        response::Response,
+        response::{Code, Response},
     },
 };
 
@@ -32,6 +32,28 @@ impl Ehlo {
     }
 }
 
+/// LHLO command, replacing EHLO in LMTP
+///
+/// Defined in [RFC2033](https://tools.ietf.org/html/rfc2033#section-4.1)
+#[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Lhlo {
+    client_id: ClientId,
+}
+
+impl Display for Lhlo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "LHLO {}\r\n", self.client_id)
+    }
+}
+
+impl Lhlo {
+    /// Creates a LHLO command
+    pub fn new(client_id: ClientId) -> Lhlo {
+        Lhlo { client_id }
+    }
+}
+
 /// STARTTLS command
 #[derive(PartialEq, Eq, Clone, Debug, Copy)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -43,6 +65,17 @@ impl Display for Starttls {
     }
 }
 
+/// COMPRESS command, with the DEFLATE algorithm
+#[derive(PartialEq, Eq, Clone, Debug, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Compress;
+
+impl Display for Compress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("COMPRESS DEFLATE\r\n")
+    }
+}
+
 /// MAIL command
 #[derive(PartialEq, Eq, Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -111,6 +144,35 @@ impl Display for Data {
     }
 }
 
+/// BDAT command
+///
+/// Defined in [RFC 3030](https://tools.ietf.org/html/rfc3030), the
+/// command is directly followed by `size` bytes of message content.
+#[derive(PartialEq, Eq, Clone, Debug, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bdat {
+    size: usize,
+    last: bool,
+}
+
+impl Display for Bdat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "BDAT {}", self.size)?;
+        if self.last {
+            f.write_str(" LAST")?;
+        }
+        f.write_str("\r\n")
+    }
+}
+
+impl Bdat {
+    /// Creates a BDAT command for a chunk of `size` bytes, `last` being
+    /// whether it ends the message content
+    pub fn new(size: usize, last: bool) -> Bdat {
+        Bdat { size, last }
+    }
+}
+
 /// QUIT command
 #[derive(PartialEq, Eq, Clone, Debug, Copy)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -222,13 +284,15 @@ impl Display for Auth {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let encoded_response = self.response.as_ref().map(crate::base64::encode);
 
-        if self.mechanism.supports_initial_response() {
-            write!(f, "AUTH {} {}", self.mechanism, encoded_response.unwrap())?;
-        } else {
-            match encoded_response {
-                Some(response) => f.write_str(&response)?,
-                None => write!(f, "AUTH {}", self.mechanism)?,
+        match encoded_response {
+            // Only the first response is sent along with the command
+            Some(response)
+                if self.challenge.is_none() && self.mechanism.supports_initial_response() =>
+            {
+                write!(f, "AUTH {} {}", self.mechanism, response)?;
             }
+            Some(response) => f.write_str(&response)?,
+            None => write!(f, "AUTH {}", self.mechanism)?,
         }
         f.write_str("\r\n")
     }
@@ -261,18 +325,8 @@ impl Auth {
         credentials: Credentials,
         response: &Response,
     ) -> Result<Auth, Error> {
-        if !response.has_code(334) {
-            return Err(error::response("Expecting a challenge"));
-        }
-
-        let encoded_challenge = response
-            .first_word()
-            .ok_or_else(|| error::response("Could not read auth challenge"))?;
-        #[cfg(feature = "tracing")]
-        tracing::debug!("auth encoded challenge: {}", encoded_challenge);
-
-        let decoded_base64 = crate::base64::decode(encoded_challenge).map_err(error::response)?;
-        let decoded_challenge = String::from_utf8(decoded_base64).map_err(error::response)?;
+        let decoded_challenge =
+            String::from_utf8(decode_challenge(response)?).map_err(error::response)?;
         #[cfg(feature = "tracing")]
         tracing::debug!("auth decoded challenge: {}", decoded_challenge);
 
@@ -285,6 +339,51 @@ impl Auth {
             response,
         })
     }
+
+    /// Creates the next AUTH command of `session`, answering the challenge
+    /// in `response`, or starting the exchange without one
+    pub(crate) fn from_session(
+        session: &mut SaslSession,
+        response: Option<&Response>,
+    ) -> Result<Auth, Error> {
+        let (challenge, response) = match response {
+            Some(response) => {
+                let challenge = decode_challenge(response)?;
+                let response = session.step(&challenge)?;
+                (
+                    Some(String::from_utf8(challenge).map_err(error::response)?),
+                    Some(response),
+                )
+            }
+            None => (None, session.initial_response()?),
+        };
+        let response = response
+            .map(String::from_utf8)
+            .transpose()
+            .map_err(error::client)?;
+
+        Ok(Auth {
+            mechanism: session.mechanism(),
+            credentials: session.credentials().clone(),
+            challenge,
+            response,
+        })
+    }
+}
+
+/// Decodes the challenge of a `334` response
+fn decode_challenge(response: &Response) -> Result<Vec<u8>, Error> {
+    if response.code() != Code::AUTH_CHALLENGE {
+        return Err(error::response("Expecting a challenge"));
+    }
+
+    let encoded_challenge = response
+        .auth_challenge()
+        .ok_or_else(|| error::response("Could not read auth challenge"))?;
+    #[cfg(feature = "tracing")]
+    tracing::debug!("auth encoded challenge: {}", encoded_challenge);
+
+    crate::base64::decode(encoded_challenge).map_err(error::response)
 }
 
 #[cfg(test)]
@@ -306,7 +405,11 @@ mod test {
             keyword: "TEST".to_owned(),
             value: Some("value".to_owned()),
         };
-        assert_eq!(format!("{}", Ehlo::new(id)), "EHLO localhost\r\n");
+        assert_eq!(format!("{}", Ehlo::new(id.clone())), "EHLO localhost\r\n");
+        assert_eq!(format!("{}", Lhlo::new(id)), "LHLO localhost\r\n");
+        assert_eq!(format!("{}", Bdat::new(42, false)), "BDAT 42\r\n");
+        assert_eq!(format!("{}", Bdat::new(0, true)), "BDAT 0 LAST\r\n");
+        assert_eq!(format!("{Compress}"), "COMPRESS DEFLATE\r\n");
         assert_eq!(
             format!("{}", Mail::new(Some(email.clone()), vec![])),
             "MAIL FROM:<test@example.com>\r\n"
@@ -368,4 +471,41 @@ mod test {
             "AUTH LOGIN\r\n"
         );
     }
+
+    #[test]
+    fn test_auth_session() {
+        let credentials = Credentials::new("user".to_owned(), "pencil".to_owned());
+        let mut session = SaslSession::new(Mechanism::ScramSha256, credentials).unwrap();
+
+        let auth = Auth::from_session(&mut session, None).unwrap().to_string();
+        assert!(
+            auth.starts_with("AUTH SCRAM-SHA-256 biwsbj11c2VyLHI9"),
+            "{auth}"
+        );
+
+        // The next responses are sent alone
+        let challenge = "334 cj1mb28sYj1Ca1JBQT09LGk9NDA5Ng==\r\n"
+            .parse::<Response>()
+            .unwrap();
+        assert!(Auth::from_session(&mut session, Some(&challenge)).is_err());
+        let nonce = auth["AUTH SCRAM-SHA-256 ".len()..].trim_end();
+        let nonce = crate::base64::decode(nonce).unwrap();
+        let nonce = std::str::from_utf8(&nonce)
+            .unwrap()
+            .split("r=")
+            .nth(1)
+            .unwrap();
+        let challenge = format!(
+            "334 {}\r\n",
+            crate::base64::encode(format!("r={nonce}server,s=c2FsdA==,i=1"))
+        )
+        .parse::<Response>()
+        .unwrap();
+        let auth = Auth::from_session(&mut session, Some(&challenge))
+            .unwrap()
+            .to_string();
+        assert!(!auth.starts_with("AUTH"), "{auth}");
+        let client_final = crate::base64::decode(auth.trim_end()).unwrap();
+        assert!(client_final.starts_with(format!("c=biws,r={nonce}server,p=").as_bytes()));
+    }
 }
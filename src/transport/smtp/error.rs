@@ -3,7 +3,12 @@
 use std::{error::Error as StdError, fmt};
 
 use crate::{
-    transport::smtp::response::{Code, Severity},
+    transport::{
+        failover::FailoverError,
+        overflow::SizeLimitError,
+        retry::TransientError,
+        smtp::response::{Code, EnhancedCode, FailureKind, Response, Severity},
+    },
     BoxError,
 };
 
@@ -17,6 +22,11 @@ pub struct Error {
 struct Inner {
     kind: Kind,
     source: Option<BoxError>,
+    /// Response of the server which caused the error
+    response: Option<Response>,
+    /// Whether the error occurred while opening the connection, before
+    /// the email was sent
+    connecting: bool,
 }
 
 impl Error {
@@ -28,13 +38,26 @@ impl Error {
             inner: Box::new(Inner {
                 kind,
                 source: source.map(Into::into),
+                response: None,
+                connecting: false,
             }),
         }
     }
 
+    /// Marks the error as having occurred while opening the connection
+    ///
+    /// This covers connecting, the greeting, `EHLO`, `STARTTLS` and `AUTH`.
+    pub(crate) fn while_connecting(mut self) -> Self {
+        self.inner.connecting = true;
+        self
+    }
+
     /// Returns true if the error is from response
+    ///
+    /// This includes the replies which couldn't be parsed, and the data which
+    /// isn't an SMTP reply at all, see [`Error::is_not_an_smtp_server`].
     pub fn is_response(&self) -> bool {
-        matches!(self.inner.kind, Kind::Response)
+        matches!(self.inner.kind, Kind::Response | Kind::NotAnSmtpServer(_))
     }
 
     /// Returns true if the error is from client
@@ -45,11 +68,85 @@ impl Error {
     /// Returns true if the error is a transient SMTP error
     pub fn is_transient(&self) -> bool {
         matches!(self.inner.kind, Kind::Transient(_))
+            || self.starttls_source().is_some_and(Error::is_transient)
     }
 
     /// Returns true if the error is a permanent SMTP error
     pub fn is_permanent(&self) -> bool {
         matches!(self.inner.kind, Kind::Permanent(_))
+            || self.starttls_source().is_some_and(Error::is_permanent)
+    }
+
+    /// Returns true if the error occurred while negotiating `STARTTLS`
+    pub fn is_starttls(&self) -> bool {
+        matches!(self.inner.kind, Kind::Starttls(_))
+    }
+
+    /// Returns the stage of the `STARTTLS` negotiation which failed,
+    /// if the error occurred while negotiating `STARTTLS`
+    pub fn starttls_stage(&self) -> Option<StarttlsStage> {
+        match self.inner.kind {
+            Kind::Starttls(stage) => Some(stage),
+            _ => None,
+        }
+    }
+
+    /// Returns true if `REQUIRETLS` was requested but the server doesn't support it
+    pub fn is_require_tls_unsupported(&self) -> bool {
+        matches!(self.inner.kind, Kind::RequireTlsUnsupported)
+    }
+
+    /// Returns true if the server advertised `STARTTLS` on a previous connection,
+    /// but not anymore, which can be caused by an attacker stripping it
+    ///
+    /// Only detected with [`Tls::Opportunistic`](super::client::Tls::Opportunistic),
+    /// as the connection fails anyway with the other TLS modes.
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls")))
+    )]
+    pub fn is_tls_downgrade(&self) -> bool {
+        matches!(self.inner.kind, Kind::TlsDowngrade)
+    }
+
+    /// Returns true if the message is larger than what the server accepts
+    ///
+    /// This is either detected before sending the message, from the limit
+    /// advertised by the server with the `SIZE` extension, or from a permanent
+    /// reply of the server which [`Error::failure_kind`] classifies as
+    /// [`FailureKind::MessageTooLarge`]. A full mailbox, like with a
+    /// `552 5.2.2` reply, isn't considered too large.
+    pub fn is_message_too_large(&self) -> bool {
+        match self.inner.kind {
+            Kind::MessageTooLarge { .. } => true,
+            Kind::Permanent(_) => self.failure_kind() == Some(FailureKind::MessageTooLarge),
+            _ => false,
+        }
+    }
+
+    /// Returns the reason of the failure, if the error was generated from
+    /// a response or the message is too large
+    ///
+    /// See [`Response::failure_kind`].
+    pub fn failure_kind(&self) -> Option<FailureKind> {
+        match self.inner.kind {
+            Kind::MessageTooLarge { .. } => Some(FailureKind::MessageTooLarge),
+            _ => match self.server_response() {
+                Some(response) => response.failure_kind(),
+                None => FailureKind::classify(self.status()?, None),
+            },
+        }
+    }
+
+    /// Returns true if the peer sent data which isn't an SMTP reply,
+    /// like an HTTP response or a PROXY protocol header
+    ///
+    /// This usually means that the host or port is wrong, or that a proxy
+    /// stands in front of the server. The beginning of the received line is
+    /// included in the message of the error.
+    pub fn is_not_an_smtp_server(&self) -> bool {
+        matches!(self.inner.kind, Kind::NotAnSmtpServer(_))
     }
 
     /// Returns true if the error is caused by a timeout
@@ -81,11 +178,108 @@ impl Error {
     pub fn status(&self) -> Option<Code> {
         match self.inner.kind {
             Kind::Transient(code) | Kind::Permanent(code) => Some(code),
+            Kind::Starttls(_) => self.starttls_source().and_then(Error::status),
+            _ => None,
+        }
+    }
+
+    /// Returns the reply code, if the error was generated from a response.
+    ///
+    /// Returns `None` for errors which didn't come from the server,
+    /// like I/O errors.
+    pub fn code(&self) -> Option<u16> {
+        self.status().map(u16::from)
+    }
+
+    /// Returns the response of the server, if the error was generated from a response.
+    pub fn server_response(&self) -> Option<&Response> {
+        self.inner
+            .response
+            .as_ref()
+            .or_else(|| self.starttls_source().and_then(Error::server_response))
+    }
+
+    /// Returns a summary of the error, for logs and analytics
+    pub fn summary(&self) -> ErrorSummary {
+        ErrorSummary {
+            code: self.code(),
+            enhanced_code: self.server_response().and_then(Response::enhanced_code),
+            transient: self.is_transient(),
+            summary: self.to_string(),
+        }
+    }
+
+    /// Returns the error wrapped by a `STARTTLS` error
+    fn starttls_source(&self) -> Option<&Error> {
+        match self.inner.kind {
+            Kind::Starttls(_) => self
+                .inner
+                .source
+                .as_ref()
+                .and_then(|e| e.downcast_ref::<Error>()),
             _ => None,
         }
     }
 }
 
+/// A summary of an [`Error`], returned by [`Error::summary`]
+///
+/// With the `serde` feature, it is serialized as an object with the reply
+/// `code`, the `enhanced_code` made of its `class`, `subject` and `detail`,
+/// the `transient` flag and the `summary` text. The codes are `null` for
+/// errors which didn't come from the server:
+///
+/// ```json
+/// {"code":550,"enhanced_code":{"class":"PermanentNegativeCompletion","subject":1,"detail":1},"transient":false,"summary":"permanent error (550): 5.1.1 No such user"}
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct ErrorSummary {
+    /// Reply code of the server, see [`Error::code`]
+    pub code: Option<u16>,
+    /// Enhanced status code of the reply of the server
+    pub enhanced_code: Option<EnhancedCode>,
+    /// Whether sending the email again later could succeed, see
+    /// [`Error::is_transient`]
+    pub transient: bool,
+    /// Description of the error, as formatted by [`Display`](fmt::Display)
+    pub summary: String,
+}
+
+/// The stage of the `STARTTLS` negotiation at which an error occurred
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StarttlsStage {
+    /// The server rejected the `STARTTLS` command, for example with a `454` reply,
+    /// or answered it with a positive reply other than `220`
+    ///
+    /// The code of a negative reply is available through [`Error::status`].
+    CommandRejected,
+    /// The TLS handshake failed, for example because of an invalid certificate
+    Handshake,
+    /// The connection failed during the TLS handshake
+    Io,
+    /// Data followed the reply to `STARTTLS` before the TLS handshake, which
+    /// could have been injected by an attacker
+    UnexpectedData,
+    /// The server doesn't advertise `STARTTLS`, so the connection
+    /// can't be encrypted
+    NotAdvertised,
+}
+
+impl fmt::Display for StarttlsStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::CommandRejected => "command rejected",
+            Self::Handshake => "handshake failed",
+            Self::Io => "i/o error during handshake",
+            Self::UnexpectedData => "unexpected data before handshake",
+            Self::NotAdvertised => "not advertised by the server",
+        })
+    }
+}
+
 #[derive(Debug)]
 pub(crate) enum Kind {
     /// Transient SMTP error, 4xx reply code
@@ -111,6 +305,17 @@ pub(crate) enum Kind {
     )]
     #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
     Tls,
+    /// STARTTLS negotiation error
+    Starttls(StarttlsStage),
+    /// REQUIRETLS was requested but isn't supported by the server
+    RequireTlsUnsupported,
+    /// STARTTLS isn't advertised anymore by a server which advertised it before
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    TlsDowngrade,
+    /// The message is larger than the limit advertised by the server
+    MessageTooLarge { size: u64, limit: u64 },
+    /// A line which doesn't start with a reply code, escaped and truncated
+    NotAnSmtpServer(String),
 }
 
 impl fmt::Debug for Error {
@@ -136,6 +341,23 @@ impl fmt::Display for Error {
             Kind::Connection => f.write_str("Connection error")?,
             #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
             Kind::Tls => f.write_str("tls error")?,
+            Kind::Starttls(ref stage) => write!(f, "starttls error ({stage})")?,
+            Kind::RequireTlsUnsupported => {
+                f.write_str("REQUIRETLS was requested but the server does not support it")?;
+            }
+            #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+            Kind::TlsDowngrade => {
+                f.write_str("STARTTLS was advertised by the server before but is now missing")?;
+            }
+            Kind::MessageTooLarge { size, limit } => {
+                write!(
+                    f,
+                    "the message is {size} bytes long but the server accepts at most {limit} bytes"
+                )?;
+            }
+            Kind::NotAnSmtpServer(ref line) => {
+                write!(f, "not an SMTP server, received \"{line}\"")?;
+            }
             Kind::Transient(ref code) => {
                 write!(f, "transient error ({code})")?;
             }
@@ -161,6 +383,27 @@ impl StdError for Error {
     }
 }
 
+impl TransientError for Error {
+    fn is_transient(&self) -> bool {
+        Error::is_transient(self)
+    }
+}
+
+impl FailoverError for Error {
+    fn should_failover(&self) -> bool {
+        // A permanent reply to the email would be the same on another server,
+        // unlike a permanent reply of the server while opening the connection
+        // (e.g. a rejected greeting, `STARTTLS` command or credentials)
+        self.inner.connecting || !matches!(self.inner.kind, Kind::Permanent(_))
+    }
+}
+
+impl SizeLimitError for Error {
+    fn is_message_too_large(&self) -> bool {
+        Error::is_message_too_large(self)
+    }
+}
+
 pub(crate) fn code(c: Code, s: Option<String>) -> Error {
     match c.severity {
         Severity::TransientNegativeCompletion => Error::new(Kind::Transient(c), s),
@@ -169,6 +412,13 @@ pub(crate) fn code(c: Code, s: Option<String>) -> Error {
     }
 }
 
+/// Creates an error from a negative response of the server
+pub(crate) fn from_response(response: Response) -> Error {
+    let mut err = code(response.code(), Some(response.message().collect()));
+    err.inner.response = Some(response);
+    err
+}
+
 pub(crate) fn response<E: Into<BoxError>>(e: E) -> Error {
     Error::new(Kind::Response, Some(e))
 }
@@ -181,6 +431,37 @@ pub(crate) fn network<E: Into<BoxError>>(e: E) -> Error {
     Error::new(Kind::Network, Some(e))
 }
 
+pub(crate) fn require_tls_unsupported() -> Error {
+    Error::new(Kind::RequireTlsUnsupported, None::<BoxError>)
+}
+
+#[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+pub(crate) fn tls_downgrade() -> Error {
+    Error::new(Kind::TlsDowngrade, None::<BoxError>)
+}
+
+pub(crate) fn message_too_large(size: u64, limit: u64) -> Error {
+    Error::new(Kind::MessageTooLarge { size, limit }, None::<BoxError>)
+}
+
+/// Creates an error for a line received instead of an SMTP reply
+///
+/// Only the beginning of the line is kept, with the control characters escaped.
+pub(crate) fn not_an_smtp_server(line: &str) -> Error {
+    const MAX_CHARS: usize = 64;
+
+    let line = line.trim_end_matches(['\r', '\n']);
+    let mut escaped = line
+        .chars()
+        .take(MAX_CHARS)
+        .flat_map(char::escape_debug)
+        .collect::<String>();
+    if line.chars().nth(MAX_CHARS).is_some() {
+        escaped.push_str("...");
+    }
+    Error::new(Kind::NotAnSmtpServer(escaped), None::<BoxError>)
+}
+
 pub(crate) fn connection<E: Into<BoxError>>(e: E) -> Error {
     Error::new(Kind::Connection, Some(e))
 }
@@ -189,3 +470,278 @@ pub(crate) fn connection<E: Into<BoxError>>(e: E) -> Error {
 pub(crate) fn tls<E: Into<BoxError>>(e: E) -> Error {
     Error::new(Kind::Tls, Some(e))
 }
+
+#[cfg(any(
+    feature = "native-tls",
+    feature = "rustls-tls",
+    feature = "boring-tls",
+    feature = "tokio1",
+    feature = "async-std1"
+))]
+pub(crate) fn starttls(stage: StarttlsStage, e: Error) -> Error {
+    Error::new(Kind::Starttls(stage), Some(e))
+}
+
+pub(crate) fn starttls_not_advertised() -> Error {
+    Error::new(
+        Kind::Starttls(StarttlsStage::NotAdvertised),
+        None::<BoxError>,
+    )
+}
+
+/// Wraps an error which occurred while upgrading the connection after `STARTTLS`
+///
+/// Errors caused by the underlying connection are reported as [`StarttlsStage::Io`],
+/// anything else as [`StarttlsStage::Handshake`].
+#[cfg(any(
+    feature = "native-tls",
+    feature = "rustls-tls",
+    feature = "boring-tls",
+    feature = "tokio1",
+    feature = "async-std1"
+))]
+pub(crate) fn starttls_upgrade(e: Error) -> Error {
+    let mut source = e.source();
+    let mut stage = StarttlsStage::Handshake;
+
+    while let Some(err) = source {
+        if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
+            // TLS libraries report protocol and certificate errors as `InvalidData`
+            if io_err.kind() != std::io::ErrorKind::InvalidData {
+                stage = StarttlsStage::Io;
+            }
+            break;
+        }
+
+        source = err.source();
+    }
+
+    starttls(stage, e)
+}
+
+#[cfg(test)]
+#[cfg(any(
+    feature = "native-tls",
+    feature = "rustls-tls",
+    feature = "boring-tls",
+    feature = "tokio1",
+    feature = "async-std1"
+))]
+mod test {
+    use std::io;
+
+    use super::*;
+    use crate::transport::smtp::response::{Category, Detail};
+
+    #[test]
+    fn not_an_smtp_server_escaped() {
+        let err = not_an_smtp_server("\u{16}\u{3}\u{1}\"hello\"\r\n");
+        assert!(err.is_not_an_smtp_server());
+        assert!(err.is_response());
+        assert_eq!(
+            err.to_string(),
+            r#"not an SMTP server, received "\u{16}\u{3}\u{1}\"hello\"""#
+        );
+    }
+
+    #[test]
+    fn starttls_command_rejected() {
+        let code = Code::new(
+            Severity::TransientNegativeCompletion,
+            Category::MailSystem,
+            Detail::Four,
+        );
+        let err = starttls(
+            StarttlsStage::CommandRejected,
+            self::code(code, Some("TLS not available".to_owned())),
+        );
+
+        assert!(err.is_starttls());
+        assert_eq!(err.starttls_stage(), Some(StarttlsStage::CommandRejected));
+        assert!(err.is_transient());
+        assert!(!err.is_permanent());
+        assert_eq!(err.status(), Some(code));
+        assert_eq!(
+            err.to_string(),
+            "starttls error (command rejected): transient error (454): TLS not available"
+        );
+    }
+
+    #[test]
+    fn from_server_response() {
+        let response = Response::new(
+            Code::new(
+                Severity::TransientNegativeCompletion,
+                Category::MailSystem,
+                Detail::Zero,
+            ),
+            vec!["Mailbox busy".to_owned()],
+        );
+        let err = from_response(response.clone());
+        assert!(err.is_transient());
+        assert!(!err.is_permanent());
+        assert_eq!(err.code(), Some(450));
+        assert_eq!(err.server_response(), Some(&response));
+        assert_eq!(err.to_string(), "transient error (450): Mailbox busy");
+
+        let response = Response::new(
+            Code::new(
+                Severity::PermanentNegativeCompletion,
+                Category::MailSystem,
+                Detail::Zero,
+            ),
+            vec!["No such user".to_owned()],
+        );
+        let err = starttls(
+            StarttlsStage::CommandRejected,
+            from_response(response.clone()),
+        );
+        assert!(err.is_permanent());
+        assert_eq!(err.code(), Some(550));
+        assert_eq!(err.server_response(), Some(&response));
+
+        let err = network(io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe"));
+        assert!(!err.is_transient() && !err.is_permanent());
+        assert_eq!(err.code(), None);
+        assert_eq!(err.server_response(), None);
+    }
+
+    #[test]
+    fn failure_kind() {
+        let err = from_response("554 5.7.1 Relay access denied\r\n".parse().unwrap());
+        assert!(err.is_permanent());
+        assert_eq!(err.failure_kind(), Some(FailureKind::Policy));
+
+        let err = starttls(
+            StarttlsStage::CommandRejected,
+            from_response("421 Too many connections\r\n".parse().unwrap()),
+        );
+        assert!(err.is_transient());
+        assert_eq!(err.failure_kind(), Some(FailureKind::Connection));
+
+        let code = Code::new(
+            Severity::TransientNegativeCompletion,
+            Category::MailSystem,
+            Detail::Two,
+        );
+        let err = self::code(code, None);
+        assert_eq!(err.failure_kind(), Some(FailureKind::InsufficientStorage));
+
+        let err = message_too_large(2048, 1024);
+        assert!(!err.is_permanent());
+        assert_eq!(err.failure_kind(), Some(FailureKind::MessageTooLarge));
+
+        let err = network(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert_eq!(err.failure_kind(), None);
+    }
+
+    #[test]
+    fn is_message_too_large() {
+        assert!(message_too_large(2048, 1024).is_message_too_large());
+
+        let err = from_response("552 Message size exceeds limit\r\n".parse().unwrap());
+        assert!(err.is_message_too_large());
+
+        let err = from_response("552 5.3.4 Message too big for system\r\n".parse().unwrap());
+        assert!(err.is_message_too_large());
+
+        // Over quota, sending the email elsewhere doesn't help
+        let err = from_response("552 5.2.2 Mailbox full\r\n".parse().unwrap());
+        assert_eq!(err.failure_kind(), Some(FailureKind::InsufficientStorage));
+        assert!(!err.is_message_too_large());
+
+        let err = from_response("452 4.3.1 Insufficient system storage\r\n".parse().unwrap());
+        assert!(!err.is_message_too_large());
+    }
+
+    #[test]
+    fn should_failover() {
+        use crate::transport::failover::FailoverError;
+
+        let err = from_response("550 5.1.1 No such user\r\n".parse().unwrap());
+        assert!(!err.should_failover());
+
+        let err = from_response("421 Too many connections\r\n".parse().unwrap());
+        assert!(err.should_failover());
+
+        // Another server may support STARTTLS
+        let err = starttls(
+            StarttlsStage::CommandRejected,
+            from_response("502 Command not implemented\r\n".parse().unwrap()),
+        );
+        assert!(err.is_permanent());
+        assert!(err.should_failover());
+
+        let err = network(io::Error::from(io::ErrorKind::ConnectionRefused));
+        assert!(err.should_failover());
+
+        // Another server may accept the connection or the credentials
+        let err = from_response("554 No SMTP service here\r\n".parse().unwrap());
+        assert!(err.while_connecting().should_failover());
+
+        let err = from_response(
+            "535 5.7.8 Authentication credentials invalid\r\n"
+                .parse()
+                .unwrap(),
+        );
+        assert!(err.while_connecting().should_failover());
+    }
+
+    #[test]
+    fn starttls_upgrade_stage() {
+        let err = starttls_upgrade(connection("certificate has expired"));
+        assert_eq!(err.starttls_stage(), Some(StarttlsStage::Handshake));
+        assert!(!err.is_transient());
+        assert_eq!(err.status(), None);
+
+        let err = starttls_upgrade(connection(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid peer certificate",
+        )));
+        assert_eq!(err.starttls_stage(), Some(StarttlsStage::Handshake));
+
+        let err = starttls_upgrade(network(io::Error::from(io::ErrorKind::ConnectionReset)));
+        assert_eq!(err.starttls_stage(), Some(StarttlsStage::Io));
+
+        assert_eq!(network("other").starttls_stage(), None);
+    }
+
+    #[test]
+    fn summary() {
+        let response = "550 5.1.1 No such user\r\n".parse::<Response>().unwrap();
+        let summary = from_response(response).summary();
+        assert_eq!(summary.code, Some(550));
+        assert_eq!(
+            summary.enhanced_code,
+            Some(EnhancedCode::new(
+                Severity::PermanentNegativeCompletion,
+                1,
+                1
+            ))
+        );
+        assert!(!summary.transient);
+        assert_eq!(summary.summary, "permanent error (550): 5.1.1 No such user");
+
+        let summary = network("connection reset").summary();
+        assert_eq!(summary.code, None);
+        assert_eq!(summary.enhanced_code, None);
+        assert!(!summary.transient);
+        assert_eq!(summary.summary, "network error: connection reset");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn summary_serde() {
+        let response = "550 5.1.1 No such user\r\n".parse::<Response>().unwrap();
+        let summary = from_response(response).summary();
+        let json = r#"{"code":550,"enhanced_code":{"class":"PermanentNegativeCompletion","subject":1,"detail":1},"transient":false,"summary":"permanent error (550): 5.1.1 No such user"}"#;
+        assert_eq!(serde_json::to_string(&summary).unwrap(), json);
+        assert_eq!(serde_json::from_str::<ErrorSummary>(json).unwrap(), summary);
+
+        let json = r#"{"code":null,"enhanced_code":null,"transient":false,"summary":"network error: connection reset"}"#;
+        assert_eq!(
+            serde_json::to_string(&network("connection reset").summary()).unwrap(),
+            json
+        );
+    }
+}
@@ -1,17 +1,20 @@
 //! ESMTP features
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     fmt::{self, Display, Formatter},
     net::{Ipv4Addr, Ipv6Addr},
     result::Result,
 };
 
-use crate::transport::smtp::{
-    authentication::Mechanism,
-    error::{self, Error},
-    response::Response,
-    util::XText,
+use crate::{
+    transport::smtp::{
+        authentication::Mechanism,
+        error::{self, Error},
+        response::Response,
+        util::XText,
+    },
+    Address,
 };
 
 /// Client identifier, the parameter to `EHLO`
@@ -87,6 +90,27 @@ pub enum Extension {
     ///
     /// Defined in [RFC 2487](https://tools.ietf.org/html/rfc2487)
     StartTls,
+    /// PIPELINING keyword
+    ///
+    /// Defined in [RFC 2920](https://tools.ietf.org/html/rfc2920)
+    Pipelining,
+    /// REQUIRETLS keyword
+    ///
+    /// Defined in [RFC 8689](https://tools.ietf.org/html/rfc8689)
+    RequireTls,
+    /// CHUNKING keyword
+    ///
+    /// Defined in [RFC 3030](https://tools.ietf.org/html/rfc3030)
+    Chunking,
+    /// DSN keyword
+    ///
+    /// Defined in [RFC 3461](https://tools.ietf.org/html/rfc3461)
+    Dsn,
+    /// COMPRESS keyword with the DEFLATE algorithm
+    ///
+    /// Not standardized for SMTP, it works like the IMAP extension
+    /// defined in [RFC 4978](https://tools.ietf.org/html/rfc4978)
+    CompressDeflate,
     /// AUTH mechanism
     Authentication(Mechanism),
 }
@@ -97,11 +121,21 @@ impl Display for Extension {
             Extension::EightBitMime => f.write_str("8BITMIME"),
             Extension::SmtpUtfEight => f.write_str("SMTPUTF8"),
             Extension::StartTls => f.write_str("STARTTLS"),
+            Extension::Pipelining => f.write_str("PIPELINING"),
+            Extension::RequireTls => f.write_str("REQUIRETLS"),
+            Extension::Chunking => f.write_str("CHUNKING"),
+            Extension::Dsn => f.write_str("DSN"),
+            Extension::CompressDeflate => f.write_str("COMPRESS DEFLATE"),
             Extension::Authentication(ref mechanism) => write!(f, "AUTH {mechanism}"),
         }
     }
 }
 
+/// Name of the server when its EHLO response doesn't contain one
+const UNKNOWN_SERVER_NAME: &str = "unknown";
+/// Maximum length of the EHLO keywords, longer ones are ignored
+const MAX_KEYWORD_LEN: usize = 64;
+
 /// Contains information about an SMTP server
 #[derive(Clone, Debug, Eq, PartialEq, Default)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -114,6 +148,17 @@ pub struct ServerInfo {
     ///
     /// It contains the features supported by the server and known by the `Extension` module.
     features: HashSet<Extension>,
+    /// Maximum size of a message accepted by the server
+    ///
+    /// Given by the `SIZE` keyword, `None` if the server doesn't advertise a limit.
+    max_message_size: Option<u64>,
+    /// All the keywords of the EHLO response, in uppercase
+    keywords: Vec<String>,
+    /// The parameters following each keyword of the EHLO response
+    parameters: HashMap<String, Vec<String>>,
+    /// Authentication mechanisms supported by the server and lettre,
+    /// in the order they were advertised
+    auth_mechanisms: Vec<Mechanism>,
 }
 
 impl Display for ServerInfo {
@@ -129,21 +174,44 @@ impl Display for ServerInfo {
 
 impl ServerInfo {
     /// Parses a EHLO response to create a `ServerInfo`
+    ///
+    /// Returns an error for which [`Error::is_response`] returns `true` if the
+    /// response is empty. If the first line doesn't contain the name of the
+    /// server, it is set to `unknown`. Keywords longer than 64 characters
+    /// are ignored, and the keywords are matched ignoring their case. Unknown
+    /// keywords are only available through [`ServerInfo::supports`],
+    /// [`ServerInfo::keywords`] and [`ServerInfo::parameters`].
     pub fn from_response(response: &Response) -> Result<ServerInfo, Error> {
-        let name = match response.first_word() {
-            Some(name) => name,
-            None => return Err(error::response("Could not read server name")),
-        };
+        if response.message().all(|line| line.trim().is_empty()) {
+            return Err(error::response("Empty EHLO response"));
+        }
+        let name = response.first_word().unwrap_or(UNKNOWN_SERVER_NAME);
 
         let mut features: HashSet<Extension> = HashSet::new();
+        let mut max_message_size = None;
+        let mut keywords: Vec<String> = Vec::new();
+        let mut parameters = HashMap::new();
+        let mut auth_mechanisms = Vec::new();
 
-        for line in response.message() {
-            if line.is_empty() {
+        // The first line is the greeting of the server
+        for line in response.message().skip(1) {
+            let mut split = line.split_whitespace();
+            let Some(keyword) = split.next() else {
+                continue;
+            };
+            if keyword.len() > MAX_KEYWORD_LEN {
                 continue;
             }
+            let keyword = keyword.to_ascii_uppercase();
+            if !keywords.contains(&keyword) {
+                keywords.push(keyword.clone());
+                parameters.insert(
+                    keyword.clone(),
+                    split.clone().map(str::to_owned).collect::<Vec<_>>(),
+                );
+            }
 
-            let mut split = line.split_whitespace();
-            match split.next().unwrap() {
+            match keyword.as_str() {
                 "8BITMIME" => {
                     features.insert(Extension::EightBitMime);
                 }
@@ -153,19 +221,40 @@ impl ServerInfo {
                 "STARTTLS" => {
                     features.insert(Extension::StartTls);
                 }
+                "PIPELINING" => {
+                    features.insert(Extension::Pipelining);
+                }
+                "REQUIRETLS" => {
+                    features.insert(Extension::RequireTls);
+                }
+                "CHUNKING" => {
+                    features.insert(Extension::Chunking);
+                }
+                "DSN" => {
+                    features.insert(Extension::Dsn);
+                }
+                "COMPRESS" if split.any(|algorithm| algorithm.eq_ignore_ascii_case("DEFLATE")) => {
+                    features.insert(Extension::CompressDeflate);
+                }
+                "SIZE" => {
+                    // A limit of 0 means that the server doesn't have a fixed limit
+                    // https://tools.ietf.org/html/rfc1870#section-4
+                    max_message_size = split
+                        .next()
+                        .and_then(|size| size.parse::<u64>().ok())
+                        .filter(|&size| size > 0);
+                }
                 "AUTH" => {
                     for mechanism in split {
-                        match mechanism {
-                            "PLAIN" => {
-                                features.insert(Extension::Authentication(Mechanism::Plain));
-                            }
-                            "LOGIN" => {
-                                features.insert(Extension::Authentication(Mechanism::Login));
-                            }
-                            "XOAUTH2" => {
-                                features.insert(Extension::Authentication(Mechanism::Xoauth2));
-                            }
-                            _ => (),
+                        let mechanism = match mechanism.to_ascii_uppercase().as_str() {
+                            "PLAIN" => Mechanism::Plain,
+                            "LOGIN" => Mechanism::Login,
+                            "XOAUTH2" => Mechanism::Xoauth2,
+                            "SCRAM-SHA-256" => Mechanism::ScramSha256,
+                            _ => continue,
+                        };
+                        if features.insert(Extension::Authentication(mechanism)) {
+                            auth_mechanisms.push(mechanism);
                         }
                     }
                 }
@@ -176,6 +265,10 @@ impl ServerInfo {
         Ok(ServerInfo {
             name: name.to_owned(),
             features,
+            max_message_size,
+            keywords,
+            parameters,
+            auth_mechanisms,
         })
     }
 
@@ -204,6 +297,46 @@ impl ServerInfo {
     pub fn name(&self) -> &str {
         self.name.as_ref()
     }
+
+    /// Checks if the server advertised `keyword` in its EHLO response, ignoring the case
+    ///
+    /// Unlike [`ServerInfo::supports_feature`], this works for the keywords
+    /// unknown to lettre.
+    pub fn supports(&self, keyword: &str) -> bool {
+        self.keywords
+            .iter()
+            .any(|k| k.eq_ignore_ascii_case(keyword))
+    }
+
+    /// The keywords of the EHLO response, in uppercase and without their parameters
+    pub fn keywords(&self) -> &[String] {
+        &self.keywords
+    }
+
+    /// The parameters following `keyword` in the EHLO response, ignoring the case
+    /// of the keyword
+    ///
+    /// Returns `None` if the server didn't advertise `keyword`, and an empty
+    /// slice if it has no parameters. For example, the parameters of
+    /// `AUTH LOGIN PLAIN` are `["LOGIN", "PLAIN"]`.
+    pub fn parameters(&self, keyword: &str) -> Option<&[String]> {
+        self.parameters
+            .get(&keyword.to_ascii_uppercase())
+            .map(Vec::as_slice)
+    }
+
+    /// The authentication mechanisms supported by both the server and lettre,
+    /// in the order the server advertised them
+    pub fn auth_mechanisms(&self) -> &[Mechanism] {
+        &self.auth_mechanisms
+    }
+
+    /// The maximum size of a message accepted by the server, in bytes
+    ///
+    /// Returns `None` if the server doesn't advertise a limit with the `SIZE` extension.
+    pub fn max_message_size(&self) -> Option<u64> {
+        self.max_message_size
+    }
 }
 
 /// A `MAIL FROM` extension parameter
@@ -262,6 +395,107 @@ impl Display for MailBodyParameter {
     }
 }
 
+/// Events for which a delivery status notification is requested
+///
+/// Sent as the `NOTIFY` parameter of `RCPT TO`, defined in
+/// [RFC 3461](https://tools.ietf.org/html/rfc3461#section-4.1).
+#[derive(PartialEq, Eq, Clone, Debug, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DsnNotify {
+    /// `NEVER`, no notification is sent, overrides the other events
+    Never,
+    /// `SUCCESS`, when the message is delivered
+    Success,
+    /// `FAILURE`, when the message can't be delivered
+    Failure,
+    /// `DELAY`, when the delivery of the message is delayed
+    Delay,
+}
+
+impl Display for DsnNotify {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            DsnNotify::Never => f.write_str("NEVER"),
+            DsnNotify::Success => f.write_str("SUCCESS"),
+            DsnNotify::Failure => f.write_str("FAILURE"),
+            DsnNotify::Delay => f.write_str("DELAY"),
+        }
+    }
+}
+
+/// Part of the message returned with a failure notification
+///
+/// Sent as the `RET` parameter of `MAIL FROM`, defined in
+/// [RFC 3461](https://tools.ietf.org/html/rfc3461#section-4.3).
+#[derive(PartialEq, Eq, Clone, Debug, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DsnReturn {
+    /// `FULL`, the whole message
+    Full,
+    /// `HDRS`, only the headers of the message
+    Headers,
+}
+
+impl Display for DsnReturn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            DsnReturn::Full => f.write_str("FULL"),
+            DsnReturn::Headers => f.write_str("HDRS"),
+        }
+    }
+}
+
+/// Builds the `MAIL FROM` parameter requesting the part of the message
+/// returned with failure notifications
+pub(crate) fn dsn_mail_parameter(ret: DsnReturn) -> MailParameter {
+    MailParameter::Other {
+        keyword: "RET".to_owned(),
+        value: Some(ret.to_string()),
+    }
+}
+
+/// Builds the `MAIL FROM` parameter identifying the envelope in notifications
+pub(crate) fn dsn_envid_parameter(id: &str) -> MailParameter {
+    MailParameter::Other {
+        keyword: "ENVID".to_owned(),
+        value: Some(id.to_owned()),
+    }
+}
+
+/// Builds the `RCPT TO` parameters requesting notifications for `notify`
+/// events for `recipient`
+pub(crate) fn dsn_rcpt_parameters(notify: &[DsnNotify], recipient: &Address) -> Vec<RcptParameter> {
+    if notify.is_empty() {
+        return Vec::new();
+    }
+
+    let events = if notify.contains(&DsnNotify::Never) {
+        DsnNotify::Never.to_string()
+    } else {
+        let mut events: Vec<String> = Vec::new();
+        for event in notify {
+            let event = event.to_string();
+            if !events.contains(&event) {
+                events.push(event);
+            }
+        }
+        events.join(",")
+    };
+
+    let mut parameters = vec![RcptParameter::Other {
+        keyword: "NOTIFY".to_owned(),
+        value: Some(events),
+    }];
+    // Internationalized addresses would require the `utf-8` address type of RFC 6533
+    if recipient.is_ascii() {
+        parameters.push(RcptParameter::Other {
+            keyword: "ORCPT".to_owned(),
+            value: Some(format!("rfc822;{recipient}")),
+        });
+    }
+    parameters
+}
+
 /// A `RCPT TO` extension parameter
 #[derive(PartialEq, Eq, Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -298,7 +532,7 @@ mod test {
     use super::*;
     use crate::transport::smtp::{
         authentication::Mechanism,
-        response::{Category, Code, Detail, Response, Severity},
+        response::{parse_response, Category, Code, Detail, Response, Severity},
     };
 
     #[test]
@@ -333,6 +567,7 @@ mod test {
                 ServerInfo {
                     name: "name".to_owned(),
                     features: eightbitmime,
+                    ..ServerInfo::default()
                 }
             ),
             "name with {EightBitMime}".to_owned()
@@ -346,6 +581,7 @@ mod test {
                 ServerInfo {
                     name: "name".to_owned(),
                     features: empty,
+                    ..ServerInfo::default()
                 }
             ),
             "name with no supported features".to_owned()
@@ -360,6 +596,7 @@ mod test {
                 ServerInfo {
                     name: "name".to_owned(),
                     features: plain,
+                    ..ServerInfo::default()
                 }
             ),
             "name with {Authentication(Plain)}".to_owned()
@@ -383,6 +620,13 @@ mod test {
         let server_info = ServerInfo {
             name: "me".to_owned(),
             features,
+            max_message_size: Some(42),
+            keywords: vec!["8BITMIME".to_owned(), "SIZE".to_owned()],
+            parameters: HashMap::from([
+                ("8BITMIME".to_owned(), vec![]),
+                ("SIZE".to_owned(), vec!["42".to_owned()]),
+            ]),
+            auth_mechanisms: Vec::new(),
         };
 
         assert_eq!(ServerInfo::from_response(&response).unwrap(), server_info);
@@ -412,6 +656,19 @@ mod test {
         let server_info2 = ServerInfo {
             name: "me".to_owned(),
             features: features2,
+            max_message_size: Some(42),
+            keywords: vec!["AUTH".to_owned(), "8BITMIME".to_owned(), "SIZE".to_owned()],
+            parameters: HashMap::from([
+                (
+                    "AUTH".to_owned(),
+                    ["PLAIN", "CRAM-MD5", "XOAUTH2", "OTHER"]
+                        .map(str::to_owned)
+                        .to_vec(),
+                ),
+                ("8BITMIME".to_owned(), vec![]),
+                ("SIZE".to_owned(), vec!["42".to_owned()]),
+            ]),
+            auth_mechanisms: vec![Mechanism::Plain, Mechanism::Xoauth2],
         };
 
         assert_eq!(ServerInfo::from_response(&response2).unwrap(), server_info2);
@@ -420,4 +677,198 @@ mod test {
         assert!(server_info2.supports_auth_mechanism(Mechanism::Plain));
         assert!(!server_info2.supports_feature(Extension::StartTls));
     }
+
+    #[test]
+    fn test_serverinfo_requiretls() {
+        let response = Response::new(
+            Code::new(
+                Severity::PositiveCompletion,
+                Category::Unspecified4,
+                Detail::One,
+            ),
+            vec!["me".to_owned(), "REQUIRETLS".to_owned()],
+        );
+
+        let server_info = ServerInfo::from_response(&response).unwrap();
+        assert!(server_info.supports_feature(Extension::RequireTls));
+        assert_eq!(format!("{}", Extension::RequireTls), "REQUIRETLS");
+    }
+
+    #[test]
+    fn test_serverinfo_chunking() {
+        let response = Response::new(
+            Code::new(
+                Severity::PositiveCompletion,
+                Category::Unspecified4,
+                Detail::One,
+            ),
+            vec!["me".to_owned(), "CHUNKING".to_owned()],
+        );
+
+        let server_info = ServerInfo::from_response(&response).unwrap();
+        assert!(server_info.supports_feature(Extension::Chunking));
+        assert_eq!(format!("{}", Extension::Chunking), "CHUNKING");
+    }
+
+    #[test]
+    fn test_serverinfo_keywords() {
+        let server_info = parse_ehlo(
+            "250-me\r\n250-starttls\r\n250-AUTH XOAUTH2 LOGIN\r\n250-X-CUSTOM 1\r\n250 STARTTLS\r\n",
+        )
+        .unwrap();
+        assert_eq!(server_info.keywords(), ["STARTTLS", "AUTH", "X-CUSTOM"]);
+        assert!(server_info.supports("starttls"));
+        assert!(server_info.supports("X-Custom"));
+        assert!(!server_info.supports("PIPELINING"));
+        assert_eq!(
+            server_info.auth_mechanisms(),
+            [Mechanism::Xoauth2, Mechanism::Login]
+        );
+        assert!(server_info.supports_auth_mechanism(Mechanism::Xoauth2));
+    }
+
+    #[test]
+    fn test_serverinfo_parameters() {
+        let server_info = parse_ehlo(concat!(
+            "250-smtp.example.com Hello [192.0.2.1]\r\n",
+            "250-SIZE 35882577\r\n",
+            "250-8BITMIME\r\n",
+            "250-AUTH LOGIN PLAIN\r\n",
+            "250-Enhancedstatuscodes\r\n",
+            "250-pipelining\r\n",
+            "250-X-UNKNOWN-KEYWORD a=1 b\r\n",
+            "250-CHUNKING\r\n",
+            "250 SMTPUTF8\r\n",
+        ))
+        .unwrap();
+
+        assert_eq!(server_info.name(), "smtp.example.com");
+        assert_eq!(server_info.max_message_size(), Some(35_882_577));
+        assert_eq!(
+            server_info.parameters("SIZE"),
+            Some(&["35882577".to_owned()][..])
+        );
+        assert_eq!(
+            server_info.auth_mechanisms(),
+            [Mechanism::Login, Mechanism::Plain]
+        );
+        assert_eq!(
+            server_info.parameters("auth"),
+            Some(&["LOGIN".to_owned(), "PLAIN".to_owned()][..])
+        );
+        assert_eq!(
+            server_info.parameters("x-unknown-keyword"),
+            Some(&["a=1".to_owned(), "b".to_owned()][..])
+        );
+        assert_eq!(server_info.parameters("ENHANCEDSTATUSCODES"), Some(&[][..]));
+        assert_eq!(server_info.parameters("STARTTLS"), None);
+
+        for extension in [
+            Extension::EightBitMime,
+            Extension::Pipelining,
+            Extension::Chunking,
+            Extension::SmtpUtfEight,
+            Extension::Authentication(Mechanism::Login),
+        ] {
+            assert!(server_info.supports_feature(extension), "{extension}");
+        }
+        assert!(!server_info.supports_feature(Extension::StartTls));
+        assert!(!server_info.supports_feature(Extension::Dsn));
+    }
+
+    #[test]
+    fn test_serverinfo_compress() {
+        let server_info = parse_ehlo("250-me\r\n250 COMPRESS DEFLATE\r\n").unwrap();
+        assert!(server_info.supports_feature(Extension::CompressDeflate));
+        assert_eq!(
+            format!("{}", Extension::CompressDeflate),
+            "COMPRESS DEFLATE"
+        );
+
+        let server_info = parse_ehlo("250-me\r\n250 COMPRESS LZ4\r\n").unwrap();
+        assert!(!server_info.supports_feature(Extension::CompressDeflate));
+    }
+
+    #[test]
+    fn test_serverinfo_size() {
+        let server_info = parse_ehlo("250-me\r\n250 SIZE 1048576\r\n").unwrap();
+        assert_eq!(server_info.max_message_size(), Some(1_048_576));
+
+        // No fixed limit
+        let server_info = parse_ehlo("250-me\r\n250 SIZE 0\r\n").unwrap();
+        assert_eq!(server_info.max_message_size(), None);
+        let server_info = parse_ehlo("250-me\r\n250 SIZE\r\n").unwrap();
+        assert_eq!(server_info.max_message_size(), None);
+    }
+
+    fn parse_ehlo(response: &str) -> Result<ServerInfo, Error> {
+        let (_, response) = parse_response(response).unwrap();
+        ServerInfo::from_response(&response)
+    }
+
+    #[test]
+    fn test_serverinfo_empty() {
+        assert!(parse_ehlo("250 \r\n").unwrap_err().is_response());
+        assert!(parse_ehlo("250-  \r\n250 \r\n").unwrap_err().is_response());
+    }
+
+    #[test]
+    fn test_serverinfo_single_line() {
+        let server_info = parse_ehlo("250 mail.example.com at your service\r\n").unwrap();
+        assert_eq!(server_info.name, "mail.example.com");
+        assert!(server_info.features.is_empty());
+    }
+
+    #[test]
+    fn test_serverinfo_blank_lines() {
+        let server_info = parse_ehlo("250- \r\n250-\r\n250-   \r\n250 8BITMIME\r\n").unwrap();
+        assert_eq!(server_info.name, "unknown");
+        assert!(server_info.supports_feature(Extension::EightBitMime));
+    }
+
+    #[test]
+    fn test_serverinfo_many_keywords() {
+        let mut response = String::from("250-me\r\n");
+        for i in 0..10_000 {
+            response.push_str(&format!("250-X-KEYWORD-{i} some params\r\n"));
+        }
+        response.push_str(&format!("250-{}\r\n", "STARTTLS".repeat(10_000)));
+        response.push_str("250 AUTH PLAIN\r\n");
+
+        let server_info = parse_ehlo(&response).unwrap();
+        assert_eq!(server_info.name, "me");
+        assert_eq!(
+            server_info.features,
+            HashSet::from([Extension::Authentication(Mechanism::Plain)])
+        );
+    }
+
+    #[test]
+    fn test_dsn_parameters() {
+        use crate::transport::smtp::commands::{Mail, Rcpt};
+
+        let address: Address = "hei+dsn@domain.tld".parse().unwrap();
+        let rcpt = |notify: &[DsnNotify]| {
+            Rcpt::new(address.clone(), dsn_rcpt_parameters(notify, &address)).to_string()
+        };
+
+        assert_eq!(
+            rcpt(&[DsnNotify::Success, DsnNotify::Failure]),
+            "RCPT TO:<hei+dsn@domain.tld> NOTIFY=SUCCESS,FAILURE ORCPT=rfc822;hei+2Bdsn@domain.tld\r\n"
+        );
+        assert_eq!(
+            rcpt(&[DsnNotify::Delay, DsnNotify::Failure, DsnNotify::Delay]),
+            "RCPT TO:<hei+dsn@domain.tld> NOTIFY=DELAY,FAILURE ORCPT=rfc822;hei+2Bdsn@domain.tld\r\n"
+        );
+        assert_eq!(
+            rcpt(&[DsnNotify::Success, DsnNotify::Never]),
+            "RCPT TO:<hei+dsn@domain.tld> NOTIFY=NEVER ORCPT=rfc822;hei+2Bdsn@domain.tld\r\n"
+        );
+        assert_eq!(rcpt(&[]), "RCPT TO:<hei+dsn@domain.tld>\r\n");
+
+        assert_eq!(
+            Mail::new(None, vec![dsn_mail_parameter(DsnReturn::Headers)]).to_string(),
+            "MAIL FROM:<> RET=HDRS\r\n"
+        );
+    }
 }
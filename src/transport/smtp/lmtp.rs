@@ -0,0 +1,186 @@
+//! The LMTP transport delivers emails to a local server speaking LMTP over a
+//! unix domain socket, like the LMTP services of Dovecot or Cyrus.
+//!
+//! LMTP, defined in [RFC2033](https://tools.ietf.org/html/rfc2033), is a variant
+//! of SMTP for final delivery, in which the server replies once for each recipient
+//! after receiving the message. The transport returns these results in the order
+//! of the recipients of the envelope.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use std::error::Error;
+//! #
+//! # #[cfg(feature = "builder")]
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! use lettre::{transport::smtp::lmtp::LmtpTransport, Message, Transport};
+//!
+//! let email = Message::builder()
+//!     .from("NoBody <nobody@domain.tld>".parse()?)
+//!     .to("Hei <hei@domain.tld>".parse()?)
+//!     .to("Yuin <yuin@domain.tld>".parse()?)
+//!     .subject("Happy new year")
+//!     .body(String::from("Be happy!"))?;
+//!
+//! let mailer = LmtpTransport::new("/var/run/dovecot/lmtp");
+//! for result in mailer.send(&email)? {
+//!     if let Err(err) = result {
+//!         eprintln!("Could not deliver the email: {err}");
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "builder"))]
+//! # fn main() {}
+//! ```
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+#[cfg(feature = "builder")]
+use super::error;
+use super::{
+    client::SmtpConnection, extension::ClientId, response::Response, Error, DEFAULT_TIMEOUT,
+};
+#[cfg(feature = "builder")]
+use crate::Message;
+use crate::{address::Envelope, Transport};
+
+/// Transport delivering emails to an LMTP server over a unix domain socket
+#[derive(Debug, Clone)]
+pub struct LmtpTransport {
+    path: PathBuf,
+    hello_name: ClientId,
+    timeout: Option<Duration>,
+}
+
+impl LmtpTransport {
+    /// Creates a new transport connecting to the socket at `path`
+    ///
+    /// A new connection is opened for each email.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            hello_name: ClientId::default(),
+            timeout: Some(DEFAULT_TIMEOUT),
+        }
+    }
+
+    /// Set the name used during LHLO
+    pub fn hello_name(mut self, name: ClientId) -> Self {
+        self.hello_name = name;
+        self
+    }
+
+    /// Set the timeout duration
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Transport for LmtpTransport {
+    /// The result of the delivery to each recipient
+    type Ok = Vec<Result<Response, Error>>;
+    type Error = Error;
+
+    #[cfg(feature = "builder")]
+    fn send(&self, message: &Message) -> Result<Self::Ok, Self::Error> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!("starting to send an email");
+
+        let raw = message.try_formatted().map_err(error::client)?;
+        self.send_raw(message.envelope(), &raw)
+    }
+
+    fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        let mut conn =
+            SmtpConnection::connect_lmtp_unix(&self.path, self.timeout, &self.hello_name)?;
+        let results = conn.send_lmtp(envelope, email)?;
+        conn.quit()?;
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env::temp_dir,
+        fs,
+        io::{BufRead, BufReader, Write},
+        os::unix::net::UnixListener,
+        thread,
+    };
+
+    use super::{LmtpTransport, Transport};
+    use crate::address::Envelope;
+
+    #[test]
+    fn lmtp_per_recipient_results() {
+        let path = temp_dir().join(format!("lettre-lmtp-{}.sock", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            stream.write_all(b"220 localhost LMTP\r\n").unwrap();
+
+            let mut commands = Vec::new();
+            let mut in_data = false;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                let reply = if in_data {
+                    in_data = line != ".\r\n";
+                    if in_data {
+                        ""
+                    } else {
+                        "250 2.1.5 <hei@domain.tld> OK\r\n452 4.2.2 <yuin@domain.tld> Mailbox full\r\n"
+                    }
+                } else {
+                    match line.split_whitespace().next().unwrap_or_default() {
+                        "LHLO" => "250-localhost\r\n250 PIPELINING\r\n",
+                        "MAIL" | "RCPT" => "250 OK\r\n",
+                        "DATA" => {
+                            in_data = true;
+                            "354 Go ahead\r\n"
+                        }
+                        "QUIT" => "221 Bye\r\n",
+                        _ => "500 Unknown command\r\n",
+                    }
+                };
+                commands.push(std::mem::take(&mut line));
+                if stream.write_all(reply.as_bytes()).is_err() || reply.starts_with("221") {
+                    break;
+                }
+            }
+            commands
+        });
+
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec![
+                "hei@domain.tld".parse().unwrap(),
+                "yuin@domain.tld".parse().unwrap(),
+            ],
+        )
+        .unwrap();
+        let results = LmtpTransport::new(&path)
+            .send_raw(&envelope, b"Subject: Hi\r\n\r\nHi")
+            .unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            results[0].as_ref().unwrap().first_line(),
+            Some("2.1.5 <hei@domain.tld> OK")
+        );
+        let err = results[1].as_ref().unwrap_err();
+        assert!(err.is_transient());
+
+        let commands = server.join().unwrap();
+        assert!(commands[0].starts_with("LHLO "));
+        assert_eq!(commands.last().unwrap(), "QUIT\r\n");
+        fs::remove_file(path).unwrap();
+    }
+}
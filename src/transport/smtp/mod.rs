@@ -135,18 +135,21 @@ use client::Tls;
 #[cfg(any(feature = "tokio1", feature = "async-std1"))]
 pub use self::async_transport::{AsyncSmtpTransport, AsyncSmtpTransportBuilder};
 #[cfg(feature = "pool")]
-pub use self::pool::PoolConfig;
+pub use self::pool::{ConnectionEvent, PoolConfig};
 pub use self::{
-    error::Error,
+    error::{Error, ErrorSummary, StarttlsStage},
     transport::{SmtpTransport, SmtpTransportBuilder},
 };
 #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
 use crate::transport::smtp::client::TlsParameters;
-use crate::transport::smtp::{
-    authentication::{Credentials, Mechanism, DEFAULT_MECHANISMS},
-    client::SmtpConnection,
-    extension::ClientId,
-    response::Response,
+use crate::{
+    address::Address,
+    transport::smtp::{
+        authentication::{Credentials, Mechanism, DEFAULT_MECHANISMS},
+        client::{NetworkStream, ProtocolTap, ProxyConfig, SmtpConnection},
+        extension::{ClientId, DsnNotify, DsnReturn},
+        response::Response,
+    },
 };
 
 #[cfg(any(feature = "tokio1", feature = "async-std1"))]
@@ -157,6 +160,9 @@ pub mod commands;
 mod connection_url;
 mod error;
 pub mod extension;
+#[cfg(unix)]
+#[cfg_attr(docsrs, doc(cfg(unix)))]
+pub mod lmtp;
 #[cfg(feature = "pool")]
 mod pool;
 pub mod response;
@@ -196,6 +202,23 @@ struct SmtpInfo {
     /// Define network timeout
     /// It can be changed later for specific needs (like a different timeout for each SMTP command)
     timeout: Option<Duration>,
+    /// Whether messages must only be relayed over TLS, using `REQUIRETLS`
+    require_tls_relay: bool,
+    /// Sender used when the server rejects the one of the envelope
+    mail_from_fallback: Option<Address>,
+    /// Whether the content is sent with `BDAT` when the server supports `CHUNKING`
+    prefer_chunking: bool,
+    /// Events for which delivery status notifications are requested
+    dsn_notify: Vec<DsnNotify>,
+    /// Part of the message returned with failure notifications
+    dsn_return: Option<DsnReturn>,
+    /// Proxy through which the connections are established
+    proxy: Option<ProxyConfig>,
+    /// Whether the connections are compressed when the server supports `COMPRESS DEFLATE`
+    #[cfg(feature = "compression")]
+    compression: bool,
+    /// Receives a copy of the bytes sent and received
+    protocol_tap: Option<ProtocolTap>,
 }
 
 impl Default for SmtpInfo {
@@ -208,6 +231,38 @@ impl Default for SmtpInfo {
             authentication: DEFAULT_MECHANISMS.into(),
             timeout: Some(DEFAULT_TIMEOUT),
             tls: Tls::None,
+            require_tls_relay: false,
+            mail_from_fallback: None,
+            prefer_chunking: true,
+            dsn_notify: Vec::new(),
+            dsn_return: None,
+            proxy: None,
+            #[cfg(feature = "compression")]
+            compression: false,
+            protocol_tap: None,
         }
     }
 }
+
+/// Records whether the server advertised `STARTTLS` to a connection with
+/// [`Tls::Opportunistic`] in `starttls_seen`, shared by the clones of a transport
+///
+/// Fails if it isn't advertised anymore although it was before, as this
+/// can be caused by an attacker stripping it from the server replies.
+#[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+fn check_starttls_advertised(
+    starttls_seen: &std::sync::atomic::AtomicBool,
+    advertised: bool,
+) -> Result<(), Error> {
+    use std::sync::atomic::Ordering;
+
+    if advertised {
+        starttls_seen.store(true, Ordering::Relaxed);
+    } else if starttls_seen.load(Ordering::Relaxed) {
+        return Err(error::tls_downgrade());
+    } else {
+        #[cfg(feature = "tracing")]
+        tracing::debug!("STARTTLS isn't advertised, continuing without encryption");
+    }
+    Ok(())
+}
@@ -13,7 +13,7 @@ use futures_util::{
 
 use super::{
     super::{client::AsyncSmtpConnection, Error},
-    PoolConfig,
+    ConnectionEvent, PoolConfig,
 };
 use crate::{executor::SpawnHandle, transport::smtp::async_transport::AsyncSmtpClient, Executor};
 
@@ -27,10 +27,12 @@ pub struct Pool<E: Executor> {
 struct ParkedConnection {
     conn: AsyncSmtpConnection,
     since: Instant,
+    uses: u32,
 }
 
 pub struct PooledConnection<E: Executor> {
     conn: Option<AsyncSmtpConnection>,
+    uses: u32,
     pool: Arc<Pool<E>>,
 }
 
@@ -91,8 +93,15 @@ impl<E: Executor> Pool<E> {
                                     }
                                 };
 
-                                let mut connections = pool.connections.lock().await;
-                                connections.push(ParkedConnection::park(conn));
+                                let event = ConnectionEvent::Connected {
+                                    addr: conn.peer_addr(),
+                                    encrypted: conn.is_encrypted(),
+                                };
+                                pool.connections
+                                    .lock()
+                                    .await
+                                    .push(ParkedConnection::park(conn, 0));
+                                pool.config.emit(event);
 
                                 #[cfg(feature = "tracing")]
                                 {
@@ -109,8 +118,11 @@ impl<E: Executor> Pool<E> {
                                 #[cfg(feature = "tracing")]
                                 tracing::debug!("dropped {} idle connections", dropped.len());
 
-                                abort_concurrent(dropped.into_iter().map(|conn| conn.unpark()))
-                                    .await;
+                                let dropped = dropped.into_iter().map(|conn| {
+                                    pool.config.emit(ConnectionEvent::IdleClosed);
+                                    conn.unpark()
+                                });
+                                close_concurrent(&pool.config, dropped).await;
                             }
                         }
                         None => {
@@ -143,40 +155,63 @@ impl<E: Executor> Pool<E> {
 
             match conn {
                 Some(conn) => {
+                    let uses = conn.uses;
                     let mut conn = conn.unpark();
 
-                    // TODO: handle the client try another connection if this one isn't good
-                    if !conn.test_connected().await {
+                    // The session is reset before being reused. If the server doesn't
+                    // acknowledge it, its state is unknown and a new connection is used.
+                    if let Err(err) = conn.reset().await {
                         #[cfg(feature = "tracing")]
-                        tracing::debug!("dropping a broken connection");
+                        tracing::debug!("dropping a connection which couldn't be reset");
 
-                        conn.abort().await;
+                        self.config.emit(ConnectionEvent::Died {
+                            reason: err.to_string(),
+                        });
+                        self.config.closed(conn.close().await);
                         continue;
                     }
 
                     #[cfg(feature = "tracing")]
                     tracing::debug!("reusing a pooled connection");
 
-                    return Ok(PooledConnection::wrap(conn, Arc::clone(self)));
+                    return Ok(PooledConnection::wrap(conn, uses + 1, Arc::clone(self)));
                 }
                 None => {
                     #[cfg(feature = "tracing")]
                     tracing::debug!("creating a new connection");
 
                     let conn = self.client.connection().await?;
-                    return Ok(PooledConnection::wrap(conn, Arc::clone(self)));
+                    self.config.emit(ConnectionEvent::Connected {
+                        addr: conn.peer_addr(),
+                        encrypted: conn.is_encrypted(),
+                    });
+                    return Ok(PooledConnection::wrap(conn, 1, Arc::clone(self)));
                 }
             }
         }
     }
 
-    async fn recycle(&self, mut conn: AsyncSmtpConnection) {
+    async fn recycle(&self, mut conn: AsyncSmtpConnection, uses: u32) {
         if conn.has_broken() {
             #[cfg(feature = "tracing")]
             tracing::debug!("dropping a broken connection instead of recycling it");
 
+            self.config.emit(ConnectionEvent::Died {
+                reason: conn
+                    .failure()
+                    .unwrap_or("the connection broke while in use")
+                    .to_owned(),
+            });
+
             conn.abort().await;
             drop(conn);
+        } else if self.config.is_used_up(uses) {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("closing a connection which reached its maximum number of uses");
+
+            self.config
+                .emit(ConnectionEvent::ReuseLimitReached { uses });
+            self.config.closed(conn.close().await);
         } else {
             #[cfg(feature = "tracing")]
             tracing::debug!("recycling connection");
@@ -184,9 +219,9 @@ impl<E: Executor> Pool<E> {
             let mut connections = self.connections.lock().await;
             if connections.len() >= self.config.max_size as usize {
                 drop(connections);
-                conn.abort().await;
+                self.config.closed(conn.close().await);
             } else {
-                let conn = ParkedConnection::park(conn);
+                let conn = ParkedConnection::park(conn, uses);
                 connections.push(conn);
             }
         }
@@ -223,22 +258,24 @@ impl<E: Executor> Drop for Pool<E> {
         tracing::debug!("dropping Pool");
 
         let connections = mem::take(self.connections.get_mut());
+        let config = self.config.clone();
         let handle = self.handle.take();
         E::spawn(async move {
             if let Some(handle) = handle {
                 handle.shutdown().await;
             }
 
-            abort_concurrent(connections.into_iter().map(|conn| conn.unpark())).await;
+            close_concurrent(&config, connections.into_iter().map(|conn| conn.unpark())).await;
         });
     }
 }
 
 impl ParkedConnection {
-    fn park(conn: AsyncSmtpConnection) -> Self {
+    fn park(conn: AsyncSmtpConnection, uses: u32) -> Self {
         Self {
             conn,
             since: Instant::now(),
+            uses,
         }
     }
 
@@ -252,9 +289,10 @@ impl ParkedConnection {
 }
 
 impl<E: Executor> PooledConnection<E> {
-    fn wrap(conn: AsyncSmtpConnection, pool: Arc<Pool<E>>) -> Self {
+    fn wrap(conn: AsyncSmtpConnection, uses: u32, pool: Arc<Pool<E>>) -> Self {
         Self {
             conn: Some(conn),
+            uses,
             pool,
         }
     }
@@ -280,21 +318,22 @@ impl<E: Executor> Drop for PooledConnection<E> {
             .conn
             .take()
             .expect("AsyncSmtpConnection hasn't been taken yet");
+        let uses = self.uses;
         let pool = Arc::clone(&self.pool);
 
         E::spawn(async move {
-            pool.recycle(conn).await;
+            pool.recycle(conn, uses).await;
         });
     }
 }
 
-async fn abort_concurrent<I>(iter: I)
+async fn close_concurrent<I>(config: &PoolConfig, iter: I)
 where
     I: Iterator<Item = AsyncSmtpConnection>,
 {
     stream::iter(iter)
         .for_each_concurrent(8, |mut conn| async move {
-            conn.abort().await;
+            config.closed(conn.close().await);
         })
         .await;
 }
@@ -1,17 +1,62 @@
-use std::time::Duration;
+use std::{
+    fmt::{self, Debug},
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
 
 #[cfg(any(feature = "tokio1", feature = "async-std1"))]
 pub mod async_impl;
 pub mod sync_impl;
 
+/// Callback receiving the events of the connections of a pool
+type EventCallback = Arc<dyn Fn(ConnectionEvent) + Send + Sync>;
+
+/// Something that happened to a connection of a pool
+///
+/// See [`PoolConfig::on_connection_event`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+#[cfg_attr(docsrs, doc(cfg(feature = "pool")))]
+pub enum ConnectionEvent {
+    /// A new connection was opened, either to send an email or to be kept idle
+    Connected {
+        /// Address of the server, or of the proxy
+        ///
+        /// `None` for connections without a socket address, like Unix sockets.
+        addr: Option<SocketAddr>,
+        /// Whether the connection uses TLS
+        encrypted: bool,
+    },
+    /// A connection is closed because it was used the maximum number of times
+    /// set with [`PoolConfig::max_uses`]
+    ReuseLimitReached {
+        /// The number of emails sent over the connection
+        uses: u32,
+    },
+    /// An idle connection is closed because it stayed unused for longer than the
+    /// [`PoolConfig::idle_timeout`]
+    IdleClosed,
+    /// A connection can't be used anymore
+    Died {
+        /// Why the connection is dropped, the message of the error which broke it
+        reason: String,
+    },
+    /// A `QUIT` command was written to close a connection which was still in a
+    /// known state
+    QuitSent,
+}
+
 /// Configuration for a connection pool
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 #[allow(missing_copy_implementations)]
 #[cfg_attr(docsrs, doc(cfg(feature = "pool")))]
 pub struct PoolConfig {
     min_idle: u32,
     max_size: u32,
     idle_timeout: Duration,
+    max_uses: Option<u32>,
+    on_connection_event: Option<EventCallback>,
 }
 
 impl PoolConfig {
@@ -53,6 +98,75 @@ impl PoolConfig {
         self.idle_timeout = idle_timeout;
         self
     }
+
+    /// Maximum number of emails sent over a connection
+    ///
+    /// Connections are closed instead of being returned to the pool
+    /// once they were used this many times.
+    ///
+    /// Defaults to no limit
+    pub fn max_uses(mut self, max_uses: u32) -> Self {
+        self.max_uses = Some(max_uses);
+        self
+    }
+
+    /// Sets a callback invoked with each [`ConnectionEvent`] of the pool
+    ///
+    /// Useful to know how often connections are opened and why they are
+    /// closed, for example to tune the [`PoolConfig::max_uses`] limit.
+    /// The callback is invoked from the threads or tasks using the pool,
+    /// so it should return quickly.
+    ///
+    /// ```rust,no_run
+    /// use lettre::transport::smtp::{ConnectionEvent, PoolConfig};
+    ///
+    /// let config = PoolConfig::new()
+    ///     .max_uses(100)
+    ///     .on_connection_event(|event| {
+    ///         if let ConnectionEvent::ReuseLimitReached { uses } = event {
+    ///             println!("reconnecting after {uses} emails");
+    ///         }
+    ///     });
+    /// ```
+    pub fn on_connection_event<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(ConnectionEvent) + Send + Sync + 'static,
+    {
+        self.on_connection_event = Some(Arc::new(callback));
+        self
+    }
+
+    fn is_used_up(&self, uses: u32) -> bool {
+        self.max_uses.is_some_and(|max| uses >= max)
+    }
+
+    fn emit(&self, event: ConnectionEvent) {
+        if let Some(callback) = &self.on_connection_event {
+            callback(event);
+        }
+    }
+
+    /// Reports a closed connection, on which `QUIT` was written if `quit_sent`
+    fn closed(&self, quit_sent: bool) {
+        if quit_sent {
+            self.emit(ConnectionEvent::QuitSent);
+        }
+    }
+}
+
+impl Debug for PoolConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoolConfig")
+            .field("min_idle", &self.min_idle)
+            .field("max_size", &self.max_size)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("max_uses", &self.max_uses)
+            .field(
+                "on_connection_event",
+                &self.on_connection_event.as_ref().map(|_| "Fn"),
+            )
+            .finish()
+    }
 }
 
 impl Default for PoolConfig {
@@ -61,6 +175,8 @@ impl Default for PoolConfig {
             min_idle: 0,
             max_size: 10,
             idle_timeout: Duration::from_secs(60),
+            max_uses: None,
+            on_connection_event: None,
         }
     }
 }
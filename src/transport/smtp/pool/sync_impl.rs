@@ -9,7 +9,7 @@ use std::{
 
 use super::{
     super::{client::SmtpConnection, Error},
-    PoolConfig,
+    ConnectionEvent, PoolConfig,
 };
 use crate::transport::smtp::transport::SmtpClient;
 
@@ -22,10 +22,12 @@ pub struct Pool {
 struct ParkedConnection {
     conn: SmtpConnection,
     since: Instant,
+    uses: u32,
 }
 
 pub struct PooledConnection {
     conn: Option<SmtpConnection>,
+    uses: u32,
     pool: Arc<Pool>,
 }
 
@@ -85,8 +87,15 @@ impl Pool {
                                 }
                             };
 
-                            let mut connections = pool.connections.lock().unwrap();
-                            connections.push(ParkedConnection::park(conn));
+                            let event = ConnectionEvent::Connected {
+                                addr: conn.peer_addr(),
+                                encrypted: conn.is_encrypted(),
+                            };
+                            pool.connections
+                                .lock()
+                                .unwrap()
+                                .push(ParkedConnection::park(conn, 0));
+                            pool.config.emit(event);
 
                             #[cfg(feature = "tracing")]
                             {
@@ -105,7 +114,8 @@ impl Pool {
 
                             for conn in dropped {
                                 let mut conn = conn.unpark();
-                                conn.abort();
+                                pool.config.emit(ConnectionEvent::IdleClosed);
+                                pool.config.closed(conn.close());
                             }
                         }
 
@@ -127,40 +137,63 @@ impl Pool {
 
             match conn {
                 Some(conn) => {
+                    let uses = conn.uses;
                     let mut conn = conn.unpark();
 
-                    // TODO: handle the client try another connection if this one isn't good
-                    if !conn.test_connected() {
+                    // The session is reset before being reused. If the server doesn't
+                    // acknowledge it, its state is unknown and a new connection is used.
+                    if let Err(err) = conn.reset() {
                         #[cfg(feature = "tracing")]
-                        tracing::debug!("dropping a broken connection");
+                        tracing::debug!("dropping a connection which couldn't be reset");
 
-                        conn.abort();
+                        self.config.emit(ConnectionEvent::Died {
+                            reason: err.to_string(),
+                        });
+                        self.config.closed(conn.close());
                         continue;
                     }
 
                     #[cfg(feature = "tracing")]
                     tracing::debug!("reusing a pooled connection");
 
-                    return Ok(PooledConnection::wrap(conn, Arc::clone(self)));
+                    return Ok(PooledConnection::wrap(conn, uses + 1, Arc::clone(self)));
                 }
                 None => {
                     #[cfg(feature = "tracing")]
                     tracing::debug!("creating a new connection");
 
                     let conn = self.client.connection()?;
-                    return Ok(PooledConnection::wrap(conn, Arc::clone(self)));
+                    self.config.emit(ConnectionEvent::Connected {
+                        addr: conn.peer_addr(),
+                        encrypted: conn.is_encrypted(),
+                    });
+                    return Ok(PooledConnection::wrap(conn, 1, Arc::clone(self)));
                 }
             }
         }
     }
 
-    fn recycle(&self, mut conn: SmtpConnection) {
+    fn recycle(&self, mut conn: SmtpConnection, uses: u32) {
         if conn.has_broken() {
             #[cfg(feature = "tracing")]
             tracing::debug!("dropping a broken connection instead of recycling it");
 
+            self.config.emit(ConnectionEvent::Died {
+                reason: conn
+                    .failure()
+                    .unwrap_or("the connection broke while in use")
+                    .to_owned(),
+            });
+
             conn.abort();
             drop(conn);
+        } else if self.config.is_used_up(uses) {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("closing a connection which reached its maximum number of uses");
+
+            self.config
+                .emit(ConnectionEvent::ReuseLimitReached { uses });
+            self.config.closed(conn.close());
         } else {
             #[cfg(feature = "tracing")]
             tracing::debug!("recycling connection");
@@ -168,9 +201,9 @@ impl Pool {
             let mut connections = self.connections.lock().unwrap();
             if connections.len() >= self.config.max_size as usize {
                 drop(connections);
-                conn.abort();
+                self.config.closed(conn.close());
             } else {
-                let conn = ParkedConnection::park(conn);
+                let conn = ParkedConnection::park(conn, uses);
                 connections.push(conn);
             }
         }
@@ -203,16 +236,17 @@ impl Drop for Pool {
         let connections = mem::take(&mut *self.connections.get_mut().unwrap());
         for conn in connections {
             let mut conn = conn.unpark();
-            conn.abort();
+            self.config.closed(conn.close());
         }
     }
 }
 
 impl ParkedConnection {
-    fn park(conn: SmtpConnection) -> Self {
+    fn park(conn: SmtpConnection, uses: u32) -> Self {
         Self {
             conn,
             since: Instant::now(),
+            uses,
         }
     }
 
@@ -226,9 +260,10 @@ impl ParkedConnection {
 }
 
 impl PooledConnection {
-    fn wrap(conn: SmtpConnection, pool: Arc<Pool>) -> Self {
+    fn wrap(conn: SmtpConnection, uses: u32, pool: Arc<Pool>) -> Self {
         Self {
             conn: Some(conn),
+            uses,
             pool,
         }
     }
@@ -254,6 +289,162 @@ impl Drop for PooledConnection {
             .conn
             .take()
             .expect("SmtpConnection hasn't been taken yet");
-        self.pool.recycle(conn);
+        self.pool.recycle(conn, self.uses);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        address::Envelope,
+        transport::smtp::{
+            client::{serve, serve_sessions, MockStep, Sessions},
+            ConnectionEvent, PoolConfig, SmtpTransport,
+        },
+        Transport,
+    };
+
+    /// Starts a server accepting any number of connections
+    fn mock_server() -> (u16, Sessions) {
+        let (addr, sessions) = serve_sessions(|_| "250 localhost\r\n".to_owned(), "");
+        (addr.port(), sessions)
+    }
+
+    /// Returns the number of emails received over each connection
+    fn emails(sessions: &Sessions) -> Vec<usize> {
+        let sessions = sessions.lock().unwrap();
+        sessions
+            .iter()
+            .map(|lines| lines.iter().filter(|line| *line == ".\r\n").count())
+            .collect()
+    }
+
+    #[test]
+    fn max_uses() {
+        let (port, sessions) = mock_server();
+        let mailer = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(port)
+            .pool_config(PoolConfig::new().max_uses(2))
+            .build();
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+
+        for _ in 0..5 {
+            mailer
+                .send_raw(&envelope, b"Subject: Hi\r\n\r\nHi")
+                .unwrap();
+        }
+
+        let emails = emails(&sessions);
+        assert_eq!(emails.iter().sum::<usize>(), 5);
+        assert!(emails.iter().all(|&count| count <= 2));
+        assert!(emails.iter().filter(|&&count| count > 0).count() >= 3);
+    }
+
+    #[test]
+    fn connection_events() {
+        let (port, _sessions) = mock_server();
+        let addr = Some(SocketAddr::from(([127, 0, 0, 1], port)));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let events_ = Arc::clone(&events);
+        let mailer = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(port)
+            .pool_config(
+                PoolConfig::new()
+                    .max_uses(2)
+                    .on_connection_event(move |event| events_.lock().unwrap().push(event)),
+            )
+            .build();
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+
+        // Wait for the idle connection opened by the pool
+        let start = Instant::now();
+        while events.lock().unwrap().is_empty() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        for _ in 0..3 {
+            mailer
+                .send_raw(&envelope, b"Subject: Hi\r\n\r\nHi")
+                .unwrap();
+        }
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                ConnectionEvent::Connected {
+                    addr,
+                    encrypted: false
+                },
+                ConnectionEvent::ReuseLimitReached { uses: 2 },
+                ConnectionEvent::QuitSent,
+                ConnectionEvent::Connected {
+                    addr,
+                    encrypted: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn connection_died() {
+        let (addr, server) = serve(vec![
+            MockStep::send("220 localhost ESMTP\r\n"),
+            MockStep::ExpectLine("EHLO ".to_owned()),
+            MockStep::send("250 localhost\r\n"),
+            MockStep::expect("RSET\r\n"),
+            MockStep::send("421 Shutting down\r\n"),
+            MockStep::CloseConnection,
+        ]);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let events_ = Arc::clone(&events);
+        let mailer = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(addr.port())
+            .pool_config(
+                PoolConfig::new()
+                    .on_connection_event(move |event| events_.lock().unwrap().push(event)),
+            )
+            .build();
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+
+        // Wait for the idle connection opened by the pool
+        let start = Instant::now();
+        while events.lock().unwrap().is_empty() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // The server only accepts the idle connection, which can't be reset
+        mailer
+            .send_raw(&envelope, b"Subject: Hi\r\n\r\nHi")
+            .unwrap_err();
+        server.join().unwrap();
+
+        let events = events.lock().unwrap();
+        let ConnectionEvent::Died { reason } = &events[1] else {
+            panic!("unexpected events {events:?}");
+        };
+        assert!(reason.contains("Shutting down"), "{reason}");
     }
 }
@@ -2,16 +2,16 @@
 //! message
 
 use std::{
+    cmp::Ordering,
     fmt::{Display, Formatter, Result},
     result,
     str::FromStr,
-    string::ToString,
 };
 
 use nom::{
     branch::alt,
     bytes::streaming::{tag, take_until},
-    combinator::{complete, map},
+    combinator::{complete, map, opt},
     multi::many0,
     sequence::{preceded, tuple},
     IResult,
@@ -20,7 +20,7 @@ use nom::{
 use crate::transport::smtp::{error, Error};
 
 /// The first digit indicates severity
-#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Severity {
     /// 2yx
@@ -33,6 +33,18 @@ pub enum Severity {
     PermanentNegativeCompletion = 5,
 }
 
+impl Severity {
+    /// The name of the severity in snake case, like `positive_completion`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::PositiveCompletion => "positive_completion",
+            Severity::PositiveIntermediate => "positive_intermediate",
+            Severity::TransientNegativeCompletion => "transient_negative_completion",
+            Severity::PermanentNegativeCompletion => "permanent_negative_completion",
+        }
+    }
+}
+
 impl Display for Severity {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         write!(f, "{}", *self as u8)
@@ -40,7 +52,7 @@ impl Display for Severity {
 }
 
 /// Second digit
-#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Category {
     /// x0z
@@ -64,7 +76,7 @@ impl Display for Category {
 }
 
 /// The detail digit of a response code (third digit)
-#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Detail {
     #[allow(missing_docs)]
@@ -96,7 +108,24 @@ impl Display for Detail {
 }
 
 /// Represents a 3 digit SMTP response code
-#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+///
+/// Codes are ordered by their numeric value, and can be compared with `u16`s:
+///
+/// ```rust
+/// use lettre::transport::smtp::response::{Category, Code, Detail, Severity};
+///
+/// let code = Code::new(
+///     Severity::TransientNegativeCompletion,
+///     Category::MailSystem,
+///     Detail::Two,
+/// );
+/// assert!(code >= 400 && code < 500);
+/// assert_eq!(u16::from(code), 452);
+/// assert_eq!(code, Code::INSUFFICIENT_STORAGE);
+/// ```
+///
+/// The codes defined by RFC5321 and RFC4954 are available as constants, like [`Code::OK`].
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Code {
     /// First digit of the response code
@@ -114,8 +143,165 @@ impl Display for Code {
 }
 
 impl Code {
+    /// `211`, system status or help reply
+    pub const SYSTEM_STATUS: Code = Code::new(
+        Severity::PositiveCompletion,
+        Category::Information,
+        Detail::One,
+    );
+    /// `214`, help message
+    pub const HELP_MESSAGE: Code = Code::new(
+        Severity::PositiveCompletion,
+        Category::Information,
+        Detail::Four,
+    );
+    /// `220`, service ready, sent as greeting
+    pub const SERVICE_READY: Code = Code::new(
+        Severity::PositiveCompletion,
+        Category::Connections,
+        Detail::Zero,
+    );
+    /// `221`, service closing the transmission channel
+    pub const SERVICE_CLOSING: Code = Code::new(
+        Severity::PositiveCompletion,
+        Category::Connections,
+        Detail::One,
+    );
+    /// `235`, authentication succeeded
+    pub const AUTHENTICATION_SUCCEEDED: Code = Code::new(
+        Severity::PositiveCompletion,
+        Category::Unspecified3,
+        Detail::Five,
+    );
+    /// `250`, requested mail action okay, completed
+    pub const OK: Code = Code::new(
+        Severity::PositiveCompletion,
+        Category::MailSystem,
+        Detail::Zero,
+    );
+    /// `251`, user not local, the message will be forwarded
+    pub const USER_NOT_LOCAL_WILL_FORWARD: Code = Code::new(
+        Severity::PositiveCompletion,
+        Category::MailSystem,
+        Detail::One,
+    );
+    /// `252`, cannot verify the user, but the message will be accepted
+    pub const CANNOT_VERIFY_USER: Code = Code::new(
+        Severity::PositiveCompletion,
+        Category::MailSystem,
+        Detail::Two,
+    );
+    /// `334`, authentication challenge
+    pub const AUTH_CHALLENGE: Code = Code::new(
+        Severity::PositiveIntermediate,
+        Category::Unspecified3,
+        Detail::Four,
+    );
+    /// `354`, start mail input
+    pub const START_MAIL_INPUT: Code = Code::new(
+        Severity::PositiveIntermediate,
+        Category::MailSystem,
+        Detail::Four,
+    );
+    /// `421`, service not available, closing the transmission channel
+    pub const SERVICE_NOT_AVAILABLE: Code = Code::new(
+        Severity::TransientNegativeCompletion,
+        Category::Connections,
+        Detail::One,
+    );
+    /// `450`, mailbox unavailable, like busy
+    pub const MAILBOX_BUSY: Code = Code::new(
+        Severity::TransientNegativeCompletion,
+        Category::MailSystem,
+        Detail::Zero,
+    );
+    /// `451`, local error in processing
+    pub const LOCAL_ERROR: Code = Code::new(
+        Severity::TransientNegativeCompletion,
+        Category::MailSystem,
+        Detail::One,
+    );
+    /// `452`, insufficient system storage
+    pub const INSUFFICIENT_STORAGE: Code = Code::new(
+        Severity::TransientNegativeCompletion,
+        Category::MailSystem,
+        Detail::Two,
+    );
+    /// `455`, server unable to accommodate parameters
+    pub const PARAMETERS_NOT_ACCOMMODATED: Code = Code::new(
+        Severity::TransientNegativeCompletion,
+        Category::MailSystem,
+        Detail::Five,
+    );
+    /// `500`, syntax error, command unrecognized
+    pub const SYNTAX_ERROR: Code = Code::new(
+        Severity::PermanentNegativeCompletion,
+        Category::Syntax,
+        Detail::Zero,
+    );
+    /// `501`, syntax error in parameters or arguments
+    pub const PARAMETER_SYNTAX_ERROR: Code = Code::new(
+        Severity::PermanentNegativeCompletion,
+        Category::Syntax,
+        Detail::One,
+    );
+    /// `502`, command not implemented
+    pub const COMMAND_NOT_IMPLEMENTED: Code = Code::new(
+        Severity::PermanentNegativeCompletion,
+        Category::Syntax,
+        Detail::Two,
+    );
+    /// `503`, bad sequence of commands
+    pub const BAD_SEQUENCE: Code = Code::new(
+        Severity::PermanentNegativeCompletion,
+        Category::Syntax,
+        Detail::Three,
+    );
+    /// `504`, command parameter not implemented
+    pub const PARAMETER_NOT_IMPLEMENTED: Code = Code::new(
+        Severity::PermanentNegativeCompletion,
+        Category::Syntax,
+        Detail::Four,
+    );
+    /// `550`, mailbox unavailable, like not found
+    pub const MAILBOX_UNAVAILABLE: Code = Code::new(
+        Severity::PermanentNegativeCompletion,
+        Category::MailSystem,
+        Detail::Zero,
+    );
+    /// `551`, user not local
+    pub const USER_NOT_LOCAL: Code = Code::new(
+        Severity::PermanentNegativeCompletion,
+        Category::MailSystem,
+        Detail::One,
+    );
+    /// `552`, exceeded storage allocation
+    pub const EXCEEDED_STORAGE: Code = Code::new(
+        Severity::PermanentNegativeCompletion,
+        Category::MailSystem,
+        Detail::Two,
+    );
+    /// `553`, mailbox name not allowed
+    pub const MAILBOX_NAME_NOT_ALLOWED: Code = Code::new(
+        Severity::PermanentNegativeCompletion,
+        Category::MailSystem,
+        Detail::Three,
+    );
+    /// `554`, transaction failed
+    pub const TRANSACTION_FAILED: Code = Code::new(
+        Severity::PermanentNegativeCompletion,
+        Category::MailSystem,
+        Detail::Four,
+    );
+    /// `555`, `MAIL FROM` or `RCPT TO` parameters not recognized
+    pub const PARAMETERS_NOT_RECOGNIZED: Code = Code::new(
+        Severity::PermanentNegativeCompletion,
+        Category::MailSystem,
+        Detail::Five,
+    );
+
     /// Creates a new `Code` structure
-    pub fn new(severity: Severity, category: Category, detail: Detail) -> Code {
+    pub const fn new(severity: Severity, category: Category, detail: Detail) -> Code {
         Code {
             severity,
             category,
@@ -130,12 +316,262 @@ impl Code {
             Severity::PositiveCompletion | Severity::PositiveIntermediate
         )
     }
+
+    /// Tells if the response is a transient failure (`4yz`), which may
+    /// succeed if the command is retried later
+    pub fn is_transient(self) -> bool {
+        self.severity == Severity::TransientNegativeCompletion
+    }
+
+    /// Tells if the response is a permanent failure (`5yz`), which won't
+    /// succeed if the command is retried as is
+    pub fn is_permanent(self) -> bool {
+        self.severity == Severity::PermanentNegativeCompletion
+    }
+
+    /// First digit of the response code
+    pub fn severity(self) -> Severity {
+        self.severity
+    }
+
+    /// Second digit of the response code
+    pub fn category(self) -> Category {
+        self.category
+    }
+
+    /// Third digit of the response code
+    pub fn detail(self) -> Detail {
+        self.detail
+    }
+}
+
+impl From<Code> for u16 {
+    fn from(code: Code) -> Self {
+        code.severity as u16 * 100 + code.category as u16 * 10 + code.detail as u16
+    }
+}
+
+impl PartialEq<u16> for Code {
+    fn eq(&self, other: &u16) -> bool {
+        u16::from(*self) == *other
+    }
+}
+
+impl PartialOrd<u16> for Code {
+    fn partial_cmp(&self, other: &u16) -> Option<Ordering> {
+        u16::from(*self).partial_cmp(other)
+    }
+}
+
+/// The reason of a failure, derived from a negative response
+///
+/// The enhanced status code is used when the server sends one, otherwise
+/// the basic reply code. Whether the failure is worth retrying is told by
+/// [`Response::is_transient`] and [`Response::is_permanent`].
+///
+/// ```rust
+/// use lettre::transport::smtp::response::{FailureKind, Response};
+///
+/// let response: Response = "550 5.7.1 Blocked\r\n".parse().unwrap();
+/// assert!(response.is_permanent());
+/// assert_eq!(response.failure_kind(), Some(FailureKind::Policy));
+/// ```
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum FailureKind {
+    /// The server can't handle the connection, like with a `421` reply,
+    /// or the message can't be routed
+    Connection,
+    /// The mailbox doesn't exist or can't receive emails, like with a
+    /// `450` or `550` reply
+    MailboxUnavailable,
+    /// The mailbox or the mail system is full, like with a `452` reply
+    InsufficientStorage,
+    /// The message is larger than what the server accepts, like with a `552` reply
+    MessageTooLarge,
+    /// The message, the sender or the client was rejected by a security or
+    /// policy decision, like with a `554` reply
+    Policy,
+    /// Any other failure
+    Other,
+}
+
+impl FailureKind {
+    /// Classifies a negative reply, returning `None` for positive ones
+    pub(crate) fn classify(code: Code, enhanced: Option<EnhancedCode>) -> Option<FailureKind> {
+        if code.is_positive() {
+            return None;
+        }
+
+        let from_enhanced =
+            enhanced.and_then(
+                |enhanced| match (enhanced.subject_kind()?, enhanced.detail) {
+                    (EnhancedSubject::Policy, _) => Some(FailureKind::Policy),
+                    (EnhancedSubject::Mailbox, 3) | (EnhancedSubject::MailSystem, 4) => {
+                        Some(FailureKind::MessageTooLarge)
+                    }
+                    (EnhancedSubject::Mailbox, 2) | (EnhancedSubject::MailSystem, 1) => {
+                        Some(FailureKind::InsufficientStorage)
+                    }
+                    (EnhancedSubject::Addressing | EnhancedSubject::Mailbox, _) => {
+                        Some(FailureKind::MailboxUnavailable)
+                    }
+                    (EnhancedSubject::Routing, _) => Some(FailureKind::Connection),
+                    _ => None,
+                },
+            );
+
+        Some(from_enhanced.unwrap_or(match u16::from(code) {
+            421 => FailureKind::Connection,
+            450 | 550 | 551 | 553 => FailureKind::MailboxUnavailable,
+            452 => FailureKind::InsufficientStorage,
+            552 => FailureKind::MessageTooLarge,
+            554 => FailureKind::Policy,
+            _ => FailureKind::Other,
+        }))
+    }
+}
+
+/// The subject of an enhanced status code (its second number)
+///
+/// Defined in [RFC3463](https://tools.ietf.org/html/rfc3463#section-3)
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum EnhancedSubject {
+    /// x.0.x, other or undefined status
+    Other,
+    /// x.1.x, addressing status
+    Addressing,
+    /// x.2.x, mailbox status
+    Mailbox,
+    /// x.3.x, mail system status
+    MailSystem,
+    /// x.4.x, network and routing status
+    Routing,
+    /// x.5.x, mail delivery protocol status
+    Protocol,
+    /// x.6.x, message content or media status
+    Content,
+    /// x.7.x, security or policy status
+    Policy,
+}
+
+/// Represents an enhanced status code, like `5.7.1`
+///
+/// Defined in [RFC3463](https://tools.ietf.org/html/rfc3463). The class is
+/// the severity of the reply, which can't be `PositiveIntermediate`.
+///
+/// ```rust
+/// use lettre::transport::smtp::response::{EnhancedSubject, Response};
+///
+/// let response: Response = "550 5.7.1 Blocked by policy\r\n".parse().unwrap();
+/// let code = response.enhanced_code().unwrap();
+/// assert_eq!(code.to_string(), "5.7.1");
+/// assert_eq!(code.subject_kind(), Some(EnhancedSubject::Policy));
+/// assert!(code.is_policy_failure());
+/// assert_eq!(response.text().next(), Some("Blocked by policy"));
+/// ```
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EnhancedCode {
+    /// First number of the code
+    pub class: Severity,
+    /// Second number of the code
+    pub subject: u16,
+    /// Third number of the code
+    pub detail: u16,
+}
+
+impl Display for EnhancedCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}.{}.{}", self.class, self.subject, self.detail)
+    }
+}
+
+impl EnhancedCode {
+    /// Creates a new `EnhancedCode` structure
+    pub fn new(class: Severity, subject: u16, detail: u16) -> EnhancedCode {
+        EnhancedCode {
+            class,
+            subject,
+            detail,
+        }
+    }
+
+    /// Parses a code like `2.1.5`, each number after the class having 1 to 3 digits
+    fn parse(s: &str) -> Option<EnhancedCode> {
+        let (class, subject, detail) = crate::enhanced_code::parse(s)?;
+        let class = match class {
+            2 => Severity::PositiveCompletion,
+            4 => Severity::TransientNegativeCompletion,
+            _ => Severity::PermanentNegativeCompletion,
+        };
+        Some(EnhancedCode::new(class, subject, detail))
+    }
+
+    /// Tells if the code reports a success
+    pub fn is_success(self) -> bool {
+        self.class == Severity::PositiveCompletion
+    }
+
+    /// Tells if the code reports a failure, either transient or permanent
+    pub fn is_failure(self) -> bool {
+        !self.is_success()
+    }
+
+    /// Tells if the code reports a permanent failure, which won't succeed if retried
+    pub fn is_permanent_failure(self) -> bool {
+        self.class == Severity::PermanentNegativeCompletion
+    }
+
+    /// The subject of the code, or `None` if it isn't defined by RFC3463
+    pub fn subject_kind(self) -> Option<EnhancedSubject> {
+        Some(match self.subject {
+            0 => EnhancedSubject::Other,
+            1 => EnhancedSubject::Addressing,
+            2 => EnhancedSubject::Mailbox,
+            3 => EnhancedSubject::MailSystem,
+            4 => EnhancedSubject::Routing,
+            5 => EnhancedSubject::Protocol,
+            6 => EnhancedSubject::Content,
+            7 => EnhancedSubject::Policy,
+            _ => return None,
+        })
+    }
+
+    /// Tells if the code reports a failure caused by an address, like an
+    /// unknown recipient (`5.1.1`)
+    pub fn is_address_failure(self) -> bool {
+        self.is_failure() && self.subject_kind() == Some(EnhancedSubject::Addressing)
+    }
+
+    /// Tells if the code reports a failure caused by the mailbox, like a
+    /// full (`4.2.2`) or disabled (`5.2.1`) one
+    pub fn is_mailbox_failure(self) -> bool {
+        self.is_failure() && self.subject_kind() == Some(EnhancedSubject::Mailbox)
+    }
+
+    /// Tells if the code reports a failure caused by a security or policy
+    /// decision, like a blocked sender (`5.7.1`) or failed authentication (`5.7.8`)
+    pub fn is_policy_failure(self) -> bool {
+        self.is_failure() && self.subject_kind() == Some(EnhancedSubject::Policy)
+    }
 }
 
 /// Contains an SMTP reply, with separated code and message
 ///
 /// The text message is optional, only the code is mandatory
-#[derive(PartialEq, Eq, Clone, Debug)]
+///
+/// With the `serde` feature, it is serialized as an object with a `code`,
+/// itself made of the names of its `severity`, `category` and `detail`,
+/// and the lines of the `message`:
+///
+/// ```json
+/// {"code":{"severity":"PositiveCompletion","category":"MailSystem","detail":"Zero"},"message":["OK"]}
+/// ```
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Response {
     /// Response code
@@ -143,22 +579,57 @@ pub struct Response {
     /// Server response string (optional)
     /// Handle multiline responses
     message: Vec<String>,
+    /// Identifier of the envelope of the sent email
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    envelope_id: Option<String>,
+}
+
+impl Display for Response {
+    /// Formats the response as sent by the server, each line ending with CRLF
+    ///
+    /// A response without text is formatted as its code alone, like `250\r\n`.
+    /// The result can be parsed back with [`FromStr`].
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let Some((last, lines)) = self.message.split_last() else {
+            return write!(f, "{}\r\n", self.code);
+        };
+        for line in lines {
+            write!(f, "{}-{line}\r\n", self.code)?;
+        }
+        write!(f, "{} {last}\r\n", self.code)
+    }
 }
 
 impl FromStr for Response {
     type Err = Error;
 
+    /// Parses a complete response, made of one or more lines ending with CRLF
+    ///
+    /// All the lines must have the same code, and nothing may follow the last line.
     fn from_str(s: &str) -> result::Result<Response, Error> {
-        parse_response(s)
-            .map(|(_, r)| r)
-            .map_err(|e| error::response(e.to_owned()))
+        match parse_response(s) {
+            Ok(("", response)) => Ok(response),
+            Ok(_) => Err(error::response("unexpected data after the response")),
+            Err(nom::Err::Incomplete(_)) => Err(error::response("incomplete response")),
+            Err(nom::Err::Failure(_)) => Err(error::response(
+                "the lines of the response have different codes",
+            )),
+            Err(nom::Err::Error(_)) => Err(error::response("malformed response")),
+        }
     }
 }
 
 impl Response {
     /// Creates a new `Response`
     pub fn new(code: Code, message: Vec<String>) -> Response {
-        Response { code, message }
+        Response {
+            code,
+            message,
+            envelope_id: None,
+        }
     }
 
     /// Tells if the response is positive
@@ -166,9 +637,30 @@ impl Response {
         self.code.is_positive()
     }
 
+    /// Tells if the response is a transient failure (`4yz`)
+    pub fn is_transient(&self) -> bool {
+        self.code.is_transient()
+    }
+
+    /// Tells if the response is a permanent failure (`5yz`)
+    pub fn is_permanent(&self) -> bool {
+        self.code.is_permanent()
+    }
+
+    /// Returns the reason of the failure, or `None` if the response is positive
+    pub fn failure_kind(&self) -> Option<FailureKind> {
+        FailureKind::classify(self.code, self.enhanced_code())
+    }
+
+    /// Tells if the response is a `252`, which is positive but means that the
+    /// server could not verify the user and will attempt the delivery anyway
+    pub fn is_provisional_positive(&self) -> bool {
+        self.code == Code::CANNOT_VERIFY_USER
+    }
+
     /// Tests code equality
     pub fn has_code(&self, code: u16) -> bool {
-        self.code.to_string() == code.to_string()
+        self.code == code
     }
 
     /// Returns only the first word of the message if possible
@@ -178,6 +670,24 @@ impl Response {
             .and_then(|line| line.split_whitespace().next())
     }
 
+    /// Returns the base64 encoded challenge of an `AUTH` reply if possible
+    ///
+    /// ASCII whitespace around the challenge is ignored. Returns `None` if the first
+    /// line of the message is empty or contains characters which are not part
+    /// of the base64 alphabet.
+    pub fn auth_challenge(&self) -> Option<&str> {
+        let challenge = self
+            .first_line()?
+            .trim_matches(|c: char| c.is_ascii_whitespace());
+
+        let is_base64 = |b: u8| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/' | b'=');
+        if challenge.is_empty() || !challenge.bytes().all(is_base64) {
+            return None;
+        }
+
+        Some(challenge)
+    }
+
     /// Returns only the line of the message if possible
     pub fn first_line(&self) -> Option<&str> {
         self.message.first().map(String::as_str)
@@ -192,6 +702,57 @@ impl Response {
     pub fn message(&self) -> impl Iterator<Item = &str> {
         self.message.iter().map(String::as_str)
     }
+
+    /// Identifier of the envelope of the email this response accepts
+    ///
+    /// Set on the response to an email whose envelope has an
+    /// [identifier](crate::address::Envelope::with_envelope_id), so that
+    /// the response can be matched with the request which sent the email.
+    pub fn envelope_id(&self) -> Option<&str> {
+        self.envelope_id.as_deref()
+    }
+
+    pub(crate) fn with_envelope_id(mut self, envelope_id: Option<&str>) -> Self {
+        self.envelope_id = envelope_id.map(ToOwned::to_owned);
+        self
+    }
+
+    /// Returns the enhanced status code starting the message, like `2.1.5`, if any
+    ///
+    /// Defined in [RFC3463](https://tools.ietf.org/html/rfc3463)
+    pub fn enhanced_code(&self) -> Option<EnhancedCode> {
+        self.first_word().and_then(EnhancedCode::parse)
+    }
+
+    /// Server response string (array of lines), without the enhanced status code
+    ///
+    /// Servers usually repeat the enhanced status code at the start of each line,
+    /// it is removed from every line starting with the one of the first line.
+    pub fn text(&self) -> impl Iterator<Item = &str> {
+        let prefix = self.enhanced_code().and(self.first_word());
+        self.message.iter().map(move |line| {
+            prefix
+                .and_then(|prefix| line.strip_prefix(prefix))
+                .filter(|rest| rest.is_empty() || rest.starts_with(' '))
+                .map_or(line.as_str(), str::trim_start)
+        })
+    }
+
+    /// Returns the fields describing the response in structured logs
+    ///
+    /// These are the `code`, the `severity`, the `enhanced_code` when present,
+    /// and the `text` of the message, its lines being separated by `\n`.
+    pub fn log_fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("code", self.code.to_string()),
+            ("severity", self.code.severity.as_str().to_owned()),
+        ];
+        if let Some(enhanced_code) = self.enhanced_code() {
+            fields.push(("enhanced_code", enhanced_code.to_string()));
+        }
+        fields.push(("text", self.message.join("\n")));
+        fields
+    }
 }
 
 // Parsers (originally from tokio-smtp)
@@ -245,14 +806,32 @@ fn parse_detail(i: &str) -> IResult<&str, Detail> {
     ))(i)
 }
 
+/// Checks that a line received from the server starts with a valid reply code
+///
+/// A line without a code means that the peer isn't an SMTP server, while an
+/// invalid code, like `999`, is reported with the code.
+pub(crate) fn check_reply_line(line: &str) -> result::Result<(), Error> {
+    let Some(code) = line
+        .get(..3)
+        .filter(|c| c.bytes().all(|b| b.is_ascii_digit()))
+    else {
+        return Err(error::not_an_smtp_server(line));
+    };
+    match parse_code(code) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(error::response(format!("invalid reply code {code}"))),
+    }
+}
+
 pub(crate) fn parse_response(i: &str) -> IResult<&str, Response> {
     let (i, lines) = many0(tuple((
         parse_code,
         preceded(tag("-"), take_until("\r\n")),
         tag("\r\n"),
     )))(i)?;
+    // The text of the last line is optional
     let (i, (last_code, last_line)) =
-        tuple((parse_code, preceded(tag(" "), take_until("\r\n"))))(i)?;
+        tuple((parse_code, opt(preceded(tag(" "), take_until("\r\n")))))(i)?;
     let (i, _) = complete(tag("\r\n"))(i)?;
 
     // Check that all codes are equal.
@@ -265,13 +844,14 @@ pub(crate) fn parse_response(i: &str) -> IResult<&str, Response> {
 
     // Extract text from lines, and append last line.
     let mut lines: Vec<String> = lines.into_iter().map(|(_, text, _)| text.into()).collect();
-    lines.push(last_line.into());
+    lines.extend(last_line.map(Into::into));
 
     Ok((
         i,
         Response {
             code: last_code,
             message: lines,
+            envelope_id: None,
         },
     ))
 }
@@ -317,6 +897,52 @@ mod test {
         assert_eq!(code.to_string(), "421");
     }
 
+    #[test]
+    fn test_code_accessors() {
+        let code = Code::new(
+            Severity::PermanentNegativeCompletion,
+            Category::MailSystem,
+            Detail::Four,
+        );
+
+        assert_eq!(code.severity(), Severity::PermanentNegativeCompletion);
+        assert_eq!(code.category(), Category::MailSystem);
+        assert_eq!(code.detail(), Detail::Four);
+        assert_eq!(u16::from(code), 554);
+    }
+
+    #[test]
+    fn test_code_ordering() {
+        let ok = Code::new(
+            Severity::PositiveCompletion,
+            Category::MailSystem,
+            Detail::Zero,
+        );
+        let busy = Code::new(
+            Severity::TransientNegativeCompletion,
+            Category::Connections,
+            Detail::One,
+        );
+        let full = Code::new(
+            Severity::TransientNegativeCompletion,
+            Category::MailSystem,
+            Detail::Two,
+        );
+        let rejected = Code::new(
+            Severity::PermanentNegativeCompletion,
+            Category::Syntax,
+            Detail::Zero,
+        );
+
+        assert!(ok < busy && busy < full && full < rejected);
+        assert_eq!(rejected.max(ok), rejected);
+        assert!(ok == 250 && ok != 251);
+        assert!(busy >= 400);
+        assert!(busy < 500);
+        assert!(full > 421 && full <= 452);
+        assert!(rejected > 499);
+    }
+
     #[test]
     fn test_response_from_str() {
         let raw_response = "250-me\r\n250-8BITMIME\r\n250-SIZE 42\r\n250 AUTH PLAIN CRAM-MD5\r\n";
@@ -334,6 +960,7 @@ mod test {
                     "SIZE 42".to_owned(),
                     "AUTH PLAIN CRAM-MD5".to_owned(),
                 ],
+                envelope_id: None,
             }
         );
 
@@ -344,6 +971,61 @@ mod test {
         assert!(wrong_end.parse::<Response>().is_err());
     }
 
+    #[test]
+    fn test_response_from_str_errors() {
+        let error = |raw: &str| raw.parse::<Response>().unwrap_err().to_string();
+
+        assert_eq!(
+            error("250-me\r\n251 OK\r\n"),
+            "response error: the lines of the response have different codes"
+        );
+        assert_eq!(
+            error("250-me\r\n250 OK\r\n250 OK\r\n"),
+            "response error: unexpected data after the response"
+        );
+        assert_eq!(
+            error("250-me\r\n250 OK"),
+            "response error: incomplete response"
+        );
+        assert_eq!(error("250=me\r\n"), "response error: malformed response");
+        assert_eq!(error("hello\r\n"), "response error: malformed response");
+    }
+
+    #[test]
+    fn test_response_from_str_without_text() {
+        let response = "250\r\n".parse::<Response>().unwrap();
+        assert!(response.has_code(250));
+        assert_eq!(response.first_line(), None);
+
+        let response = "250-\r\n250 OK\r\n".parse::<Response>().unwrap();
+        assert_eq!(response.message().collect::<Vec<_>>(), ["", "OK"]);
+    }
+
+    #[test]
+    fn test_response_display() {
+        for raw in [
+            "250-me\r\n250-8BITMIME\r\n250 AUTH PLAIN\r\n",
+            "250-\r\n250 OK\r\n",
+            "354 Go ahead\r\n",
+            "250 \r\n",
+            "250\r\n",
+        ] {
+            let response = raw.parse::<Response>().unwrap();
+            assert_eq!(response.to_string(), raw);
+            assert_eq!(response.to_string().parse::<Response>().unwrap(), response);
+        }
+
+        let response = Response::new(
+            Code::new(
+                Severity::PositiveCompletion,
+                Category::MailSystem,
+                Detail::Zero,
+            ),
+            vec!["first".to_owned(), "last".to_owned()],
+        );
+        assert_eq!(response.to_string(), "250-first\r\n250 last\r\n");
+    }
+
     #[test]
     fn test_response_is_positive() {
         assert!(Response::new(
@@ -366,6 +1048,38 @@ mod test {
         .is_positive());
     }
 
+    #[test]
+    fn test_response_is_provisional_positive() {
+        let response = "252 2.1.5 Cannot VRFY user, but will accept message\r\n"
+            .parse::<Response>()
+            .unwrap();
+        assert!(response.is_positive());
+        assert!(response.is_provisional_positive());
+
+        let response = "250 2.1.5 Ok\r\n".parse::<Response>().unwrap();
+        assert!(response.is_positive());
+        assert!(!response.is_provisional_positive());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_response_serde() {
+        let response = "250-me\r\n250 2.1.5 OK\r\n".parse::<Response>().unwrap();
+        let json = r#"{"code":{"severity":"PositiveCompletion","category":"MailSystem","detail":"Zero"},"message":["me","2.1.5 OK"]}"#;
+
+        assert_eq!(serde_json::to_string(&response).unwrap(), json);
+        assert_eq!(serde_json::from_str::<Response>(json).unwrap(), response);
+    }
+
+    #[test]
+    fn test_response_hash() {
+        let mut responses = std::collections::HashSet::new();
+        responses.insert("250 OK\r\n".parse::<Response>().unwrap());
+        responses.insert("250 OK\r\n".parse::<Response>().unwrap());
+        responses.insert("451 Try again\r\n".parse::<Response>().unwrap());
+        assert_eq!(responses.len(), 2);
+    }
+
     #[test]
     fn test_response_has_code() {
         assert!(Response::new(
@@ -468,6 +1182,38 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_response_first_word_whitespace() {
+        let response: Response = "250  \tmx.example.org\r ESMTP\r\n".parse().unwrap();
+        assert_eq!(response.first_word(), Some("mx.example.org"));
+    }
+
+    #[test]
+    fn test_response_auth_challenge() {
+        let response: Response = "334 VXNlcm5hbWU6\r\n".parse().unwrap();
+        assert_eq!(response.auth_challenge(), Some("VXNlcm5hbWU6"));
+
+        // Indented challenge
+        let response: Response = "334  UGFzc3dvcmQ6\r\n".parse().unwrap();
+        assert_eq!(response.auth_challenge(), Some("UGFzc3dvcmQ6"));
+
+        // Trailing CR remnant
+        let response: Response =
+            "334 PDQxOTI5NDIzNDEuMTI4Mjg0NzJAc291cmNlZm91ci5hbmRyZXcuY211LmVkdT4=\r\r\n"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            response.auth_challenge(),
+            Some("PDQxOTI5NDIzNDEuMTI4Mjg0NzJAc291cmNlZm91ci5hbmRyZXcuY211LmVkdT4=")
+        );
+
+        let response: Response = "334 \r\n".parse().unwrap();
+        assert_eq!(response.auth_challenge(), None);
+
+        let response: Response = "334 not base64!\r\n".parse().unwrap();
+        assert_eq!(response.auth_challenge(), None);
+    }
+
     #[test]
     fn test_response_incomplete() {
         let raw_response = "250-smtp.example.org\r\n";
@@ -478,6 +1224,290 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_response_enhanced_code() {
+        let enhanced_code = |raw: &str| {
+            raw.parse::<Response>()
+                .unwrap()
+                .enhanced_code()
+                .map(|code| code.to_string())
+        };
+
+        assert_eq!(enhanced_code("250 2.1.5 Ok\r\n").as_deref(), Some("2.1.5"));
+        assert_eq!(
+            enhanced_code("550 5.7.100 Denied\r\n").as_deref(),
+            Some("5.7.100")
+        );
+        assert_eq!(enhanced_code("250 OK\r\n"), None);
+        assert_eq!(enhanced_code("250 3.1.5 Ok\r\n"), None);
+        assert_eq!(enhanced_code("250 2.1 Ok\r\n"), None);
+        assert_eq!(enhanced_code("250 2.1.5.1 Ok\r\n"), None);
+        assert_eq!(enhanced_code("250 2.1.1000 Ok\r\n"), None);
+        assert_eq!(enhanced_code("250 2..5 Ok\r\n"), None);
+        assert_eq!(enhanced_code("250 2.1.x Ok\r\n"), None);
+        assert_eq!(enhanced_code("250\r\n"), None);
+    }
+
+    #[test]
+    fn test_response_enhanced_code_real_world() {
+        let response: Response = "250 2.1.5 Recipient OK\r\n".parse().unwrap();
+        let code = response.enhanced_code().unwrap();
+        assert_eq!(code, EnhancedCode::new(Severity::PositiveCompletion, 1, 5));
+        assert!(code.is_success());
+        assert!(!code.is_failure());
+        assert!(!code.is_address_failure());
+        assert_eq!(response.text().collect::<Vec<_>>(), ["Recipient OK"]);
+
+        // Gmail repeats the code on each line
+        let response: Response = "550-5.7.1 [192.0.2.1] Our system has detected that this message is\r\n\
+                                  550-5.7.1 likely unsolicited mail.\r\n\
+                                  550 5.7.1  https://support.google.com/mail/?p=UnsolicitedMessageError\r\n"
+            .parse()
+            .unwrap();
+        let code = response.enhanced_code().unwrap();
+        assert_eq!(code.to_string(), "5.7.1");
+        assert_eq!(code.subject_kind(), Some(EnhancedSubject::Policy));
+        assert!(code.is_permanent_failure());
+        assert!(code.is_policy_failure());
+        assert!(!code.is_mailbox_failure());
+        assert_eq!(
+            response.text().collect::<Vec<_>>(),
+            [
+                "[192.0.2.1] Our system has detected that this message is",
+                "likely unsolicited mail.",
+                "https://support.google.com/mail/?p=UnsolicitedMessageError",
+            ]
+        );
+
+        let response: Response = "550 5.1.1 <nobody@example.com>: Recipient address rejected: User unknown in virtual mailbox table\r\n"
+            .parse()
+            .unwrap();
+        let code = response.enhanced_code().unwrap();
+        assert!(code.is_address_failure());
+        assert!(!code.is_policy_failure());
+
+        let response: Response = "452 4.2.2 Mailbox full\r\n".parse().unwrap();
+        let code = response.enhanced_code().unwrap();
+        assert!(code.is_mailbox_failure());
+        assert!(!code.is_permanent_failure());
+
+        let response: Response = "421 4.4.2 mx.example.com Error: timeout exceeded\r\n"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            response.enhanced_code().unwrap().subject_kind(),
+            Some(EnhancedSubject::Routing)
+        );
+
+        let response: Response = "554 5.9.1 Something new\r\n".parse().unwrap();
+        assert_eq!(response.enhanced_code().unwrap().subject_kind(), None);
+
+        // Replies without an enhanced status code are left untouched
+        let response: Response = "220 mx.example.com ESMTP Postfix\r\n".parse().unwrap();
+        assert_eq!(response.enhanced_code(), None);
+        assert_eq!(
+            response.text().collect::<Vec<_>>(),
+            ["mx.example.com ESMTP Postfix"]
+        );
+
+        let response: Response = "354 End data with <CR><LF>.<CR><LF>\r\n".parse().unwrap();
+        assert_eq!(response.enhanced_code(), None);
+        assert_eq!(
+            response.text().collect::<Vec<_>>(),
+            ["End data with <CR><LF>.<CR><LF>"]
+        );
+
+        // Only the code of the first line is removed, and only as a whole word
+        let response: Response = "250-2.0.0 Ok\r\n250-2.0.0\r\n250 2.0.00 queued\r\n"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            response.text().collect::<Vec<_>>(),
+            ["Ok", "", "2.0.00 queued"]
+        );
+    }
+
+    #[test]
+    fn test_code_constants() {
+        let constants = [
+            (Code::SERVICE_READY, 220),
+            (Code::SERVICE_CLOSING, 221),
+            (Code::AUTHENTICATION_SUCCEEDED, 235),
+            (Code::OK, 250),
+            (Code::CANNOT_VERIFY_USER, 252),
+            (Code::AUTH_CHALLENGE, 334),
+            (Code::START_MAIL_INPUT, 354),
+            (Code::SERVICE_NOT_AVAILABLE, 421),
+            (Code::INSUFFICIENT_STORAGE, 452),
+            (Code::PARAMETERS_NOT_ACCOMMODATED, 455),
+            (Code::BAD_SEQUENCE, 503),
+            (Code::MAILBOX_UNAVAILABLE, 550),
+            (Code::TRANSACTION_FAILED, 554),
+            (Code::PARAMETERS_NOT_RECOGNIZED, 555),
+        ];
+        for (code, value) in constants {
+            assert_eq!(u16::from(code), value);
+            assert_eq!(code.to_string(), value.to_string());
+            let response: Response = format!("{value} Text\r\n").parse().unwrap();
+            assert_eq!(response.code(), code);
+            assert!(response.has_code(value));
+        }
+        assert!(Code::OK.is_positive());
+        assert!(Code::SERVICE_NOT_AVAILABLE.is_transient());
+        assert!(Code::MAILBOX_UNAVAILABLE.is_permanent());
+    }
+
+    #[test]
+    fn test_code_high_detail() {
+        for (raw, severity, category, detail) in [
+            (
+                "256",
+                Severity::PositiveCompletion,
+                Category::MailSystem,
+                Detail::Six,
+            ),
+            (
+                "557",
+                Severity::PermanentNegativeCompletion,
+                Category::MailSystem,
+                Detail::Seven,
+            ),
+            (
+                "458",
+                Severity::TransientNegativeCompletion,
+                Category::MailSystem,
+                Detail::Eight,
+            ),
+            (
+                "539",
+                Severity::PermanentNegativeCompletion,
+                Category::Unspecified3,
+                Detail::Nine,
+            ),
+        ] {
+            let response: Response = format!("{raw} Text\r\n").parse().unwrap();
+            let code = response.code();
+            assert_eq!(code, Code::new(severity, category, detail));
+            assert_eq!(code.to_string(), raw);
+            assert_eq!(u16::from(code), raw.parse::<u16>().unwrap());
+            assert!(response.has_code(raw.parse().unwrap()));
+            assert_eq!(response.to_string(), format!("{raw} Text\r\n"));
+        }
+        assert!(
+            Code::new(
+                Severity::PositiveCompletion,
+                Category::MailSystem,
+                Detail::Nine
+            ) > 258
+        );
+    }
+
+    #[test]
+    fn test_response_failure_kind() {
+        let classify = |raw: &str| {
+            let response: Response = raw.parse().unwrap();
+            (
+                response.is_transient(),
+                response.is_permanent(),
+                response.failure_kind(),
+            )
+        };
+
+        assert_eq!(
+            classify("421 mx.example.com Service not available\r\n"),
+            (true, false, Some(FailureKind::Connection))
+        );
+        assert_eq!(
+            classify("450 Mailbox unavailable\r\n"),
+            (true, false, Some(FailureKind::MailboxUnavailable))
+        );
+        assert_eq!(
+            classify("452 Insufficient system storage\r\n"),
+            (true, false, Some(FailureKind::InsufficientStorage))
+        );
+        assert_eq!(
+            classify("550 No such user here\r\n"),
+            (false, true, Some(FailureKind::MailboxUnavailable))
+        );
+        assert_eq!(
+            classify("552 Message size exceeds fixed maximum message size\r\n"),
+            (false, true, Some(FailureKind::MessageTooLarge))
+        );
+        assert_eq!(
+            classify("554 Transaction failed\r\n"),
+            (false, true, Some(FailureKind::Policy))
+        );
+        assert_eq!(
+            classify("500 Syntax error\r\n"),
+            (false, true, Some(FailureKind::Other))
+        );
+        assert_eq!(classify("250 OK\r\n"), (false, false, None));
+        assert_eq!(classify("354 Go ahead\r\n"), (false, false, None));
+
+        // The enhanced status code is more precise than the reply code
+        assert_eq!(
+            classify(
+                "550 5.7.1 Service unavailable; client host blocked using zen.spamhaus.org\r\n"
+            ),
+            (false, true, Some(FailureKind::Policy))
+        );
+        assert_eq!(
+            classify("552 5.2.2 The email account that you tried to reach is over quota\r\n"),
+            (false, true, Some(FailureKind::InsufficientStorage))
+        );
+        assert_eq!(
+            classify("452 4.2.2 Mailbox full\r\n"),
+            (true, false, Some(FailureKind::InsufficientStorage))
+        );
+        assert_eq!(
+            classify("552 5.3.4 Message size exceeds fixed limit\r\n"),
+            (false, true, Some(FailureKind::MessageTooLarge))
+        );
+        assert_eq!(
+            classify("554 5.1.1 <nobody@example.com>: Recipient address rejected\r\n"),
+            (false, true, Some(FailureKind::MailboxUnavailable))
+        );
+        assert_eq!(
+            classify("451 4.4.1 No answer from host\r\n"),
+            (true, false, Some(FailureKind::Connection))
+        );
+        // Subjects which don't refine the reply code
+        assert_eq!(
+            classify("452 4.5.3 Too many recipients\r\n"),
+            (true, false, Some(FailureKind::InsufficientStorage))
+        );
+        assert_eq!(
+            classify("421 4.3.2 System not accepting network messages\r\n"),
+            (true, false, Some(FailureKind::Connection))
+        );
+    }
+
+    #[test]
+    fn test_response_log_fields() {
+        let response = "250-2.1.5 Ok\r\n250 queued as 42\r\n"
+            .parse::<Response>()
+            .unwrap();
+        assert_eq!(
+            response.log_fields(),
+            [
+                ("code", "250".to_owned()),
+                ("severity", "positive_completion".to_owned()),
+                ("enhanced_code", "2.1.5".to_owned()),
+                ("text", "2.1.5 Ok\nqueued as 42".to_owned()),
+            ]
+        );
+
+        let response = "421 Busy\r\n".parse::<Response>().unwrap();
+        assert_eq!(
+            response.log_fields(),
+            [
+                ("code", "421".to_owned()),
+                ("severity", "transient_negative_completion".to_owned()),
+                ("text", "Busy".to_owned()),
+            ]
+        );
+    }
+
     #[test]
     fn test_response_first_line() {
         assert_eq!(
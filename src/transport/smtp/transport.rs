@@ -1,15 +1,38 @@
-#[cfg(feature = "pool")]
+#[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+use std::sync::atomic::AtomicBool;
+#[cfg(any(
+    feature = "pool",
+    feature = "native-tls",
+    feature = "rustls-tls",
+    feature = "boring-tls"
+))]
 use std::sync::Arc;
-use std::{fmt::Debug, time::Duration};
-
+use std::{
+    fmt::Debug,
+    io::{Read, Write},
+    time::Duration,
+};
+
+#[cfg(feature = "compression")]
+use super::extension::Extension;
 #[cfg(feature = "pool")]
 use super::pool::sync_impl::Pool;
 #[cfg(feature = "pool")]
 use super::PoolConfig;
-use super::{ClientId, Credentials, Error, Mechanism, Response, SmtpConnection, SmtpInfo};
 #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
-use super::{Tls, TlsParameters, SUBMISSIONS_PORT, SUBMISSION_PORT};
-use crate::{address::Envelope, Transport};
+use super::{check_starttls_advertised, Tls, TlsParameters, SUBMISSIONS_PORT, SUBMISSION_PORT};
+use super::{
+    error,
+    extension::{DsnNotify, DsnReturn},
+    ClientId, Credentials, Error, Mechanism, NetworkStream, ProtocolTap, ProxyConfig, Response,
+    SmtpConnection, SmtpInfo,
+};
+#[cfg(feature = "builder")]
+use crate::Message;
+use crate::{
+    address::{Address, Envelope},
+    Transport,
+};
 
 /// Sends emails using the SMTP protocol
 #[cfg_attr(docsrs, doc(cfg(feature = "smtp-transport")))]
@@ -25,9 +48,36 @@ impl Transport for SmtpTransport {
     type Ok = Response;
     type Error = Error;
 
+    /// Sends an email
+    ///
+    /// The content is formatted while being transmitted, from [`Message::reader`],
+    /// so that [`LazyAttachment`]s are streamed from disk.
+    ///
+    /// [`LazyAttachment`]: crate::message::LazyAttachment
+    #[cfg(feature = "builder")]
+    fn send(&self, message: &Message) -> Result<Self::Ok, Self::Error> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!("starting to send an email");
+
+        let size = message.formatted_len().map_err(error::client)?;
+        let mut conn = self.inner.connection().map_err(Error::while_connecting)?;
+        conn.check_size(size)?;
+
+        let result = conn.send_from_reader(
+            message.envelope(),
+            message.is_ascii(),
+            &mut message.reader(),
+        )?;
+
+        #[cfg(not(feature = "pool"))]
+        conn.quit()?;
+
+        Ok(result)
+    }
+
     /// Sends an email
     fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
-        let mut conn = self.inner.connection()?;
+        let mut conn = self.inner.connection().map_err(Error::while_connecting)?;
 
         let result = conn.send(envelope, email)?;
 
@@ -199,6 +249,32 @@ impl SmtpTransport {
         super::connection_url::from_connection_url(connection_url)
     }
 
+    /// Sends an email whose formatted content is read from `reader`
+    ///
+    /// The content is streamed to the server, so it never has to be held in
+    /// memory in full. `is_ascii` tells whether it only contains ASCII characters.
+    /// See [`SmtpConnection::send_from_reader`].
+    ///
+    /// As the length of the content isn't known in advance, it isn't checked
+    /// against the size limit advertised by the server with the `SIZE`
+    /// extension: a server enforcing it rejects the email only once it was
+    /// sent in full.
+    pub fn send_from_reader(
+        &self,
+        envelope: &Envelope,
+        is_ascii: bool,
+        reader: &mut dyn Read,
+    ) -> Result<Response, Error> {
+        let mut conn = self.inner.connection().map_err(Error::while_connecting)?;
+
+        let result = conn.send_from_reader(envelope, is_ascii, reader)?;
+
+        #[cfg(not(feature = "pool"))]
+        conn.quit()?;
+
+        Ok(result)
+    }
+
     /// Tests the SMTP connection
     ///
     /// `test_connection()` tests the connection by using the SMTP NOOP command.
@@ -264,6 +340,111 @@ impl SmtpTransportBuilder {
         self
     }
 
+    /// Require the messages to only be relayed over TLS
+    ///
+    /// Sends the `REQUIRETLS` parameter defined in
+    /// [RFC 8689](https://tools.ietf.org/html/rfc8689) with each message.
+    /// Sending fails with an error for which [`Error::is_require_tls_unsupported`]
+    /// returns `true` if the server doesn't support it. Defaults to `false`.
+    ///
+    /// To signal that a message may be delivered even if TLS can't be used,
+    /// set the `TLS-Required: No` header with [`MessageBuilder::tls_not_required`].
+    ///
+    /// [`MessageBuilder::tls_not_required`]: crate::message::MessageBuilder::tls_not_required
+    pub fn require_tls_relay(mut self, require: bool) -> Self {
+        self.info.require_tls_relay = require;
+        self
+    }
+
+    /// Set the sender used when the server permanently rejects the sender of an email
+    ///
+    /// Some relays reject senders whose domain they can't verify. With a
+    /// fallback, like `noreply@` an address of a verified domain, the email is
+    /// sent again once from it, with the original sender in an `X-Original-From`
+    /// header, instead of failing. Only the errors rejecting the sender are
+    /// retried, not transient or size errors. Defaults to `None`.
+    ///
+    /// See [`SmtpConnection::mail_from_fallback`](super::client::SmtpConnection::mail_from_fallback).
+    pub fn mail_from_fallback(mut self, fallback: Option<Address>) -> Self {
+        self.info.mail_from_fallback = fallback;
+        self
+    }
+
+    /// Request delivery status notifications for the `notify` events
+    ///
+    /// Sends the `NOTIFY` parameter defined in [RFC 3461](https://tools.ietf.org/html/rfc3461)
+    /// with each recipient, along with its address as `ORCPT`. Multiple events are
+    /// combined, unless [`DsnNotify::Never`] is given. Nothing is requested from servers
+    /// which don't advertise the `DSN` extension. Defaults to no parameter, leaving the
+    /// server's default behavior.
+    pub fn dsn_notify(mut self, notify: &[DsnNotify]) -> Self {
+        self.info.dsn_notify = notify.to_vec();
+        self
+    }
+
+    /// Set the part of the message returned with failure notifications
+    ///
+    /// Sends the `RET` parameter defined in [RFC 3461](https://tools.ietf.org/html/rfc3461)
+    /// to servers advertising the `DSN` extension.
+    pub fn dsn_return(mut self, ret: DsnReturn) -> Self {
+        self.info.dsn_return = Some(ret);
+        self
+    }
+
+    /// Establish the connections through a proxy
+    ///
+    /// The proxy connects to the server and port of the transport, and
+    /// TLS is still negotiated with the server and verified against its name.
+    ///
+    /// Only the synchronous transport supports proxies for now, the
+    /// connections of the `AsyncSmtpTransport` are always direct.
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.info.proxy = Some(proxy);
+        self
+    }
+
+    /// Send the content with `BDAT` when the server supports it
+    ///
+    /// When the server advertises the `CHUNKING` extension defined in
+    /// [RFC 3030](https://tools.ietf.org/html/rfc3030), the content is sent
+    /// in `BDAT` chunks, which some servers prefer over `DATA` and which
+    /// doesn't require dot-stuffing. `DATA` is used with the other servers,
+    /// or for all of them when set to `false`. Defaults to `true`.
+    pub fn prefer_chunking(mut self, prefer: bool) -> Self {
+        self.info.prefer_chunking = prefer;
+        self
+    }
+
+    /// Compress the connections with DEFLATE when the server supports `COMPRESS DEFLATE`
+    ///
+    /// This saves bandwidth on slow links. The compression is negotiated after
+    /// the authentication. Defaults to `false`.
+    #[cfg(feature = "compression")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+    pub fn compression(mut self, compression: bool) -> Self {
+        self.info.compression = compression;
+        self
+    }
+
+    /// Copy every byte sent to and received from the server to `tap`
+    ///
+    /// Unlike the `tracing` logs, the copy is raw and complete, including the
+    /// content of the emails and the credentials, and is taken after TLS
+    /// decryption and DEFLATE decompression. The bytes received are preceded
+    /// by `<< ` and the bytes sent by `>> `, with one marker for each read
+    /// or write, which doesn't necessarily match the lines of the protocol.
+    /// All the connections of the transport share the same `tap`.
+    ///
+    /// This is meant for debugging: the transcript contains secrets, and
+    /// errors writing to `tap` are ignored.
+    ///
+    /// Only the synchronous transport supports it for now, the builder of
+    /// the `AsyncSmtpTransport` has no equivalent.
+    pub fn protocol_tap(mut self, tap: Box<dyn Write + Send>) -> Self {
+        self.info.protocol_tap = Some(ProtocolTap::new(tap));
+        self
+    }
+
     /// Set the port to use
     pub fn port(mut self, port: u16) -> Self {
         self.info.port = port;
@@ -296,22 +477,49 @@ impl SmtpTransportBuilder {
     /// If the `pool` feature is enabled, an `Arc` wrapped pool is created.
     /// Defaults can be found at [`PoolConfig`]
     pub fn build(self) -> SmtpTransport {
-        let client = SmtpClient { info: self.info };
+        let client = SmtpClient::new(self.info);
 
         #[cfg(feature = "pool")]
         let client = Pool::new(self.pool_config, client);
 
         SmtpTransport { inner: client }
     }
+
+    /// Build the transport and check that it can be used
+    ///
+    /// Connects to the server, authenticates if credentials were provided and
+    /// sends a `NOOP` command, like [`SmtpTransport::test_connection`], so that
+    /// an unreachable server or invalid credentials are reported at startup
+    /// instead of when sending the first email.
+    pub fn build_and_verify(self) -> Result<SmtpTransport, Error> {
+        let transport = self.build();
+
+        if transport.test_connection()? {
+            Ok(transport)
+        } else {
+            Err(error::client("The server did not accept the NOOP command"))
+        }
+    }
 }
 
 /// Build client
 #[derive(Debug, Clone)]
 pub struct SmtpClient {
     info: SmtpInfo,
+    /// Whether the server advertised `STARTTLS`, shared between clones
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    starttls_seen: Arc<AtomicBool>,
 }
 
 impl SmtpClient {
+    fn new(info: SmtpInfo) -> Self {
+        Self {
+            info,
+            #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+            starttls_seen: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
     /// Creates a new connection directly usable to send emails
     ///
     /// Handles encryption and authentication
@@ -323,19 +531,36 @@ impl SmtpClient {
             _ => None,
         };
 
+        let mut stream = match &self.info.proxy {
+            Some(proxy) => NetworkStream::connect_through_proxy(
+                proxy,
+                &self.info.server,
+                self.info.port,
+                self.info.timeout,
+                tls_parameters,
+                None,
+            )?,
+            None => NetworkStream::connect::<(&str, u16)>(
+                (self.info.server.as_ref(), self.info.port),
+                self.info.timeout,
+                tls_parameters,
+                None,
+            )?,
+        };
+        // Set before the greeting, so that it is copied too
+        stream.set_protocol_tap(self.info.protocol_tap.clone());
         #[allow(unused_mut)]
-        let mut conn = SmtpConnection::connect::<(&str, u16)>(
-            (self.info.server.as_ref(), self.info.port),
-            self.info.timeout,
-            &self.info.hello_name,
-            tls_parameters,
-            None,
-        )?;
+        let mut conn = SmtpConnection::greet(stream, self.info.timeout, &self.info.hello_name)?;
 
         #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
         match self.info.tls {
             Tls::Opportunistic(ref tls_parameters) => {
-                if conn.can_starttls() {
+                let advertised = conn.can_starttls();
+                if let Err(err) = check_starttls_advertised(&self.starttls_seen, advertised) {
+                    conn.abort();
+                    return Err(err);
+                }
+                if advertised {
                     conn.starttls(tls_parameters, &self.info.hello_name)?;
                 }
             }
@@ -348,17 +573,255 @@ impl SmtpClient {
         if let Some(credentials) = &self.info.credentials {
             conn.auth(&self.info.authentication, credentials)?;
         }
+        #[cfg(feature = "compression")]
+        if self.info.compression
+            && conn
+                .server_info()
+                .supports_feature(Extension::CompressDeflate)
+        {
+            conn.compress()?;
+        }
+        conn.require_tls_relay(self.info.require_tls_relay);
+        conn.mail_from_fallback(self.info.mail_from_fallback.clone());
+        conn.prefer_chunking(self.info.prefer_chunking);
+        conn.dsn_notify(&self.info.dsn_notify);
+        conn.dsn_return(self.info.dsn_return);
         Ok(conn)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        io::{self, Write},
+        sync::{Arc, Mutex},
+    };
+
+    use super::SmtpClient;
     use crate::{
-        transport::smtp::{authentication::Credentials, client::Tls},
+        transport::smtp::{
+            authentication::Credentials,
+            client::{serve_sessions, Sessions, Tls},
+        },
         SmtpTransport,
     };
 
+    /// Starts a server answering `AUTH` with `auth_reply`
+    fn mock_server(auth_reply: &'static str) -> (u16, Sessions) {
+        mock_server_with("250-localhost\r\n250 AUTH PLAIN\r\n".to_owned(), auth_reply)
+    }
+
+    /// Starts a server like [`mock_server`], answering `EHLO` with `ehlo_reply`
+    fn mock_server_with(ehlo_reply: String, auth_reply: &'static str) -> (u16, Sessions) {
+        let (addr, sessions) = serve_sessions(move |_| ehlo_reply.clone(), auth_reply);
+        (addr.port(), sessions)
+    }
+
+    /// Returns the content of the messages received by a server, each one
+    /// ending with the `.` line
+    fn messages(sessions: &Sessions) -> Vec<String> {
+        let mut messages = Vec::new();
+        for lines in sessions.lock().unwrap().iter() {
+            let mut lines = lines.iter();
+            while lines.any(|line| line == "DATA\r\n") {
+                let mut message = String::new();
+                for line in lines.by_ref() {
+                    message.push_str(line);
+                    if line == ".\r\n" {
+                        break;
+                    }
+                }
+                messages.push(message);
+            }
+        }
+        messages
+    }
+
+    #[test]
+    fn build_and_verify() {
+        let credentials = Credentials::new("user".to_owned(), "password".to_owned());
+
+        let (port, _) = mock_server("235 Authentication successful\r\n");
+        SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(port)
+            .credentials(credentials.clone())
+            .build_and_verify()
+            .unwrap();
+
+        let (port, _) = mock_server("535 Authentication credentials invalid\r\n");
+        let err = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(port)
+            .credentials(credentials)
+            .build_and_verify()
+            .unwrap_err();
+        assert!(err.is_permanent());
+    }
+
+    #[test]
+    fn protocol_tap() {
+        /// Sink appending to a shared buffer
+        struct Tap(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for Tap {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let (port, _) = mock_server("235 Authentication successful\r\n");
+        let transcript = Arc::new(Mutex::new(Vec::new()));
+        let builder = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(port)
+            .protocol_tap(Box::new(Tap(Arc::clone(&transcript))));
+        // Without the pool, which opens connections in the background
+        let mut conn = SmtpClient::new(builder.info).connection().unwrap();
+        assert!(conn.test_connected());
+        conn.quit().unwrap();
+
+        let transcript = String::from_utf8(transcript.lock().unwrap().clone()).unwrap();
+        assert!(
+            transcript.starts_with("<< 220 localhost ESMTP\r\n>> EHLO "),
+            "{transcript}"
+        );
+        assert!(
+            transcript.contains("<< 250-localhost\r\n250 AUTH PLAIN\r\n"),
+            "{transcript}"
+        );
+        assert!(
+            transcript.contains(">> NOOP\r\n<< 250 OK\r\n"),
+            "{transcript}"
+        );
+    }
+
+    #[cfg(feature = "builder")]
+    #[test]
+    fn send_lazy_attachment() {
+        use crate::{
+            message::{header::ContentType, Attachment, MultiPart, SinglePart},
+            Message, Transport,
+        };
+
+        let path = std::env::temp_dir().join("lettre-send-lazy-attachment.bin");
+        let content = (0..1_000_000u32)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        std::fs::write(&path, content).unwrap();
+
+        let attachment = Attachment::new(String::from("data.bin")).lazy(
+            &path,
+            ContentType::parse("application/octet-stream").unwrap(),
+        );
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Data")
+            .multipart(
+                MultiPart::mixed()
+                    .singlepart(SinglePart::plain(String::from(".\nSee attached")))
+                    .lazy_attachment(attachment),
+            )
+            .unwrap();
+
+        let (port, sessions) = mock_server("235 Authentication successful\r\n");
+        SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(port)
+            .build()
+            .send(&email)
+            .unwrap();
+
+        let mut expected = email.formatted();
+        expected.extend_from_slice(b".\r\n");
+        // The leading dot of the plain text part is escaped
+        let received = messages(&sessions)
+            .pop()
+            .unwrap()
+            .replacen("\r\n..\r\n", "\r\n.\r\n", 1);
+        assert!(
+            received.as_bytes() == expected,
+            "received a different message"
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(port)
+            .build()
+            .send(&email)
+            .unwrap_err()
+            .is_client());
+    }
+
+    #[test]
+    fn send_size_limit() {
+        use crate::{
+            message::{header::ContentType, Attachment, MultiPart},
+            Message, Transport,
+        };
+
+        let path = std::env::temp_dir().join("lettre-send-size-limit.bin");
+        std::fs::write(&path, [0; 1000]).unwrap();
+
+        let attachment = Attachment::new(String::from("data.bin")).lazy(
+            &path,
+            ContentType::parse("application/octet-stream").unwrap(),
+        );
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .multipart(MultiPart::mixed().lazy_attachment(attachment))
+            .unwrap();
+        let limit = email.formatted().len() - 1;
+
+        // The size of the attachment is known without reading the file
+        let (port, sessions) = mock_server_with(
+            format!("250-localhost\r\n250 SIZE {limit}\r\n"),
+            "235 Authentication successful\r\n",
+        );
+        let err = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(port)
+            .build()
+            .send(&email)
+            .unwrap_err();
+        assert!(err.is_message_too_large());
+        assert!(messages(&sessions).is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn failover_rejected_credentials() {
+        use crate::{address::Envelope, transport::failover::FailoverTransport, Transport};
+
+        let credentials = Credentials::new("user".to_owned(), "password".to_owned());
+        let (rejecting, rejected) = mock_server("535 Authentication credentials invalid\r\n");
+        let (accepting, accepted) = mock_server("235 Authentication successful\r\n");
+        let transport = FailoverTransport::new(
+            [rejecting, accepting]
+                .into_iter()
+                .map(|port| {
+                    SmtpTransport::builder_dangerous("127.0.0.1")
+                        .port(port)
+                        .credentials(credentials.clone())
+                        .build()
+                })
+                .collect(),
+        );
+
+        // Another relay may accept the credentials, unlike the recipient
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+        transport.send_raw(&envelope, b"Hi").unwrap();
+        assert!(messages(&rejected).is_empty());
+        assert_eq!(messages(&accepted), ["Hi\r\n.\r\n"]);
+    }
+
     #[test]
     fn transport_from_url() {
         let builder = SmtpTransport::from_url("smtp://127.0.0.1:2525").unwrap();
@@ -409,4 +872,139 @@ mod tests {
         assert_eq!(builder.info.credentials, None);
         assert!(matches!(builder.info.tls, Tls::Wrapper(_)));
     }
+
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    #[test]
+    fn starttls_required() {
+        use super::SmtpClient;
+        use crate::transport::smtp::{
+            client::{serve, MockStep, TlsParameters},
+            extension::ClientId,
+            StarttlsStage,
+        };
+
+        let tls = Tls::Required(TlsParameters::new("localhost".to_owned()).unwrap());
+
+        // STARTTLS follows the first EHLO, the handshake fails on the closed connection
+        let (addr, server) = serve(vec![
+            MockStep::send("220 localhost ESMTP\r\n"),
+            MockStep::ExpectLine("EHLO ".to_owned()),
+            MockStep::send("250-localhost\r\n250 STARTTLS\r\n"),
+            MockStep::expect("STARTTLS\r\n"),
+            MockStep::send("220 Go ahead\r\n"),
+            MockStep::CloseConnection,
+        ]);
+        let builder = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(addr.port())
+            .hello_name(ClientId::Domain("localhost".to_owned()))
+            .tls(tls.clone());
+        let err = SmtpClient::new(builder.info).connection().err().unwrap();
+        assert!(matches!(
+            err.starttls_stage(),
+            Some(StarttlsStage::Io | StarttlsStage::Handshake)
+        ));
+        assert_eq!(
+            server.join().unwrap(),
+            [
+                "S: 220 localhost ESMTP",
+                "C: EHLO localhost",
+                "S: 250-localhost",
+                "S: 250 STARTTLS",
+                "C: STARTTLS",
+                "S: 220 Go ahead",
+            ]
+        );
+
+        // Nothing is sent in plaintext when the server doesn't advertise STARTTLS
+        let (addr, server) = serve(vec![
+            MockStep::send("220 localhost ESMTP\r\n"),
+            MockStep::ExpectLine("EHLO ".to_owned()),
+            MockStep::send("250-localhost\r\n250 AUTH PLAIN\r\n"),
+            MockStep::ExpectLine("QUIT".to_owned()),
+        ]);
+        let builder = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(addr.port())
+            .tls(tls)
+            .credentials(Credentials::new("user".to_owned(), "password".to_owned()));
+        let err = SmtpClient::new(builder.info).connection().err().unwrap();
+        assert_eq!(err.starttls_stage(), Some(StarttlsStage::NotAdvertised));
+        assert_eq!(
+            err.to_string(),
+            "starttls error (not advertised by the server)"
+        );
+        let transcript = server.join().unwrap();
+        assert!(!transcript.iter().any(|line| line.starts_with("C: AUTH")));
+    }
+
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    #[test]
+    fn starttls_opportunistic_and_none() {
+        use super::SmtpClient;
+        use crate::transport::smtp::{
+            client::{serve, MockStep, TlsParameters},
+            extension::ClientId,
+        };
+
+        let plaintext_session = |server_ehlo: &'static str, tls: Tls| {
+            let (addr, server) = serve(vec![
+                MockStep::send("220 localhost ESMTP\r\n"),
+                MockStep::ExpectLine("EHLO ".to_owned()),
+                MockStep::send(server_ehlo),
+                MockStep::expect("QUIT\r\n"),
+                MockStep::send("221 Bye\r\n"),
+            ]);
+            let builder = SmtpTransport::builder_dangerous("127.0.0.1")
+                .port(addr.port())
+                .hello_name(ClientId::Domain("localhost".to_owned()))
+                .tls(tls);
+            let mut conn = SmtpClient::new(builder.info).connection().unwrap();
+            assert!(!conn.is_encrypted());
+            conn.quit().unwrap();
+            server.join().unwrap()
+        };
+
+        // Without STARTTLS, the opportunistic policy continues in plaintext
+        let tls = Tls::Opportunistic(TlsParameters::new("localhost".to_owned()).unwrap());
+        let transcript = plaintext_session("250-localhost\r\n250 AUTH PLAIN\r\n", tls);
+        assert_eq!(transcript[1], "C: EHLO localhost");
+        assert_eq!(transcript.last().unwrap(), "S: 221 Bye");
+
+        // STARTTLS is never sent without a TLS policy, even when advertised
+        let transcript = plaintext_session("250-localhost\r\n250 STARTTLS\r\n", Tls::None);
+        assert!(!transcript.iter().any(|line| line == "C: STARTTLS"));
+    }
+
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls", feature = "boring-tls"))]
+    #[test]
+    fn starttls_downgrade() {
+        use super::SmtpClient;
+        use crate::transport::smtp::client::TlsParameters;
+
+        // The first connection advertises STARTTLS, the next ones don't
+        let (addr, _sessions) = serve_sessions(
+            |i| match i {
+                0 => "250-localhost\r\n250 STARTTLS\r\n".to_owned(),
+                _ => "250 localhost\r\n".to_owned(),
+            },
+            "",
+        );
+        let port = addr.port();
+
+        let tls = Tls::Opportunistic(TlsParameters::new("localhost".to_owned()).unwrap());
+        let builder = SmtpTransport::builder_dangerous("127.0.0.1")
+            .port(port)
+            .tls(tls);
+
+        let client = SmtpClient::new(builder.info.clone());
+        let err = client.connection().err().unwrap();
+        assert!(!err.is_tls_downgrade());
+
+        // STARTTLS was stripped, clones share what was seen before
+        let err = client.clone().connection().err().unwrap();
+        assert!(err.is_tls_downgrade());
+
+        // A server which never advertised STARTTLS is used in plaintext
+        let client = SmtpClient::new(builder.info);
+        assert!(!client.connection().unwrap().is_encrypted());
+    }
 }
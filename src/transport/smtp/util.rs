@@ -38,6 +38,10 @@ mod tests {
             ("bjørn", "bjørn"),
             ("Ø+= ❤️‰", "Ø+2B+3D+20❤️‰"),
             ("+", "+2B"),
+            ("", ""),
+            ("a", "a"),
+            (" ", "+20"),
+            ("a=b c", "a+3Db+20c"),
         ]
         .iter()
         {
@@ -51,8 +51,13 @@ use futures_util::lock::Mutex as FuturesMutex;
 
 #[cfg(any(feature = "tokio1", feature = "async-std1"))]
 use crate::AsyncTransport;
+#[cfg(feature = "builder")]
+use crate::Message;
 use crate::{address::Envelope, Transport};
 
+/// Envelopes and contents of the messages sent through a stub transport
+type MessageLog = Vec<(Envelope, Vec<u8>)>;
+
 /// An error returned by the stub transport
 #[non_exhaustive]
 #[derive(Debug, Copy, Clone)]
@@ -70,7 +75,7 @@ impl StdError for Error {}
 #[derive(Debug, Clone)]
 pub struct StubTransport {
     response: Result<(), Error>,
-    message_log: Arc<StdMutex<Vec<(Envelope, String)>>>,
+    message_log: Arc<StdMutex<MessageLog>>,
 }
 
 /// This transport logs messages and always returns the given response
@@ -79,7 +84,7 @@ pub struct StubTransport {
 #[cfg_attr(docsrs, doc(cfg(any(feature = "tokio1", feature = "async-std1"))))]
 pub struct AsyncStubTransport {
     response: Result<(), Error>,
-    message_log: Arc<FuturesMutex<Vec<(Envelope, String)>>>,
+    message_log: Arc<FuturesMutex<MessageLog>>,
 }
 
 impl StubTransport {
@@ -108,7 +113,18 @@ impl StubTransport {
     }
 
     /// Return all logged messages sent using [`Transport::send_raw`]
+    ///
+    /// Invalid UTF-8 sequences in the messages are replaced, use
+    /// [`StubTransport::raw_messages`] to get their exact content.
     pub fn messages(&self) -> Vec<(Envelope, String)> {
+        self.raw_messages()
+            .into_iter()
+            .map(|(envelope, email)| (envelope, String::from_utf8_lossy(&email).into()))
+            .collect()
+    }
+
+    /// Return all logged messages sent using [`Transport::send_raw`], as bytes
+    pub fn raw_messages(&self) -> Vec<(Envelope, Vec<u8>)> {
         self.message_log
             .lock()
             .expect("Couldn't acquire lock to write message log")
@@ -143,8 +159,21 @@ impl AsyncStubTransport {
     }
 
     /// Return all logged messages sent using [`AsyncTransport::send_raw`]
+    ///
+    /// Invalid UTF-8 sequences in the messages are replaced, use
+    /// [`AsyncStubTransport::raw_messages`] to get their exact content.
     #[cfg(any(feature = "tokio1", feature = "async-std1"))]
     pub async fn messages(&self) -> Vec<(Envelope, String)> {
+        self.raw_messages()
+            .await
+            .into_iter()
+            .map(|(envelope, email)| (envelope, String::from_utf8_lossy(&email).into()))
+            .collect()
+    }
+
+    /// Return all logged messages sent using [`AsyncTransport::send_raw`], as bytes
+    #[cfg(any(feature = "tokio1", feature = "async-std1"))]
+    pub async fn raw_messages(&self) -> Vec<(Envelope, Vec<u8>)> {
         self.message_log.lock().await.clone()
     }
 }
@@ -153,11 +182,20 @@ impl Transport for StubTransport {
     type Ok = ();
     type Error = Error;
 
+    #[cfg(feature = "builder")]
+    fn send(&self, message: &Message) -> Result<Self::Ok, Self::Error> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!("starting to send an email");
+
+        let raw = message.try_formatted().map_err(|_| Error)?;
+        self.send_raw(message.envelope(), &raw)
+    }
+
     fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
         self.message_log
             .lock()
             .expect("Couldn't acquire lock to write message log")
-            .push((envelope.clone(), String::from_utf8_lossy(email).into()));
+            .push((envelope.clone(), email.to_vec()));
         self.response
     }
 }
@@ -168,11 +206,20 @@ impl AsyncTransport for AsyncStubTransport {
     type Ok = ();
     type Error = Error;
 
+    #[cfg(feature = "builder")]
+    async fn send(&self, message: Message) -> Result<Self::Ok, Self::Error> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!("starting to send an email");
+
+        let raw = message.try_formatted().map_err(|_| Error)?;
+        self.send_raw(message.envelope(), &raw).await
+    }
+
     async fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
         self.message_log
             .lock()
             .await
-            .push((envelope.clone(), String::from_utf8_lossy(email).into()));
+            .push((envelope.clone(), email.to_vec()));
         self.response
     }
 }
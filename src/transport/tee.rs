@@ -0,0 +1,144 @@
+//! The tee transport sends each email through a primary transport, and
+//! copies it to a secondary one.
+//!
+//! The result of the primary transport is the one returned to the caller.
+//! Sending through the secondary transport is best-effort: its errors are
+//! only logged, and never fail the sending. This is useful when migrating
+//! to a new transport, for example to keep a copy of the sent emails with
+//! the [`file`][super::file] transport.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use std::error::Error;
+//! #
+//! # #[cfg(all(feature = "builder", feature = "smtp-transport", feature = "file-transport"))]
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! use std::env::temp_dir;
+//!
+//! use lettre::{transport::tee::TeeTransport, FileTransport, Message, SmtpTransport, Transport};
+//!
+//! let email = Message::builder()
+//!     .from("NoBody <nobody@domain.tld>".parse()?)
+//!     .to("Hei <hei@domain.tld>".parse()?)
+//!     .subject("Happy new year")
+//!     .body(String::from("Be happy!"))?;
+//!
+//! let mailer = TeeTransport::new(
+//!     SmtpTransport::relay("smtp.example.com")?.build(),
+//!     FileTransport::new(temp_dir()),
+//! );
+//! mailer.send(&email)?;
+//! # Ok(())
+//! # }
+//! # #[cfg(not(all(feature = "builder", feature = "smtp-transport", feature = "file-transport")))]
+//! # fn main() {}
+//! ```
+
+use std::fmt::Debug;
+
+use super::Transport;
+use crate::address::Envelope;
+#[cfg(feature = "builder")]
+use crate::Message;
+
+/// A transport sending emails through a primary transport, and copying
+/// them to a secondary transport
+#[derive(Debug, Clone)]
+pub struct TeeTransport<P, S> {
+    primary: P,
+    secondary: S,
+}
+
+impl<P, S> TeeTransport<P, S> {
+    /// Creates a new transport sending emails through `primary`,
+    /// and copying them to `secondary`
+    pub fn new(primary: P, secondary: S) -> Self {
+        Self { primary, secondary }
+    }
+
+    /// Returns the primary transport
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    /// Returns the secondary transport
+    pub fn secondary(&self) -> &S {
+        &self.secondary
+    }
+}
+
+impl<P, S> Transport for TeeTransport<P, S>
+where
+    P: Transport,
+    S: Transport,
+    S::Error: Debug,
+{
+    type Ok = P::Ok;
+    type Error = P::Error;
+
+    #[cfg(feature = "builder")]
+    fn send(&self, message: &Message) -> Result<Self::Ok, Self::Error> {
+        let result = self.primary.send(message);
+        copy(self.secondary.send(message));
+        result
+    }
+
+    fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        let result = self.primary.send_raw(envelope, email);
+        copy(self.secondary.send_raw(envelope, email));
+        result
+    }
+}
+
+/// Logs the error of the secondary transport
+fn copy<O, E: Debug>(result: Result<O, E>) {
+    if let Err(err) = result {
+        #[cfg(feature = "tracing")]
+        tracing::warn!("secondary transport couldn't send the email {:?}", err);
+        #[cfg(not(feature = "tracing"))]
+        let _ = err;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TeeTransport, Transport};
+    use crate::transport::{envelope, stub::StubTransport};
+
+    #[test]
+    fn tee_secondary_error() {
+        let transport = TeeTransport::new(StubTransport::new_ok(), StubTransport::new_error());
+
+        assert!(transport.send_raw(&envelope(), b"Hi").is_ok());
+        assert_eq!(transport.primary().messages().len(), 1);
+        assert_eq!(transport.secondary().messages().len(), 1);
+    }
+
+    #[test]
+    fn tee_primary_error() {
+        let transport = TeeTransport::new(StubTransport::new_error(), StubTransport::new_ok());
+
+        assert!(transport.send_raw(&envelope(), b"Hi").is_err());
+        assert_eq!(
+            transport.secondary().messages(),
+            vec![(envelope(), "Hi".to_owned())]
+        );
+    }
+
+    #[cfg(feature = "builder")]
+    #[test]
+    fn tee_removed_attachment() {
+        use crate::transport::message_with_removed_attachment;
+
+        let transport = TeeTransport::new(StubTransport::new_ok(), StubTransport::new_ok());
+
+        assert!(transport
+            .send(&message_with_removed_attachment(
+                "lettre-tee-removed-attachment.pdf"
+            ))
+            .is_err());
+        assert!(transport.primary().messages().is_empty());
+        assert!(transport.secondary().messages().is_empty());
+    }
+}
@@ -11,10 +11,13 @@ fn default_date() -> std::time::SystemTime {
 mod sync {
     use std::{
         env::temp_dir,
-        fs::{read_to_string, remove_file},
+        fs::{create_dir_all, read_dir, read_to_string, remove_dir_all, remove_file, write},
+        path::PathBuf,
+        thread::sleep,
+        time::Duration,
     };
 
-    use lettre::{FileTransport, Message, Transport};
+    use lettre::{transport::file::SpoolPolicy, FileTransport, Message, Transport};
 
     use crate::default_date;
 
@@ -101,6 +104,141 @@ mod sync {
         remove_file(eml_file).unwrap();
         remove_file(json_file).unwrap();
     }
+
+    #[test]
+    fn file_transport_eml_parses() {
+        use lettre::message::RawEmail;
+
+        let dir = spool_dir("lettre-eml-parses");
+        let sender = FileTransport::new(&dir);
+        let email = spool_email();
+        let id = sender.send(&email).unwrap();
+
+        // The file holds the raw message, which can be read back like any `.eml` file
+        let raw = RawEmail::from_file(dir.join(format!("{id}.eml"))).unwrap();
+        assert_eq!(raw.envelope(), email.envelope());
+        assert_eq!(raw.formatted(), email.formatted());
+        assert_eq!(read_dir(&dir).unwrap().count(), 1);
+
+        remove_dir_all(dir).unwrap();
+    }
+
+    fn spool_dir(name: &str) -> PathBuf {
+        let dir = temp_dir().join(name);
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn spool_email() -> Message {
+        Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .date(default_date())
+            .body(String::from("Be happy!"))
+            .unwrap()
+    }
+
+    #[test]
+    fn file_transport_spool_full() {
+        let dir = spool_dir("lettre-spool-full");
+        let sender = FileTransport::new(&dir).max_spool_files(2);
+        let email = spool_email();
+
+        let first = sender.send(&email).unwrap();
+        sender.send(&email).unwrap();
+        let usage = sender.spool_usage().unwrap();
+        assert_eq!(usage.files(), 2);
+        assert_eq!(usage.bytes(), 2 * email.formatted().len() as u64);
+
+        let err = sender.send(&email).unwrap_err();
+        assert!(err.is_spool_full());
+        assert_eq!(sender.spool_usage().unwrap(), usage);
+
+        // Removing a message from the spool makes room again
+        remove_file(dir.join(format!("{first}.eml"))).unwrap();
+        sender.send(&email).unwrap();
+        assert_eq!(sender.spool_usage().unwrap().files(), 2);
+
+        let len = email.formatted().len() as u64;
+        let sender = FileTransport::new(&dir).max_spool_bytes(3 * len - 1);
+        assert!(sender.send(&email).unwrap_err().is_spool_full());
+
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn file_transport_spool_existing() {
+        let dir = spool_dir("lettre-spool-existing");
+        let email = spool_email();
+        FileTransport::new(&dir).send(&email).unwrap();
+        FileTransport::new(&dir).send(&email).unwrap();
+
+        // A new transport scans the directory
+        let sender = FileTransport::new(&dir).max_spool_files(2);
+        assert_eq!(sender.spool_usage().unwrap().files(), 2);
+        assert!(sender.send(&email).unwrap_err().is_spool_full());
+
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn file_transport_temporary_files() {
+        let dir = spool_dir("lettre-spool-tmp");
+        let email = spool_email();
+
+        // Left behind by a writer which was killed
+        write(dir.join("interrupted.eml.1234.0.tmp"), "From: Nob").unwrap();
+
+        let sender = FileTransport::new(&dir).max_spool_files(2);
+        let id = sender.send(&email).unwrap();
+        assert_eq!(sender.spool_usage().unwrap().files(), 1);
+        assert_eq!(
+            read_to_string(dir.join(format!("{id}.eml"))).unwrap(),
+            String::from_utf8(email.formatted()).unwrap()
+        );
+
+        let mut names = read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            [format!("{id}.eml"), "interrupted.eml.1234.0.tmp".to_owned()]
+        );
+
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn file_transport_spool_evict_oldest() {
+        let dir = spool_dir("lettre-spool-evict");
+        let sender = FileTransport::new(&dir)
+            .max_spool_files(2)
+            .spool_policy(SpoolPolicy::EvictOldest);
+        let email = spool_email();
+
+        let first = sender.send(&email).unwrap();
+        sleep(Duration::from_millis(20));
+        let second = sender.send(&email).unwrap();
+        sleep(Duration::from_millis(20));
+        let third = sender.send(&email).unwrap();
+
+        assert!(!dir.join(format!("{first}.eml")).exists());
+        assert!(dir.join(format!("{second}.eml")).exists());
+        assert!(dir.join(format!("{third}.eml")).exists());
+        assert_eq!(sender.spool_usage().unwrap().files(), 2);
+
+        // A message which can never fit is rejected without evicting anything
+        let sender = sender.max_spool_bytes(1);
+        assert!(sender.send(&email).unwrap_err().is_spool_full());
+        assert!(dir.join(format!("{second}.eml")).exists());
+        assert!(dir.join(format!("{third}.eml")).exists());
+
+        remove_dir_all(dir).unwrap();
+    }
 }
 
 #[cfg(test)]
@@ -108,10 +246,12 @@ mod sync {
 mod tokio_1 {
     use std::{
         env::temp_dir,
-        fs::{read_to_string, remove_file},
+        fs::{create_dir_all, read_to_string, remove_dir_all, remove_file},
     };
 
-    use lettre::{AsyncFileTransport, AsyncTransport, Message, Tokio1Executor};
+    use lettre::{
+        transport::file::SpoolPolicy, AsyncFileTransport, AsyncTransport, Message, Tokio1Executor,
+    };
     use tokio1_crate as tokio;
 
     use crate::default_date;
@@ -149,6 +289,35 @@ mod tokio_1 {
         );
         remove_file(eml_file).unwrap();
     }
+
+    #[tokio::test]
+    async fn file_transport_spool_tokio1() {
+        let dir = temp_dir().join("lettre-spool-tokio1");
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+
+        let sender = AsyncFileTransport::<Tokio1Executor>::new(&dir)
+            .max_spool_files(1)
+            .spool_policy(SpoolPolicy::EvictOldest);
+        let email = Message::builder()
+            .from("NoBody <nobody@domain.tld>".parse().unwrap())
+            .to("Hei <hei@domain.tld>".parse().unwrap())
+            .subject("Happy new year")
+            .date(default_date())
+            .body(String::from("Be happy!"))
+            .unwrap();
+
+        let first = sender.send(email.clone()).await.unwrap();
+        let second = sender.send(email.clone()).await.unwrap();
+        assert!(!dir.join(format!("{first}.eml")).exists());
+        assert!(dir.join(format!("{second}.eml")).exists());
+
+        let sender = sender.max_spool_bytes(1);
+        assert!(sender.send(email).await.unwrap_err().is_spool_full());
+        assert!(dir.join(format!("{second}.eml")).exists());
+
+        remove_dir_all(dir).unwrap();
+    }
 }
 
 #[cfg(test)]
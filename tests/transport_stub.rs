@@ -1,7 +1,7 @@
 #[cfg(test)]
 #[cfg(feature = "builder")]
 mod sync {
-    use lettre::{transport::stub::StubTransport, Message, Transport};
+    use lettre::{address::Envelope, transport::stub::StubTransport, Message, Transport};
 
     #[test]
     fn stub_transport() {
@@ -24,6 +24,28 @@ mod sync {
         )];
         assert_eq!(sender_ok.messages(), expected_messages);
     }
+
+    #[test]
+    fn stub_transport_raw_messages() {
+        let sender = StubTransport::new_ok();
+        let envelope = Envelope::new(
+            Some("nobody@domain.tld".parse().unwrap()),
+            vec!["hei@domain.tld".parse().unwrap()],
+        )
+        .unwrap();
+        let email = b"Subject: Bytes\r\n\r\n\xff\xfe\r\n";
+
+        sender.send_raw(&envelope, email).unwrap();
+
+        assert_eq!(sender.raw_messages(), [(envelope.clone(), email.to_vec())]);
+        assert_eq!(
+            sender.messages(),
+            [(
+                envelope,
+                String::from("Subject: Bytes\r\n\r\n\u{fffd}\u{fffd}\r\n")
+            )]
+        );
+    }
 }
 
 #[cfg(test)]